@@ -0,0 +1,30 @@
+//! Example: Exposing nucleus plugins to MCP clients
+//!
+//! Runs nucleus as an MCP server over stdio, so editors such as Claude Desktop
+//! or Zed can use its plugins as tools. Point the client at this binary, e.g.:
+//!
+//! ```json
+//! { "mcpServers": { "nucleus": { "command": "cargo", "args": ["run", "--example", "mcp_serve"] } } }
+//! ```
+//!
+//! Only read-only plugins are granted here; `exec` and `write_file` are not exposed.
+
+use nucleus_core::server::McpServer;
+use nucleus_plugin::{Permission, PluginRegistry};
+use nucleus_std::{EnvInfoPlugin, ExecPlugin, ReadFilePlugin, ReadFilesPlugin, SearchPlugin};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut registry = PluginRegistry::new(Permission::READ_ONLY);
+    registry.register(ReadFilePlugin::new()).await;
+    registry.register(ReadFilesPlugin::new()).await;
+    registry.register(SearchPlugin::new()).await;
+    registry.register(EnvInfoPlugin::new()).await;
+    // Denied by the READ_ONLY permission, so it is never offered to clients
+    registry.register(ExecPlugin::new()).await;
+
+    // stdout carries the protocol, so nothing else may be printed to it
+    McpServer::new(registry).serve_stdio().await?;
+
+    Ok(())
+}
@@ -36,7 +36,7 @@ async fn main() -> anyhow::Result<()> {
 
 fn print_rag_config(config: &Config) {
     println!("RAG Configuration:");
-    match &config.storage.storage_mode {
+    match &config.resolved_storage().storage_mode {
         nucleus_core::config::StorageMode::Embedded { path } => {
             println!("  Storage: Embedded at {}", path);
         }
@@ -81,7 +81,7 @@ async fn query_example(manager: &ChatManager) -> anyhow::Result<()> {
 
 fn print_summary(config: &Config, doc_count: usize) {
     println!("=== Summary ===");
-    match &config.storage.storage_mode {
+    match &config.resolved_storage().storage_mode {
         nucleus_core::config::StorageMode::Embedded { path } => {
             println!(
                 "Collection '{}' at {}",
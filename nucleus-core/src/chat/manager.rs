@@ -27,18 +27,24 @@
 //! while the final `done=true` chunk contains no tool calls. The manager
 //! preserves tool calls from any chunk to ensure they're not lost.
 
+use super::tool_loop::{run_tool_loop, stream_turn, Conversation, ToolEvent};
 use crate::config::Config;
 use crate::models::EmbeddingModel;
+use crate::prompt::{render_prompt, PromptVars};
 use crate::provider::{
-    create_provider, ChatRequest, ChatResponse, Message, Provider, ProviderType, StructuredOutput,
-    Tool, ToolCall, ToolFunction,
+    create_provider, ChatRequest, Message, Provider, ProviderType, StructuredOutput, Tool,
+    ToolFunction,
+};
+use crate::rag::{
+    ChunkPreview, EmbedProgress, IndexProgress, IndexReport, IndexedSource, RagEngine, SearchResult,
 };
-use crate::rag::RagEngine;
 use anyhow::{Context, Result};
 use futures::future::join_all;
 use nucleus_plugin::{Permission, PluginRegistry};
+use std::ops::Range;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tracing::{debug, info};
 
 /// Manages multi-turn conversations with tool-augmented LLM capabilities.
@@ -77,6 +83,7 @@ use tracing::{debug, info};
 /// - Tool calls arrive in streaming chunks and must be preserved across chunks
 /// - The conversation loop continues until the LLM returns a non-tool response
 /// - All conversation history is maintained for context
+#[derive(Clone)]
 pub struct ChatManager {
     /// Nucleus core configuration
     pub config: Config,
@@ -88,6 +95,21 @@ pub struct ChatManager {
     rag_engine: Option<Arc<RagEngine>>,
     /// Optional JSON schema for forcing a structured JSON output
     pub structured_output: Option<StructuredOutput>,
+    /// The last summary made to fit a history into its token budget, shared
+    /// with forks
+    history_summary: Arc<Mutex<Option<HistorySummary>>>,
+}
+
+/// Older turns of a conversation condensed by
+/// [`fit_history`](ChatManager::fit_history), kept so that later queries
+/// continuing the conversation reuse the summary instead of making it again.
+struct HistorySummary {
+    /// The summarized messages, serialized to compare them with later histories
+    turns: String,
+    /// Number of messages summarized
+    len: usize,
+    /// The system message holding the summary
+    message: Message,
 }
 
 impl ChatManager {
@@ -139,13 +161,17 @@ impl ChatManager {
     /// let registry = PluginRegistry::new(Permission::READ_ONLY);
     ///
     /// // Override LLM model
-    /// let manager = ChatManager::builder(config.clone(), registry.clone())
+    /// let manager = ChatManager::builder()
+    ///     .with_config(config.clone())
+    ///     .with_registry(registry.clone())
     ///     .with_llm_model("Qwen/Qwen3-1.6B-Instruct")
     ///     .build()
     ///     .await?;
     ///
     /// // Override both LLM and embedding models
-    /// let manager = ChatManager::builder(config, registry)
+    /// let manager = ChatManager::builder()
+    ///     .with_config(config)
+    ///     .with_registry(registry)
     ///     .with_llm_model("Qwen/Qwen3-1.6B-Instruct")
     ///     .with_embedding_model("BAAI/bge-small-en-v1.5")
     ///     .build()
@@ -153,7 +179,7 @@ impl ChatManager {
     /// # Ok(())
     /// # }
     /// ```
-    fn builder() -> ChatManagerBuilder {
+    pub fn builder() -> ChatManagerBuilder {
         ChatManagerBuilder::new()
     }
 
@@ -259,6 +285,183 @@ impl ChatManager {
         }
     }
 
+    /// Indexes a single file into the knowledge base, replacing the chunks
+    /// stored for it by earlier indexing.
+    ///
+    /// Unlike [`index_directory`](Self::index_directory), the file is indexed
+    /// even if the indexer's extension or exclude filters would skip it.
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks indexed from the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or embedded.
+    pub async fn index_file(&self, path: &Path) -> Result<usize> {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .index_file(&path.to_string_lossy())
+                .await
+                .context("Failed to index file"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Rebuilds the knowledge base from the files it was indexed from with the
+    /// current settings, calling `on_progress` after each file. Files that no
+    /// longer exist are dropped and listed in the report's `missing`; sources
+    /// that aren't files are embedded again and listed in `kept`.
+    pub async fn reindex_all_with_progress<F>(&self, on_progress: F) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+    {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .reindex_all_with_progress(on_progress)
+                .await
+                .context("Failed to reindex knowledge base"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Indexes a directory into the knowledge base, reporting which files were
+    /// indexed, skipped or failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be read, or on the first failing
+    /// file when `indexer.abort_on_error` is set.
+    pub async fn index_directory_report(&self, dir_path: &Path) -> Result<IndexReport> {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .index_directory_report(dir_path)
+                .await
+                .context("Failed to index directory"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Indexes a directory like [`index_directory_report`](Self::index_directory_report),
+    /// calling `on_progress` after each file.
+    pub async fn index_directory_with_progress<F>(
+        &self,
+        dir_path: &Path,
+        on_progress: F,
+    ) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+    {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .index_directory_with_progress(dir_path, on_progress)
+                .await
+                .context("Failed to index directory"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Indexes a directory like [`index_directory_with_progress`](Self::index_directory_with_progress),
+    /// also calling `on_embedded` after each batch of chunks is embedded.
+    pub async fn index_directory_with_embed_progress<F, E>(
+        &self,
+        dir_path: &Path,
+        on_progress: F,
+        on_embedded: E,
+    ) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+        E: FnMut(&EmbedProgress) + Send,
+    {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .index_directory_with_embed_progress(dir_path, on_progress, on_embedded)
+                .await
+                .context("Failed to index directory"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Watches a directory and reindexes files in the knowledge base as they
+    /// are created, modified or deleted.
+    ///
+    /// Runs until the returned future is dropped; see
+    /// [`RagEngine::watch`] for how changes are handled.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::ChatManager;
+    /// # use std::path::Path;
+    /// # async fn example(manager: ChatManager) -> anyhow::Result<()> {
+    /// manager.index_directory(Path::new("./src")).await?;
+    /// manager.watch_and_index(Path::new("./src")).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be watched.
+    pub async fn watch_and_index(&self, dir_path: &Path) -> Result<()> {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .watch(dir_path)
+                .await
+                .context("Failed to watch directory"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Lists every source in the knowledge base with its number of chunks.
+    pub async fn indexed_sources(&self) -> Result<Vec<IndexedSource>> {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .indexed_sources()
+                .await
+                .context("Failed to list indexed sources"),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Splits a file into the chunks it would be indexed as, using the
+    /// configured chunk settings, without embedding or storing them.
+    ///
+    /// Useful for tuning `indexer.chunk_size` and `indexer.chunk_overlap`.
+    pub async fn preview_chunks(&self, path: &Path) -> Result<Vec<ChunkPreview>> {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine
+                .preview_chunks(path)
+                .await
+                .with_context(|| format!("Failed to chunk {}", path.display())),
+            None => Err(anyhow::anyhow!("RAG Engine not configured")),
+        }
+    }
+
+    /// Override the system prompt from the configuration.
+    ///
+    /// The prompt may contain `{pwd}`, `{date}` and `{project}` placeholders,
+    /// which are filled in for each query. See [`prompt`](crate::prompt) for details.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_plugin::{PluginRegistry, Permission};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let config = Config::load_or_default();
+    /// # let registry = PluginRegistry::new(Permission::READ_ONLY);
+    /// let manager = ChatManager::new(config, registry)
+    ///     .await?
+    ///     .with_system_prompt("You are reviewing code in {project}. Today is {date}.");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.system_prompt = prompt.into();
+        self
+    }
+
     /// Sets the structured output for the `ChatManager`.
     pub fn set_structured_output(&mut self, schema: serde_json::Value) {
         self.structured_output = Some(StructuredOutput::new(schema));
@@ -379,61 +582,366 @@ impl ChatManager {
     where
         F: FnMut(&str) + Send,
     {
-        let (context, mut messages) = match messages {
-            Some(messages) => (String::new(), messages.clone()),
+        let (context, messages) = match messages {
+            Some(messages) => (String::new(), self.fit_history(messages.clone()).await?),
             None => self.prepare_messages(user_message).await,
         };
 
-        let tools = self.build_tools().await;
+        self.run_conversation(context, messages, &mut on_chunk, None)
+            .await
+            .map(|conversation| conversation.response)
+    }
 
-        loop {
-            let mut request = ChatRequest::new(&self.config.llm.model, messages.clone())
-                .with_temperature(self.config.llm.temperature);
+    /// Sends a query like [`query`](Self::query), also recording every tool
+    /// call the model made on the way to its answer and how quickly the
+    /// response streamed in.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_plugin::PluginRegistry;
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let config = Config::load_or_default();
+    /// # let registry = PluginRegistry::new(nucleus_plugin::Permission::READ_ONLY);
+    /// # let manager = ChatManager::new(config, registry).await?;
+    /// let traced = manager.query_with_trace(None, "What's in Cargo.toml?").await?;
+    /// for call in &traced.tool_calls {
+    ///     println!("{}({}) took {}ms", call.tool_name, call.arguments, call.duration_ms);
+    /// }
+    /// println!("Response: {}", traced.response);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_with_trace(
+        &self,
+        messages: Option<&Vec<Message>>,
+        user_message: &str,
+    ) -> Result<QueryTrace> {
+        let (context, messages) = match messages {
+            Some(messages) => (String::new(), self.fit_history(messages.clone()).await?),
+            None => self.prepare_messages(user_message).await,
+        };
 
-            if !tools.is_empty() {
-                request.tools = Some(tools.clone());
-            }
+        let mut tool_calls = Vec::new();
+        let mut arrivals = Vec::new();
+        let started = Instant::now();
+        let conversation = self
+            .run_conversation(
+                context,
+                messages,
+                &mut |_: &str| arrivals.push(Instant::now()),
+                Some(&mut tool_calls),
+            )
+            .await?;
 
-            if let Some(structured_output) = &self.structured_output {
-                request = request.with_structured_output(structured_output.clone());
-            }
+        Ok(QueryTrace {
+            response: conversation.response,
+            tool_calls,
+            tool_limit_reached: conversation.tool_limit_reached,
+            timing: StreamTiming::from_arrivals(started, &arrivals),
+        })
+    }
 
-            let assistant_message = self.process_response_stream(request, &mut on_chunk).await?;
+    /// Condenses older turns of a conversation into a single summary message.
+    ///
+    /// The leading system prompt and the most recent
+    /// [`history_keep_recent`](crate::config::LlmConfig::history_keep_recent)
+    /// messages are kept verbatim; everything in between is summarized by the
+    /// model and replaced with one system message holding the summary. Tool
+    /// results are never separated from the assistant message that requested them.
+    ///
+    /// Returns the messages unchanged if there is nothing old enough to summarize.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_core::provider::Message;
+    /// # use nucleus_plugin::PluginRegistry;
+    /// # async fn example(history: Vec<Message>) -> anyhow::Result<()> {
+    /// # let config = Config::load_or_default();
+    /// # let registry = PluginRegistry::new(nucleus_plugin::Permission::READ_ONLY);
+    /// # let manager = ChatManager::new(config, registry).await?;
+    /// let history = manager.summarize_history(&history).await?;
+    /// let response = manager.query(Some(&history), "").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn summarize_history(&self, messages: &[Message]) -> Result<Vec<Message>> {
+        Ok(match self.summarize_turns(messages).await? {
+            Some((turns, summary)) => replace_turns(messages.to_vec(), turns, summary),
+            None => messages.to_vec(),
+        })
+    }
 
-            if let Some(tool_calls) = assistant_message.tool_calls {
-                let mut new_messages = messages.clone();
-                new_messages.push(Message {
-                    role: "assistant".to_string(),
-                    context: Some(context.clone()),
-                    content: assistant_message.content.clone(),
-                    images: None,
-                    tool_calls: Some(tool_calls.clone()),
-                });
+    /// Summarizes the older turns of `messages` as described for
+    /// [`summarize_history`](Self::summarize_history), returning which
+    /// messages were summarized and the system message replacing them.
+    async fn summarize_turns(
+        &self,
+        messages: &[Message],
+    ) -> Result<Option<(Range<usize>, Message)>> {
+        let start = usize::from(messages.first().is_some_and(|m| m.role == "system"));
+        let mut split = messages
+            .len()
+            .saturating_sub(self.config.llm.history_keep_recent)
+            .max(start);
+        while split > start && messages.get(split).is_some_and(|m| m.role == "tool") {
+            split -= 1;
+        }
 
-                for tool_call in tool_calls {
-                    let result = self
-                        .registry
-                        .execute(
-                            &tool_call.function.name,
-                            tool_call.function.arguments.clone(),
-                        )
-                        .await?;
-
-                    new_messages.push(Message {
-                        role: "tool".to_string(),
-                        context: Some(context.clone()),
-                        content: result.content,
-                        images: None,
-                        tool_calls: None,
-                    });
-                }
+        if split == start {
+            return Ok(None);
+        }
 
-                messages = new_messages;
-                continue;
-            }
+        let transcript = messages[start..split]
+            .iter()
+            .map(|m| format!("{}: {}", m.role, m.content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let request = ChatRequest::new(
+            &self.config.llm.model,
+            vec![
+                Message::system(None, SUMMARY_PROMPT),
+                Message::user(None, transcript),
+            ],
+        )
+        .with_temperature(self.config.llm.temperature);
+
+        let summary = self
+            .process_response_stream(request, |_| {})
+            .await
+            .context("Failed to summarize conversation history")?;
+
+        info!(
+            summarized = split - start,
+            kept = messages.len() - split,
+            "Summarized conversation history"
+        );
+
+        let summary = Message::system(
+            None,
+            format!("{}{}", SUMMARY_PREFIX, summary.content.trim()),
+        );
+        Ok(Some((start..split, summary)))
+    }
+
+    /// Summarizes `messages` if they exceed the configured history token budget.
+    ///
+    /// The summary is kept, so when a later history continues the same
+    /// conversation its summarized turns are replaced with it again without
+    /// asking the model; only if that still exceeds the budget is the history
+    /// summarized anew.
+    async fn fit_history(&self, messages: Vec<Message>) -> Result<Vec<Message>> {
+        let budget = self.config.llm.history_token_budget;
+        if budget == 0 || estimate_tokens(&messages) <= budget {
+            return Ok(messages);
+        }
 
-            return Ok(assistant_message.content);
+        let messages = self.reuse_summary(messages);
+        if estimate_tokens(&messages) <= budget {
+            debug!("Reused the summary of earlier turns");
+            return Ok(messages);
         }
+
+        debug!(
+            estimated = estimate_tokens(&messages),
+            budget, "History exceeds token budget"
+        );
+        let Some((turns, summary)) = self.summarize_turns(&messages).await? else {
+            return Ok(messages);
+        };
+        if let Ok(serialized) = serde_json::to_string(&messages[turns.clone()]) {
+            *self.history_summary.lock().unwrap() = Some(HistorySummary {
+                turns: serialized,
+                len: turns.len(),
+                message: summary.clone(),
+            });
+        }
+        Ok(replace_turns(messages, turns, summary))
+    }
+
+    /// Replaces the turns of `messages` the last summary was made of with it,
+    /// if they follow the leading system prompt unchanged.
+    fn reuse_summary(&self, messages: Vec<Message>) -> Vec<Message> {
+        let cached = self.history_summary.lock().unwrap();
+        let Some(summary) = cached.as_ref() else {
+            return messages;
+        };
+
+        let start = usize::from(messages.first().is_some_and(|m| m.role == "system"));
+        let turns = start..start + summary.len;
+        let unchanged = messages.get(turns.clone()).is_some_and(|summarized| {
+            serde_json::to_string(summarized).is_ok_and(|serialized| serialized == summary.turns)
+        });
+        if !unchanged {
+            return messages;
+        }
+        replace_turns(messages, turns, summary.message.clone())
+    }
+
+    /// Sends a query to the LLM and returns the response along with retrieval details.
+    ///
+    /// This behaves like [`query`](Self::query), but also reports what the RAG
+    /// engine retrieved and the exact messages sent to the LLM. Use it to diagnose
+    /// poor answers caused by irrelevant or missing context.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_plugin::PluginRegistry;
+    /// # use std::sync::Arc;
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let config = Config::load_or_default();
+    /// # let registry = Arc::new(PluginRegistry::new(nucleus_plugin::Permission::READ_ONLY));
+    /// # let manager = ChatManager::new(config, registry).await?;
+    /// let debug = manager.query_debug("Where is the config loaded?").await?;
+    /// for result in &debug.retrieved {
+    ///     println!("{:.3} {:?}", result.score, result.document.location());
+    /// }
+    /// println!("Response: {}", debug.response);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn query_debug(&self, user_message: &str) -> Result<QueryDebug> {
+        let retrieved = self.retrieve(user_message).await;
+        let context = self.assemble_context(&retrieved);
+        let messages = self.build_messages(&context, user_message);
+
+        let response = self
+            .run_conversation(context.clone(), messages.clone(), &mut |_: &str| {}, None)
+            .await?
+            .response;
+
+        Ok(QueryDebug {
+            response,
+            retrieved,
+            context,
+            messages,
+        })
+    }
+
+    /// Estimates how many tokens the prompt for `user_message` would take,
+    /// without calling the LLM.
+    ///
+    /// Retrieval and context assembly run exactly as in [`query`](Self::query),
+    /// so the estimate includes the system prompt and any RAG context. Use it
+    /// to decide whether to trim the knowledge base or the query before
+    /// running an expensive request. The count is rough, at about four bytes
+    /// per token.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_plugin::{PluginRegistry, Permission};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// # let registry = PluginRegistry::new(Permission::READ_ONLY);
+    /// # let manager = ChatManager::new(Config::load_or_default(), registry).await?;
+    /// let tokens = manager.estimate_prompt_tokens("Summarize the config module").await;
+    /// if tokens > manager.config.llm.context_length {
+    ///     println!("Prompt is too long ({} tokens)", tokens);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn estimate_prompt_tokens(&self, user_message: &str) -> usize {
+        let (_, messages) = self.prepare_messages(user_message).await;
+        estimate_tokens(&messages)
+    }
+
+    /// Creates an independent copy of this manager for trying alternatives.
+    ///
+    /// The fork shares the provider, plugin registry and knowledge base, but has
+    /// its own `config` and `structured_output`, so changing them on one side
+    /// doesn't affect the other. Conversation history is passed to each query
+    /// rather than kept by the manager, so to branch a conversation, give each
+    /// side its own clone of the messages. The fork starts without a summary of
+    /// earlier turns and keeps its own.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use nucleus_core::{ChatManager, Config};
+    /// # use nucleus_core::provider::Message;
+    /// # use nucleus_plugin::{PluginRegistry, Permission};
+    /// # async fn example(history: Vec<Message>) -> anyhow::Result<()> {
+    /// # let registry = PluginRegistry::new(Permission::READ_ONLY);
+    /// # let manager = ChatManager::new(Config::load_or_default(), registry).await?;
+    /// let mut creative = manager.fork();
+    /// creative.config.llm.temperature = 1.2;
+    ///
+    /// let branch = history.clone();
+    /// let careful = manager.query(Some(&history), "Name this module").await?;
+    /// let playful = creative.query(Some(&branch), "Name this module").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fork(&self) -> ChatManager {
+        ChatManager {
+            history_summary: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Runs the tool-calling conversation loop until the LLM gives a final answer.
+    ///
+    /// Each tool call is appended to `trace` when given. If the model still
+    /// requests tools after `llm.max_tool_iterations` rounds, the loop stops
+    /// and the partial answer is returned with [`TOOL_LIMIT_MARKER`] appended.
+    async fn run_conversation<F>(
+        &self,
+        context: String,
+        messages: Vec<Message>,
+        on_chunk: &mut F,
+        mut trace: Option<&mut Vec<ToolCallTrace>>,
+    ) -> Result<Conversation>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let mut request = ChatRequest::new(&self.config.llm.model, messages)
+            .with_temperature(self.config.llm.temperature);
+
+        let tools = self.build_tools().await;
+        if !tools.is_empty() {
+            request.tools = Some(tools);
+        }
+
+        if let Some(structured_output) = &self.structured_output {
+            request = request.with_structured_output(structured_output.clone());
+        }
+
+        let conversation = run_tool_loop(
+            self.provider.as_ref(),
+            Some(&self.registry),
+            &self.config.llm,
+            request,
+            Some(&context),
+            on_chunk,
+            &mut |event: ToolEvent| {
+                if let (
+                    Some(trace),
+                    ToolEvent::Result {
+                        name,
+                        arguments,
+                        result,
+                        duration,
+                    },
+                ) = (trace.as_deref_mut(), event)
+                {
+                    trace.push(ToolCallTrace {
+                        tool_name: name.to_string(),
+                        arguments: arguments.clone(),
+                        result: result.to_string(),
+                        duration_ms: duration.as_millis() as u64,
+                    });
+                }
+            },
+        )
+        .await?;
+        Ok(conversation)
     }
 
     /// Converts registered plugins into tool definitions.
@@ -446,19 +954,7 @@ impl ChatManager {
     /// This method is called once at the start of each query. Tools are
     /// included in every LLM request throughout the conversation loop.
     async fn build_tools(&self) -> Vec<Tool> {
-        join_all(self.registry.all().iter().map(async move |plugin| {
-            let plugin = plugin.lock().await;
-            let spec = plugin.parameter_schema();
-            Tool {
-                tool_type: "function".to_string(),
-                function: ToolFunction {
-                    name: plugin.name().to_string(),
-                    description: plugin.description().to_string(),
-                    parameters: spec,
-                },
-            }
-        }))
-        .await
+        tool_definitions(&self.registry).await
     }
 
     /// Prepare initial messages with RAG context.
@@ -475,46 +971,75 @@ impl ChatManager {
     /// A tuple of (context, messages) where context is the retrieved RAG context
     /// and messages is a vector containing the initial user message.
     async fn prepare_messages(&self, user_message: &str) -> (String, Vec<Message>) {
-        let (rag_count, context) = match self.rag_engine.as_ref() {
+        let results = self.retrieve(user_message).await;
+        let context = self.assemble_context(&results);
+        let messages = self.build_messages(&context, user_message);
+
+        (context, messages)
+    }
+
+    /// Formats retrieved results with the configured `rag.context_template`.
+    fn assemble_context(&self, results: &[SearchResult]) -> String {
+        match self.rag_engine.as_ref() {
+            Some(engine) => engine.assemble_context(results),
+            None => RagEngine::format_context(results),
+        }
+    }
+
+    /// Retrieves RAG results for a query, or nothing if RAG is unavailable.
+    ///
+    /// Retrieval failures are logged and treated as "no context" so that a
+    /// broken knowledge base never blocks a conversation.
+    async fn retrieve(&self, user_message: &str) -> Vec<SearchResult> {
+        match self.rag_engine.as_ref() {
             Some(engine) => {
                 let count = engine.count().await;
                 debug!("RAG knowledge base has {} documents", count);
-                
+
                 if count > 0 {
                     debug!("Retrieving RAG context for query: {}", user_message);
-                    let ctx = engine
-                        .retrieve_context(user_message)
-                        .await
-                        .unwrap_or_else(|e| {
-                            debug!("Could not retrieve RAG context: {}", e);
-                            String::new()
-                        });
-                    (count, ctx)
+                    engine.retrieve(user_message).await.unwrap_or_else(|e| {
+                        debug!("Could not retrieve RAG context: {}", e);
+                        Vec::new()
+                    })
                 } else {
                     debug!("RAG knowledge base is empty, skipping context retrieval");
-                    (count, String::new())
+                    Vec::new()
                 }
             }
             None => {
                 debug!("RAG engine not configured, skipping context retrieval");
-                (0, String::new())
+                Vec::new()
             }
-        };
+        }
+    }
 
-        let enhanced_message = if !context.is_empty() {
+    /// Builds the initial messages: the rendered system prompt, followed by the
+    /// user message prefixed with RAG context if there is any.
+    fn build_messages(&self, context: &str, user_message: &str) -> Vec<Message> {
+        let context = if !context.is_empty() {
             debug!(
                 "Enhanced message with {} characters of RAG context",
                 context.len()
             );
-            format!("{}{}", context, user_message)
+            Some(context.to_string())
         } else {
             debug!("No RAG context available, using original message");
-            user_message.to_string()
+            None
         };
 
-        let messages = vec![Message::user(Some(context.clone()), &enhanced_message)];
+        let pwd = std::env::current_dir()
+            .ok()
+            .map(|dir| dir.to_string_lossy().to_string());
+        let vars = PromptVars::new(pwd.as_deref());
+        let system_prompt = render_prompt(&self.config.system_prompt, &vars);
 
-        (context, messages)
+        let mut messages = vec![
+            Message::system(None, system_prompt),
+            Message::user(context, user_message),
+        ];
+        inline_context(&mut messages);
+        messages
     }
 
     /// Process LLM response stream and accumulate content.
@@ -530,42 +1055,14 @@ impl ChatManager {
     /// # Returns
     ///
     /// The complete assistant message with accumulated content and preserved tool calls.
-    async fn process_response_stream<F>(
-        &self,
-        request: ChatRequest,
-        mut on_chunk: F,
-    ) -> Result<Message>
+    async fn process_response_stream<F>(&self, request: ChatRequest, on_chunk: F) -> Result<Message>
     where
         F: FnMut(&str) + Send,
     {
-        let mut accumulated_content = String::new();
-        let mut final_response: Option<ChatResponse> = None;
-        let mut tool_calls: Option<Vec<ToolCall>> = None;
-
-        self.provider
-            .chat(
-                request,
-                Box::new(|response| {
-                    if !response.done && !response.content.is_empty() {
-                        on_chunk(&response.content);
-                        accumulated_content.push_str(&response.content);
-                    }
-
-                    if let Some(ref calls) = response.message.tool_calls {
-                        tool_calls = Some(calls.clone());
-                    }
-
-                    final_response = Some(response);
-                }),
-            )
+        stream_turn(self.provider.as_ref(), request, on_chunk)
             .await
-            .context("Failed to get LLM response")?;
-
-        let mut response = final_response.context("No response from LLM")?;
-        response.message.content = accumulated_content;
-        response.message.tool_calls = tool_calls;
-
-        Ok(response.message)
+            .context("Failed to get LLM response")?
+            .context("No response from LLM")
     }
     /// Handle tool execution loop.
     ///
@@ -596,13 +1093,10 @@ impl ChatManager {
 
             if let Some(tool_calls) = &assistant_message.tool_calls {
                 // Add assistant message with tool calls to history
-                current_messages.push(Message {
-                    role: "assistant".to_string(),
-                    context: Some(context.to_string()),
-                    content: assistant_message.content.clone(),
-                    images: None,
-                    tool_calls: Some(tool_calls.clone()),
-                });
+                current_messages.push(
+                    Message::assistant(Some(context.to_string()), &assistant_message.content)
+                        .with_tool_calls(tool_calls.clone()),
+                );
 
                 // Execute each requested tool
                 for tool_call in tool_calls {
@@ -617,13 +1111,10 @@ impl ChatManager {
                         .with_context(|| format!("Failed to execute tool: {}", tool_name))?;
 
                     // Add tool result to conversation
-                    current_messages.push(Message {
-                        role: "tool".to_string(),
-                        context: Some(context.to_string()),
-                        content: result.content,
-                        images: None,
-                        tool_calls: None,
-                    });
+                    current_messages.push(Message::tool(
+                        tool_call.id.clone(),
+                        truncate_tool_result(result.content, self.config.llm.max_tool_result_bytes),
+                    ));
                 }
 
                 // Continue loop to get LLM's response using tool results
@@ -635,6 +1126,187 @@ impl ChatManager {
     }
 }
 
+/// Replaces the messages in `turns` with `summary`.
+fn replace_turns(
+    mut messages: Vec<Message>,
+    turns: Range<usize>,
+    summary: Message,
+) -> Vec<Message> {
+    messages.splice(turns, [summary]);
+    messages
+}
+
+/// Instructions given to the model when condensing conversation history.
+const SUMMARY_PROMPT: &str = "Summarize the following conversation in a few sentences. \
+Keep facts, decisions, names and open questions that later turns may rely on. \
+Reply with the summary only.";
+
+/// Prefix of the system message that replaces summarized turns.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+/// Appended to a response when the tool loop hit `llm.max_tool_iterations`
+/// before the model gave a final answer.
+pub const TOOL_LIMIT_MARKER: &str =
+    "[Stopped: the tool call limit was reached before a final answer]";
+
+/// Tool definitions for every plugin in `registry`, with each plugin's name,
+/// description and parameter schema.
+pub(crate) async fn tool_definitions(registry: &PluginRegistry) -> Vec<Tool> {
+    join_all(registry.all().iter().map(async move |plugin| {
+        let plugin = plugin.lock().await;
+        let spec = plugin.parameter_schema();
+        Tool {
+            tool_type: "function".to_string(),
+            function: ToolFunction {
+                name: plugin.name().to_string(),
+                description: plugin.description().to_string(),
+                parameters: spec,
+            },
+        }
+    }))
+    .await
+}
+
+/// Roughly estimates the number of tokens in `messages`, including any RAG
+/// context attached to them, at about four bytes per token.
+pub(crate) fn estimate_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|m| m.content.len() + m.context.as_ref().map_or(0, String::len))
+        .map(|bytes| bytes.div_ceil(4))
+        .sum()
+}
+
+/// Moves the RAG context attached to each message into its content, as
+/// providers only see message content.
+///
+/// Context stays attached until the messages have been fitted to the context
+/// length, so that it can be dropped first, and is only counted once either way.
+pub(crate) fn inline_context(messages: &mut [Message]) {
+    for message in messages {
+        if let Some(context) = message.context.take() {
+            message.content = format!("{}{}", context, message.content);
+        }
+    }
+}
+
+/// Shortens a tool result to at most `max_bytes` (plus a marker) before it is
+/// fed back to the model, so huge outputs don't blow the context window.
+///
+/// The start and end of the output are kept, since both tend to matter (e.g. a
+/// command's header and its final error), with `...[truncated N bytes]...` in
+/// between. A `max_bytes` of 0 disables truncation.
+pub(crate) fn truncate_tool_result(content: String, max_bytes: usize) -> String {
+    if max_bytes == 0 || content.len() <= max_bytes {
+        return content;
+    }
+
+    let mut head_end = max_bytes / 2;
+    while !content.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = content.len() - (max_bytes - head_end);
+    while !content.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+
+    info!(
+        original = content.len(),
+        limit = max_bytes,
+        "Truncating tool result"
+    );
+
+    format!(
+        "{}\n...[truncated {} bytes]...\n{}",
+        &content[..head_end],
+        tail_start - head_end,
+        &content[tail_start..]
+    )
+}
+
+/// Result of [`ChatManager::query_debug`], exposing how the response was produced.
+#[derive(Debug, Clone)]
+pub struct QueryDebug {
+    /// The LLM's final response, identical to what [`ChatManager::query`] returns
+    pub response: String,
+    /// Documents retrieved from the knowledge base, with their similarity scores
+    pub retrieved: Vec<SearchResult>,
+    /// The context block assembled from `retrieved` and prepended to the query
+    pub context: String,
+    /// The messages sent to the LLM in the first request
+    pub messages: Vec<Message>,
+}
+
+/// Result of [`ChatManager::query_with_trace`]: the answer and the tool calls behind it.
+#[derive(Debug, Clone)]
+pub struct QueryTrace {
+    /// The LLM's final response, identical to what [`ChatManager::query`] returns
+    pub response: String,
+    /// Every tool call made during the conversation, in the order they ran
+    pub tool_calls: Vec<ToolCallTrace>,
+    /// Whether the tool loop was cut off at `llm.max_tool_iterations`, in
+    /// which case `response` is partial and ends with [`TOOL_LIMIT_MARKER`]
+    pub tool_limit_reached: bool,
+    /// When the streamed chunks of the response arrived
+    pub timing: StreamTiming,
+}
+
+/// Streaming latency of a query, recorded by [`ChatManager::query_with_trace`].
+///
+/// Every field is `None` if the response didn't stream enough chunks to
+/// measure it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamTiming {
+    /// Time from sending the request to receiving the first chunk
+    pub ttft_ms: Option<u64>,
+    /// Median time between consecutive chunks
+    pub inter_token_ms_p50: Option<u64>,
+    /// 95th percentile time between consecutive chunks
+    pub inter_token_ms_p95: Option<u64>,
+}
+
+impl StreamTiming {
+    /// Computes timings from when the request was sent and when each chunk
+    /// arrived, in order.
+    fn from_arrivals(started: Instant, arrivals: &[Instant]) -> Self {
+        let mut intervals: Vec<u64> = arrivals
+            .windows(2)
+            .map(|pair| pair[1].duration_since(pair[0]).as_millis() as u64)
+            .collect();
+        intervals.sort_unstable();
+
+        Self {
+            ttft_ms: arrivals
+                .first()
+                .map(|first| first.duration_since(started).as_millis() as u64),
+            inter_token_ms_p50: percentile(&intervals, 50),
+            inter_token_ms_p95: percentile(&intervals, 95),
+        }
+    }
+}
+
+/// Nearest-rank percentile of already sorted values.
+fn percentile(sorted: &[u64], percent: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100);
+    Some(sorted[rank.saturating_sub(1)])
+}
+
+/// A single tool call recorded by [`ChatManager::query_with_trace`].
+#[derive(Debug, Clone)]
+pub struct ToolCallTrace {
+    /// Name of the plugin that was called
+    pub tool_name: String,
+    /// Arguments the model passed to the plugin
+    pub arguments: serde_json::Value,
+    /// The plugin's output, as given to the model (truncated like any tool result)
+    pub result: String,
+    /// How long the plugin took to run
+    pub duration_ms: u64,
+}
+
 /// Builder for configuring and creating a `ChatManager`.
 ///
 /// This builder provides a fluent API for customizing LLM and embedding models
@@ -651,24 +1323,32 @@ impl ChatManager {
 /// let registry = PluginRegistry::new(Permission::READ_ONLY);
 ///
 /// // Use defaults from config
-/// let manager = ChatManager::builder(config.clone(), registry.clone())
+/// let manager = ChatManager::builder()
+///     .with_config(config.clone())
+///     .with_registry(registry.clone())
 ///     .build()
 ///     .await?;
 ///
 /// // Override LLM model
-/// let manager = ChatManager::builder(config.clone(), registry.clone())
+/// let manager = ChatManager::builder()
+///     .with_config(config.clone())
+///     .with_registry(registry.clone())
 ///     .with_llm_model("Qwen/Qwen3-1.6B-Instruct")
 ///     .build()
 ///     .await?;
 ///
 /// // Override embedding model
-/// let manager = ChatManager::builder(config.clone(), registry.clone())
+/// let manager = ChatManager::builder()
+///     .with_config(config.clone())
+///     .with_registry(registry.clone())
 ///     .with_embedding_model("Qwen/Qwen3-Embedding-0.6B")
 ///     .build()
 ///     .await?;
 ///
 /// // Override both
-/// let manager = ChatManager::builder(config, registry)
+/// let manager = ChatManager::builder()
+///     .with_config(config)
+///     .with_registry(registry)
 ///     .with_llm_model("Qwen/Qwen3-1.6B-Instruct")
 ///     .with_embedding_model("Qwen/Qwen3-Embedding-0.6B")
 ///     .build()
@@ -686,7 +1366,8 @@ pub struct ChatManagerBuilder {
 }
 
 impl ChatManagerBuilder {
-    /// Creates a new builder with the given config and registry.
+    /// Creates a builder with the default config and an empty registry
+    /// without permissions.
     pub fn new() -> Self {
         let config = Config::default();
         let registry = Arc::new(PluginRegistry::new(Permission::NONE));
@@ -700,17 +1381,23 @@ impl ChatManagerBuilder {
         }
     }
 
+    /// Sets the configuration the manager is built from.
     pub fn with_config(mut self, config: Config) -> Self {
         self.config = config;
         self
     }
 
+    /// Sets the plugin registry shared by the manager and its provider.
     pub fn with_registry(mut self, registry: impl Into<Arc<PluginRegistry>>) -> Self {
         self.registry = registry.into();
         self
     }
+
     /// Override the default LLM model from the configuration.
     ///
+    /// Replaces `config.llm.model` before the provider is created, so the
+    /// provider loads this model instead of the configured one.
+    ///
     /// Accepts a model identifier, which may be:
     /// - A Hugging Face repo ID: `"Qwen/Qwen3-1.6B-Instruct"`
     /// - A local GGUF path: `"/path/to/model.gguf"`
@@ -723,7 +1410,9 @@ impl ChatManagerBuilder {
     /// # use nucleus_core::{ChatManager, Config};
     /// # use nucleus_plugin::{PluginRegistry, Permission};
     /// # async fn example() -> anyhow::Result<()> {
-    /// let manager = ChatManager::builder(Config::load_or_default(), PluginRegistry::new(Permission::READ_ONLY))
+    /// let manager = ChatManager::builder()
+    ///     .with_config(Config::load_or_default())
+    ///     .with_registry(PluginRegistry::new(Permission::READ_ONLY))
     ///     .with_llm_model("Qwen/Qwen3-1.6B-Instruct")
     ///     .build()
     ///     .await?;
@@ -736,17 +1425,19 @@ impl ChatManagerBuilder {
     /// # use nucleus_core::{ChatManager, Config};
     /// # use nucleus_plugin::{PluginRegistry, Permission};
     /// # async fn example() -> anyhow::Result<()> {
-    /// let manager = ChatManager::builder(Config::load_or_default(), PluginRegistry::new(Permission::READ_ONLY))
+    /// let manager = ChatManager::builder()
+    ///     .with_config(Config::load_or_default())
+    ///     .with_registry(PluginRegistry::new(Permission::READ_ONLY))
     ///     .with_llm_model("/Users/alice/models/mistral-7b-instruct-v0.2.Q4_K_M.gguf")
     ///     .build()
     ///     .await?;
     /// # Ok(())
     /// # }
-    /// ````
+    /// ```
     ///
     /// **Local GGUF Blob (Ollama) — NOT CURRENTLY SUPPORTED**
-    /// ```
-    /// let manager = ChatManager::builder(config, registry)
+    /// ```ignore
+    /// let manager = ChatManager::builder()
     ///     .with_llm_model("~/.ollama/models/blobs/sha256-0d003f6662faee786ed5da3e31b29c978de5ae5d275c8794c606a7f3c01aa8f5")  // Q4_K_M
     ///     .build()
     ///     .await?;
@@ -770,7 +1461,9 @@ impl ChatManagerBuilder {
     /// # async fn example() -> anyhow::Result<()> {
     /// # let config = Config::load_or_default();
     /// # let registry = PluginRegistry::new(Permission::READ_ONLY);
-    /// let manager = ChatManager::builder(config, registry)
+    /// let manager = ChatManager::builder()
+    ///     .with_config(config)
+    ///     .with_registry(registry)
     ///     .with_embedding_model("Qwen/Qwen3-Embedding-0.6B")
     ///     .build()
     ///     .await?;
@@ -825,7 +1518,7 @@ impl ChatManagerBuilder {
     /// - The provider fails to initialize
     /// - The RAG system fails to initialize
     pub async fn build(self) -> Result<ChatManager> {
-        let mut config = self.config.clone();
+        let mut config = self.config;
 
         if let Some(llm_model) = self.llm_model_override {
             config.llm.model = llm_model;
@@ -835,17 +1528,17 @@ impl ChatManagerBuilder {
             config.llm.provider = provider_type.as_str().to_string();
         }
 
-        let provider = create_provider(&config, Arc::clone(&self.registry)).await?;
-        let mut rag_engine = None;
-
-        if self.config.rag.clone().is_some() {
-            if let Some(embedding_model) = self.embedding_model_override {
-                config.rag.clone().unwrap().embedding_model = embedding_model;
-            }
-
-            let rag_engine = Arc::new(RagEngine::new(&config, provider.clone()).await?);
+        if let (Some(rag), Some(embedding_model)) =
+            (config.rag.as_mut(), self.embedding_model_override)
+        {
+            rag.embedding_model = embedding_model;
         }
 
+        let provider = create_provider(&config, Arc::clone(&self.registry)).await?;
+        let rag_engine = match config.rag {
+            Some(_) => Some(Arc::new(RagEngine::new(&config, provider.clone()).await?)),
+            None => None,
+        };
 
         Ok(ChatManager {
             config,
@@ -853,6 +1546,641 @@ impl ChatManagerBuilder {
             registry: self.registry,
             rag_engine,
             structured_output: self.structured_output,
+            history_summary: Arc::default(),
         })
     }
 }
+
+impl Default for ChatManagerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test_config, MockProvider};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    async fn test_manager(config: Config, provider: Arc<MockProvider>) -> ChatManager {
+        let rag_engine = RagEngine::new(&config, provider.clone()).await.unwrap();
+
+        ChatManager {
+            config,
+            provider,
+            registry: Arc::new(PluginRegistry::new(Permission::NONE)),
+            rag_engine: Some(Arc::new(rag_engine)),
+            structured_output: None,
+            history_summary: Arc::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fork_diverges_from_parent() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("Answer"));
+        let parent = test_manager(test_config(temp.path()), provider.clone()).await;
+
+        let mut history = vec![Message::system(None, "You are helpful")];
+        for turn in ["First question", "Second question"] {
+            history.push(Message::user(None, turn));
+            let answer = parent.query(Some(&history), turn).await.unwrap();
+            history.push(Message::assistant(None, answer));
+        }
+
+        let mut fork = parent.fork();
+        fork.config.llm.temperature = 1.5;
+        let mut branch = history.clone();
+
+        history.push(Message::user(None, "Parent follow-up"));
+        parent.query(Some(&history), "").await.unwrap();
+        branch.push(Message::user(None, "Fork follow-up"));
+        fork.query(Some(&branch), "").await.unwrap();
+
+        let contents = |messages: &[Message]| -> Vec<String> {
+            messages.iter().map(|m| m.content.clone()).collect()
+        };
+        assert_eq!(history.len(), 6);
+        assert_eq!(branch.len(), 6);
+        assert_eq!(contents(&history[..5]), contents(&branch[..5]));
+        assert_ne!(history[5].content, branch[5].content);
+
+        // Both sides use the shared provider, each with its own settings
+        let requests = provider.requests.lock().unwrap();
+        let (parent_request, fork_request) = (&requests[2], &requests[3]);
+        assert_eq!(
+            parent_request.messages.last().unwrap().content,
+            "Parent follow-up"
+        );
+        assert_eq!(
+            fork_request.messages.last().unwrap().content,
+            "Fork follow-up"
+        );
+        assert_eq!(parent_request.temperature, parent.config.llm.temperature);
+        assert_eq!(fork_request.temperature, 1.5);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_prompt_tokens_includes_context() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("unused"));
+        let manager = test_manager(test_config(temp.path()), provider.clone()).await;
+        let query = "How is the configuration loaded?";
+
+        let bare = manager.estimate_prompt_tokens(query).await;
+        assert!(bare > 0);
+
+        let rag = manager.rag_engine.as_ref().unwrap();
+        rag.add_knowledge("Config::load reads nucleus.yaml on startup", "a.md")
+            .await
+            .unwrap();
+        let one = manager.estimate_prompt_tokens(query).await;
+        assert!(one > bare);
+
+        rag.add_knowledge("Missing config files fall back to Config::default", "b.md")
+            .await
+            .unwrap();
+        let two = manager.estimate_prompt_tokens(query).await;
+        assert!(two > one);
+
+        assert!(provider.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_estimate_prompt_tokens_counts_context_once() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("unused"));
+        let manager = test_manager(test_config(temp.path()), provider).await;
+        let query = "How is the configuration loaded?";
+        let rag = manager.rag_engine.as_ref().unwrap();
+        rag.add_knowledge("Config::load reads nucleus.yaml on startup", "a.md")
+            .await
+            .unwrap();
+
+        let (context, messages) = manager.prepare_messages(query).await;
+        assert!(!context.is_empty());
+        assert_eq!(messages[1].content, format!("{}{}", context, query));
+        assert!(messages[1].context.is_none());
+
+        let expected: usize = messages.iter().map(|m| m.content.len().div_ceil(4)).sum();
+        assert_eq!(manager.estimate_prompt_tokens(query).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_builder_overrides_llm_model() {
+        // The mock replies with the model it was created for
+        crate::provider::register_provider("builder-echo", |config, _registry| async move {
+            Ok(Arc::new(MockProvider::new(config.llm.model)) as Arc<dyn Provider>)
+        });
+
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.provider = "builder-echo".to_string();
+        config.llm.model = "configured-model".to_string();
+
+        let manager = ChatManager::builder()
+            .with_config(config.clone())
+            .with_registry(PluginRegistry::new(Permission::READ_ONLY))
+            .with_llm_model("override-model")
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(manager.config.llm.model, "override-model");
+        assert!(manager.rag_engine.is_some());
+        assert_eq!(
+            manager.query(None, "Which model?").await.unwrap(),
+            "override-model"
+        );
+
+        let manager = ChatManager::builder()
+            .with_config(config)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.query(None, "Which model?").await.unwrap(),
+            "configured-model"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_debug_reports_retrieval() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("It lives in config.rs"));
+        let manager = test_manager(test_config(temp.path()), provider.clone()).await;
+
+        let known = "Configuration is loaded by Config::load in config.rs";
+        manager
+            .rag_engine
+            .as_ref()
+            .unwrap()
+            .add_knowledge(known, "notes.md")
+            .await
+            .unwrap();
+
+        let debug = manager
+            .query_debug("Where is the configuration loaded?")
+            .await
+            .unwrap();
+
+        assert_eq!(debug.response, "It lives in config.rs");
+        assert_eq!(debug.retrieved.len(), 1);
+        assert_eq!(debug.retrieved[0].document.content, known);
+        assert_eq!(
+            debug.retrieved[0].document.metadata.get("source").map(String::as_str),
+            Some("notes.md")
+        );
+        assert!(debug.context.contains(known));
+
+        let user_message = debug.messages.last().unwrap();
+        assert_eq!(user_message.role, "user");
+        assert!(user_message.content.contains(known));
+        assert!(user_message
+            .content
+            .ends_with("Where is the configuration loaded?"));
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].messages.last().unwrap().content,
+            user_message.content
+        );
+    }
+
+    struct HugeOutputPlugin;
+
+    #[async_trait::async_trait]
+    impl nucleus_plugin::Plugin for HugeOutputPlugin {
+        fn name(&self) -> &str {
+            "dump"
+        }
+
+        fn description(&self) -> &str {
+            "Returns a lot of output"
+        }
+
+        fn parameter_schema(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object", "properties": {} })
+        }
+
+        fn required_permission(&self) -> Permission {
+            Permission::NONE
+        }
+
+        async fn execute(
+            &self,
+            _input: serde_json::Value,
+        ) -> nucleus_plugin::Result<nucleus_plugin::PluginOutput> {
+            let output = format!("BEGIN{}END", "x".repeat(5 * 1024 * 1024));
+            Ok(nucleus_plugin::PluginOutput::new(output))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_huge_tool_result_is_truncated() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.max_tool_result_bytes = 1024;
+
+        let provider =
+            Arc::new(MockProvider::new("Done").with_tool_call("dump", serde_json::json!({})));
+        let mut registry = PluginRegistry::new(Permission::NONE);
+        assert!(registry.register(HugeOutputPlugin).await);
+
+        let mut manager = test_manager(config, provider.clone()).await;
+        manager.registry = Arc::new(registry);
+
+        let response = manager.query(None, "dump everything").await.unwrap();
+        assert_eq!(response, "Done");
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+
+        let tool_message = requests[1].messages.last().unwrap();
+        assert_eq!(tool_message.role, "tool");
+        assert_eq!(tool_message.tool_call_id.as_deref(), Some("call_0"));
+        assert!(tool_message.content.starts_with("BEGIN"));
+        assert!(tool_message.content.ends_with("END"));
+        assert!(tool_message.content.contains(&format!(
+            "...[truncated {} bytes]...",
+            5 * 1024 * 1024 + 8 - 1024
+        )));
+        assert!(tool_message.content.len() < 1024 + 64);
+    }
+
+    struct EchoPlugin;
+
+    #[async_trait::async_trait]
+    impl nucleus_plugin::Plugin for EchoPlugin {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Echoes its input"
+        }
+
+        fn parameter_schema(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object", "properties": { "text": { "type": "string" } } })
+        }
+
+        fn required_permission(&self) -> Permission {
+            Permission::NONE
+        }
+
+        async fn execute(
+            &self,
+            input: serde_json::Value,
+        ) -> nucleus_plugin::Result<nucleus_plugin::PluginOutput> {
+            let text = input["text"].as_str().unwrap_or_default();
+            Ok(nucleus_plugin::PluginOutput::new(format!("echo: {}", text)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_with_trace_records_tool_calls() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(
+            MockProvider::new("Done").with_tool_call("echo", serde_json::json!({ "text": "hi" })),
+        );
+        let mut registry = PluginRegistry::new(Permission::NONE);
+        assert!(registry.register(EchoPlugin).await);
+
+        let mut manager = test_manager(test_config(temp.path()), provider).await;
+        manager.registry = Arc::new(registry);
+
+        let traced = manager.query_with_trace(None, "say hi").await.unwrap();
+        assert_eq!(traced.response, "Done");
+        assert_eq!(traced.tool_calls.len(), 1);
+
+        let call = &traced.tool_calls[0];
+        assert_eq!(call.tool_name, "echo");
+        assert_eq!(call.arguments, serde_json::json!({ "text": "hi" }));
+        assert_eq!(call.result, "echo: hi");
+    }
+
+    #[test]
+    fn test_stream_timing_percentiles() {
+        let started = Instant::now();
+        let at = |ms| started + Duration::from_millis(ms);
+        // Chunks arrive 10ms, then 20ms, 30ms, 40ms and 100ms apart
+        let arrivals = [at(50), at(60), at(80), at(110), at(150), at(250)];
+
+        let timing = StreamTiming::from_arrivals(started, &arrivals);
+        assert_eq!(timing.ttft_ms, Some(50));
+        assert_eq!(timing.inter_token_ms_p50, Some(30));
+        assert_eq!(timing.inter_token_ms_p95, Some(100));
+
+        let single = StreamTiming::from_arrivals(started, &arrivals[..1]);
+        assert_eq!(single.ttft_ms, Some(50));
+        assert_eq!(single.inter_token_ms_p50, None);
+        assert_eq!(
+            StreamTiming::from_arrivals(started, &[]),
+            StreamTiming::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_with_trace_records_stream_timing() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::streaming(
+            vec![
+                "one ".to_string(),
+                "two ".to_string(),
+                "three ".to_string(),
+                "four".to_string(),
+            ],
+            Some(Duration::from_millis(20)),
+        ));
+        let manager = test_manager(test_config(temp.path()), provider).await;
+
+        let traced = manager.query_with_trace(None, "count").await.unwrap();
+        assert_eq!(traced.response, "one two three four");
+        assert!(traced.timing.ttft_ms.unwrap() >= 20);
+        assert!(traced.timing.inter_token_ms_p50.unwrap() >= 20);
+        assert!(traced.timing.inter_token_ms_p95.unwrap() >= 20);
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_stops_at_iteration_limit() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.max_tool_iterations = 3;
+
+        let provider = Arc::new(
+            MockProvider::new("unused")
+                .with_looping_tool_call("echo", serde_json::json!({ "text": "again" })),
+        );
+        let mut registry = PluginRegistry::new(Permission::NONE);
+        assert!(registry.register(EchoPlugin).await);
+
+        let mut manager = test_manager(config, provider.clone()).await;
+        manager.registry = Arc::new(registry);
+
+        let traced = manager
+            .query_with_trace(None, "loop forever")
+            .await
+            .unwrap();
+        assert!(traced.tool_limit_reached);
+        assert_eq!(traced.tool_calls.len(), 3);
+        assert_eq!(traced.response, TOOL_LIMIT_MARKER);
+        // Three rounds of tool calls, then the request that hit the limit
+        assert_eq!(provider.requests.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_truncate_tool_result_respects_char_boundaries() {
+        let content = "é".repeat(100);
+        let truncated = truncate_tool_result(content.clone(), 11);
+        assert!(truncated.starts_with("éé"));
+        assert!(truncated.ends_with("ééé"));
+        assert!(truncated.contains("[truncated"));
+
+        assert_eq!(truncate_tool_result(content.clone(), 0), content);
+        assert_eq!(truncate_tool_result("short".to_string(), 11), "short");
+    }
+
+    #[tokio::test]
+    async fn test_long_history_is_summarized() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.history_token_budget = 200;
+        config.llm.history_keep_recent = 2;
+
+        let provider = Arc::new(MockProvider::new("The user asked about lots of things"));
+        let manager = test_manager(config, provider.clone()).await;
+
+        let mut history = vec![Message::system(None, "You are helpful")];
+        for i in 0..20 {
+            history.push(Message::user(
+                None,
+                format!("Question {} {}", i, "x".repeat(40)),
+            ));
+            history.push(Message::assistant(None, format!("Answer {}", i)));
+        }
+        history.push(Message::user(None, "Latest question"));
+
+        manager
+            .query(Some(&history), "Latest question")
+            .await
+            .unwrap();
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+
+        // The first request condenses the older turns
+        let transcript = &requests[0].messages[1].content;
+        assert!(transcript.contains("Question 0"));
+        assert!(transcript.contains("Question 19"));
+        assert!(!transcript.contains("Latest question"));
+
+        // The conversation continues with the summary in their place
+        let messages = &requests[1].messages;
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].content, "You are helpful");
+        assert_eq!(messages[1].role, "system");
+        assert_eq!(
+            messages[1].content,
+            format!("{}The user asked about lots of things", SUMMARY_PREFIX)
+        );
+        assert_eq!(messages[2].content, "Answer 19");
+        assert_eq!(messages[3].content, "Latest question");
+    }
+
+    #[tokio::test]
+    async fn test_history_summary_is_reused_by_later_turns() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.history_token_budget = 200;
+        config.llm.history_keep_recent = 2;
+
+        let provider = Arc::new(MockProvider::new("The user asked about lots of things"));
+        let manager = test_manager(config, provider.clone()).await;
+
+        let mut history = vec![Message::system(None, "You are helpful")];
+        for i in 0..20 {
+            history.push(Message::user(
+                None,
+                format!("Question {} {}", i, "x".repeat(40)),
+            ));
+            history.push(Message::assistant(None, format!("Answer {}", i)));
+        }
+        history.push(Message::user(None, "Latest question"));
+
+        let answer = manager.query(Some(&history), "").await.unwrap();
+        history.push(Message::assistant(None, answer));
+        history.push(Message::user(None, "Follow-up question"));
+        manager.query(Some(&history), "").await.unwrap();
+        // A fork keeps its own summary
+        assert!(manager.fork().history_summary.lock().unwrap().is_none());
+
+        // The second query only asks for an answer, with the same summary
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        let messages = &requests[2].messages;
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[1].content, requests[1].messages[1].content);
+        assert_eq!(messages[5].content, "Follow-up question");
+    }
+
+    #[tokio::test]
+    async fn test_summarize_history_keeps_tool_results_with_their_call() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.history_keep_recent = 2;
+
+        let provider = Arc::new(MockProvider::new("summary"));
+        let manager = test_manager(config, provider.clone()).await;
+
+        let history = vec![
+            Message::system(None, "You are helpful"),
+            Message::user(None, "List files"),
+            Message::assistant(None, ""),
+            Message::tool(None, "a.rs"),
+            Message::tool(None, "b.rs"),
+        ];
+        let summarized = manager.summarize_history(&history).await.unwrap();
+
+        let roles: Vec<_> = summarized.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "system", "assistant", "tool", "tool"]);
+
+        // Short histories are left alone
+        let short = &history[..2];
+        assert_eq!(manager.summarize_history(short).await.unwrap().len(), 2);
+        assert_eq!(provider.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_with_system_prompt_renders_placeholders() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("ok"));
+        let manager = test_manager(test_config(temp.path()), provider.clone())
+            .await
+            .with_system_prompt("Project {project} in {pwd}, {unknown} stays");
+
+        manager.query(None, "hello").await.unwrap();
+
+        let cwd = std::env::current_dir().unwrap();
+        let project = cwd.file_name().unwrap().to_string_lossy();
+        let requests = provider.requests.lock().unwrap();
+        let system = &requests[0].messages[0];
+
+        assert_eq!(system.role, "system");
+        assert_eq!(
+            system.content,
+            format!(
+                "Project {} in {}, {{unknown}} stays",
+                project,
+                cwd.display()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_and_index_follows_file_changes() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        // Default patterns exclude paths containing "tmp", which tempdirs do
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+        let manager = test_manager(config, Arc::new(MockProvider::new(""))).await;
+
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("lib.rs");
+        let source = file.to_string_lossy().to_string();
+
+        // Polls the knowledge base until the file's chunk count satisfies `done`
+        let wait_for = |done: fn(Option<usize>) -> bool| {
+            let (manager, source) = (&manager, &source);
+            async move {
+                for _ in 0..100 {
+                    let chunks = manager
+                        .indexed_sources()
+                        .await
+                        .unwrap()
+                        .into_iter()
+                        .find(|s| &s.source == source)
+                        .map(|s| s.chunks);
+                    if done(chunks) {
+                        return;
+                    }
+                    tokio::time::sleep(crate::rag::WATCH_DEBOUNCE / 5).await;
+                }
+                panic!("knowledge base never caught up with {}", source);
+            }
+        };
+
+        tokio::select! {
+            biased;
+            result = manager.watch_and_index(dir.path()) => {
+                panic!("watcher stopped: {:?}", result)
+            }
+            _ = async {
+                std::fs::write(&file, "pub fn first() {}\n").unwrap();
+                wait_for(|chunks| chunks == Some(1)).await;
+
+                std::fs::write(&file, "pub fn second() {}\n".repeat(100)).unwrap();
+                wait_for(|chunks| chunks.is_some_and(|n| n > 1)).await;
+
+                std::fs::remove_file(&file).unwrap();
+                wait_for(|chunks| chunks.is_none()).await;
+            } => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_preview_chunks_splits_without_indexing() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.chunk_size = 10;
+        indexer.chunk_overlap = 5;
+        let provider = Arc::new(MockProvider::new(""));
+        let manager = test_manager(config, provider.clone()).await;
+
+        let file = temp.path().join("notes.txt");
+        std::fs::write(&file, "aaaa\nbbbb\ncccc\ndddd\n").unwrap();
+
+        let embed_calls = provider.embed_calls.load(Ordering::SeqCst);
+        let chunks = manager.preview_chunks(&file).await.unwrap();
+        let spans: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.start_byte, c.end_byte, c.start_line, c.end_line))
+            .collect();
+        assert_eq!(spans, vec![(0, 10, 1, 2), (5, 15, 2, 3), (10, 20, 3, 4)]);
+        assert_eq!(chunks[1].content, "bbbb\ncccc\n");
+        assert!(chunks.iter().all(|c| c.tokens == 3));
+
+        assert_eq!(provider.embed_calls.load(Ordering::SeqCst), embed_calls);
+        assert_eq!(manager.knowledge_base_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_index_file_replaces_its_previous_chunks() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.chunk_size = 10;
+        indexer.chunk_overlap = 0;
+        let manager = test_manager(config, Arc::new(MockProvider::new(""))).await;
+
+        let file = temp.path().join("notes.txt");
+        std::fs::write(&file, "aaaa\nbbbb\ncccc\ndddd\n").unwrap();
+        assert_eq!(manager.index_file(&file).await.unwrap(), 2);
+
+        std::fs::write(&file, "eeee\n").unwrap();
+        assert_eq!(manager.index_file(&file).await.unwrap(), 1);
+
+        let sources = manager.indexed_sources().await.unwrap();
+        assert_eq!(sources.len(), 1);
+        assert_eq!(sources[0].chunks, 1);
+        let debug = manager.query_debug("notes").await.unwrap();
+        let contents: Vec<_> = debug
+            .retrieved
+            .iter()
+            .map(|r| r.document.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["eeee\n"]);
+    }
+}
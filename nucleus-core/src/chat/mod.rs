@@ -1,3 +1,9 @@
 mod manager;
+mod tool_loop;
 
-pub use manager::{ChatManager, ChatManagerBuilder};
+pub(crate) use manager::{estimate_tokens, inline_context, tool_definitions, truncate_tool_result};
+pub use manager::{
+    ChatManager, ChatManagerBuilder, QueryDebug, QueryTrace, StreamTiming, ToolCallTrace,
+    TOOL_LIMIT_MARKER,
+};
+pub(crate) use tool_loop::{run_tool_loop, ToolEvent, ToolLoopError};
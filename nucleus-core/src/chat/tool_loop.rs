@@ -0,0 +1,165 @@
+//! The tool-calling loop shared by [`ChatManager`](super::ChatManager) and
+//! the server's chat requests.
+
+use super::manager::{truncate_tool_result, TOOL_LIMIT_MARKER};
+use crate::config::LlmConfig;
+use crate::provider::{ChatRequest, Message, Provider, ProviderError, ToolCall};
+use nucleus_plugin::{PluginError, PluginRegistry};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// A tool call made by [`run_tool_loop`], reported as it happens.
+pub(crate) enum ToolEvent<'a> {
+    /// The model called `name` with `arguments`, which runs next
+    Call { name: &'a str, arguments: &'a Value },
+    /// The call finished with `result`, truncated as it was given to the model
+    Result {
+        name: &'a str,
+        arguments: &'a Value,
+        result: &'a str,
+        duration: Duration,
+    },
+}
+
+/// Why [`run_tool_loop`] stopped before the model gave an answer.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ToolLoopError {
+    #[error("Failed to get LLM response: {0}")]
+    Provider(#[from] ProviderError),
+
+    #[error("No response from LLM")]
+    NoResponse,
+
+    #[error("Failed to execute tool {name}: {source}")]
+    Tool { name: String, source: PluginError },
+}
+
+/// Outcome of [`run_tool_loop`].
+pub(crate) struct Conversation {
+    /// The model's last answer, ending with [`TOOL_LIMIT_MARKER`] when the
+    /// loop was cut off
+    pub response: String,
+    pub tool_limit_reached: bool,
+}
+
+/// Sends `request` to `provider` and, while the model asks for tools, runs
+/// them with `registry` and sends the results back for another answer.
+///
+/// `context` is attached to the assistant messages that carry tool calls. If
+/// the model still requests tools after `llm.max_tool_iterations` rounds, the
+/// loop stops and the partial answer is returned with [`TOOL_LIMIT_MARKER`]
+/// appended. Without a registry, tool calls are ignored.
+pub(crate) async fn run_tool_loop<F, T>(
+    provider: &dyn Provider,
+    registry: Option<&PluginRegistry>,
+    llm: &LlmConfig,
+    mut request: ChatRequest,
+    context: Option<&str>,
+    on_chunk: &mut F,
+    on_tool: &mut T,
+) -> Result<Conversation, ToolLoopError>
+where
+    F: FnMut(&str) + Send,
+    T: FnMut(ToolEvent),
+{
+    let max_iterations = llm.max_tool_iterations;
+    let mut iterations = 0;
+
+    loop {
+        let assistant_message = stream_turn(provider, request.clone(), &mut *on_chunk)
+            .await?
+            .ok_or(ToolLoopError::NoResponse)?;
+
+        let (Some(registry), Some(tool_calls)) = (registry, assistant_message.tool_calls) else {
+            return Ok(Conversation {
+                response: assistant_message.content,
+                tool_limit_reached: false,
+            });
+        };
+
+        if max_iterations > 0 && iterations == max_iterations {
+            warn!(max_iterations, "Stopped tool loop at the iteration limit");
+            let partial = assistant_message.content.trim_end();
+            let response = if partial.is_empty() {
+                TOOL_LIMIT_MARKER.to_string()
+            } else {
+                format!("{}\n\n{}", partial, TOOL_LIMIT_MARKER)
+            };
+            return Ok(Conversation {
+                response,
+                tool_limit_reached: true,
+            });
+        }
+        iterations += 1;
+
+        request.messages.push(
+            Message::assistant(context.map(str::to_string), &assistant_message.content)
+                .with_tool_calls(tool_calls.clone()),
+        );
+
+        for tool_call in tool_calls {
+            let name = &tool_call.function.name;
+            let arguments = &tool_call.function.arguments;
+            on_tool(ToolEvent::Call { name, arguments });
+
+            let started = Instant::now();
+            let output = registry
+                .execute(name, arguments.clone())
+                .await
+                .map_err(|source| ToolLoopError::Tool {
+                    name: name.clone(),
+                    source,
+                })?;
+            let result = truncate_tool_result(output.content, llm.max_tool_result_bytes);
+            on_tool(ToolEvent::Result {
+                name,
+                arguments,
+                result: &result,
+                duration: started.elapsed(),
+            });
+
+            request.messages.push(Message::tool(tool_call.id, result));
+        }
+    }
+}
+
+/// Streams one response to `request`, passing its content to `on_chunk`.
+///
+/// Returns the assembled assistant message, with tool calls from any chunk of
+/// the stream (they may arrive before the final, empty `done` chunk), or
+/// `None` if the provider sent nothing.
+pub(crate) async fn stream_turn<F>(
+    provider: &dyn Provider,
+    request: ChatRequest,
+    mut on_chunk: F,
+) -> Result<Option<Message>, ProviderError>
+where
+    F: FnMut(&str) + Send,
+{
+    let mut content = String::new();
+    let mut last_message: Option<Message> = None;
+    let mut tool_calls: Option<Vec<ToolCall>> = None;
+
+    provider
+        .chat(
+            request,
+            Box::new(|response| {
+                if !response.done && !response.content.is_empty() {
+                    on_chunk(&response.content);
+                    content.push_str(&response.content);
+                }
+                if let Some(calls) = &response.message.tool_calls {
+                    tool_calls = Some(calls.clone());
+                }
+                last_message = Some(response.message);
+            }),
+        )
+        .await?;
+
+    Ok(last_message.map(|mut message| {
+        message.content = content;
+        message.tool_calls = tool_calls;
+        message
+    }))
+}
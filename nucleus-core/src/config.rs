@@ -1,17 +1,32 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 use crate::models::EmbeddingModel;
+use crate::rag::{ContextTemplate, SimilarityMetric};
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("Failed to read config file: {0}")]
     FileRead(#[from] std::io::Error),
 
+    #[error("Failed to write config file: {0}")]
+    FileWrite(std::io::Error),
+
     #[error("Failed to parse config: {0}")]
     Parse(#[from] serde_yaml::Error),
+
+    #[error("None of the config files exist: {0}")]
+    NoLayers(String),
+
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+
+    #[error("Environment variable {0} is not set and has no default")]
+    UndefinedVariable(String),
 }
 
 pub type Result<T> = std::result::Result<T, ConfigError>;
@@ -19,23 +34,68 @@ pub type Result<T> = std::result::Result<T, ConfigError>;
 /// Configuration for the entire chat/agent
 ///
 /// This includes the LLM model itself, as well as the features and customization you want it have
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
+    /// Root directory for persisted data.
+    ///
+    /// Relative storage paths (chat history, tool state, the embedded vector
+    /// database and preferences) are resolved against it; absolute paths are
+    /// used as-is. Defaults to the platform data directory, e.g.
+    /// `~/.local/share/nucleus` on Linux.
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
     pub system_prompt: String,
     pub llm: LlmConfig,
+    /// Settings of the request server; all optional
+    #[serde(default)]
+    pub server: ServerConfig,
     pub rag: Option<RagConfig>,
     pub storage: StorageConfig,
     pub personalization: PersonalizationConfig,
 
     #[serde(skip)]
     pub permission: Permission,
+
+    /// Strings that referenced environment variables when loaded, so `save`
+    /// writes them back as written
+    #[serde(skip)]
+    templates: VarTemplates,
+}
+
+/// Where a string sits in a YAML document: the mapping keys and sequence
+/// indices leading to it.
+type YamlPath = Vec<serde_yaml::Value>;
+
+/// Strings of a loaded config that referenced environment variables: where
+/// they are, the text as written and the value it expanded to.
+#[derive(Debug, Clone, Default)]
+struct VarTemplates(Vec<(YamlPath, String, String)>);
+
+impl VarTemplates {
+    /// The text `value` at `path` was expanded from, if it still holds the
+    /// expanded value.
+    fn template_for(&self, path: &YamlPath, value: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(at, _, _)| at == path)
+            .filter(|(_, _, expanded)| expanded == value)
+            .map(|(_, template, _)| template.as_str())
+    }
+}
+
+/// Where values came from doesn't make two configs different.
+impl PartialEq for VarTemplates {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
 /// Permissions granted to the AI.
 ///
 /// **Note**: A permission granted here does not mean it will automatically perform the actions.
 /// However, if false, the functionality will not exist to begin with.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Permission {
     /// Read directories and files
     pub read: bool,
@@ -56,7 +116,7 @@ impl Default for Permission {
 }
 
 /// Configuration for the AI model
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LlmConfig {
     /// Provider type: "ollama", "mistralrs", or "coreml"
     #[serde(default = "default_provider")]
@@ -71,6 +131,100 @@ pub struct LlmConfig {
     /// CoreML-specific: output feature name
     #[serde(default = "default_output_name")]
     pub coreml_output_name: String,
+    /// CoreML-specific: HuggingFace repo id (e.g. `meta-llama/Llama-3.2-1B`)
+    /// to download `tokenizer.json` from when the model directory has none.
+    /// The download is cached next to the model
+    #[serde(default)]
+    pub coreml_tokenizer_repo: Option<String>,
+    /// Maximum size in bytes of a tool result fed back to the model.
+    /// Longer results are truncated in the middle; 0 disables the limit
+    #[serde(default = "default_max_tool_result_bytes")]
+    pub max_tool_result_bytes: usize,
+    /// Maximum rounds of tool calls in one query. When the model still asks
+    /// for tools after this many, the loop stops and the response says it was
+    /// cut off; 0 disables the limit
+    #[serde(default = "default_max_tool_iterations")]
+    pub max_tool_iterations: usize,
+    /// Approximate token budget for conversation history. When a supplied
+    /// history grows past it, older turns are condensed into a summary
+    /// message; 0 (default) disables summarization
+    #[serde(default)]
+    pub history_token_budget: usize,
+    /// Number of most recent messages kept verbatim when history is summarized
+    #[serde(default = "default_history_keep_recent")]
+    pub history_keep_recent: usize,
+    /// Load the model when the server starts instead of on the first request
+    #[serde(default)]
+    pub warmup: bool,
+    /// Number of models the mistral.rs and CoreML providers keep loaded to
+    /// serve requests for other models than `model`, evicting the least
+    /// recently used; 0 (default) serves `model` only
+    #[serde(default)]
+    pub model_cache_size: usize,
+    /// Models requests may switch to besides `model` when `model_cache_size`
+    /// is set. Models found in `server.models_dir` are allowed as well
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// Maximum number of chats and embeddings the provider runs at once,
+    /// whoever calls it; further calls wait. 0 (default) means no limit
+    #[serde(default)]
+    pub max_concurrency: usize,
+    /// Skip checking at startup that `model` is in a format the built-in
+    /// `provider` loads (e.g. a `.gguf` file for mistral.rs, a `.mlpackage`
+    /// for CoreML), for model names the check misjudges
+    #[serde(default)]
+    pub skip_model_check: bool,
+}
+
+/// Configuration for the request server
+///
+/// Most of these settings used to live under `llm`; configs that still set
+/// them there are moved here when loaded, with a warning (see
+/// [`MOVED_SERVER_SETTINGS`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Shared secret every request must carry in `auth_token`; unset (default)
+    /// accepts all requests
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// Maximum number of requests the server handles at once. Further requests
+    /// queue by priority; 0 (default) means no limit
+    #[serde(default)]
+    pub max_concurrent_requests: usize,
+    /// Seconds the server waits for a client to send its request before
+    /// dropping the connection; 0 means no timeout
+    #[serde(default = "default_request_read_timeout_secs")]
+    pub request_read_timeout_secs: u64,
+    /// Response chunks buffered for a client that reads slower than the model
+    /// generates. Once the buffer is full, generation waits for the client
+    #[serde(default = "default_stream_buffer_size")]
+    pub stream_buffer_size: usize,
+    /// Download and load the model in the background so the server starts
+    /// straight away; until it is ready, chat and embed requests get a
+    /// "model loading" error, with the download progress of mistral.rs models
+    /// fetched from HuggingFace
+    #[serde(default)]
+    pub background_load: bool,
+    /// Remove `<think>...</think>` reasoning blocks from chat responses before
+    /// they are sent to clients
+    #[serde(default)]
+    pub strip_thinking: bool,
+    /// Apply the diff an edit request's response proposes with the
+    /// `apply_patch` plugin, subject to the plugin registry's permissions
+    #[serde(default)]
+    pub apply_edits: bool,
+    /// Offer the plugin registry's tools to the model in chat requests. Off by
+    /// default: every client that can reach the server can then have the
+    /// model read files, write them or run commands with the registry's
+    /// permissions, so only enable it together with `auth_token` or on a
+    /// socket only trusted users can open
+    #[serde(default)]
+    pub tools_enabled: bool,
+    /// Directory scanned for local GGUF files and CoreML bundles by
+    /// `list_local_models` requests, whose models requests may switch to
+    /// (see `llm.model_cache_size`)
+    #[serde(default = "default_models_dir")]
+    pub models_dir: PathBuf,
 }
 
 fn default_provider() -> String {
@@ -85,18 +239,65 @@ fn default_output_name() -> String {
     "output".to_string()
 }
 
+fn default_stream_buffer_size() -> usize {
+    256
+}
+
+fn default_models_dir() -> PathBuf {
+    PathBuf::from("models")
+}
+
+fn default_max_tool_result_bytes() -> usize {
+    32 * 1024
+}
+
+fn default_max_tool_iterations() -> usize {
+    10
+}
+
+fn default_history_keep_recent() -> usize {
+    6
+}
+
+fn default_request_read_timeout_secs() -> u64 {
+    30
+}
+
 /// Configuration for RAG processing.
 ///
 /// This covers embedding settings and text processing behavior (chunking, indexing).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RagConfig {
     pub embedding_model: EmbeddingModel,
     #[serde(default)]
     pub indexer: IndexerConfig,
+    /// How retrieved chunks are assembled into the context given to the model.
+    #[serde(default)]
+    pub context_template: ContextTemplate,
+    /// Pulling neighboring chunks of retrieved results into the context.
+    #[serde(default)]
+    pub expansion: ExpansionConfig,
+    /// Reusing the results of recent retrievals for repeated queries.
+    #[serde(default)]
+    pub cache: RetrievalCacheConfig,
+    /// Excerpts of retrieved chunks highlighting the query terms.
+    #[serde(default)]
+    pub snippets: SnippetConfig,
+    /// Rewriting queries into hypothetical answers before searching.
+    #[serde(default)]
+    pub query_rewrite: QueryRewriteConfig,
+    /// Multipliers for the scores of results by source path prefix, e.g.
+    /// `docs/: 1.5` and `tests/: 0.5`. Prefixes are matched by whole path
+    /// components against the path relative to the directory a file was
+    /// indexed from; the longest matching prefix wins. Scores are normalized
+    /// to `0..=1` before they are weighted, and only reported weighted with
+    /// `vector_db.normalize_scores`.
+    #[serde(default)]
+    pub source_weights: BTreeMap<String, f32>,
 }
 
 /// Configuration for file indexing behavior.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct IndexerConfig {
     /// File extensions to index (e.g., ["rs", "go", "py"])
     /// Empty list (default) means index all readable text files
@@ -108,21 +309,417 @@ pub struct IndexerConfig {
     #[serde(default = "default_exclude_patterns")]
     pub exclude_patterns: Vec<String>,
 
-    /// Size of text chunks in bytes for splitting documents
+    /// Size of text chunks for splitting documents, in `chunk_unit`s
     pub chunk_size: usize,
 
-    /// Overlap between consecutive chunks in bytes
+    /// Overlap between consecutive chunks, in `chunk_unit`s. Must be smaller
+    /// than `chunk_size`
     pub chunk_overlap: usize,
+
+    /// Whether `chunk_size` and `chunk_overlap` count bytes (default) or tokens
+    #[serde(default)]
+    pub chunk_unit: ChunkUnit,
+
+    /// Path to a `tokenizer.json` used when `chunk_unit` is `tokens`. Without
+    /// one, chunks fall back to being measured in bytes
+    #[serde(default)]
+    pub tokenizer_path: Option<PathBuf>,
+
+    /// Stop indexing at the first file that fails (unreadable, embedding failed)
+    /// instead of recording the failure in the report and carrying on
+    #[serde(default)]
+    pub abort_on_error: bool,
+
+    /// How many more times to try embedding a batch of chunks after it fails
+    /// (e.g. a transient out-of-memory error) before giving up on it
+    #[serde(default = "default_embed_retries")]
+    pub embed_retries: usize,
+
+    /// Delay before the first embedding retry, in milliseconds, doubled for
+    /// each one after. Up to half of each delay is taken off at random
+    #[serde(default = "default_embed_initial_backoff_ms")]
+    pub embed_initial_backoff_ms: u64,
+
+    /// Longest delay between embedding retries, in milliseconds
+    #[serde(default = "default_embed_max_backoff_ms")]
+    pub embed_max_backoff_ms: u64,
+
+    /// Skip chunks whose content is identical to a chunk already indexed in the
+    /// same run (e.g. duplicated or vendored files)
+    #[serde(default)]
+    pub dedup: bool,
+
+    /// Files larger than this many bytes (default 1 MiB) are skipped, as they
+    /// are usually generated (minified JS, large JSON) rather than source
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Guess the encoding of files that aren't valid UTF-8 (e.g. Latin-1,
+    /// Shift_JIS) and index their decoded text, instead of replacing every
+    /// invalid byte with U+FFFD. Needs the `encoding` feature; without it
+    /// a warning is printed and invalid bytes are replaced
+    #[serde(default = "default_detect_encoding")]
+    pub detect_encoding: bool,
+}
+
+/// Unit in which chunk sizes are measured.
+///
+/// Embedding models truncate input past their maximum sequence length, which is
+/// counted in tokens, so measuring chunks in tokens guarantees that nothing is
+/// silently cut off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkUnit {
+    #[default]
+    Bytes,
+    Tokens,
+}
+
+/// Configuration for expanding retrieved chunks with their neighbors.
+///
+/// A retrieved chunk often references code defined just above or below it. When
+/// enabled, the chunks adjacent to each hit in the same source file (by line
+/// range) are added to the results, best hits first, until `max_chunks` extra
+/// chunks have been added.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExpansionConfig {
+    /// Whether to expand retrieved results (off by default)
+    pub enabled: bool,
+
+    /// How many chunks to take on each side of a hit
+    pub neighbors: usize,
+
+    /// Maximum number of chunks added by expansion across all hits
+    pub max_chunks: usize,
+}
+
+impl Default for ExpansionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            neighbors: 1,
+            max_chunks: 4,
+        }
+    }
+}
+
+/// Configuration for snippets of retrieved results.
+///
+/// When enabled, each search result carries a short excerpt of its chunk
+/// centered on the first query term it contains, with every query term in it
+/// marked, to show at a glance why it matched. The full chunk is still what the
+/// model receives as context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnippetConfig {
+    /// Whether to generate snippets (off by default)
+    pub enabled: bool,
+
+    /// Approximate length of a snippet, in bytes
+    pub window: usize,
+
+    /// Text placed before and after each matched term
+    pub marker: String,
+}
+
+impl Default for SnippetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: 160,
+            marker: "**".to_string(),
+        }
+    }
+}
+
+/// Configuration for rewriting queries before retrieval (HyDE).
+///
+/// Short queries often embed far from the chunks that answer them. With
+/// rewriting enabled, the model first writes a hypothetical passage answering
+/// the query, and that passage is embedded for the search instead. Rewrites are
+/// cached per query, so repeating a query doesn't ask the model again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryRewriteConfig {
+    /// Whether to rewrite queries (off by default)
+    pub enabled: bool,
+
+    /// Prompt asking the model for the hypothetical passage. `{query}` is
+    /// replaced by the query
+    pub prompt: String,
+
+    /// Search with the average of the query and passage embeddings, instead of
+    /// the passage embedding alone
+    pub include_query: bool,
+
+    /// Maximum number of rewrites kept, least recently used dropped first.
+    /// `0` disables caching
+    pub cache_capacity: usize,
+}
+
+impl Default for QueryRewriteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prompt: "Write a short passage that answers the question below, as it might \
+                     appear in documentation or source code. Reply with the passage only.\n\n\
+                     Question: {query}"
+                .to_string(),
+            include_query: false,
+            cache_capacity: 64,
+        }
+    }
+}
+
+/// Configuration for caching retrieval results.
+///
+/// Repeating a query (ignoring case and surrounding whitespace) reuses the
+/// results of the last retrieval instead of embedding the query and searching
+/// again. Any change to the knowledge base empties the cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetrievalCacheConfig {
+    /// Maximum number of queries kept, least recently used dropped first.
+    /// `0` disables the cache
+    pub capacity: usize,
+
+    /// How long cached results stay valid, in seconds
+    pub ttl_secs: u64,
+}
+
+impl Default for RetrievalCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 64,
+            ttl_secs: 300,
+        }
+    }
 }
 
 fn default_exclude_patterns() -> Vec<String> {
     crate::patterns::default_exclude_patterns()
 }
 
+/// Settings that moved from the `llm` section to `server`.
+pub const MOVED_SERVER_SETTINGS: &[&str] = &[
+    "auth_token",
+    "max_concurrent_requests",
+    "request_read_timeout_secs",
+    "stream_buffer_size",
+    "background_load",
+    "strip_thinking",
+    "apply_edits",
+    "models_dir",
+];
+
+/// Moves the settings of [`MOVED_SERVER_SETTINGS`] a config still has under
+/// `llm` to `server`, warning about each. A setting already under `server`
+/// wins.
+fn migrate_server_settings(value: &mut serde_yaml::Value) {
+    let Some(llm) = value
+        .get_mut("llm")
+        .and_then(serde_yaml::Value::as_mapping_mut)
+    else {
+        return;
+    };
+    let moved: Vec<(serde_yaml::Value, serde_yaml::Value)> = MOVED_SERVER_SETTINGS
+        .iter()
+        .filter_map(|&key| llm.remove(key).map(|setting| (key.into(), setting)))
+        .collect();
+    if moved.is_empty() {
+        return;
+    }
+
+    let Some(root) = value.as_mapping_mut() else {
+        return;
+    };
+    let server = root
+        .entry("server".into())
+        .or_insert_with(|| serde_yaml::Mapping::new().into());
+    if server.is_null() {
+        *server = serde_yaml::Mapping::new().into();
+    }
+    let Some(server) = server.as_mapping_mut() else {
+        return;
+    };
+    for (key, setting) in moved {
+        tracing::warn!(
+            "llm.{0} has moved to server.{0}; please update your config",
+            key.as_str().unwrap_or_default()
+        );
+        if !server.contains_key(&key) {
+            server.insert(key, setting);
+        }
+    }
+}
+
+/// Merges `overlay` into `base`: mappings are merged key by key, nulls (such
+/// as an empty layer) leave `base` as it is, and anything else in `overlay`
+/// replaces what is in `base`.
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (_, serde_yaml::Value::Null) => {}
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_yaml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Replaces every string in `value` (but not mapping keys) with `f` applied to
+/// its path and text.
+fn map_strings(
+    value: &mut serde_yaml::Value,
+    f: &mut impl FnMut(&YamlPath, &str) -> Result<String>,
+) -> Result<()> {
+    map_strings_at(value, &mut Vec::new(), f)
+}
+
+fn map_strings_at(
+    value: &mut serde_yaml::Value,
+    path: &mut YamlPath,
+    f: &mut impl FnMut(&YamlPath, &str) -> Result<String>,
+) -> Result<()> {
+    match value {
+        serde_yaml::Value::String(string) => *string = f(path, string)?,
+        serde_yaml::Value::Sequence(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                path.push(serde_yaml::Value::from(index));
+                map_strings_at(item, path, f)?;
+                path.pop();
+            }
+        }
+        serde_yaml::Value::Mapping(mapping) => {
+            for (key, item) in mapping.iter_mut() {
+                path.push(key.clone());
+                map_strings_at(item, path, f)?;
+                path.pop();
+            }
+        }
+        serde_yaml::Value::Tagged(tagged) => map_strings_at(&mut tagged.value, path, f)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expands `${VAR}` and `${VAR:-default}` in `text` with `lookup`.
+///
+/// The default is used when the variable is unset or empty; an unset variable
+/// without a default is an error. `$${` is an escaped, literal `${`.
+fn expand_vars(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| {
+                ConfigError::Invalid(format!("unterminated variable reference in {:?}", text))
+            })?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            let value = match (lookup(name), default) {
+                (Some(value), Some(default)) if value.is_empty() => default.to_string(),
+                (Some(value), _) => value,
+                (None, Some(default)) => default.to_string(),
+                (None, None) => return Err(ConfigError::UndefinedVariable(name.to_string())),
+            };
+            expanded.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parses YAML, expanding environment variables in its string values and
+/// recording the strings that changed in `templates`.
+///
+/// Settings that moved to another section are moved first, so that `templates`
+/// records them where they are saved.
+fn parse_yaml(contents: &str, templates: &mut VarTemplates) -> Result<serde_yaml::Value> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(contents)?;
+    migrate_server_settings(&mut value);
+    map_strings(&mut value, &mut |path, text| {
+        let expanded = expand_vars(text, |name| std::env::var(name).ok())?;
+        if expanded != text {
+            templates
+                .0
+                .push((path.clone(), text.to_string(), expanded.clone()));
+        }
+        Ok(expanded)
+    })?;
+    Ok(value)
+}
+
+fn default_data_dir() -> PathBuf {
+    directories::ProjectDirs::from("", "", "nucleus")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("./data"))
+}
+
+fn default_max_file_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_embed_retries() -> usize {
+    2
+}
+
+fn default_embed_initial_backoff_ms() -> u64 {
+    100
+}
+
+fn default_embed_max_backoff_ms() -> u64 {
+    2000
+}
+
+fn default_detect_encoding() -> bool {
+    true
+}
+
 fn default_top_k() -> usize {
     5
 }
 
+impl IndexerConfig {
+    /// Checks that chunks are non-empty and that consecutive chunks advance,
+    /// i.e. `chunk_overlap` is smaller than `chunk_size`.
+    pub fn validate(&self) -> Result<()> {
+        if self.chunk_size == 0 {
+            return Err(ConfigError::Invalid(
+                "indexer.chunk_size must be greater than 0".to_string(),
+            ));
+        }
+        if self.chunk_overlap >= self.chunk_size {
+            return Err(ConfigError::Invalid(format!(
+                "indexer.chunk_overlap ({}) must be smaller than indexer.chunk_size ({})",
+                self.chunk_overlap, self.chunk_size
+            )));
+        }
+        Ok(())
+    }
+}
+
 impl Default for IndexerConfig {
     fn default() -> Self {
         Self {
@@ -130,12 +727,21 @@ impl Default for IndexerConfig {
             exclude_patterns: default_exclude_patterns(),
             chunk_size: 512,
             chunk_overlap: 50,
+            chunk_unit: ChunkUnit::Bytes,
+            tokenizer_path: None,
+            abort_on_error: false,
+            embed_retries: default_embed_retries(),
+            embed_initial_backoff_ms: default_embed_initial_backoff_ms(),
+            embed_max_backoff_ms: default_embed_max_backoff_ms(),
+            dedup: false,
+            max_file_size: default_max_file_size(),
+            detect_encoding: default_detect_encoding(),
         }
     }
 }
 
 /// Vector database storage mode
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "mode", rename_all = "lowercase")]
 pub enum StorageMode {
     /// Embedded storage - runs in-process with zero setup (default)
@@ -147,33 +753,46 @@ pub enum StorageMode {
 impl Default for StorageMode {
     fn default() -> Self {
         Self::Embedded {
-            path: "./data/nucleus_vectordb".to_string(),
+            path: "nucleus_vectordb".to_string(),
         }
     }
 }
 
 impl Default for RagConfig {
     fn default() -> Self {
-        let embedding_model = EmbeddingModel::default();
-
-        let indexer = IndexerConfig {
-            extensions: Vec::new(),
-            exclude_patterns: default_exclude_patterns(),
-            chunk_size: embedding_model.embedding_dim,
-            chunk_overlap: 50,
-        };
-
         Self {
-            embedding_model,
-            indexer,
+            embedding_model: EmbeddingModel::default(),
+            indexer: IndexerConfig::default(),
+            context_template: ContextTemplate::default(),
+            expansion: ExpansionConfig::default(),
+            cache: RetrievalCacheConfig::default(),
+            snippets: SnippetConfig::default(),
+            query_rewrite: QueryRewriteConfig::default(),
+            source_weights: BTreeMap::new(),
+        }
+    }
+}
+
+impl RagConfig {
+    /// Checks that the settings are usable.
+    pub fn validate(&self) -> Result<()> {
+        self.indexer.validate()?;
+        for (prefix, weight) in &self.source_weights {
+            if !weight.is_finite() || *weight <= 0.0 {
+                return Err(ConfigError::Invalid(format!(
+                    "rag.source_weights[{:?}] must be a positive number, got {}",
+                    prefix, weight
+                )));
+            }
         }
+        Ok(())
     }
 }
 
 /// Storage configuration for all persistence.
 ///
 /// This includes chat history, tool state, and vector database storage.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StorageConfig {
     pub chat_history_path: String,
     pub tool_state_path: String,
@@ -186,19 +805,70 @@ pub struct StorageConfig {
     /// Number of results to return from vector similarity searches
     #[serde(default = "default_top_k")]
     pub top_k: usize,
+    /// Number of the fetched results that make it into the prompt. Lets the
+    /// store fetch more candidates (`top_k`) than are used as context. Defaults
+    /// to all of them
+    #[serde(default)]
+    pub rag_context_count: Option<usize>,
+    /// Fall back to an in-memory vector store, with a warning, when the
+    /// embedded storage path can't be written to (e.g. a read-only filesystem).
+    /// The knowledge base is then lost on exit. Off by default.
+    #[serde(default)]
+    pub memory_fallback: bool,
 }
 
 /// Vector database configuration (collection/index name, etc.).
 ///
 /// Provider-agnostic configuration that works with any vector DB backend
 /// (Qdrant, LanceDB, etc.).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct VectorDbConfig {
     /// Collection/index name for storing vectors
     pub collection_name: String,
+    /// How embeddings are compared during search (default: cosine)
+    #[serde(default)]
+    pub metric: SimilarityMetric,
+    /// Report search scores in `0..=1`, higher meaning a closer match, whatever
+    /// the metric (see [`SimilarityMetric::normalize`]). Raw scores are
+    /// reported by default.
+    #[serde(default)]
+    pub normalize_scores: bool,
+    /// Retrying operations that fail transiently against a remote store
+    #[serde(default)]
+    pub retry: StoreRetryConfig,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Configuration for retrying operations against a remote vector store.
+///
+/// Adds, searches and counts against Qdrant that fail with a connection error
+/// or timeout are tried again after an exponentially growing delay. Other
+/// errors, such as a schema mismatch, fail straight away, and the embedded
+/// store is never retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreRetryConfig {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: usize,
+
+    /// Delay before the first retry, in milliseconds, doubled for each one after.
+    /// Up to half of each delay is taken off at random
+    pub initial_backoff_ms: u64,
+
+    /// Longest delay between retries, in milliseconds
+    pub max_backoff_ms: u64,
+}
+
+impl Default for StoreRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 200,
+            max_backoff_ms: 5000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PersonalizationConfig {
     pub learn_from_interactions: bool,
     pub save_conversations: bool,
@@ -209,7 +879,7 @@ impl Default for PersonalizationConfig {
         Self {
             learn_from_interactions: true,
             save_conversations: true,
-            user_preferences_path: "./data/preferences.json".to_string(),
+            user_preferences_path: "preferences.json".to_string(),
         }
     }
 }
@@ -218,6 +888,9 @@ impl Default for VectorDbConfig {
     fn default() -> Self {
         Self {
             collection_name: "nucleus_kb".to_string(),
+            metric: SimilarityMetric::default(),
+            normalize_scores: false,
+            retry: StoreRetryConfig::default(),
         }
     }
 }
@@ -225,11 +898,13 @@ impl Default for VectorDbConfig {
 impl Default for StorageConfig {
     fn default() -> Self {
         Self {
-            chat_history_path: "./data/history".to_string(),
-            tool_state_path: "./data/tool_state".to_string(),
+            chat_history_path: "history".to_string(),
+            tool_state_path: "tool_state".to_string(),
             storage_mode: StorageMode::default(),
             vector_db: VectorDbConfig::default(),
             top_k: default_top_k(),
+            rag_context_count: None,
+            memory_fallback: false,
         }
     }
 }
@@ -244,6 +919,32 @@ impl Default for LlmConfig {
             context_length: 32768,
             coreml_input_name: default_input_name(),
             coreml_output_name: default_output_name(),
+            coreml_tokenizer_repo: None,
+            max_tool_result_bytes: default_max_tool_result_bytes(),
+            max_tool_iterations: default_max_tool_iterations(),
+            history_token_budget: 0,
+            history_keep_recent: default_history_keep_recent(),
+            warmup: false,
+            model_cache_size: 0,
+            allowed_models: Vec::new(),
+            max_concurrency: 0,
+            skip_model_check: false,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            auth_token: None,
+            max_concurrent_requests: 0,
+            request_read_timeout_secs: default_request_read_timeout_secs(),
+            stream_buffer_size: default_stream_buffer_size(),
+            background_load: false,
+            strip_thinking: false,
+            apply_edits: false,
+            tools_enabled: false,
+            models_dir: default_models_dir(),
         }
     }
 }
@@ -251,7 +952,9 @@ impl Default for LlmConfig {
 impl Default for Config {
     fn default() -> Self {
         Self {
+            data_dir: default_data_dir(),
             llm: LlmConfig::default(),
+            server: ServerConfig::default(),
             system_prompt:
                 "You are a helpful AI assistant specializing in programming and development tasks."
                     .to_string(),
@@ -259,24 +962,156 @@ impl Default for Config {
             storage: StorageConfig::default(),
             personalization: PersonalizationConfig::default(),
             permission: Permission::default(),
+            templates: VarTemplates::default(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from a YAML file.
+    ///
+    /// String values may reference environment variables as `${VAR}`, or
+    /// `${VAR:-default}` to fall back to `default` when `VAR` is unset or empty,
+    /// e.g. `base_url: ${OLLAMA_URL:-http://localhost:11434}`. Write `$${` for a
+    /// literal `${`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::UndefinedVariable`] if a referenced variable is
+    /// unset and has no default.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let contents = fs::read_to_string(path)?;
-        let mut config: Config = serde_yaml::from_str(&contents)?;
+        let mut templates = VarTemplates::default();
+        let mut config: Config = serde_yaml::from_value(parse_yaml(&contents, &mut templates)?)?;
+        config.validate()?;
 
         config.permission = Permission::default();
+        config.templates = templates;
 
         Ok(config)
     }
 
-    /// Load configuration from `config.yaml` if it exists, otherwise use defaults.
+    /// Save configuration to a YAML file that [`load`](Self::load) reads back.
+    ///
+    /// The YAML is written to a temporary file next to `path` and then renamed
+    /// over it, so an interrupted save never leaves a truncated config behind.
+    /// The file is only readable by its owner, since it may hold secrets.
+    /// `permission` is not saved; loading the file grants the default permissions.
+    ///
+    /// Strings that were loaded from `${VAR}` references are saved as written,
+    /// unless they have been changed since, so secrets taken from the
+    /// environment aren't written to disk. Any other `${` in string values is
+    /// escaped, so it isn't expanded when loaded.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut value = serde_yaml::to_value(self)?;
+        map_strings(&mut value, &mut |path, text| {
+            Ok(match self.templates.template_for(path, text) {
+                Some(template) => template.to_string(),
+                None => text.replace("${", "$${"),
+            })
+        })?;
+        let contents = serde_yaml::to_string(&value)?;
+
+        if path.file_name().is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "not a file path: {}",
+                path.display()
+            )));
+        }
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(ConfigError::FileWrite)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            temp.as_file()
+                .set_permissions(fs::Permissions::from_mode(0o600))
+                .map_err(ConfigError::FileWrite)?;
+        }
+        temp.write_all(contents.as_bytes())
+            .map_err(ConfigError::FileWrite)?;
+        temp.as_file().sync_all().map_err(ConfigError::FileWrite)?;
+        temp.persist(path)
+            .map_err(|e| ConfigError::FileWrite(e.error))?;
+        Ok(())
+    }
+
+    /// Load configuration from several YAML files, each overriding the ones
+    /// before it.
+    ///
+    /// Layers are merged key by key before the result is parsed, so nested
+    /// sections such as `llm` or `rag` only have the fields a later layer
+    /// specifies replaced. Lists and scalars are replaced wholesale. Layers that
+    /// don't exist are skipped, but at least one must exist. Environment
+    /// variables are expanded in each layer as in [`load`](Self::load).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use nucleus_core::Config;
+    /// // config.local.yaml holds machine-specific overrides, e.g. `llm: { model: ... }`
+    /// let config = Config::load_layered(&["config.yaml", "config.local.yaml"]).unwrap();
+    /// ```
+    pub fn load_layered<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut merged: Option<serde_yaml::Value> = None;
+        let mut templates = VarTemplates::default();
+
+        for path in paths {
+            let contents = match fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let layer = parse_yaml(&contents, &mut templates)?;
+
+            match merged.as_mut() {
+                Some(base) => merge_yaml(base, layer),
+                None => merged = Some(layer),
+            }
+        }
+
+        let merged = merged.ok_or_else(|| {
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|p| p.as_ref().display().to_string())
+                .collect();
+            ConfigError::NoLayers(paths.join(", "))
+        })?;
+
+        let mut config: Config = serde_yaml::from_value(merged)?;
+        config.validate()?;
+        config.permission = Permission::default();
+        config.templates = templates;
+
+        Ok(config)
+    }
+
+    /// Checks settings that would otherwise fail later in confusing ways.
+    pub fn validate(&self) -> Result<()> {
+        match &self.rag {
+            Some(rag) => rag.validate(),
+            None => Ok(()),
+        }
+    }
+
+    /// Load configuration from `config.yaml`, overlaid by `config.local.yaml`,
+    /// if either exists, otherwise use defaults.
+    ///
+    /// A layer that exists but can't be loaded is logged before falling back
+    /// to defaults.
     pub fn load_or_default() -> Self {
-        Self::load("config.yaml").unwrap_or_default()
+        match Self::load_layered(&["config.yaml", "config.local.yaml"]) {
+            Ok(config) => config,
+            Err(ConfigError::NoLayers(_)) => Self::default(),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to load config, using defaults");
+                Self::default()
+            }
+        }
     }
 
     /// Create a new Config with default values and builder-style configuration.
@@ -284,6 +1119,43 @@ impl Config {
         Self::default()
     }
 
+    /// Set the root directory that relative storage paths resolve against.
+    pub fn with_data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = data_dir.into();
+        self
+    }
+
+    /// Resolves a storage path against [`data_dir`](Self::data_dir).
+    ///
+    /// Absolute paths are returned unchanged.
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.data_dir.join(path)
+        }
+    }
+
+    /// The storage configuration with every relative path resolved against
+    /// [`data_dir`](Self::data_dir).
+    pub fn resolved_storage(&self) -> StorageConfig {
+        let resolve = |path: &str| self.resolve_path(path).to_string_lossy().to_string();
+
+        let mut storage = self.storage.clone();
+        storage.chat_history_path = resolve(&storage.chat_history_path);
+        storage.tool_state_path = resolve(&storage.tool_state_path);
+        if let StorageMode::Embedded { path } = &mut storage.storage_mode {
+            *path = resolve(path);
+        }
+        storage
+    }
+
+    /// Path of the user preferences file, resolved against [`data_dir`](Self::data_dir).
+    pub fn user_preferences_path(&self) -> PathBuf {
+        self.resolve_path(&self.personalization.user_preferences_path)
+    }
+
     /// Set the LLM model identifier.
     pub fn with_model(mut self, model: impl Into<String>) -> Self {
         self.llm.model = model.into();
@@ -320,6 +1192,12 @@ impl Config {
         self
     }
 
+    /// Configure the request server.
+    pub fn with_server_config(mut self, server_config: ServerConfig) -> Self {
+        self.server = server_config;
+        self
+    }
+
     /// Configure RAG settings.
     pub fn with_rag_config(mut self, rag_config: RagConfig) -> Self {
         self.rag = Some(rag_config);
@@ -363,15 +1241,338 @@ mod tests {
     #[test]
     fn test_storage_config_defaults() {
         let config = StorageConfig::default();
-        assert_eq!(config.chat_history_path, "./data/history");
-        assert_eq!(config.tool_state_path, "./data/tool_state");
+        assert_eq!(config.chat_history_path, "history");
+        assert_eq!(config.tool_state_path, "tool_state");
         assert_eq!(config.vector_db.collection_name, "nucleus_kb");
         assert_eq!(config.top_k, 5);
     }
 
+    #[test]
+    fn test_relative_storage_paths_resolve_under_data_dir() {
+        let config = Config::default().with_data_dir("/var/lib/nucleus");
+        let storage = config.resolved_storage();
+
+        assert_eq!(storage.chat_history_path, "/var/lib/nucleus/history");
+        assert_eq!(storage.tool_state_path, "/var/lib/nucleus/tool_state");
+        match storage.storage_mode {
+            StorageMode::Embedded { path } => {
+                assert_eq!(path, "/var/lib/nucleus/nucleus_vectordb")
+            }
+            StorageMode::Grpc { .. } => panic!("expected embedded storage"),
+        }
+        assert_eq!(
+            config.user_preferences_path(),
+            PathBuf::from("/var/lib/nucleus/preferences.json")
+        );
+    }
+
+    #[test]
+    fn test_absolute_storage_paths_pass_through() {
+        let mut config = Config::default().with_data_dir("/var/lib/nucleus");
+        config.storage.chat_history_path = "/srv/history".to_string();
+        config.storage.storage_mode = StorageMode::Embedded {
+            path: "/srv/vectors".to_string(),
+        };
+        config.personalization.user_preferences_path = "/etc/nucleus/prefs.json".to_string();
+
+        let storage = config.resolved_storage();
+        assert_eq!(storage.chat_history_path, "/srv/history");
+        assert_eq!(storage.tool_state_path, "/var/lib/nucleus/tool_state");
+        assert!(matches!(
+            storage.storage_mode,
+            StorageMode::Embedded { path } if path == "/srv/vectors"
+        ));
+        assert_eq!(
+            config.user_preferences_path(),
+            PathBuf::from("/etc/nucleus/prefs.json")
+        );
+    }
+
+    #[test]
+    fn test_data_dir_from_yaml() {
+        let yaml = r#"
+data_dir: /opt/nucleus
+system_prompt: hi
+llm:
+  model: m
+  base_url: http://localhost:11434
+  temperature: 0.5
+  context_length: 1024
+storage:
+  chat_history_path: history
+  tool_state_path: tool_state
+personalization:
+  learn_from_interactions: false
+  save_conversations: false
+  user_preferences_path: preferences.json
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.data_dir, PathBuf::from("/opt/nucleus"));
+        assert_eq!(
+            config.resolved_storage().chat_history_path,
+            "/opt/nucleus/history"
+        );
+    }
+
+    #[test]
+    fn test_server_section_is_optional() {
+        let config: Config = serde_yaml::from_str(BASE_LAYER).unwrap();
+        assert_eq!(config.server, ServerConfig::default());
+
+        let yaml = format!(
+            "{}server:\n  auth_token: s3cret\n  apply_edits: true\n",
+            BASE_LAYER
+        );
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config.server.auth_token.as_deref(), Some("s3cret"));
+        assert!(config.server.apply_edits);
+        assert_eq!(config.server.stream_buffer_size, 256);
+    }
+
+    #[test]
+    fn test_server_settings_under_llm_are_moved() {
+        let yaml = BASE_LAYER.replacen(
+            "llm:\n",
+            "llm:\n  auth_token: s3cret\n  apply_edits: true\n  max_concurrent_requests: 2\n",
+            1,
+        );
+        let yaml = format!("{}server:\n  max_concurrent_requests: 8\n", yaml);
+        let mut templates = VarTemplates::default();
+        let config: Config =
+            serde_yaml::from_value(parse_yaml(&yaml, &mut templates).unwrap()).unwrap();
+
+        assert_eq!(config.server.auth_token.as_deref(), Some("s3cret"));
+        assert!(config.server.apply_edits);
+        // A setting already under server wins
+        assert_eq!(config.server.max_concurrent_requests, 8);
+    }
+
     #[test]
     fn test_rag_config_defaults() {
         let config = RagConfig::default();
         assert_eq!(config.embedding_model.name, EmbeddingModel::default().name);
     }
+
+    #[test]
+    fn test_default_chunk_size_is_independent_of_embedding_dim() {
+        let config = RagConfig::default();
+        assert_eq!(config.indexer.chunk_size, 512);
+        assert_ne!(
+            config.indexer.chunk_size,
+            config.embedding_model.embedding_dim
+        );
+        assert!(config.indexer.validate().is_ok());
+    }
+
+    #[test]
+    fn test_overlap_not_smaller_than_chunk_size_is_rejected() {
+        let mut config = Config::default().with_rag_config(RagConfig::default());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.chunk_size = 100;
+        indexer.chunk_overlap = 100;
+
+        let err = config.validate().unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+        assert!(err.to_string().contains("chunk_overlap (100)"));
+
+        config.rag.as_mut().unwrap().indexer.chunk_overlap = 99;
+        assert!(config.validate().is_ok());
+    }
+
+    const BASE_LAYER: &str = r#"
+system_prompt: base prompt
+llm:
+  model: base-model
+  base_url: http://localhost:11434
+  temperature: 0.5
+  context_length: 1024
+storage:
+  chat_history_path: history
+  tool_state_path: tool_state
+personalization:
+  learn_from_interactions: false
+  save_conversations: false
+  user_preferences_path: preferences.json
+"#;
+
+    #[test]
+    fn test_load_layered_overrides_only_given_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("config.yaml");
+        let local = dir.path().join("config.local.yaml");
+        std::fs::write(&base, BASE_LAYER).unwrap();
+        std::fs::write(
+            &local,
+            "llm:\n  model: local-model\nstorage:\n  tool_state_path: local_state\n",
+        )
+        .unwrap();
+
+        let config = Config::load_layered(&[&base, &local]).unwrap();
+
+        assert_eq!(config.llm.model, "local-model");
+        assert_eq!(config.llm.base_url, "http://localhost:11434");
+        assert_eq!(config.llm.temperature, 0.5);
+        assert_eq!(config.system_prompt, "base prompt");
+
+        assert_eq!(config.storage.tool_state_path, "local_state");
+        assert_eq!(config.storage.chat_history_path, "history");
+    }
+
+    #[test]
+    fn test_load_layered_ignores_null_overlays() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("config.yaml");
+        let local = dir.path().join("config.local.yaml");
+        std::fs::write(&base, BASE_LAYER).unwrap();
+        std::fs::write(&local, "").unwrap();
+
+        let config = Config::load_layered(&[&base, &local]).unwrap();
+        assert_eq!(config.llm.model, "base-model");
+
+        std::fs::write(&local, "llm:\n  model: ~\nsystem_prompt: local prompt\n").unwrap();
+        let config = Config::load_layered(&[&base, &local]).unwrap();
+        assert_eq!(config.llm.model, "base-model");
+        assert_eq!(config.system_prompt, "local prompt");
+    }
+
+    #[test]
+    fn test_load_layered_skips_missing_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("config.yaml");
+        std::fs::write(&base, BASE_LAYER).unwrap();
+
+        let config =
+            Config::load_layered(&[base.clone(), dir.path().join("config.local.yaml")]).unwrap();
+        assert_eq!(config.llm.model, "base-model");
+
+        let missing = Config::load_layered(&[dir.path().join("nope.yaml")]);
+        assert!(matches!(missing, Err(ConfigError::NoLayers(_))));
+    }
+
+    #[test]
+    fn test_save_round_trips_through_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+
+        let mut config = Config::new()
+            .with_data_dir("/opt/nucleus")
+            .with_model("saved-model")
+            .with_temperature(0.3)
+            .with_rag_config(RagConfig::default());
+        config.storage.storage_mode = StorageMode::Grpc {
+            url: "http://localhost:6334".to_string(),
+        };
+        config.storage.rag_context_count = Some(3);
+        config.permission = Permission {
+            read: true,
+            write: false,
+            command: false,
+        };
+
+        config.save(&path).unwrap();
+        let loaded = Config::load(&path).unwrap();
+
+        // Permissions aren't persisted, so they come back as the defaults
+        config.permission = Permission::default();
+        assert_eq!(loaded, config);
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1, "no temporary file is left behind");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_save_keeps_variable_references() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        std::env::set_var("NUCLEUS_TEST_SAVE_TOKEN", "s3cret");
+        std::env::set_var("NUCLEUS_TEST_SAVE_HOST", "gpu-box");
+        let contents = BASE_LAYER.replace(
+            "base_url: http://localhost:11434",
+            "base_url: http://${NUCLEUS_TEST_SAVE_HOST}:11434",
+        ) + "server:\n  auth_token: ${NUCLEUS_TEST_SAVE_TOKEN}\n";
+        std::fs::write(&path, contents).unwrap();
+
+        let mut config = Config::load(&path).unwrap();
+        assert_eq!(config.server.auth_token.as_deref(), Some("s3cret"));
+        assert_eq!(config.llm.base_url, "http://gpu-box:11434");
+        config.llm.base_url = "http://localhost:11434".to_string();
+        config.save(&path).unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(!saved.contains("s3cret"), "{}", saved);
+        assert!(saved.contains("${NUCLEUS_TEST_SAVE_TOKEN}"), "{}", saved);
+        assert!(saved.contains("http://localhost:11434"), "{}", saved);
+        assert_eq!(Config::load(&path).unwrap(), config);
+    }
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("gpu-box".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand_vars_substitutes_and_falls_back() {
+        assert_eq!(
+            expand_vars("http://${HOST}:11434", lookup).unwrap(),
+            "http://gpu-box:11434"
+        );
+        assert_eq!(
+            expand_vars("${MISSING:-localhost}/${HOST:-unused}", lookup).unwrap(),
+            "localhost/gpu-box"
+        );
+        assert_eq!(
+            expand_vars("${EMPTY:-fallback}", lookup).unwrap(),
+            "fallback"
+        );
+        assert_eq!(expand_vars("${MISSING:-}", lookup).unwrap(), "");
+        assert_eq!(expand_vars("costs $5", lookup).unwrap(), "costs $5");
+    }
+
+    #[test]
+    fn test_expand_vars_rejects_undefined_variables() {
+        let err = expand_vars("${MISSING}", lookup).unwrap_err();
+        assert!(matches!(err, ConfigError::UndefinedVariable(name) if name == "MISSING"));
+
+        let err = expand_vars("${HOST", lookup).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid(_)));
+    }
+
+    #[test]
+    fn test_expand_vars_escapes_literal_references() {
+        assert_eq!(
+            expand_vars("$${HOST} is ${HOST}", lookup).unwrap(),
+            "${HOST} is gpu-box"
+        );
+    }
+
+    #[test]
+    fn test_load_expands_environment_variables() {
+        std::env::set_var("NUCLEUS_TEST_CONFIG_MODEL", "env-model");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yaml");
+        let yaml = BASE_LAYER
+            .replace("base-model", "${NUCLEUS_TEST_CONFIG_MODEL}")
+            .replace("base prompt", "Use $${vars} literally");
+        std::fs::write(&path, yaml).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.llm.model, "env-model");
+        assert_eq!(config.system_prompt, "Use ${vars} literally");
+
+        // Saving escapes the literal again, so it survives another load
+        config.save(&path).unwrap();
+        assert_eq!(
+            Config::load(&path).unwrap().system_prompt,
+            config.system_prompt
+        );
+    }
 }
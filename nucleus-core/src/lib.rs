@@ -18,13 +18,20 @@ pub mod config;
 pub mod detection;
 pub mod models;
 pub mod patterns;
+pub mod prompt;
 pub mod provider;
 pub mod qdrant_helper;
 pub mod rag;
 pub mod server;
 
+#[cfg(test)]
+mod testing;
+
 // Public exports
-pub use chat::{ChatManager, ChatManagerBuilder};
+pub use chat::{
+    ChatManager, ChatManagerBuilder, QueryDebug, QueryTrace, StreamTiming, ToolCallTrace,
+    TOOL_LIMIT_MARKER,
+};
 pub use config::{Config, IndexerConfig};
 pub use detection::{check_ollama_silent, detect_ollama, DetectionError, OllamaInfo};
 pub use rag::RagEngine;
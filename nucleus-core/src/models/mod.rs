@@ -1,3 +1,5 @@
 mod registry;
+mod scan;
 
 pub use registry::{default_models, ChatModel, EmbeddingModel, Model, ModelRegistry};
+pub use scan::{scan_models_dir, LocalModel, LocalModelFormat};
@@ -11,7 +11,7 @@ pub struct ChatModel {
     pub description: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EmbeddingModel {
     pub id: String,
     pub name: String,
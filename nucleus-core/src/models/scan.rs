@@ -0,0 +1,153 @@
+//! Finding the local models a server can load in its models directory.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// How many directories deep below the models directory models are looked for.
+const MAX_DEPTH: usize = 8;
+
+/// File format of a model found on disk, which decides the provider that loads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalModelFormat {
+    /// A `.gguf` file, loaded by mistral.rs
+    Gguf,
+    /// A `.mlpackage` or `.mlmodelc` bundle, loaded by CoreML
+    CoreMl,
+}
+
+impl LocalModelFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gguf" if path.is_file() => Some(Self::Gguf),
+            "mlpackage" | "mlmodelc" if path.is_dir() => Some(Self::CoreMl),
+            _ => None,
+        }
+    }
+
+    /// The `llm.provider` that loads models of this format.
+    pub fn provider(&self) -> &'static str {
+        match self {
+            Self::Gguf => "mistralrs",
+            Self::CoreMl => "coreml",
+        }
+    }
+}
+
+/// A model found by [`scan_models_dir`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalModel {
+    /// File name without its extension
+    pub name: String,
+    /// Path of the model, usable as `llm.model` with the format's provider
+    pub model: String,
+    pub format: LocalModelFormat,
+}
+
+/// Finds the GGUF files and CoreML bundles in `dir` and its subdirectories,
+/// sorted by path.
+///
+/// Symlinked models are found, but symlinked directories aren't descended
+/// into, and neither is anything more than `MAX_DEPTH` levels down.
+/// Directories that can't be read are skipped, so a missing `dir` yields no
+/// models. This walks the file system, so async code should call it with
+/// [`spawn_blocking`](tokio::task::spawn_blocking).
+pub fn scan_models_dir(dir: impl AsRef<Path>) -> Vec<LocalModel> {
+    let mut models = Vec::new();
+    let mut entries = WalkDir::new(dir)
+        .follow_links(false)
+        .min_depth(1)
+        .max_depth(MAX_DEPTH)
+        .into_iter();
+
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let path = entry.path();
+        let Some(format) = LocalModelFormat::from_path(path) else {
+            continue;
+        };
+        // CoreML bundles are directories too, but are never descended into
+        if entry.file_type().is_dir() {
+            entries.skip_current_dir();
+        }
+        models.push(LocalModel {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            model: path.to_string_lossy().to_string(),
+            format,
+        });
+    }
+
+    models.sort_by(|a, b| a.model.cmp(&b.model));
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_scan_detects_gguf_files_and_coreml_bundles() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("qwen3-0.6b.gguf"), b"GGUF").unwrap();
+        std::fs::write(root.join("README.md"), b"notes").unwrap();
+        std::fs::create_dir_all(root.join("llama.mlpackage/Data")).unwrap();
+        std::fs::write(root.join("llama.mlpackage/Data/weights.gguf"), b"").unwrap();
+        std::fs::create_dir_all(root.join("apple/mistral.mlmodelc")).unwrap();
+        std::fs::create_dir_all(root.join("fake.gguf")).unwrap();
+
+        let models = scan_models_dir(root);
+
+        let found: Vec<_> = models
+            .iter()
+            .map(|m| (m.name.as_str(), m.format, m.format.provider()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("mistral", LocalModelFormat::CoreMl, "coreml"),
+                ("llama", LocalModelFormat::CoreMl, "coreml"),
+                ("qwen3-0.6b", LocalModelFormat::Gguf, "mistralrs"),
+            ]
+        );
+        assert_eq!(
+            models[2].model,
+            root.join("qwen3-0.6b.gguf").to_string_lossy()
+        );
+
+        assert!(scan_models_dir(root.join("missing")).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_does_not_follow_directory_links() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("models")).unwrap();
+        std::fs::write(root.join("models/qwen.gguf"), b"GGUF").unwrap();
+        // A link back up would otherwise be walked forever
+        std::os::unix::fs::symlink(root, root.join("models/loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("models/qwen.gguf"), root.join("linked.gguf"))
+            .unwrap();
+
+        let names: Vec<_> = scan_models_dir(root)
+            .into_iter()
+            .map(|model| model.model)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                root.join("linked.gguf").to_string_lossy().to_string(),
+                root.join("models/qwen.gguf").to_string_lossy().to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! System prompt templating.
+//!
+//! System prompts may contain placeholders that are filled in per request:
+//!
+//! - `{pwd}` - the working directory of the request
+//! - `{date}` - today's date (UTC) as `YYYY-MM-DD`
+//! - `{project}` - the name of the working directory (e.g. `nucleus` for `/src/nucleus`)
+//!
+//! Unknown placeholders, and placeholders without a value (such as `{pwd}` when
+//! the request has no working directory), are left in the prompt unchanged.
+//!
+//! # Example
+//!
+//! ```
+//! use nucleus_core::prompt::{render_prompt, PromptVars};
+//!
+//! let vars = PromptVars::new(Some("/home/user/nucleus")).with_date("2024-01-01");
+//! let prompt = render_prompt("You are working on {project} in {pwd} on {date}.", &vars);
+//! assert_eq!(
+//!     prompt,
+//!     "You are working on nucleus in /home/user/nucleus on 2024-01-01."
+//! );
+//! ```
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Values substituted into a prompt template.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptVars {
+    pub pwd: Option<String>,
+    pub date: Option<String>,
+    pub project: Option<String>,
+}
+
+impl PromptVars {
+    /// Creates variables for a request made from `pwd`, dated today.
+    ///
+    /// The project name is taken from the final component of `pwd`.
+    pub fn new(pwd: Option<&str>) -> Self {
+        let project = pwd
+            .and_then(|pwd| Path::new(pwd).file_name())
+            .map(|name| name.to_string_lossy().to_string());
+
+        Self {
+            pwd: pwd.map(str::to_string),
+            date: Some(today()),
+            project,
+        }
+    }
+
+    /// Override the date, mainly useful for reproducible prompts.
+    pub fn with_date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Override the project name.
+    pub fn with_project(mut self, project: impl Into<String>) -> Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "pwd" => self.pwd.as_deref(),
+            "date" => self.date.as_deref(),
+            "project" => self.project.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Substitutes `{placeholder}`s in `template` with values from `vars`.
+///
+/// Anything that is not a known placeholder with a value is copied through
+/// verbatim, so prompts containing literal braces (e.g. JSON examples) are safe.
+pub fn render_prompt(template: &str, vars: &PromptVars) -> String {
+    render_template(template, |name| vars.get(name).map(str::to_string))
+}
+
+/// Substitutes `{placeholder}`s in `template` with the values returned by `lookup`.
+///
+/// This is the engine behind [`render_prompt`], for templates with their own set
+/// of placeholders. Placeholders for which `lookup` returns `None` are left as is.
+pub fn render_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let after_open = &rest[open + 1..];
+
+        match after_open.find(['{', '}']) {
+            Some(close) if after_open.as_bytes()[close] == b'}' => {
+                let name = &after_open[..close];
+                match lookup(name) {
+                    Some(value) => rendered.push_str(&value),
+                    None => rendered.push_str(&rest[open..open + close + 2]),
+                }
+                rest = &after_open[close + 1..];
+            }
+            _ => {
+                rendered.push('{');
+                rest = after_open;
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Today's date in UTC, formatted as `YYYY-MM-DD`.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) date.
+///
+/// Uses Howard Hinnant's `civil_from_days` algorithm for the proleptic
+/// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> PromptVars {
+        PromptVars::new(Some("/home/user/nucleus")).with_date("2024-03-09")
+    }
+
+    #[test]
+    fn test_render_known_placeholders() {
+        let rendered = render_prompt("In {project} at {pwd} on {date}", &vars());
+        assert_eq!(rendered, "In nucleus at /home/user/nucleus on 2024-03-09");
+    }
+
+    #[test]
+    fn test_render_repeated_placeholder() {
+        let rendered = render_prompt("{project}/{project}", &vars());
+        assert_eq!(rendered, "nucleus/nucleus");
+    }
+
+    #[test]
+    fn test_unknown_placeholders_left_intact() {
+        let rendered = render_prompt("Hello {user}, see {project}", &vars());
+        assert_eq!(rendered, "Hello {user}, see nucleus");
+    }
+
+    #[test]
+    fn test_missing_values_left_intact() {
+        let vars = PromptVars::new(None).with_date("2024-03-09");
+        let rendered = render_prompt("Working in {pwd} ({project}) on {date}", &vars);
+        assert_eq!(rendered, "Working in {pwd} ({project}) on 2024-03-09");
+    }
+
+    #[test]
+    fn test_literal_braces_preserved() {
+        let template = r#"Respond as {"dir": "{pwd}"} {unclosed {{date}"#;
+        let rendered = render_prompt(template, &vars());
+        assert_eq!(
+            rendered,
+            r#"Respond as {"dir": "/home/user/nucleus"} {unclosed {2024-03-09"#
+        );
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_791), (2024, 3, 9));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+    }
+}
@@ -0,0 +1,284 @@
+//! Provider that loads in the background.
+
+use super::types::*;
+use crate::models::EmbeddingModel;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// Sentinel for a download whose progress hasn't been reported.
+const UNKNOWN_PROGRESS: u8 = u8::MAX;
+
+/// Progress of a model download, reported by the loader of a [`BackgroundProvider`].
+#[derive(Debug, Clone)]
+pub struct DownloadProgress(Arc<AtomicU8>);
+
+impl Default for DownloadProgress {
+    fn default() -> Self {
+        Self(Arc::new(AtomicU8::new(UNKNOWN_PROGRESS)))
+    }
+}
+
+impl DownloadProgress {
+    /// Records that `fraction` (`0.0..=1.0`) of the model has been downloaded.
+    pub fn set(&self, fraction: f64) {
+        let percent = (fraction.clamp(0.0, 1.0) * 100.0).floor() as u8;
+        self.0.store(percent, Ordering::Relaxed);
+    }
+
+    /// Records that nothing is downloading, e.g. because the download finished
+    /// and the model is now loading.
+    pub fn clear(&self) {
+        self.0.store(UNKNOWN_PROGRESS, Ordering::Relaxed);
+    }
+
+    /// Percentage downloaded so far, if a download is being reported.
+    pub fn percent(&self) -> Option<u8> {
+        match self.0.load(Ordering::Relaxed) {
+            UNKNOWN_PROGRESS => None,
+            percent => Some(percent),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum LoadState {
+    Loading,
+    Ready(Arc<dyn Provider>),
+    Failed(String),
+}
+
+/// Wraps a provider whose model is downloaded and loaded in a background task,
+/// so the server can start accepting connections straight away.
+///
+/// Until the provider is ready, chat and embedding requests fail immediately
+/// with [`ProviderError::ModelLoading`], describing how far the download has
+/// got, or that the model is loading when nothing is downloading. Once it is
+/// ready, every call goes to the loaded provider.
+///
+/// ```no_run
+/// # use nucleus_core::provider::{BackgroundProvider, MistralRsProvider, Provider};
+/// # use nucleus_core::Config;
+/// # use std::sync::Arc;
+/// # fn example(config: Config, registry: Arc<nucleus_plugin::PluginRegistry>) {
+/// let provider = BackgroundProvider::spawn(move |_progress| async move {
+///     let provider = MistralRsProvider::new(&config, registry).await?;
+///     Ok(Arc::new(provider) as Arc<dyn Provider>)
+/// });
+/// # }
+/// ```
+pub struct BackgroundProvider {
+    state: watch::Receiver<LoadState>,
+    progress: DownloadProgress,
+    task: AbortHandle,
+}
+
+impl BackgroundProvider {
+    /// Starts loading a provider with `load` on the current tokio runtime.
+    ///
+    /// `load` receives a [`DownloadProgress`] to report how much of the model
+    /// has been downloaded; without reports, requests are only told that the
+    /// model is loading.
+    pub fn spawn<F, Fut>(load: F) -> Self
+    where
+        F: FnOnce(DownloadProgress) -> Fut,
+        Fut: Future<Output = Result<Arc<dyn Provider>>> + Send + 'static,
+    {
+        let progress = DownloadProgress::default();
+        let (sender, state) = watch::channel(LoadState::Loading);
+
+        let loading = load(progress.clone());
+        let task = tokio::spawn(async move {
+            let state = match loading.await {
+                Ok(provider) => {
+                    info!("Model loaded in the background");
+                    LoadState::Ready(provider)
+                }
+                Err(e) => {
+                    warn!(error = %e, "Background model load failed");
+                    LoadState::Failed(e.to_string())
+                }
+            };
+            let _ = sender.send(state);
+        });
+
+        Self {
+            state,
+            progress,
+            task: task.abort_handle(),
+        }
+    }
+
+    /// Whether the provider has finished loading.
+    pub fn is_ready(&self) -> bool {
+        matches!(*self.state.borrow(), LoadState::Ready(_))
+    }
+
+    /// Progress of the model download, see [`DownloadProgress::percent`].
+    pub fn progress(&self) -> Option<u8> {
+        self.progress.percent()
+    }
+
+    /// Waits until the provider has loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error the provider failed to load with.
+    pub async fn wait_ready(&self) -> Result<()> {
+        self.state
+            .clone()
+            .wait_for(|state| !matches!(state, LoadState::Loading))
+            .await
+            .map_err(|_| ProviderError::Other("Background model load was aborted".to_string()))?;
+        self.provider().map(|_| ())
+    }
+
+    /// The loaded provider, or why it can't be used yet.
+    fn provider(&self) -> Result<Arc<dyn Provider>> {
+        match &*self.state.borrow() {
+            LoadState::Ready(provider) => Ok(Arc::clone(provider)),
+            LoadState::Loading => Err(ProviderError::ModelLoading(match self.progress() {
+                Some(percent) => format!("downloading, {}% complete", percent),
+                None => "loading".to_string(),
+            })),
+            LoadState::Failed(e) => {
+                Err(ProviderError::Other(format!("Model failed to load: {}", e)))
+            }
+        }
+    }
+
+    fn ready(&self) -> Option<Arc<dyn Provider>> {
+        self.provider().ok()
+    }
+}
+
+#[async_trait]
+impl Provider for BackgroundProvider {
+    async fn chat<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        self.provider()?.chat(request, callback).await
+    }
+
+    async fn chat_n<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(usize, ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        self.provider()?.chat_n(request, callback).await
+    }
+
+    /// Warms up the loaded provider; does nothing while it is still loading.
+    async fn warmup(&self) -> Result<()> {
+        match self.ready() {
+            Some(provider) => provider.warmup().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.provider()?.list_models().await
+    }
+
+    fn accelerator(&self) -> AcceleratorType {
+        self.ready()
+            .map_or(AcceleratorType::None, |provider| provider.accelerator())
+    }
+
+    fn supports_model_switching(&self) -> bool {
+        self.ready()
+            .is_some_and(|provider| provider.supports_model_switching())
+    }
+
+    /// Stops loading if it hasn't finished, otherwise shuts the loaded provider down.
+    async fn shutdown(&self) -> Result<()> {
+        self.task.abort();
+        match self.ready() {
+            Some(provider) => provider.shutdown().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        self.provider()?.embed(text, model).await
+    }
+
+    async fn embed_batch(&self, texts: &[&str], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        self.provider()?.embed_batch(texts, model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+
+    #[tokio::test]
+    async fn test_failed_load_is_reported() {
+        let provider = BackgroundProvider::spawn(|_progress| async {
+            Err(ProviderError::ModelNotFound("qwen".to_string()))
+        });
+
+        assert!(provider.wait_ready().await.is_err());
+        let err = provider
+            .embed("text", &EmbeddingModel::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Model failed to load"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_loading_is_reported_without_a_download() {
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let provider = BackgroundProvider::spawn(|progress| async move {
+            progress.set(1.0);
+            progress.clear();
+            let _ = released.await;
+            Ok(Arc::new(MockProvider::new("ready")) as Arc<dyn Provider>)
+        });
+
+        let err = provider
+            .embed("text", &EmbeddingModel::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Model not ready yet: loading");
+
+        release.send(()).unwrap();
+        provider.wait_ready().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_progress_is_reported_while_loading() {
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let provider = BackgroundProvider::spawn(|progress| async move {
+            progress.set(0.421);
+            let _ = released.await;
+            Ok(Arc::new(MockProvider::new("ready")) as Arc<dyn Provider>)
+        });
+
+        while provider.progress().is_none() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(provider.progress(), Some(42));
+        let err = provider
+            .embed("text", &EmbeddingModel::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProviderError::ModelLoading(_)));
+        assert!(err.to_string().contains("42% complete"), "{err}");
+
+        release.send(()).unwrap();
+        provider.wait_ready().await.unwrap();
+        assert!(provider.is_ready());
+        assert!(provider
+            .embed("text", &EmbeddingModel::default())
+            .await
+            .is_ok());
+    }
+}
@@ -1,3 +1,4 @@
 mod provider;
+mod tokenizer;
 
 pub use provider::CoreMLProvider;
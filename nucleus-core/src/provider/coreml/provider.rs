@@ -3,8 +3,11 @@
 //! This module provides inference using Apple's CoreML framework.
 //! Only available on macOS with the `coreml` feature enabled.
 
+use super::tokenizer::load_tokenizer;
 use crate::models::EmbeddingModel;
-use crate::provider::{ChatRequest, ChatResponse, Message, Provider, ProviderError, Result};
+use crate::provider::{
+    hub, AcceleratorType, ChatRequest, ChatResponse, Message, Provider, ProviderError, Result,
+};
 use crate::Config;
 use async_trait::async_trait;
 use nucleus_plugin::PluginRegistry;
@@ -83,25 +86,6 @@ fn argmax(logits: &[f32]) -> u32 {
         .unwrap_or(0)
 }
 
-/// Encodes text as Unicode codepoints clamped to 127999 (fallback tokenizer).
-fn simple_encode(text: &str) -> Vec<u32> {
-    text.chars().map(|c| (c as u32).min(127_999)).collect()
-}
-
-/// Decodes token IDs back to text by treating them as Unicode codepoints.
-fn simple_decode(tokens: &[u32]) -> String {
-    tokens
-        .iter()
-        .filter_map(|&id| {
-            if id < 128_000 {
-                char::from_u32(id)
-            } else {
-                None
-            }
-        })
-        .collect()
-}
-
 /// Creates a lower-triangular attention mask for autoregressive generation.
 fn create_causal_mask(seq_len: usize) -> Vec<f32> {
     let mut mask = vec![f32::NEG_INFINITY; seq_len * seq_len];
@@ -157,8 +141,8 @@ pub struct CoreMLProvider {
     output_name: String,
     _registry: Arc<PluginRegistry>,
     _config: Config,
-    _tokenizer: Option<Tokenizer>,
-    _vocab_size: usize,
+    tokenizer: Arc<Tokenizer>,
+    vocab_size: usize,
     #[allow(unused)]
     max_cache_length: usize,
 }
@@ -166,7 +150,9 @@ pub struct CoreMLProvider {
 impl CoreMLProvider {
     /// Creates a new CoreML provider.
     ///
-    /// Loads the CoreML model from the configured path.
+    /// Loads the CoreML model from the configured path, along with the
+    /// `tokenizer.json` next to it. A missing tokenizer is downloaded from
+    /// `llm.coreml_tokenizer_repo` when set.
     pub async fn new(config: &Config, registry: Arc<PluginRegistry>) -> Result<Arc<Self>> {
         info!("CoreML provider initialized with Apple Neural Engine acceleration");
 
@@ -195,6 +181,17 @@ impl CoreMLProvider {
             .to_str()
             .ok_or_else(|| ProviderError::Other("Invalid UTF-8 in model path".to_string()))?;
 
+        let tokenizer_parent = path
+            .parent()
+            .ok_or_else(|| ProviderError::Other("Invalid model path".to_string()))?;
+        let tokenizer = load_tokenizer(
+            tokenizer_parent,
+            config.llm.coreml_tokenizer_repo.as_deref(),
+            &hub::endpoint(),
+        )
+        .await?;
+        let vocab_size = tokenizer.get_vocab_size(false);
+
         let c_path = CString::new(path_str)
             .map_err(|e| ProviderError::Other(format!("Invalid path: {}", e)))?;
 
@@ -214,35 +211,6 @@ impl CoreMLProvider {
             Some(CoreMLStateRef(state_handle))
         };
 
-        let tokenizer_parent = Path::new(&expanded_path)
-            .parent()
-            .ok_or_else(|| ProviderError::Other("Invalid model path".to_string()))?;
-        let tokenizer_path = tokenizer_parent.join("tokenizer.json");
-
-        let tokenizer = if tokenizer_path.exists() {
-            let tok = Tokenizer::from_file(&tokenizer_path)
-                .map_err(|e| ProviderError::Other(format!("Tokenizer load failed: {}", e)))?;
-
-            let vocab_size = tok.get_vocab_size(false);
-            info!("Tokenizer loaded: {} tokens", vocab_size);
-
-            Some(tok)
-        } else {
-            info!(
-                "No tokenizer.json found at {:?}, using default vocab size from config",
-                tokenizer_path
-            );
-            None
-        };
-
-        let vocab_size = tokenizer
-            .as_ref()
-            .map(|t| t.get_vocab_size(false))
-            .unwrap_or_else(|| {
-                debug!("Using context_length 128256 as default vocab size");
-                128_256
-            });
-
         info!("CoreML model loaded: {}", path.display());
 
         Ok(Arc::new(Self {
@@ -253,117 +221,64 @@ impl CoreMLProvider {
             output_name: "logits".to_string(),
             _registry: registry,
             _config: config.clone(),
-            _tokenizer: tokenizer,
-            _vocab_size: vocab_size,
+            tokenizer,
+            vocab_size,
             max_cache_length: 2048,
         }))
     }
 
     fn format_chat_prompt(&self, messages: &[Message]) -> Result<(String, Vec<u32>)> {
-        if let Some(ref tokenizer) = self._tokenizer {
-            let mut prompt = String::new();
-
-            for message in messages {
-                match message.role.as_str() {
-                    "system" => {
-                        prompt.push_str(
-                            "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n",
-                        );
-                        if let Some(ref context) = message.context {
-                            prompt.push_str(context);
-                            prompt.push_str("\n\n");
-                        }
-                        prompt.push_str(&message.content);
-                        prompt.push_str("<|eot_id|>");
-                    }
-                    "user" => {
-                        prompt.push_str("<|start_header_id|>user<|end_header_id|>\n\n");
-                        if let Some(ref context) = message.context {
-                            prompt.push_str(context);
-                            prompt.push_str("\n\n");
-                        }
-                        prompt.push_str(&message.content);
-                        prompt.push_str("<|eot_id|>");
-                    }
-                    "assistant" => {
-                        prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
-                        prompt.push_str(&message.content);
-                        prompt.push_str("<|eot_id|>");
+        let mut prompt = String::new();
+
+        for message in messages {
+            match message.role.as_str() {
+                "system" => {
+                    prompt.push_str(
+                        "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n",
+                    );
+                    if let Some(ref context) = message.context {
+                        prompt.push_str(context);
+                        prompt.push_str("\n\n");
                     }
-                    _ => {
-                        return Err(ProviderError::Other(format!(
-                            "Unsupported role: {}",
-                            message.role
-                        )));
+                    prompt.push_str(&message.content);
+                    prompt.push_str("<|eot_id|>");
+                }
+                "user" => {
+                    prompt.push_str("<|start_header_id|>user<|end_header_id|>\n\n");
+                    if let Some(ref context) = message.context {
+                        prompt.push_str(context);
+                        prompt.push_str("\n\n");
                     }
+                    prompt.push_str(&message.content);
+                    prompt.push_str("<|eot_id|>");
+                }
+                "assistant" => {
+                    prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+                    prompt.push_str(&message.content);
+                    prompt.push_str("<|eot_id|>");
                 }
-            }
-
-            prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
-
-            let encoding = tokenizer
-                .encode(prompt.as_str(), false)
-                .map_err(|e| ProviderError::Other(format!("Tokenization failed: {}", e)))?;
-            let token_ids = encoding.get_ids().to_vec();
-
-            Ok((prompt, token_ids))
-        } else {
-            let token_ids = self.encode_without_tokenizer(messages)?;
-            Ok((String::new(), token_ids))
-        }
-    }
-
-    fn encode_without_tokenizer(&self, messages: &[Message]) -> Result<Vec<u32>> {
-        const BOS: u32 = 128_000;
-        const START_HEADER: u32 = 128_006;
-        const END_HEADER: u32 = 128_007;
-        const EOT: u32 = 128_009;
-
-        let mut token_ids = vec![BOS];
-
-        for message in messages {
-            token_ids.push(START_HEADER);
-
-            let role_tokens = match message.role.as_str() {
-                "system" => simple_encode("system"),
-                "user" => simple_encode("user"),
-                "assistant" => simple_encode("assistant"),
                 _ => {
                     return Err(ProviderError::Other(format!(
                         "Unsupported role: {}",
                         message.role
-                    )))
+                    )));
                 }
-            };
-            token_ids.extend(role_tokens);
-            token_ids.push(END_HEADER);
-            token_ids.push(198);
-            token_ids.push(198);
-
-            if let Some(ref context) = message.context {
-                token_ids.extend(simple_encode(context));
-                token_ids.push(198);
-                token_ids.push(198);
             }
-
-            token_ids.extend(simple_encode(&message.content));
-            token_ids.push(EOT);
         }
 
-        token_ids.push(START_HEADER);
-        token_ids.extend(simple_encode("assistant"));
-        token_ids.push(END_HEADER);
-        token_ids.push(198);
-        token_ids.push(198);
+        prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+
+        let encoding = self
+            .tokenizer
+            .encode(prompt.as_str(), false)
+            .map_err(|e| ProviderError::Other(format!("Tokenization failed: {}", e)))?;
+        let token_ids = encoding.get_ids().to_vec();
 
-        Ok(token_ids)
+        Ok((prompt, token_ids))
     }
 
     pub fn generate(&self, prompt: &str, max_tokens: usize) -> Result<String> {
-        let tokenizer = self
-            ._tokenizer
-            .as_ref()
-            .ok_or_else(|| ProviderError::Other("Tokenizer not loaded".to_string()))?;
+        let tokenizer = &self.tokenizer;
 
         let encoding = tokenizer
             .encode(prompt, false)
@@ -381,13 +296,13 @@ impl CoreMLProvider {
         for step in 0..max_tokens {
             let input_floats: Vec<f32> = input_ids.iter().map(|&id| id as f32).collect();
 
-            let mut logits = vec![0.0f32; self._vocab_size];
+            let mut logits = vec![0.0f32; self.vocab_size];
 
             self.predict(&input_floats, &mut logits)?;
 
             let next_token_id = argmax(&logits);
 
-            if next_token_id == 0 || next_token_id >= self._vocab_size as u32 {
+            if next_token_id == 0 || next_token_id >= self.vocab_size as u32 {
                 debug!("EOS or invalid token {} at step {}", next_token_id, step);
                 break;
             }
@@ -533,6 +448,12 @@ impl Provider for CoreMLProvider {
         request: ChatRequest,
         mut callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
     ) -> Result<()> {
+        if request.has_images() {
+            return Err(ProviderError::Unsupported(
+                "CoreML provider does not support images".to_string(),
+            ));
+        }
+
         let (_prompt_text, mut input_ids) = self.format_chat_prompt(&request.messages)?;
 
         let max_tokens = 512;
@@ -546,7 +467,7 @@ impl Provider for CoreMLProvider {
         // Note: state is stored on self and reused every call; reset_state can be used between conversations.
         // Before the loop: run a single forward pass on the full prompt to fill KV cache.
         // First forward pass: full prompt.
-        let mut logits = vec![0.0f32; self._vocab_size];
+        let mut logits = vec![0.0f32; self.vocab_size];
         self.predict_stateful(&input_ids, &mut logits)?;
 
         for step in 0..max_tokens {
@@ -556,7 +477,7 @@ impl Provider for CoreMLProvider {
                 argmax(&logits)
             };
 
-            if next_token_id == 0 || next_token_id >= self._vocab_size as u32 {
+            if next_token_id == 0 || next_token_id >= self.vocab_size as u32 {
                 callback(ChatResponse {
                     model: request.model.clone(),
                     content: String::new(),
@@ -568,13 +489,10 @@ impl Provider for CoreMLProvider {
 
             input_ids.push(next_token_id);
 
-            let token_str = if let Some(ref tokenizer) = self._tokenizer {
-                tokenizer
-                    .decode(&[next_token_id], true)
-                    .map_err(|e| ProviderError::Other(format!("Detokenization failed: {}", e)))?
-            } else {
-                simple_decode(&[next_token_id])
-            };
+            let token_str = self
+                .tokenizer
+                .decode(&[next_token_id], true)
+                .map_err(|e| ProviderError::Other(format!("Detokenization failed: {}", e)))?;
 
             callback(ChatResponse {
                 model: request.model.clone(),
@@ -594,9 +512,59 @@ impl Provider for CoreMLProvider {
         Ok(())
     }
 
+    fn accelerator(&self) -> AcceleratorType {
+        AcceleratorType::NeuralEngine
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let models_dir = Path::new(&self.model_path)
+            .parent()
+            .ok_or_else(|| ProviderError::Other("Invalid model path".to_string()))?;
+        Ok(compiled_models(models_dir))
+    }
+
     async fn embed(&self, _text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
-        Err(ProviderError::Other(
+        Err(ProviderError::Unsupported(
             "CoreML provider does not support embed interface. Use predict() directly.".to_string(),
         ))
     }
 }
+
+/// Paths of the CoreML models (`.mlpackage` or `.mlmodelc`) in `dir`, sorted.
+fn compiled_models(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut models: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "mlpackage" || ext == "mlmodelc")
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    models.sort();
+    models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compiled_models() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("llama.mlpackage")).unwrap();
+        std::fs::create_dir(dir.path().join("qwen.mlmodelc")).unwrap();
+        std::fs::write(dir.path().join("tokenizer.json"), b"{}").unwrap();
+
+        let expected: Vec<String> = ["llama.mlpackage", "qwen.mlmodelc"]
+            .iter()
+            .map(|name| dir.path().join(name).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(compiled_models(dir.path()), expected);
+    }
+}
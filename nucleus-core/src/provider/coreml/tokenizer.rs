@@ -0,0 +1,148 @@
+//! Locating the tokenizer of a CoreML model, downloading it if needed.
+
+use crate::provider::hub;
+use crate::provider::{ProviderError, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokenizers::Tokenizer;
+use tracing::info;
+
+const TOKENIZER_FILE: &str = "tokenizer.json";
+
+/// Tokenizers already loaded, by the file they were loaded from, so that
+/// providers for the same model share one.
+fn loaded() -> &'static Mutex<HashMap<PathBuf, Arc<Tokenizer>>> {
+    static LOADED: OnceLock<Mutex<HashMap<PathBuf, Arc<Tokenizer>>>> = OnceLock::new();
+    LOADED.get_or_init(Default::default)
+}
+
+/// Loads `tokenizer.json` from `model_dir`, or returns the one already loaded
+/// from there.
+///
+/// CoreML conversions often ship without one, so when it is missing and
+/// `repo` names the HuggingFace repo of the original model, the repo's
+/// tokenizer is downloaded from `endpoint` (see [`hub::endpoint`]) and cached
+/// in `model_dir` for next time.
+pub(super) async fn load_tokenizer(
+    model_dir: &Path,
+    repo: Option<&str>,
+    endpoint: &str,
+) -> Result<Arc<Tokenizer>> {
+    let path = model_dir.join(TOKENIZER_FILE);
+    if let Some(tokenizer) = loaded().lock().unwrap().get(&path) {
+        return Ok(tokenizer.clone());
+    }
+
+    if !path.exists() {
+        let repo = repo.ok_or_else(|| {
+            ProviderError::ModelNotFound(format!(
+                "No {} found at {}; add one next to the model or set \
+                 llm.coreml_tokenizer_repo to the HuggingFace repo to download it from",
+                TOKENIZER_FILE,
+                path.display()
+            ))
+        })?;
+        download_tokenizer(repo, endpoint, &path).await?;
+    }
+
+    let tokenizer = Tokenizer::from_file(&path)
+        .map_err(|e| ProviderError::Other(format!("Tokenizer load failed: {}", e)))?;
+    info!(
+        "Tokenizer loaded: {} tokens",
+        tokenizer.get_vocab_size(false)
+    );
+    Ok(loaded()
+        .lock()
+        .unwrap()
+        .entry(path)
+        .or_insert_with(|| Arc::new(tokenizer))
+        .clone())
+}
+
+async fn download_tokenizer(repo: &str, endpoint: &str, path: &Path) -> Result<()> {
+    let url = format!(
+        "{}/{}/resolve/main/{}",
+        endpoint.trim_end_matches('/'),
+        repo,
+        TOKENIZER_FILE
+    );
+    info!("Downloading tokenizer from {}", url);
+
+    let response = hub::get(&url)?.send().await?;
+    if !response.status().is_success() {
+        return Err(ProviderError::ModelNotFound(format!(
+            "Failed to download the tokenizer of {} ({}): {}",
+            repo,
+            url,
+            response.status()
+        )));
+    }
+    let bytes = response.bytes().await?;
+
+    // Written aside and renamed, so an interrupted download isn't cached
+    let partial = path.with_extension("json.part");
+    let cache_error = |e: std::io::Error| {
+        ProviderError::Other(format!(
+            "Failed to cache tokenizer at {}: {}",
+            path.display(),
+            e
+        ))
+    };
+    tokio::fs::write(&partial, &bytes)
+        .await
+        .map_err(cache_error)?;
+    tokio::fs::rename(&partial, path).await.map_err(cache_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve_once;
+    use tempfile::tempdir;
+
+    const TOKENIZER_JSON: &str = r#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": null,
+        "pre_tokenizer": { "type": "Whitespace" },
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "WordLevel",
+            "vocab": { "[UNK]": 0, "hello": 1, "world": 2 },
+            "unk_token": "[UNK]"
+        }
+    }"#;
+
+    #[tokio::test]
+    async fn test_missing_tokenizer_is_downloaded_and_cached() {
+        let dir = tempdir().unwrap();
+
+        let error = load_tokenizer(dir.path(), None, hub::DEFAULT_HF_ENDPOINT)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("llm.coreml_tokenizer_repo"));
+
+        let (endpoint, server) = serve_once(TOKENIZER_JSON).await;
+        let tokenizer = load_tokenizer(dir.path(), Some("org/model"), &endpoint)
+            .await
+            .unwrap();
+        assert_eq!(
+            server.await.unwrap(),
+            "GET /org/model/resolve/main/tokenizer.json HTTP/1.1"
+        );
+        let encoding = tokenizer.encode("hello world", false).unwrap();
+        assert_eq!(encoding.get_ids(), [1, 2]);
+
+        // Cached, so the now unreachable endpoint isn't needed again, and
+        // loaded once
+        assert!(dir.path().join(TOKENIZER_FILE).exists());
+        let cached = load_tokenizer(dir.path(), Some("org/model"), &endpoint)
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&cached, &tokenizer));
+    }
+}
@@ -1,27 +1,136 @@
 //! Provider factory for creating LLM providers based on configuration.
+//!
+//! Besides the built-in providers, downstream crates can make their own
+//! providers available by name with [`register_provider`]. Registration must
+//! happen before the provider is created (e.g. before `Server::new`).
+//!
+//! ```no_run
+//! use nucleus_core::provider::{register_provider, OllamaProvider, Provider};
+//! use std::sync::Arc;
+//!
+//! register_provider("my-cloud", |config, _registry| async move {
+//!     // Construct your own provider here
+//!     Ok(Arc::new(OllamaProvider::new(&config)) as Arc<dyn Provider>)
+//! });
+//! ```
 
 use super::types::*;
 #[cfg(any(target_os = "macos", feature = "coreml"))]
 use super::CoreMLProvider;
-use super::{MistralRsProvider, OllamaProvider};
+use super::{ConcurrencyLimitProvider, MistralRsProvider, ModelCacheProvider, OllamaProvider};
+use crate::models::scan_models_dir;
 use crate::Config;
 use nucleus_plugin::PluginRegistry;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
 use tracing::info;
 
+/// Names of the providers built into nucleus.
+pub const BUILTIN_PROVIDERS: [&str; 3] = ["ollama", "mistralrs", "coreml"];
+
+/// Extensions of CoreML models, which only the CoreML provider loads.
+const COREML_EXTENSIONS: [&str; 2] = ["mlpackage", "mlmodelc"];
+
+/// Future returned by a [`ProviderFactory`].
+pub type ProviderFuture = Pin<Box<dyn Future<Output = Result<Arc<dyn Provider>>> + Send>>;
+
+/// Factory closure creating a provider from configuration.
+pub type ProviderFactory = Arc<dyn Fn(Config, Arc<PluginRegistry>) -> ProviderFuture + Send + Sync>;
+
+fn factories() -> &'static RwLock<HashMap<String, ProviderFactory>> {
+    static FACTORIES: OnceLock<RwLock<HashMap<String, ProviderFactory>>> = OnceLock::new();
+    FACTORIES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Register a factory for a custom provider, selected when `llm.provider` matches `name`.
+///
+/// Names are case-insensitive. Registering a name again replaces the previous
+/// factory. Returns false if `name` is one of the [`BUILTIN_PROVIDERS`], which
+/// cannot be replaced.
+pub fn register_provider<F, Fut>(name: impl Into<String>, factory: F) -> bool
+where
+    F: Fn(Config, Arc<PluginRegistry>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Arc<dyn Provider>>> + Send + 'static,
+{
+    let name = name.into().to_lowercase();
+    if BUILTIN_PROVIDERS.contains(&name.as_str()) {
+        return false;
+    }
+
+    let factory: ProviderFactory =
+        Arc::new(move |config, registry| Box::pin(factory(config, registry)));
+    factories()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name, factory);
+    true
+}
+
+/// Names of all available providers, built-in ones first.
+pub fn registered_providers() -> Vec<String> {
+    let mut custom: Vec<String> = factories()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .keys()
+        .cloned()
+        .collect();
+    custom.sort();
+
+    BUILTIN_PROVIDERS
+        .iter()
+        .map(|name| name.to_string())
+        .chain(custom)
+        .collect()
+}
+
+fn registered_factory(name: &str) -> Option<ProviderFactory> {
+    factories()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(name)
+        .cloned()
+}
+
 /// Creates a provider instance based on configuration.
 ///
 /// Supported providers:
 /// - `"ollama"` - Ollama API provider
 /// - `"mistralrs"` - mistral.rs in-process provider
 /// - `"coreml"` - CoreML inference (macOS only, requires `coreml` feature)
+/// - any name registered with [`register_provider`]
+///
+/// With `llm.max_concurrency` set, the provider is wrapped in a
+/// [`ConcurrencyLimitProvider`].
+///
+/// # Errors
+///
+/// Unless `llm.skip_model_check` is set, returns
+/// [`ProviderError::IncompatibleModel`] if `llm.model` is in a format a
+/// built-in provider can't load, such as a GGUF file for CoreML.
 pub async fn create_provider(
     config: &Config,
     registry: Arc<PluginRegistry>,
+) -> Result<Arc<dyn Provider>> {
+    let provider = create_unlimited_provider(config, registry).await?;
+    Ok(match config.llm.max_concurrency {
+        0 => provider,
+        limit => Arc::new(ConcurrencyLimitProvider::new(provider, limit)),
+    })
+}
+
+async fn create_unlimited_provider(
+    config: &Config,
+    registry: Arc<PluginRegistry>,
 ) -> Result<Arc<dyn Provider>> {
     let provider_type = config.llm.provider.to_lowercase();
 
     info!("Creating provider: {}", provider_type);
+    if !config.llm.skip_model_check {
+        check_model_format(&provider_type, &config.llm.model)?;
+    }
 
     match provider_type.as_str() {
         "ollama" => {
@@ -30,12 +139,32 @@ pub async fn create_provider(
         }
         "mistralrs" => {
             info!("Using mistral.rs provider with model: {}", config.llm.model);
+            if config.llm.model_cache_size > 0 {
+                return Ok(with_model_cache(
+                    config,
+                    registry,
+                    |config, registry| async move {
+                        let provider = MistralRsProvider::new(&config, registry).await?;
+                        Ok(Arc::new(provider) as Arc<dyn Provider>)
+                    },
+                ));
+            }
             let provider = MistralRsProvider::new(config, registry).await?;
             Ok(Arc::new(provider))
         }
         #[cfg(any(target_os = "macos", feature = "coreml"))]
         "coreml" => {
             info!("Using CoreML provider with model: {}", config.llm.model);
+            if config.llm.model_cache_size > 0 {
+                return Ok(with_model_cache(
+                    config,
+                    registry,
+                    |config, registry| async move {
+                        let provider = CoreMLProvider::new(&config, registry).await?;
+                        Ok(provider as Arc<dyn Provider>)
+                    },
+                ));
+            }
             let provider = CoreMLProvider::new(config, registry).await?;
             Ok(provider)
         }
@@ -43,9 +172,232 @@ pub async fn create_provider(
         "coreml" => Err(ProviderError::Other(
             "CoreML provider is only available on macOS".to_string(),
         )),
-        _ => Err(ProviderError::Other(format!(
-            "Unknown provider type: {}. Supported: ollama, mistralrs, coreml",
-            provider_type
-        ))),
+        name => match registered_factory(name) {
+            Some(factory) => {
+                info!("Using registered provider: {}", name);
+                factory(config.clone(), registry).await
+            }
+            None => Err(ProviderError::Other(format!(
+                "Unknown provider type: {}. Supported: {}",
+                provider_type,
+                registered_providers().join(", ")
+            ))),
+        },
+    }
+}
+
+/// Checks that `model` is in a format the built-in `provider` loads, so that
+/// a mismatch fails with an explanation rather than deep inside model loading.
+/// Custom providers aren't checked.
+fn check_model_format(provider: &str, model: &str) -> Result<()> {
+    let path = Path::new(model.trim_end_matches(['/', '\\']));
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let is_gguf = extension.as_deref() == Some("gguf");
+    let is_coreml = extension
+        .as_deref()
+        .is_some_and(|ext| COREML_EXTENSIONS.contains(&ext));
+
+    let mismatch = match provider {
+        "coreml" if is_gguf => {
+            "GGUF models are loaded by the mistralrs provider, while CoreML needs a \
+             .mlpackage or .mlmodelc model"
+        }
+        "coreml" if !is_coreml => "CoreML needs a .mlpackage or .mlmodelc model",
+        "mistralrs" | "ollama" if is_coreml => "CoreML models are loaded by the coreml provider",
+        "mistralrs" if is_ollama_tag(model) => {
+            "this looks like an Ollama model tag, served by the ollama provider, while \
+             mistral.rs needs a GGUF file, `Repo/Model-GGUF:file.gguf` or a HuggingFace model id"
+        }
+        "ollama" if is_gguf => {
+            "Ollama serves models by name (e.g. `llama3.2`), while GGUF files are loaded \
+             by the mistralrs provider"
+        }
+        _ => return Ok(()),
+    };
+
+    Err(ProviderError::IncompatibleModel(format!(
+        "'{}' can't be used with provider '{}': {}",
+        model, provider, mismatch
+    )))
+}
+
+/// Whether `model` is an Ollama `name:tag` such as `llama3.2:latest`, rather
+/// than a mistral.rs `Repo/Model-GGUF:file.gguf` or a local file.
+fn is_ollama_tag(model: &str) -> bool {
+    let Some((name, tag)) = model.split_once(':') else {
+        return false;
+    };
+    let is_path = |part: &str| part.contains(['/', '\\']);
+    !is_path(name) && !is_path(tag) && !tag.ends_with(".gguf") && !Path::new(model).exists()
+}
+
+/// Wraps a single-model provider in a [`ModelCacheProvider`] holding up to
+/// `llm.model_cache_size` models, each loaded by `load` with `llm.model` set to
+/// the requested model.
+///
+/// Only `llm.model`, `llm.allowed_models` and the models found in
+/// `server.models_dir` can be requested. Unless `llm.skip_model_check` is set,
+/// each is checked to be in a format the provider loads before loading it.
+fn with_model_cache<F, Fut>(
+    config: &Config,
+    registry: Arc<PluginRegistry>,
+    load: F,
+) -> Arc<dyn Provider>
+where
+    F: Fn(Config, Arc<PluginRegistry>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Arc<dyn Provider>>> + Send + 'static,
+{
+    let base = config.clone();
+    let allowed_models = config.llm.allowed_models.clone();
+    let models_dir = config.server.models_dir.clone();
+    let provider = ModelCacheProvider::new(
+        config.llm.model.clone(),
+        config.llm.model_cache_size,
+        move |model| {
+            let mut config = base.clone();
+            config.llm.model = model;
+            let checked = if config.llm.skip_model_check {
+                Ok(())
+            } else {
+                check_model_format(&config.llm.provider.to_lowercase(), &config.llm.model)
+            };
+            let loaded = load(config, Arc::clone(&registry));
+            async move {
+                checked?;
+                loaded.await
+            }
+        },
+    )
+    .with_allowed_models(move |model| {
+        allowed_models.iter().any(|allowed| allowed == model)
+            || scan_models_dir(&models_dir)
+                .iter()
+                .any(|local| local.model == model)
+    });
+    Arc::new(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+    use nucleus_plugin::Permission;
+
+    fn config_for(provider: &str) -> Config {
+        let mut config = Config::default();
+        config.llm.provider = provider.to_string();
+        config
+    }
+
+    fn plugins() -> Arc<PluginRegistry> {
+        Arc::new(PluginRegistry::new(Permission::NONE))
+    }
+
+    #[tokio::test]
+    async fn test_registered_provider_is_selected_by_name() {
+        assert!(register_provider(
+            "stub-cloud",
+            |config, _registry| async move {
+                Ok(Arc::new(MockProvider::new(config.llm.model)) as Arc<dyn Provider>)
+            }
+        ));
+
+        let mut config = config_for("Stub-Cloud");
+        config.llm.model = "stub-model".to_string();
+        let provider = create_provider(&config, plugins()).await.unwrap();
+
+        let request = ChatRequest::new("stub-model", vec![Message::user(None, "Hi")]);
+        let mut reply = String::new();
+        provider
+            .chat(
+                request,
+                Box::new(|response| reply.push_str(&response.content)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply, "stub-model");
+
+        assert!(registered_providers().contains(&"stub-cloud".to_string()));
+    }
+
+    #[test]
+    fn test_builtin_providers_cannot_be_replaced() {
+        let registered = register_provider("Ollama", |_config, _registry| async move {
+            Ok(Arc::new(MockProvider::new("")) as Arc<dyn Provider>)
+        });
+        assert!(!registered);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_provider_lists_available() {
+        let err = match create_provider(&config_for("does-not-exist"), plugins()).await {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("expected unknown provider error"),
+        };
+        assert!(err.contains("does-not-exist"));
+        assert!(err.contains("ollama, mistralrs, coreml"));
+    }
+
+    #[test]
+    fn test_model_format_mismatches_are_explained() {
+        let mismatch = |provider: &str, model: &str| match check_model_format(provider, model) {
+            Err(ProviderError::IncompatibleModel(message)) => message,
+            other => panic!(
+                "expected a mismatch for {} with {}: {:?}",
+                provider, model, other
+            ),
+        };
+
+        let message = mismatch("coreml", "models/qwen3-0.6b.gguf");
+        assert!(message.contains("'models/qwen3-0.6b.gguf' can't be used with provider 'coreml'"));
+        assert!(message.contains("GGUF models are loaded by the mistralrs provider"));
+        assert!(mismatch("coreml", "Qwen/Qwen3-0.6B").contains(".mlpackage or .mlmodelc"));
+        assert!(mismatch("mistralrs", "models/llama.mlpackage/").contains("coreml provider"));
+        assert!(mismatch("ollama", "models/llama.mlmodelc").contains("coreml provider"));
+        assert!(mismatch("mistralrs", "llama3.2:latest").contains("Ollama model tag"));
+        assert!(mismatch("ollama", "models/qwen3-0.6b.gguf").contains("mistralrs provider"));
+
+        let defaults = Config::default();
+        assert!(check_model_format(&defaults.llm.provider, &defaults.llm.model).is_ok());
+        assert!(check_model_format("mistralrs", "Qwen/Qwen3-0.6B").is_ok());
+        assert!(check_model_format("ollama", "llama3.2:latest").is_ok());
+        assert!(check_model_format("coreml", "models/llama.mlpackage").is_ok());
+        assert!(check_model_format("my-cloud", "anything.gguf").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_model_cache_checks_the_format_of_requested_models() {
+        let mut config = config_for("mistralrs");
+        config.llm.model = "Qwen/Qwen3-0.6B".to_string();
+        config.llm.allowed_models = vec!["models/llama.mlpackage".to_string()];
+        let load = |config: Config, _registry: Arc<PluginRegistry>| async move {
+            Ok(Arc::new(MockProvider::new(config.llm.model)) as Arc<dyn Provider>)
+        };
+        let request =
+            || ChatRequest::new("models/llama.mlpackage", vec![Message::user(None, "Hi")]);
+
+        let provider = with_model_cache(&config, plugins(), load);
+        let result = provider.chat_once(request()).await;
+        assert!(matches!(result, Err(ProviderError::IncompatibleModel(_))));
+
+        config.llm.skip_model_check = true;
+        let provider = with_model_cache(&config, plugins(), load);
+        assert!(provider.chat_once(request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_provider_rejects_incompatible_model() {
+        let mut config = config_for("Ollama");
+        config.llm.model = "models/qwen3-0.6b.gguf".to_string();
+        let err = match create_provider(&config, plugins()).await {
+            Err(e) => e,
+            Ok(_) => panic!("expected incompatible model error"),
+        };
+        assert!(matches!(err, ProviderError::IncompatibleModel(_)));
+
+        config.llm.skip_model_check = true;
+        assert!(create_provider(&config, plugins()).await.is_ok());
     }
 }
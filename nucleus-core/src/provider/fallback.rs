@@ -0,0 +1,310 @@
+//! Provider that falls back through an ordered list of providers.
+
+use super::types::*;
+use crate::models::EmbeddingModel;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Wraps several providers, trying each in order until one succeeds.
+///
+/// A typical setup is a local provider first with a remote one as backup:
+///
+/// ```no_run
+/// # use nucleus_core::provider::{FallbackProvider, MistralRsProvider, OllamaProvider, Provider};
+/// # use std::sync::Arc;
+/// # fn example(local: MistralRsProvider, remote: OllamaProvider) {
+/// let provider = FallbackProvider::new(vec![Arc::new(local), Arc::new(remote)]);
+/// # }
+/// ```
+///
+/// A chat only falls back if the failing provider had not streamed anything
+/// yet; once content has reached the callback, switching providers would
+/// produce a garbled response, so the error is returned instead. If every
+/// provider fails, the last error is returned.
+pub struct FallbackProvider {
+    providers: Vec<Arc<dyn Provider>>,
+}
+
+impl FallbackProvider {
+    pub fn new(providers: Vec<Arc<dyn Provider>>) -> Self {
+        Self { providers }
+    }
+
+    fn no_providers() -> ProviderError {
+        ProviderError::Other("FallbackProvider has no providers configured".to_string())
+    }
+}
+
+#[async_trait]
+impl Provider for FallbackProvider {
+    async fn chat<'a>(
+        &'a self,
+        request: ChatRequest,
+        mut callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        let mut last_error = None;
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            let mut streamed = false;
+            let result = provider
+                .chat(
+                    request.clone(),
+                    Box::new(|response| {
+                        streamed = true;
+                        callback(response);
+                    }),
+                )
+                .await;
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if streamed => return Err(e),
+                Err(e) => {
+                    warn!(provider = i, error = %e, "Chat failed, trying next provider");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(Self::no_providers))
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let mut last_error = None;
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            match provider.embed(text, model).await {
+                Ok(embedding) => return Ok(embedding),
+                Err(e) => {
+                    warn!(provider = i, error = %e, "Embedding failed, trying next provider");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(Self::no_providers))
+    }
+
+    async fn embed_batch(&self, texts: &[&str], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let mut last_error = None;
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            match provider.embed_batch(texts, model).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(e) => {
+                    warn!(provider = i, error = %e, "Batch embedding failed, trying next provider");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(Self::no_providers))
+    }
+
+    /// Lists the models of every provider that supports listing them, in
+    /// provider order without duplicates.
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let mut models: Vec<String> = Vec::new();
+        let mut last_error = None;
+        let mut listed = false;
+
+        for (i, provider) in self.providers.iter().enumerate() {
+            match provider.list_models().await {
+                Ok(provider_models) => {
+                    listed = true;
+                    for model in provider_models {
+                        if !models.contains(&model) {
+                            models.push(model);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!(provider = i, error = %e, "Listing models failed");
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if listed {
+            Ok(models)
+        } else {
+            Err(last_error.unwrap_or_else(Self::no_providers))
+        }
+    }
+
+    /// The accelerator of the first provider, which answers unless it fails.
+    fn accelerator(&self) -> AcceleratorType {
+        self.providers
+            .first()
+            .map_or(AcceleratorType::None, |provider| provider.accelerator())
+    }
+
+    /// Only true if every provider can switch, since any of them may end up
+    /// answering the request.
+    fn supports_model_switching(&self) -> bool {
+        !self.providers.is_empty()
+            && self
+                .providers
+                .iter()
+                .all(|provider| provider.supports_model_switching())
+    }
+
+    /// Warms up the primary provider only; backups load when first used.
+    async fn warmup(&self) -> Result<()> {
+        match self.providers.first() {
+            Some(provider) => provider.warmup().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        let mut result = Ok(());
+        for provider in &self.providers {
+            if let Err(e) = provider.shutdown().await {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+    use std::sync::atomic::Ordering;
+
+    /// Provider whose chat always fails, optionally after streaming a chunk.
+    struct FailingProvider {
+        partial: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl Provider for FailingProvider {
+        async fn chat<'a>(
+            &'a self,
+            request: ChatRequest,
+            mut callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+        ) -> Result<()> {
+            if let Some(partial) = self.partial {
+                callback(ChatResponse {
+                    model: request.model,
+                    content: partial.to_string(),
+                    done: false,
+                    message: Message::assistant(None, partial),
+                });
+            }
+            Err(ProviderError::Api("model crashed".to_string()))
+        }
+
+        async fn embed(&self, _text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
+            Err(ProviderError::Api("no embeddings".to_string()))
+        }
+    }
+
+    /// Provider that only knows how to list a fixed set of models.
+    struct ListingProvider(&'static [&'static str]);
+
+    #[async_trait]
+    impl Provider for ListingProvider {
+        async fn chat<'a>(
+            &'a self,
+            _request: ChatRequest,
+            _callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+        ) -> Result<()> {
+            Err(ProviderError::Unsupported("chat".to_string()))
+        }
+
+        async fn embed(&self, _text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
+            Err(ProviderError::Unsupported("embed".to_string()))
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(self.0.iter().map(|model| model.to_string()).collect())
+        }
+    }
+
+    async fn chat(provider: &FallbackProvider) -> Result<String> {
+        let request = ChatRequest::new("model", vec![Message::user(None, "Hi")]);
+        let mut reply = String::new();
+        provider
+            .chat(
+                request,
+                Box::new(|response| reply.push_str(&response.content)),
+            )
+            .await?;
+        Ok(reply)
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_secondary() {
+        let secondary = Arc::new(MockProvider::new("from secondary"));
+        let provider = FallbackProvider::new(vec![
+            Arc::new(FailingProvider { partial: None }),
+            secondary.clone(),
+        ]);
+
+        assert_eq!(chat(&provider).await.unwrap(), "from secondary");
+        assert_eq!(secondary.requests.lock().unwrap().len(), 1);
+
+        let embedding = provider
+            .embed("text", &EmbeddingModel::default())
+            .await
+            .unwrap();
+        assert!(!embedding.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_surfaces_last_error_when_all_fail() {
+        let provider = FallbackProvider::new(vec![
+            Arc::new(FailingProvider { partial: None }),
+            Arc::new(FailingProvider { partial: None }),
+        ]);
+
+        let err = chat(&provider).await.unwrap_err();
+        assert_eq!(err.to_string(), "API error: model crashed");
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_after_partial_stream() {
+        let secondary = Arc::new(MockProvider::new("from secondary"));
+        let provider = FallbackProvider::new(vec![
+            Arc::new(FailingProvider {
+                partial: Some("half an ans"),
+            }),
+            secondary.clone(),
+        ]);
+
+        assert!(chat(&provider).await.is_err());
+        assert!(secondary.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_models_merges_providers() {
+        let provider = FallbackProvider::new(vec![
+            Arc::new(ListingProvider(&["qwen3:0.6b", "llama3.2"])),
+            Arc::new(FailingProvider { partial: None }),
+            Arc::new(ListingProvider(&["llama3.2", "gemma3"])),
+        ]);
+
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            ["qwen3:0.6b", "llama3.2", "gemma3"]
+        );
+
+        let unsupported = FallbackProvider::new(vec![Arc::new(FailingProvider { partial: None })]);
+        assert!(unsupported.list_models().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_warmup_only_warms_the_primary() {
+        let primary = Arc::new(MockProvider::new("primary"));
+        let secondary = Arc::new(MockProvider::new("secondary"));
+        let provider = FallbackProvider::new(vec![primary.clone(), secondary.clone()]);
+
+        provider.warmup().await.unwrap();
+        assert_eq!(primary.warmup_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(secondary.warmup_calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,192 @@
+//! Talking to the HuggingFace Hub and its local cache.
+
+use super::background::DownloadProgress;
+use super::types::Result;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// HuggingFace Hub used when `HF_ENDPOINT` isn't set.
+pub(crate) const DEFAULT_HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// How long a request to the Hub may take before it is abandoned.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often the cache is checked while a model downloads.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The HuggingFace Hub to use: `HF_ENDPOINT`, as for the HuggingFace tools, or
+/// the public Hub.
+pub(crate) fn endpoint() -> String {
+    std::env::var("HF_ENDPOINT").unwrap_or_else(|_| DEFAULT_HF_ENDPOINT.to_string())
+}
+
+/// Starts a GET request to `url` on the Hub, authenticated with `HF_TOKEN`
+/// when it is set so that gated and private repos can be read.
+pub(crate) fn get(url: &str) -> Result<reqwest::RequestBuilder> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let request = client.get(url);
+    Ok(match std::env::var("HF_TOKEN") {
+        Ok(token) if !token.is_empty() => request.bearer_auth(token),
+        _ => request,
+    })
+}
+
+/// The HuggingFace hub cache directory, following the same environment
+/// variables as the `hf-hub` crate.
+pub(crate) fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("HF_HUB_CACHE") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(home) = std::env::var_os("HF_HOME") {
+        return Some(PathBuf::from(home).join("hub"));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache/huggingface/hub"))
+}
+
+/// Reports to `progress` how much of the files of `repo` selected by `wanted`
+/// has reached the hub cache, while something else downloads them.
+///
+/// The task ends, clearing `progress`, once the files are all there, straight
+/// away if they already were. It does nothing if the sizes of the files can't
+/// be looked up. Returns `None` if there is no cache directory to watch.
+pub(crate) fn report_download<F>(
+    repo: &str,
+    wanted: F,
+    progress: DownloadProgress,
+) -> Option<JoinHandle<()>>
+where
+    F: Fn(&str) -> bool + Send + 'static,
+{
+    let cache_dir = cache_dir()?;
+    Some(tokio::spawn(track_download(
+        endpoint(),
+        cache_dir,
+        repo.to_string(),
+        wanted,
+        progress,
+    )))
+}
+
+#[derive(Deserialize)]
+struct TreeEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+    #[serde(default)]
+    size: u64,
+}
+
+async fn track_download<F>(
+    endpoint: String,
+    cache_dir: PathBuf,
+    repo: String,
+    wanted: F,
+    progress: DownloadProgress,
+) where
+    F: Fn(&str) -> bool,
+{
+    let total = match repo_size(&endpoint, &repo, &wanted).await {
+        Ok(total) if total > 0 => total,
+        Ok(_) => return,
+        Err(e) => {
+            debug!(repo, error = %e, "Can't look up the download size");
+            return;
+        }
+    };
+    let repo_dir = cache_dir.join(format!("models--{}", repo.replace('/', "--")));
+
+    loop {
+        let downloaded = cached_bytes(&repo_dir).await;
+        if downloaded >= total {
+            progress.clear();
+            return;
+        }
+        progress.set(downloaded as f64 / total as f64);
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Total size of the files of `repo` selected by `wanted`.
+async fn repo_size(endpoint: &str, repo: &str, wanted: impl Fn(&str) -> bool) -> Result<u64> {
+    let url = format!(
+        "{}/api/models/{}/tree/main?recursive=true",
+        endpoint.trim_end_matches('/'),
+        repo
+    );
+    let entries: Vec<TreeEntry> = get(&url)?.send().await?.error_for_status()?.json().await?;
+    Ok(entries
+        .iter()
+        .filter(|entry| entry.kind == "file" && wanted(&entry.path))
+        .map(|entry| entry.size)
+        .sum())
+}
+
+/// Bytes stored in a repo's cache directory, including partial downloads.
+/// Snapshot symlinks aren't followed, so each file counts once.
+async fn cached_bytes(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(metadata) = tokio::fs::symlink_metadata(entry.path()).await else {
+                continue;
+            };
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() {
+                total += metadata.len();
+            }
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve_once;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_download_progress_follows_the_cache() {
+        let (endpoint, _) = serve_once(
+            r#"[
+                {"type": "file", "path": "model-q4.gguf", "size": 100},
+                {"type": "file", "path": "model-q8.gguf", "size": 200},
+                {"type": "directory", "path": "docs", "size": 0}
+            ]"#,
+        )
+        .await;
+        let cache = tempdir().unwrap();
+        let blobs = cache.path().join("models--org--model-GGUF/blobs");
+        std::fs::create_dir_all(&blobs).unwrap();
+        std::fs::write(blobs.join("abc.incomplete"), [0; 40]).unwrap();
+
+        let progress = DownloadProgress::default();
+        let task = tokio::spawn(track_download(
+            endpoint,
+            cache.path().to_path_buf(),
+            "org/model-GGUF".to_string(),
+            |path| path == "model-q4.gguf",
+            progress.clone(),
+        ));
+
+        while progress.percent().is_none() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(progress.percent(), Some(40));
+
+        std::fs::remove_file(blobs.join("abc.incomplete")).unwrap();
+        std::fs::write(blobs.join("abc"), [0; 100]).unwrap();
+        task.await.unwrap();
+        assert_eq!(progress.percent(), None, "done downloading, now loading");
+    }
+}
@@ -0,0 +1,141 @@
+//! Provider limiting how many operations run at once.
+
+use super::types::*;
+use crate::models::EmbeddingModel;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Wraps a provider so that at most `limit` chats and embeddings run at once,
+/// however many callers share it. Further calls wait for a running one to
+/// finish.
+///
+/// Useful for a single local model, where many concurrent embeddings during
+/// bulk indexing would contend for the GPU or run out of memory.
+///
+/// ```no_run
+/// # use nucleus_core::provider::{ConcurrencyLimitProvider, OllamaProvider};
+/// # use nucleus_core::Config;
+/// # use std::sync::Arc;
+/// # fn example(config: Config) {
+/// let provider = ConcurrencyLimitProvider::new(Arc::new(OllamaProvider::new(&config)), 2);
+/// # }
+/// ```
+pub struct ConcurrencyLimitProvider {
+    inner: Arc<dyn Provider>,
+    permits: Semaphore,
+}
+
+impl ConcurrencyLimitProvider {
+    /// Limits `inner` to `limit` concurrent operations (at least one).
+    pub fn new(inner: Arc<dyn Provider>, limit: usize) -> Self {
+        Self {
+            inner,
+            permits: Semaphore::new(limit.max(1)),
+        }
+    }
+
+    async fn permit(&self) -> Result<SemaphorePermit<'_>> {
+        self.permits
+            .acquire()
+            .await
+            .map_err(|e| ProviderError::Other(e.to_string()))
+    }
+}
+
+#[async_trait]
+impl Provider for ConcurrencyLimitProvider {
+    async fn chat<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        let _permit = self.permit().await?;
+        self.inner.chat(request, callback).await
+    }
+
+    async fn chat_n<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(usize, ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        let _permit = self.permit().await?;
+        self.inner.chat_n(request, callback).await
+    }
+
+    async fn warmup(&self) -> Result<()> {
+        self.inner.warmup().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.inner.list_models().await
+    }
+
+    fn accelerator(&self) -> AcceleratorType {
+        self.inner.accelerator()
+    }
+
+    fn supports_model_switching(&self) -> bool {
+        self.inner.supports_model_switching()
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.inner.shutdown().await
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let _permit = self.permit().await?;
+        self.inner.embed(text, model).await
+    }
+
+    async fn embed_batch(&self, texts: &[&str], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        let _permit = self.permit().await?;
+        self.inner.embed_batch(texts, model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Provider whose embeddings take a while, recording how many overlap.
+    #[derive(Default)]
+    struct PeakTracker {
+        running: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Provider for PeakTracker {
+        async fn chat<'a>(
+            &'a self,
+            _request: ChatRequest,
+            _callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn embed(&self, _text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![0.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embeds_never_exceed_the_limit() {
+        let tracker = Arc::new(PeakTracker::default());
+        let provider = ConcurrencyLimitProvider::new(tracker.clone(), 2);
+        let model = EmbeddingModel::default();
+
+        let results = join_all((0..8).map(|_| provider.embed("text", &model))).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(tracker.peak.load(Ordering::SeqCst), 2);
+    }
+}
@@ -6,6 +6,8 @@
 use crate::models::EmbeddingModel;
 use crate::Config;
 
+use super::background::DownloadProgress;
+use super::hub;
 use super::types::*;
 use async_trait::async_trait;
 use mistralrs::{
@@ -16,9 +18,10 @@ use nucleus_plugin::PluginRegistry;
 use tracing::{debug, info, warn};
 
 use futures::future::join_all;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
 
 /// mistral.rs in-process provider.
 ///
@@ -82,6 +85,35 @@ impl MistralRsProvider {
         })
     }
 
+    /// Reports to `progress` how far the download of `model` from HuggingFace
+    /// has got, until the returned task ends or is aborted.
+    ///
+    /// Returns `None` for local files, which aren't downloaded.
+    pub fn report_download_progress(
+        model: &str,
+        progress: DownloadProgress,
+    ) -> Option<JoinHandle<()>> {
+        if Path::new(&expand_home(model)).is_file() {
+            return None;
+        }
+
+        match model.split_once(':') {
+            Some((repo, file)) => {
+                let file = file.to_string();
+                hub::report_download(repo, move |path| path == file, progress)
+            }
+            None => hub::report_download(
+                model,
+                |path| {
+                    path.ends_with(".safetensors")
+                        || path.ends_with(".json")
+                        || path.ends_with("tokenizer.model")
+                },
+                progress,
+            ),
+        }
+    }
+
     async fn build_model(config: Config, _registry: Arc<PluginRegistry>) -> Result<Model> {
         let model_name = config.llm.model;
 
@@ -167,28 +199,21 @@ impl Provider for MistralRsProvider {
         request: ChatRequest,
         mut callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
     ) -> Result<()> {
-        // Build messages using TextMessages builder
-        let mut messages = TextMessages::new();
-
-        for msg in &request.messages {
-            let role = match msg.role.as_str() {
-                "system" => TextMessageRole::System,
-                "user" => TextMessageRole::User,
-                "assistant" => TextMessageRole::Assistant,
-                "tool" => TextMessageRole::Tool,
-                _ => TextMessageRole::User,
-            };
-
-            messages = messages.add_message(role, &msg.content);
+        if request.has_images() {
+            return Err(ProviderError::Unsupported(
+                "mistral.rs provider does not support images yet".to_string(),
+            ));
+        }
+        if let Some(seed) = request.seed {
+            warn!(
+                seed,
+                "mistral.rs provider does not support sampling seeds, ignoring it"
+            );
         }
 
-        // Convert to RequestBuilder
-        let mut builder = RequestBuilder::from(messages);
+        let mut builder = add_messages(RequestBuilder::new(), &request.messages);
 
         if let Some(structured_output) = &request.structured_output {
-            // Rebuild messages with schema instructions
-            let mut messages_with_schema = TextMessages::new();
-
             // Add system message with JSON schema instructions
             let schema_instructions = format!(
                 "You MUST respond with valid JSON matching this schema:\n{}\n Do NOT wrap it in markdown code fences. Stricly return only the JSON.",
@@ -211,23 +236,11 @@ impl Provider for MistralRsProvider {
             // Add schema instructions
             system_message.push_str(&schema_instructions);
 
-            messages_with_schema =
-                messages_with_schema.add_message(TextMessageRole::System, &system_message);
-
-            // Add original messages
-            for msg in &request.messages {
-                let role = match msg.role.as_str() {
-                    "system" => TextMessageRole::System,
-                    "user" => TextMessageRole::User,
-                    "assistant" => TextMessageRole::Assistant,
-                    "tool" => TextMessageRole::Tool,
-                    _ => TextMessageRole::User,
-                };
-                messages_with_schema = messages_with_schema.add_message(role, &msg.content);
-            }
-
-            // Recreate builder with schema-enhanced messages
-            builder = RequestBuilder::from(messages_with_schema);
+            // Rebuild messages with the schema instructions first
+            builder = add_messages(
+                RequestBuilder::new().add_message(TextMessageRole::System, &system_message),
+                &request.messages,
+            );
         }
 
         // Convert plugins to mistral.rs tool definitions
@@ -300,7 +313,7 @@ impl Provider for MistralRsProvider {
                         timeout_secs = timeout_duration.as_secs(),
                         "Stream creation timed out"
                     );
-                    ProviderError::Other(format!(
+                    ProviderError::Timeout(format!(
                         "Stream creation timed out after {} seconds.",
                         timeout_duration.as_secs()
                     ))
@@ -322,7 +335,7 @@ impl Provider for MistralRsProvider {
                         "Stream chunk timed out after {} seconds",
                         chunk_timeout.as_secs()
                     );
-                    ProviderError::Other(format!(
+                    ProviderError::Timeout(format!(
                         "No response chunk received after {} seconds. Generation stalled.",
                         chunk_timeout.as_secs()
                     ))
@@ -351,6 +364,7 @@ impl Provider for MistralRsProvider {
                                     context: None,
                                     images: None,
                                     tool_calls: None,
+                                    tool_call_id: None,
                                 },
                             });
                         }
@@ -360,6 +374,7 @@ impl Provider for MistralRsProvider {
                             final_tool_calls = Some(
                                 tcs.iter()
                                     .map(|tc| super::types::ToolCall {
+                                        id: Some(tc.id.clone()),
                                         function: super::types::ToolCallFunction {
                                             name: tc.function.name.clone(),
                                             arguments: serde_json::from_str(&tc.function.arguments)
@@ -389,12 +404,55 @@ impl Provider for MistralRsProvider {
                 context: None,
                 images: None,
                 tool_calls: final_tool_calls,
+                tool_call_id: None,
             },
         });
 
         Ok(())
     }
 
+    async fn warmup(&self) -> Result<()> {
+        info!("Warming up model: {}", self.model_name);
+
+        // Generating a single token forces the lazy initialization that would
+        // otherwise slow down the first real request.
+        let messages = TextMessages::new().add_message(TextMessageRole::User, "Hi");
+        let request = RequestBuilder::from(messages).set_sampler_max_len(1);
+        self.model
+            .send_chat_request(request)
+            .await
+            .map_err(|e| ProviderError::Other(format!("Warmup generation failed: {:?}", e)))?;
+
+        if self.config.rag.is_some() {
+            self.embed("warmup", &EmbeddingModel::default()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn accelerator(&self) -> AcceleratorType {
+        compiled_accelerator()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let mut models = hub::cache_dir()
+            .map(|dir| hf_cache_models(&dir))
+            .unwrap_or_default();
+
+        // GGUF files next to a configured local model can be used the same way
+        let configured = expand_home(&self.model_name);
+        let configured = Path::new(&configured);
+        if configured.is_file() {
+            if let Some(dir) = configured.parent() {
+                models.extend(local_gguf_models(dir));
+            }
+        }
+
+        models.sort();
+        models.dedup();
+        Ok(models)
+    }
+
     async fn embed(&self, text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {     
         let rag = &self.config.rag.clone().unwrap();
        
@@ -440,3 +498,174 @@ impl Provider for MistralRsProvider {
         Ok(embedding)
     }
 }
+
+/// Maps a message role to mistral.rs; unknown roles are sent as user messages.
+fn text_message_role(role: &str) -> TextMessageRole {
+    match role {
+        "system" => TextMessageRole::System,
+        "user" => TextMessageRole::User,
+        "assistant" => TextMessageRole::Assistant,
+        "tool" => TextMessageRole::Tool,
+        _ => TextMessageRole::User,
+    }
+}
+
+/// Adds `messages` to `builder`, linking tool results to the call they answer.
+fn add_messages(mut builder: RequestBuilder, messages: &[Message]) -> RequestBuilder {
+    for msg in messages {
+        builder = match (text_message_role(&msg.role), &msg.tool_call_id) {
+            (TextMessageRole::Tool, Some(id)) => builder.add_tool_message(&msg.content, id),
+            (role, _) => builder.add_message(role, &msg.content),
+        };
+    }
+    builder
+}
+
+/// The accelerator mistral.rs was compiled for, through the `metal` or `cuda`
+/// feature; without either it runs on the CPU.
+fn compiled_accelerator() -> AcceleratorType {
+    if cfg!(feature = "metal") {
+        AcceleratorType::Metal
+    } else if cfg!(feature = "cuda") {
+        AcceleratorType::Cuda
+    } else {
+        AcceleratorType::None
+    }
+}
+
+/// Model ids of the repositories downloaded to a HuggingFace hub cache.
+///
+/// Repositories holding GGUF files are listed once per file, as
+/// `Repo/Model-GGUF:file.gguf`; other repositories by their id alone.
+fn hf_cache_models(cache_dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return Vec::new();
+    };
+
+    let mut models = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(repo) = name.strip_prefix("models--") else {
+            continue;
+        };
+        let repo = repo.replace("--", "/");
+
+        let snapshots = std::fs::read_dir(entry.path().join("snapshots"));
+        let mut gguf_files: Vec<String> = snapshots
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flat_map(|snapshot| std::fs::read_dir(snapshot.path()).into_iter().flatten())
+            .flatten()
+            .map(|file| file.file_name().to_string_lossy().to_string())
+            .filter(|file| file.ends_with(".gguf"))
+            .collect();
+        gguf_files.sort();
+        gguf_files.dedup();
+
+        if gguf_files.is_empty() {
+            models.push(repo);
+        } else {
+            models.extend(gguf_files.iter().map(|file| format!("{}:{}", repo, file)));
+        }
+    }
+
+    models
+}
+
+/// `path` with a leading `~` replaced by the home directory, if `HOME` is set.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix('~'), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Paths of the `.gguf` files in `dir`.
+fn local_gguf_models(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "gguf"))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"").unwrap();
+    }
+
+    #[cfg(not(any(feature = "metal", feature = "cuda")))]
+    #[test]
+    fn test_cpu_build_reports_no_accelerator() {
+        assert_eq!(compiled_accelerator(), AcceleratorType::None);
+    }
+
+    #[test]
+    fn test_tool_messages_map_to_tool_role() {
+        assert!(matches!(text_message_role("tool"), TextMessageRole::Tool));
+        assert!(matches!(
+            text_message_role("assistant"),
+            TextMessageRole::Assistant
+        ));
+        assert!(matches!(text_message_role("critic"), TextMessageRole::User));
+
+        let message = Message::tool(Some("call_0".to_string()), "42");
+        assert!(matches!(
+            text_message_role(&message.role),
+            TextMessageRole::Tool
+        ));
+    }
+
+    #[test]
+    fn test_hf_cache_models() {
+        let cache = tempdir().unwrap();
+        let gguf = cache
+            .path()
+            .join("models--Qwen--Qwen3-0.6B-GGUF/snapshots/abc123");
+        touch(&gguf.join("Qwen3-0.6B-Q4_K_M.gguf"));
+        touch(&gguf.join("Qwen3-0.6B-Q8_0.gguf"));
+        touch(&gguf.join("README.md"));
+        touch(
+            &cache
+                .path()
+                .join("models--google--gemma-3-1b-it/snapshots/def456/config.json"),
+        );
+        touch(&cache.path().join("datasets--squad/snapshots/1/data.json"));
+
+        let mut models = hf_cache_models(cache.path());
+        models.sort();
+
+        assert_eq!(
+            models,
+            [
+                "Qwen/Qwen3-0.6B-GGUF:Qwen3-0.6B-Q4_K_M.gguf",
+                "Qwen/Qwen3-0.6B-GGUF:Qwen3-0.6B-Q8_0.gguf",
+                "google/gemma-3-1b-it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_local_gguf_models() {
+        let dir = tempdir().unwrap();
+        touch(&dir.path().join("qwen3-0.6b.gguf"));
+        touch(&dir.path().join("notes.txt"));
+
+        let expected = dir.path().join("qwen3-0.6b.gguf");
+        assert_eq!(
+            local_gguf_models(dir.path()),
+            [expected.to_string_lossy().to_string()]
+        );
+    }
+}
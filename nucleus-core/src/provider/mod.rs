@@ -3,8 +3,13 @@
 //! This module defines a common interface for different LLM backends
 //! (Ollama, mistral.rs, etc.) to provide chat completions and embeddings.
 
+mod background;
 mod factory;
+mod fallback;
+mod hub;
+mod limit;
 pub mod mistralrs;
+mod model_cache;
 pub mod ollama;
 mod types;
 
@@ -13,13 +18,21 @@ pub mod coreml;
 
 // Re-export common types
 pub use types::{
-    ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, Message, Provider, ProviderError,
-    ProviderType, Result, StructuredOutput, Tool, ToolCall, ToolCallFunction, ToolFunction,
+    AcceleratorType, ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, Message, Provider,
+    ProviderError, ProviderType, Result, StructuredOutput, Tool, ToolCall, ToolCallFunction,
+    ToolFunction,
 };
 
 // Re-export provider implementations
-pub use factory::create_provider;
+pub use background::{BackgroundProvider, DownloadProgress};
+pub use factory::{
+    create_provider, register_provider, registered_providers, ProviderFactory, ProviderFuture,
+    BUILTIN_PROVIDERS,
+};
+pub use fallback::FallbackProvider;
+pub use limit::ConcurrencyLimitProvider;
 pub use mistralrs::MistralRsProvider;
+pub use model_cache::ModelCacheProvider;
 pub use ollama::OllamaProvider;
 
 #[cfg(any(target_os = "macos", feature = "coreml"))]
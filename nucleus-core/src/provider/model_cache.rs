@@ -0,0 +1,364 @@
+//! Provider keeping recently used models loaded.
+
+use super::factory::ProviderFuture;
+use super::types::*;
+use crate::models::EmbeddingModel;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Loads the provider for a model id.
+type ModelLoader = Box<dyn Fn(String) -> ProviderFuture + Send + Sync>;
+
+/// Decides whether a model id other than the default may be loaded.
+type ModelFilter = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Adds model switching to providers that load a single model, such as
+/// mistral.rs and CoreML.
+///
+/// Each request is served by a provider for [`ChatRequest::model`], loaded on
+/// first use. Up to `capacity` models stay resident, so switching back to a
+/// recently used one is instant; loading another first evicts the least
+/// recently used model, which is freed once requests still using it finish.
+/// Each model is loaded once even if several requests ask for it, while
+/// requests for models already loaded are served meanwhile. Embeddings and
+/// warmup use the default model.
+///
+/// Which other models requests may ask for is set with
+/// [`with_allowed_models`](Self::with_allowed_models); by default any.
+///
+/// ```no_run
+/// # use nucleus_core::provider::{MistralRsProvider, ModelCacheProvider, Provider};
+/// # use nucleus_core::Config;
+/// # use std::sync::Arc;
+/// # fn example(config: Config, registry: Arc<nucleus_plugin::PluginRegistry>) {
+/// let default_model = config.llm.model.clone();
+/// let provider = ModelCacheProvider::new(default_model, 2, move |model| {
+///     let mut config = config.clone();
+///     config.llm.model = model;
+///     let registry = Arc::clone(&registry);
+///     async move {
+///         let provider = MistralRsProvider::new(&config, registry).await?;
+///         Ok(Arc::new(provider) as Arc<dyn Provider>)
+///     }
+/// });
+/// # }
+/// ```
+pub struct ModelCacheProvider {
+    load: ModelLoader,
+    allowed: Option<ModelFilter>,
+    default_model: String,
+    capacity: usize,
+    /// Loaded models, ordered from least to most recently used
+    models: Mutex<Vec<(String, Arc<dyn Provider>)>>,
+    /// Held while a model loads, so each model is only loaded once; removed
+    /// once the load finishes
+    loading: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ModelCacheProvider {
+    /// Creates a cache of up to `capacity` models (at least one), loading each
+    /// with `load`. Requests without a model use `default_model`.
+    pub fn new<F, Fut>(default_model: impl Into<String>, capacity: usize, load: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Arc<dyn Provider>>> + Send + 'static,
+    {
+        Self {
+            load: Box::new(move |model| Box::pin(load(model))),
+            allowed: None,
+            default_model: default_model.into(),
+            capacity: capacity.max(1),
+            models: Mutex::new(Vec::new()),
+            loading: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Only load models other than the default for which `allowed` returns
+    /// true; requests for other models fail with
+    /// [`ProviderError::ModelNotFound`] without evicting anything.
+    ///
+    /// `allowed` runs on a blocking thread, so it may read the file system.
+    pub fn with_allowed_models<F>(mut self, allowed: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.allowed = Some(Arc::new(allowed));
+        self
+    }
+
+    /// Whether `model` may be loaded, see
+    /// [`with_allowed_models`](Self::with_allowed_models).
+    async fn is_allowed(&self, model: &str) -> bool {
+        match &self.allowed {
+            Some(allowed) if model != self.default_model => {
+                let allowed = Arc::clone(allowed);
+                let model = model.to_string();
+                tokio::task::spawn_blocking(move || allowed(&model))
+                    .await
+                    .unwrap_or(false)
+            }
+            _ => true,
+        }
+    }
+
+    /// Ids of the loaded models, least recently used first.
+    pub async fn loaded_models(&self) -> Vec<String> {
+        let models = self.models.lock().unwrap();
+        models.iter().map(|(model, _)| model.clone()).collect()
+    }
+
+    /// The provider for `model` if it is resident, marking it most recently used.
+    fn cached(&self, model: &str) -> Option<Arc<dyn Provider>> {
+        let mut models = self.models.lock().unwrap();
+        let index = models.iter().position(|(loaded, _)| loaded == model)?;
+        let entry = models.remove(index);
+        let provider = Arc::clone(&entry.1);
+        models.push(entry);
+        Some(provider)
+    }
+
+    /// Evicts least recently used models until at most `keep` remain.
+    fn evict_to(&self, keep: usize) {
+        let mut models = self.models.lock().unwrap();
+        while models.len() > keep {
+            let (evicted, _) = models.remove(0);
+            info!(model = %evicted, "Evicting least recently used model");
+        }
+    }
+
+    /// The provider for `model`, loading it if it isn't resident.
+    async fn provider(&self, model: &str) -> Result<Arc<dyn Provider>> {
+        let model = if model.is_empty() {
+            self.default_model.as_str()
+        } else {
+            model
+        };
+
+        if let Some(provider) = self.cached(model) {
+            return Ok(provider);
+        }
+        if !self.is_allowed(model).await {
+            return Err(ProviderError::ModelNotFound(format!(
+                "'{}' is not one of the models this server may load",
+                model
+            )));
+        }
+
+        let lock = {
+            let mut loading = self.loading.lock().unwrap();
+            Arc::clone(loading.entry(model.to_string()).or_default())
+        };
+        let _loading = lock.lock().await;
+        // Another request may have loaded it while this one waited
+        if let Some(provider) = self.cached(model) {
+            return Ok(provider);
+        }
+
+        // Free memory for the new model before loading it
+        self.evict_to(self.capacity - 1);
+        info!(model, "Loading model");
+        let loaded = (self.load)(model.to_string()).await;
+        {
+            // Requests already waiting hold the lock; later ones find the model
+            // loaded or, if it failed, try again with a lock of their own
+            let mut loading = self.loading.lock().unwrap();
+            if loading
+                .get(model)
+                .is_some_and(|entry| Arc::ptr_eq(entry, &lock))
+            {
+                loading.remove(model);
+            }
+        }
+        let provider = loaded?;
+
+        // Other models may have loaded meanwhile
+        self.evict_to(self.capacity - 1);
+        self.models
+            .lock()
+            .unwrap()
+            .push((model.to_string(), Arc::clone(&provider)));
+        Ok(provider)
+    }
+}
+
+#[async_trait]
+impl Provider for ModelCacheProvider {
+    async fn chat<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        self.provider(&request.model)
+            .await?
+            .chat(request, callback)
+            .await
+    }
+
+    async fn chat_n<'a>(
+        &'a self,
+        request: ChatRequest,
+        callback: Box<dyn FnMut(usize, ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        self.provider(&request.model)
+            .await?
+            .chat_n(request, callback)
+            .await
+    }
+
+    async fn warmup(&self) -> Result<()> {
+        self.provider(&self.default_model).await?.warmup().await
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        self.provider(&self.default_model)
+            .await?
+            .list_models()
+            .await
+    }
+
+    /// The accelerator of the most recently used model, if one is loaded.
+    fn accelerator(&self) -> AcceleratorType {
+        self.models
+            .lock()
+            .unwrap()
+            .last()
+            .map_or(AcceleratorType::None, |(_, provider)| {
+                provider.accelerator()
+            })
+    }
+
+    fn supports_model_switching(&self) -> bool {
+        true
+    }
+
+    /// Shuts down and unloads every resident model.
+    async fn shutdown(&self) -> Result<()> {
+        let models = std::mem::take(&mut *self.models.lock().unwrap());
+        for (_, provider) in models {
+            provider.shutdown().await?;
+        }
+        Ok(())
+    }
+
+    async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>> {
+        self.provider(&self.default_model)
+            .await?
+            .embed(text, model)
+            .await
+    }
+
+    async fn embed_batch(&self, texts: &[&str], model: &EmbeddingModel) -> Result<Vec<Vec<f32>>> {
+        self.provider(&self.default_model)
+            .await?
+            .embed_batch(texts, model)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Weak;
+
+    async fn chat(provider: &ModelCacheProvider, model: &str) -> String {
+        let request = ChatRequest::new(model, vec![Message::user(None, "Hi")]);
+        provider.chat_once(request).await.unwrap().content
+    }
+
+    #[tokio::test]
+    async fn test_recently_used_models_stay_loaded() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let loaded: Arc<std::sync::Mutex<Vec<Weak<dyn Provider>>>> = Arc::default();
+        let provider = ModelCacheProvider::new("a", 2, {
+            let loads = Arc::clone(&loads);
+            let loaded = Arc::clone(&loaded);
+            move |model| {
+                loads.fetch_add(1, Ordering::SeqCst);
+                let provider: Arc<dyn Provider> = Arc::new(MockProvider::new(model));
+                loaded.lock().unwrap().push(Arc::downgrade(&provider));
+                async move { Ok(provider) }
+            }
+        });
+
+        assert_eq!(chat(&provider, "a").await, "a");
+        assert_eq!(chat(&provider, "b").await, "b");
+        assert_eq!(chat(&provider, "a").await, "a");
+        assert_eq!(
+            loads.load(Ordering::SeqCst),
+            2,
+            "second load of a is a cache hit"
+        );
+
+        // b is now the least recently used, so loading c evicts and frees it
+        assert_eq!(chat(&provider, "c").await, "c");
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert_eq!(provider.loaded_models().await, vec!["a", "c"]);
+        assert!(loaded.lock().unwrap()[1].upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_model_is_evicted_before_loading() {
+        let loaded: Arc<std::sync::Mutex<Vec<Weak<dyn Provider>>>> = Arc::default();
+        let provider = ModelCacheProvider::new("a", 1, {
+            let loaded = Arc::clone(&loaded);
+            move |model| {
+                // Nothing else is resident while a model loads
+                let resident = loaded
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|provider| provider.upgrade().is_some())
+                    .count();
+                assert_eq!(resident, 0, "{} loaded next to another model", model);
+
+                let provider: Arc<dyn Provider> = Arc::new(MockProvider::new(model));
+                loaded.lock().unwrap().push(Arc::downgrade(&provider));
+                async move { Ok(provider) }
+            }
+        });
+
+        assert_eq!(chat(&provider, "a").await, "a");
+        assert_eq!(chat(&provider, "b").await, "b");
+        assert_eq!(provider.loaded_models().await, vec!["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_load_a_model_once() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let provider = ModelCacheProvider::new("a", 2, {
+            let loads = Arc::clone(&loads);
+            move |model| {
+                loads.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    Ok(Arc::new(MockProvider::new(model)) as Arc<dyn Provider>)
+                }
+            }
+        });
+
+        let (first, second) = tokio::join!(chat(&provider, "b"), chat(&provider, "b"));
+        assert_eq!((first.as_str(), second.as_str()), ("b", "b"));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(provider.loading.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_models_outside_the_allowed_set_are_refused() {
+        let provider = ModelCacheProvider::new("a", 1, |model| async move {
+            Ok(Arc::new(MockProvider::new(model)) as Arc<dyn Provider>)
+        })
+        .with_allowed_models(|model| model == "b");
+
+        assert_eq!(chat(&provider, "a").await, "a");
+        let request = ChatRequest::new("/etc/passwd", vec![Message::user(None, "Hi")]);
+        let result = provider.chat_once(request).await;
+        assert!(matches!(result, Err(ProviderError::ModelNotFound(_))));
+        assert_eq!(provider.loaded_models().await, vec!["a"], "nothing evicted");
+        assert_eq!(chat(&provider, "b").await, "b");
+    }
+}
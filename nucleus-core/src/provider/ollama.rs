@@ -73,6 +73,9 @@ impl Provider for OllamaProvider {
                     "temperature".to_string(),
                     serde_json::json!(request.temperature),
                 );
+                if let Some(seed) = request.seed {
+                    opts.insert("seed".to_string(), serde_json::json!(seed));
+                }
                 Some(opts)
             },
             stream: true,
@@ -99,7 +102,11 @@ impl Provider for OllamaProvider {
             .await?;
 
         if !response.status().is_success() {
+            let status = response.status();
             let error_text = response.text().await?;
+            if status == reqwest::StatusCode::NOT_FOUND {
+                return Err(ProviderError::ModelNotFound(error_text));
+            }
             return Err(ProviderError::Api(error_text));
         }
 
@@ -130,9 +137,11 @@ impl Provider for OllamaProvider {
                             content: ollama_response.message.content.clone(),
                             context: None,
                             images: ollama_response.message.images.clone(),
+                            tool_call_id: None,
                             tool_calls: ollama_response.message.tool_calls.as_ref().map(|tcs| {
                                 tcs.iter()
                                     .map(|tc| ToolCall {
+                                        id: None,
                                         function: ToolCallFunction {
                                             name: tc.function.name.clone(),
                                             arguments: tc.function.arguments.clone(),
@@ -149,6 +158,54 @@ impl Provider for OllamaProvider {
         Ok(())
     }
 
+    async fn warmup(&self) -> Result<()> {
+        // A chat request without messages makes Ollama load the model into
+        // memory without generating anything.
+        let url = format!("{}/api/chat", self.base_url);
+        let warmup_request = OllamaChatRequest {
+            model: self.config.llm.model.clone(),
+            messages: Vec::new(),
+            options: None,
+            stream: false,
+            tools: None,
+        };
+
+        let response = self
+            .http_client
+            .post(&url)
+            .json(&warmup_request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+            if status == reqwest::StatusCode::NOT_FOUND {
+                return Err(ProviderError::ModelNotFound(error_text));
+            }
+            return Err(ProviderError::Api(error_text));
+        }
+
+        Ok(())
+    }
+
+    /// Ollama loads whichever model a request names.
+    fn supports_model_switching(&self) -> bool {
+        true
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/api/tags", self.base_url);
+        let response = self.http_client.get(&url).send().await?;
+
+        if !response.status().is_success() {
+            return Err(ProviderError::Api(response.text().await?));
+        }
+
+        let tags = response.json::<OllamaTags>().await?;
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
     async fn embed(&self, text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
         let url = format!("{}/api/embed", self.base_url);
 
@@ -165,7 +222,11 @@ impl Provider for OllamaProvider {
             .await?;
 
         if !response.status().is_success() {
+            let status = response.status();
             let error_text = response.text().await?;
+            if status == reqwest::StatusCode::NOT_FOUND {
+                return Err(ProviderError::ModelNotFound(error_text));
+            }
             return Err(ProviderError::Api(error_text));
         }
 
@@ -197,6 +258,17 @@ fn default_stream() -> bool {
     true
 }
 
+/// Response of `/api/tags`, listing the locally pulled models.
+#[derive(Debug, Deserialize)]
+struct OllamaTags {
+    models: Vec<OllamaModelTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaModelTag {
+    name: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct OllamaMessage {
     role: String,
@@ -243,3 +315,24 @@ struct OllamaToolCallFunction {
     name: String,
     arguments: serde_json::Value,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve_once;
+
+    #[tokio::test]
+    async fn test_list_models_reads_tags() {
+        let (base_url, server) = serve_once(
+            r#"{"models":[{"name":"llama3.2:latest","size":2019393189},{"name":"nomic-embed-text:latest","size":274302450}]}"#,
+        )
+        .await;
+
+        let mut config = crate::Config::default();
+        config.llm.base_url = base_url;
+        let models = OllamaProvider::new(&config).list_models().await.unwrap();
+
+        assert_eq!(models, ["llama3.2:latest", "nomic-embed-text:latest"]);
+        assert!(server.await.unwrap().starts_with("GET /api/tags "));
+    }
+}
@@ -25,6 +25,21 @@ impl ProviderType {
     }
 }
 
+/// Hardware a provider runs inference on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AcceleratorType {
+    /// Apple GPU through Metal
+    Metal,
+    /// NVIDIA GPU through CUDA
+    Cuda,
+    /// Apple Neural Engine through CoreML
+    NeuralEngine,
+    /// The CPU, or an accelerator nucleus can't see (e.g. inside Ollama)
+    #[default]
+    None,
+}
+
 /// Errors that can occur when interacting with a provider.
 #[derive(Debug, Error)]
 pub enum ProviderError {
@@ -37,6 +52,21 @@ pub enum ProviderError {
     #[error("API error: {0}")]
     Api(String),
 
+    #[error("Not supported by this provider: {0}")]
+    Unsupported(String),
+
+    #[error("Model not found: {0}")]
+    ModelNotFound(String),
+
+    #[error("Incompatible model: {0}")]
+    IncompatibleModel(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    #[error("Model not ready yet: {0}")]
+    ModelLoading(String),
+
     #[error("Provider error: {0}")]
     Other(String),
 }
@@ -58,6 +88,108 @@ pub trait Provider: Send + Sync {
         callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
     ) -> Result<()>;
 
+    /// Stream `request.n` completions of the same prompt.
+    ///
+    /// The callback receives the index of the completion each chunk belongs to.
+    /// The default implementation runs [`chat`](Provider::chat) once per
+    /// completion, each with a different seed; providers that can sample
+    /// several completions natively should override it.
+    async fn chat_n<'a>(
+        &'a self,
+        request: ChatRequest,
+        mut callback: Box<dyn FnMut(usize, ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        let n = request.n.unwrap_or(1).max(1);
+        let base_seed = request.seed.unwrap_or_else(random_seed);
+
+        for index in 0..n {
+            let mut single = request.clone();
+            single.n = None;
+            single.seed = Some(base_seed.wrapping_add(index as u64));
+
+            self.chat(single, Box::new(|response| callback(index, response)))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a chat completion and return the whole response at once, for callers
+    /// that don't need streaming.
+    ///
+    /// The default implementation drives [`chat`](Provider::chat) and returns
+    /// its final response, with `content` and `message.content` holding the
+    /// concatenated chunks and `message.tool_calls` any tool calls requested.
+    async fn chat_once(&self, request: ChatRequest) -> Result<ChatResponse> {
+        let mut content = String::new();
+        let mut tool_calls = None;
+        let mut last = None;
+
+        self.chat(
+            request,
+            Box::new(|response| {
+                if !response.done {
+                    content.push_str(&response.content);
+                }
+                if response.message.tool_calls.is_some() {
+                    tool_calls = response.message.tool_calls.clone();
+                }
+                last = Some(response);
+            }),
+        )
+        .await?;
+
+        let mut response =
+            last.ok_or_else(|| ProviderError::Other("Provider sent no response".to_string()))?;
+        response.done = true;
+        response.content = content.clone();
+        response.message.content = content;
+        response.message.tool_calls = tool_calls;
+        Ok(response)
+    }
+
+    /// Load the model ahead of the first request.
+    ///
+    /// Models are often loaded, quantized or compiled lazily, so the first chat
+    /// pays for it. Providers for which that is true override this to run a
+    /// minimal request up front. Called by the server at startup when
+    /// `llm.warmup` is set. The default implementation does nothing.
+    async fn warmup(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// List the models available to this provider locally, as ids usable for
+    /// `llm.model` in the config.
+    ///
+    /// The default implementation returns [`ProviderError::Unsupported`].
+    async fn list_models(&self) -> Result<Vec<String>> {
+        Err(ProviderError::Unsupported("listing models".to_string()))
+    }
+
+    /// The accelerator this provider runs inference on.
+    ///
+    /// The default implementation reports [`AcceleratorType::None`].
+    fn accelerator(&self) -> AcceleratorType {
+        AcceleratorType::None
+    }
+
+    /// Whether [`ChatRequest::model`] selects the model for each request.
+    ///
+    /// Providers that load a single model up front ignore the requested model,
+    /// so callers should not offer per-request model overrides for them. The
+    /// default implementation returns false.
+    fn supports_model_switching(&self) -> bool {
+        false
+    }
+
+    /// Release resources held by the provider (models, GPU memory, background
+    /// tasks) before the process exits.
+    ///
+    /// Called by the server on shutdown. The default implementation does nothing.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
     /// Generate an embedding vector for the given text.
     async fn embed(&self, text: &str, model: &EmbeddingModel) -> Result<Vec<f32>>;
 
@@ -72,6 +204,14 @@ pub trait Provider: Send + Sync {
     }
 }
 
+/// Seed for requests that did not specify one, so repeated requests still vary.
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
 /// Request for chat completion.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatRequest {
@@ -80,6 +220,14 @@ pub struct ChatRequest {
     pub temperature: f64,
     pub tools: Option<Vec<Tool>>,
     pub structured_output: Option<StructuredOutput>,
+    /// Number of completions to generate (defaults to 1).
+    ///
+    /// Only honored by [`Provider::chat_n`]; [`Provider::chat`] always produces one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+    /// Sampling seed, for providers that support reproducible sampling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 }
 
 impl ChatRequest {
@@ -90,6 +238,8 @@ impl ChatRequest {
             temperature: 0.7,
             tools: None,
             structured_output: None,
+            n: None,
+            seed: None,
         }
     }
 
@@ -107,6 +257,23 @@ impl ChatRequest {
         self.tools = Some(tools);
         self
     }
+
+    /// Whether any message carries images, for providers without vision support.
+    pub fn has_images(&self) -> bool {
+        self.messages
+            .iter()
+            .any(|m| m.images.as_ref().is_some_and(|images| !images.is_empty()))
+    }
+
+    pub fn with_n(mut self, n: usize) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
 }
 
 /// Response from chat completion (streaming chunk).
@@ -132,6 +299,10 @@ pub struct Message {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+
+    /// For `tool` messages, the id of the tool call this is the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 impl Message {
@@ -142,6 +313,7 @@ impl Message {
             content: content.into(),
             images: None,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -152,6 +324,7 @@ impl Message {
             content: content.into(),
             images: None,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
@@ -162,18 +335,28 @@ impl Message {
             content: content.into(),
             images: None,
             tool_calls: None,
+            tool_call_id: None,
         }
     }
 
-    pub fn tool(context: Option<String>, content: impl Into<String>) -> Self {
+    /// The result of a tool call, answering the call with id `tool_call_id`
+    /// if the provider gave it one.
+    pub fn tool(tool_call_id: Option<String>, content: impl Into<String>) -> Self {
         Self {
             role: "tool".to_string(),
-            context: Some(context).unwrap_or(None),
+            context: None,
             content: content.into(),
             images: None,
             tool_calls: None,
+            tool_call_id,
         }
     }
+
+    /// Attach the tool calls an assistant message requested.
+    pub fn with_tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = Some(tool_calls);
+        self
+    }
 }
 
 /// Tool specification for function calling.
@@ -195,6 +378,9 @@ pub struct ToolFunction {
 /// Tool call requested by the LLM.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
+    /// Id the provider assigned to the call, echoed back with its result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub function: ToolCallFunction,
 }
 
@@ -264,3 +450,78 @@ impl From<StructuredOutput> for serde_json::Value {
         serde_json::to_value(output).unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+
+    #[test]
+    fn test_tool_message_serializes_with_tool_role() {
+        let message = Message::tool(Some("call_7".to_string()), "3 files");
+        let json = serde_json::to_value(&message).unwrap();
+
+        assert_eq!(json["role"], "tool");
+        assert_eq!(json["content"], "3 files");
+        assert_eq!(json["tool_call_id"], "call_7");
+
+        let json = serde_json::to_value(Message::user(None, "hi")).unwrap();
+        assert!(json.get("tool_call_id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chat_once_returns_concatenated_response() {
+        let provider = MockProvider::streaming(
+            vec!["Hel".to_string(), "lo, ".to_string(), "world".to_string()],
+            None,
+        );
+        let request = ChatRequest::new("mock", vec![Message::user(None, "Hi")]);
+
+        let response = provider.chat_once(request).await.unwrap();
+
+        assert!(response.done);
+        assert_eq!(response.content, "Hello, world");
+        assert_eq!(response.message.content, "Hello, world");
+        assert_eq!(response.message.role, "assistant");
+        assert!(response.message.tool_calls.is_none());
+
+        let provider = MockProvider::new("").with_tool_call("read_file", serde_json::json!({}));
+        let request = ChatRequest::new("mock", vec![Message::user(None, "Read it")]);
+        let response = provider.chat_once(request).await.unwrap();
+        assert_eq!(
+            response.message.tool_calls.unwrap()[0].function.name,
+            "read_file"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_n_samples_each_completion_with_its_own_seed() {
+        let provider = MockProvider::new("reply");
+        let request = ChatRequest::new("mock", vec![Message::user(None, "Hi")])
+            .with_n(3)
+            .with_seed(10);
+
+        let mut completions = vec![String::new(); 3];
+        provider
+            .chat_n(
+                request,
+                Box::new(|index, response| completions[index].push_str(&response.content)),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(completions, vec!["reply #10", "reply #11", "reply #12"]);
+
+        let seeds: Vec<_> = provider
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| (request.n, request.seed))
+            .collect();
+        assert_eq!(
+            seeds,
+            vec![(None, Some(10)), (None, Some(11)), (None, Some(12))]
+        );
+    }
+}
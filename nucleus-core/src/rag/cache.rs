@@ -0,0 +1,264 @@
+//! Caching of retrieval results for repeated queries.
+
+use super::store::VectorStore;
+use super::types::{Document, SearchResult};
+use crate::config::RetrievalCacheConfig;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    query: String,
+    value: V,
+    cached_at: Instant,
+}
+
+/// Least recently used cache of values keyed by normalized query, optionally
+/// expiring entries after a time to live.
+pub(super) struct QueryCache<V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    /// Entries ordered from least to most recently used.
+    entries: Mutex<Vec<Entry<V>>>,
+}
+
+impl<V: Clone> QueryCache<V> {
+    /// A cache of up to `capacity` entries; 0 disables caching.
+    pub(super) fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the cached value for `query`, unless it has expired.
+    pub(super) fn get(&self, query: &str) -> Option<V> {
+        let query = normalize(query);
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|entry| entry.query == query)?;
+
+        let entry = entries.remove(index);
+        if self.ttl.is_some_and(|ttl| entry.cached_at.elapsed() >= ttl) {
+            return None;
+        }
+        let value = entry.value.clone();
+        entries.push(entry);
+        Some(value)
+    }
+
+    /// Caches `value` for `query`, evicting the least recently used entry if
+    /// the cache is full.
+    pub(super) fn insert(&self, query: &str, value: V) {
+        self.insert_if(query, value, || true);
+    }
+
+    /// Like [`insert`](Self::insert), but only if `keep` returns true, checked
+    /// while no other thread can change the cache.
+    fn insert_if(&self, query: &str, value: V, keep: impl FnOnce() -> bool) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let query = normalize(query);
+        let mut entries = self.entries.lock().unwrap();
+        if !keep() {
+            return;
+        }
+        entries.retain(|entry| entry.query != query);
+        if entries.len() >= self.capacity {
+            entries.remove(0);
+        }
+        entries.push(Entry {
+            query,
+            value,
+            cached_at: Instant::now(),
+        });
+    }
+
+    /// Empties the cache, calling `on_clear` while no other thread can change it.
+    fn clear_with(&self, on_clear: impl FnOnce()) {
+        let mut entries = self.entries.lock().unwrap();
+        on_clear();
+        entries.clear();
+    }
+}
+
+/// Least recently used cache of retrieval results, keyed by normalized query.
+pub(super) struct RetrievalCache {
+    results: QueryCache<Vec<SearchResult>>,
+    /// Bumped on every clear, so results retrieved before a change to the
+    /// knowledge base aren't cached after it.
+    generation: AtomicU64,
+}
+
+impl RetrievalCache {
+    pub(super) fn new(config: &RetrievalCacheConfig) -> Self {
+        Self {
+            results: QueryCache::new(config.capacity, Some(Duration::from_secs(config.ttl_secs))),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// The current generation, to pass to [`insert`](Self::insert) once the
+    /// results have been retrieved.
+    pub(super) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Returns the cached results for `query`, unless they have expired.
+    pub(super) fn get(&self, query: &str) -> Option<Vec<SearchResult>> {
+        self.results.get(query)
+    }
+
+    /// Caches `results` for `query`, unless the cache was cleared since
+    /// `generation` was read.
+    pub(super) fn insert(&self, query: &str, results: Vec<SearchResult>, generation: u64) {
+        self.results
+            .insert_if(query, results, || self.generation() == generation);
+    }
+
+    pub(super) fn clear(&self) {
+        self.results.clear_with(|| {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Queries differing only in case or whitespace share a cache entry.
+fn normalize(query: &str) -> String {
+    query
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Vector store that empties a [`RetrievalCache`] whenever its contents change,
+/// so cached results never outlive the documents they came from.
+pub(super) struct InvalidatingStore {
+    inner: Arc<dyn VectorStore>,
+    cache: Arc<RetrievalCache>,
+}
+
+impl InvalidatingStore {
+    pub(super) fn new(inner: Arc<dyn VectorStore>, cache: Arc<RetrievalCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+#[async_trait]
+impl VectorStore for InvalidatingStore {
+    async fn add(&self, documents: Vec<Document>) -> Result<()> {
+        let result = self.inner.add(documents).await;
+        self.cache.clear();
+        result
+    }
+
+    async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+        self.inner.search(query_embedding).await
+    }
+
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        self.inner.search_with_k(query_embedding, k).await
+    }
+
+    async fn count(&self) -> Result<usize> {
+        self.inner.count().await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let result = self.inner.clear().await;
+        self.cache.clear();
+        result
+    }
+
+    async fn get_indexed_paths(&self) -> Result<Vec<String>> {
+        self.inner.get_indexed_paths().await
+    }
+
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
+        self.inner.count_by_source().await
+    }
+
+    async fn get_by_source(&self, source: &str) -> Result<Vec<Document>> {
+        self.inner.get_by_source(source).await
+    }
+
+    async fn remove_by_source(&self, source_path: &str) -> Result<usize> {
+        let result = self.inner.remove_by_source(source_path).await;
+        self.cache.clear();
+        result
+    }
+
+    async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        let result = self.inner.evict_older_than(age).await;
+        self.cache.clear();
+        result
+    }
+
+    fn vector_size(&self) -> u64 {
+        self.inner.vector_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(id: &str) -> Vec<SearchResult> {
+        vec![SearchResult {
+            document: Document::new(id, id, Vec::new()),
+            score: 1.0,
+            explanation: None,
+            snippet: None,
+        }]
+    }
+
+    fn cache(capacity: usize, ttl_secs: u64) -> RetrievalCache {
+        RetrievalCache::new(&RetrievalCacheConfig { capacity, ttl_secs })
+    }
+
+    fn cached_id(cache: &RetrievalCache, query: &str) -> Option<String> {
+        cache
+            .get(query)
+            .map(|results| results[0].document.id.clone())
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = cache(2, 60);
+        cache.insert("first", result("a"), 0);
+        cache.insert("second", result("b"), 0);
+        // Using "first" makes "second" the least recently used
+        assert_eq!(cached_id(&cache, "  FIRST "), Some("a".to_string()));
+
+        cache.insert("third", result("c"), 0);
+        assert_eq!(cached_id(&cache, "first"), Some("a".to_string()));
+        assert_eq!(cached_id(&cache, "second"), None);
+        assert_eq!(cached_id(&cache, "third"), Some("c".to_string()));
+    }
+
+    #[test]
+    fn test_expired_and_disabled() {
+        let expired = cache(4, 0);
+        expired.insert("query", result("a"), 0);
+        assert_eq!(cached_id(&expired, "query"), None);
+
+        let disabled = cache(0, 60);
+        disabled.insert("query", result("a"), 0);
+        assert_eq!(cached_id(&disabled, "query"), None);
+    }
+
+    #[test]
+    fn test_results_retrieved_before_clear_are_not_cached() {
+        let cache = cache(4, 60);
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("query", result("stale"), generation);
+        assert_eq!(cached_id(&cache, "query"), None);
+    }
+}
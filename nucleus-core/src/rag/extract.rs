@@ -0,0 +1,215 @@
+//! Plain text extraction from files that aren't plain text.
+//!
+//! Extractors are chosen by file extension while a directory is indexed, and
+//! the text they produce is chunked like any other file. Built-in extractors
+//! are enabled with cargo features, as they pull in heavy dependencies:
+//! - `pdf`: PDF documents, via `pdf-extract`
+//! - `docx`: Word documents
+//!
+//! Other formats can be supported by implementing [`TextExtractor`] and
+//! registering it with [`RagEngine::with_extractor`](super::RagEngine::with_extractor).
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Error returned when a file's text can't be extracted.
+#[derive(Debug, Error)]
+#[error("Failed to extract text: {0}")]
+pub struct ExtractError(pub String);
+
+/// Turns the contents of a file into plain text for indexing.
+///
+/// # Example
+///
+/// ```
+/// use nucleus_core::rag::{ExtractError, TextExtractor};
+///
+/// /// Indexes the body of `.eml` files, without their headers.
+/// struct EmailExtractor;
+///
+/// impl TextExtractor for EmailExtractor {
+///     fn extensions(&self) -> &[&str] {
+///         &["eml"]
+///     }
+///
+///     fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+///         let email = std::str::from_utf8(bytes).map_err(|e| ExtractError(e.to_string()))?;
+///         let body = email.split_once("\r\n\r\n").map_or(email, |(_, body)| body);
+///         Ok(body.to_string())
+///     }
+/// }
+/// ```
+pub trait TextExtractor: Send + Sync {
+    /// Extensions of the files this extractor handles, without the leading dot.
+    /// Matching ignores case.
+    fn extensions(&self) -> &[&str];
+
+    /// Extracts the text of a file from its contents.
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError>;
+}
+
+/// The extractors available to an indexer, most recently added first.
+#[derive(Clone)]
+pub(crate) struct Extractors(Vec<Arc<dyn TextExtractor>>);
+
+impl Extractors {
+    /// The extractors enabled by cargo features.
+    #[allow(unused_mut)]
+    pub(crate) fn builtin() -> Self {
+        let mut extractors: Vec<Arc<dyn TextExtractor>> = Vec::new();
+        #[cfg(feature = "pdf")]
+        extractors.push(Arc::new(PdfExtractor));
+        #[cfg(feature = "docx")]
+        extractors.push(Arc::new(DocxExtractor));
+        Self(extractors)
+    }
+
+    /// Adds an extractor, taking precedence over existing ones for the same extension.
+    pub(crate) fn add(&mut self, extractor: Arc<dyn TextExtractor>) {
+        self.0.insert(0, extractor);
+    }
+
+    /// The extractor handling `path`'s extension, if any.
+    pub(crate) fn for_path(&self, path: &Path) -> Option<&dyn TextExtractor> {
+        let extension = path.extension()?.to_str()?;
+        self.0
+            .iter()
+            .find(|extractor| {
+                extractor
+                    .extensions()
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(extension))
+            })
+            .map(Arc::as_ref)
+    }
+}
+
+impl fmt::Debug for Extractors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|extractor| extractor.extensions()))
+            .finish()
+    }
+}
+
+/// Extracts the text of PDF documents.
+#[cfg(feature = "pdf")]
+pub struct PdfExtractor;
+
+#[cfg(feature = "pdf")]
+impl TextExtractor for PdfExtractor {
+    fn extensions(&self) -> &[&str] {
+        &["pdf"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        pdf_extract::extract_text_from_mem(bytes).map_err(|e| ExtractError(e.to_string()))
+    }
+}
+
+/// Extracts the text of Word (`.docx`) documents, one line per paragraph.
+#[cfg(feature = "docx")]
+pub struct DocxExtractor;
+
+#[cfg(feature = "docx")]
+impl TextExtractor for DocxExtractor {
+    fn extensions(&self) -> &[&str] {
+        &["docx"]
+    }
+
+    fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+        use quick_xml::events::Event;
+        use std::io::Read;
+
+        let error = |e: &dyn fmt::Display| ExtractError(e.to_string());
+
+        let mut archive =
+            zip::ZipArchive::new(std::io::Cursor::new(bytes)).map_err(|e| error(&e))?;
+        let mut xml = String::new();
+        archive
+            .by_name("word/document.xml")
+            .map_err(|e| error(&e))?
+            .read_to_string(&mut xml)
+            .map_err(|e| error(&e))?;
+
+        let mut reader = quick_xml::Reader::from_str(&xml);
+        let mut text = String::new();
+        let mut in_run_text = false;
+        loop {
+            match reader.read_event().map_err(|e| error(&e))? {
+                Event::Start(e) if e.name().as_ref() == b"w:t" => in_run_text = true,
+                Event::End(e) => match e.name().as_ref() {
+                    b"w:t" => in_run_text = false,
+                    b"w:p" => text.push('\n'),
+                    _ => {}
+                },
+                Event::Empty(e) => match e.name().as_ref() {
+                    b"w:tab" => text.push('\t'),
+                    b"w:br" => text.push('\n'),
+                    _ => {}
+                },
+                Event::Text(e) if in_run_text => {
+                    text.push_str(&e.unescape().map_err(|e| error(&e))?);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Upper(&'static [&'static str]);
+
+    impl TextExtractor for Upper {
+        fn extensions(&self) -> &[&str] {
+            self.0
+        }
+
+        fn extract(&self, bytes: &[u8]) -> Result<String, ExtractError> {
+            Ok(String::from_utf8_lossy(bytes).to_uppercase())
+        }
+    }
+
+    #[test]
+    fn test_extractor_is_chosen_by_extension() {
+        let mut extractors = Extractors(Vec::new());
+        extractors.add(Arc::new(Upper(&["note"])));
+
+        let extractor = extractors.for_path(Path::new("docs/todo.NOTE")).unwrap();
+        assert_eq!(extractor.extract(b"ship it").unwrap(), "SHIP IT");
+        assert!(extractors.for_path(Path::new("docs/todo.txt")).is_none());
+        assert!(extractors.for_path(Path::new("Makefile")).is_none());
+    }
+
+    #[cfg(feature = "docx")]
+    #[test]
+    fn test_docx_paragraphs_become_lines() {
+        use std::io::Write;
+
+        let mut docx = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        docx.start_file(
+            "word/document.xml",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        docx.write_all(
+            br#"<w:document><w:body>
+                <w:p><w:r><w:t>Install &amp; run</w:t></w:r></w:p>
+                <w:p><w:r><w:t xml:space="preserve">with </w:t></w:r><w:r><w:t>cargo</w:t></w:r></w:p>
+            </w:body></w:document>"#,
+        )
+        .unwrap();
+        let bytes = docx.finish().unwrap().into_inner();
+
+        let text = DocxExtractor.extract(&bytes).unwrap();
+        assert_eq!(text, "Install & run\nwith cargo\n");
+    }
+}
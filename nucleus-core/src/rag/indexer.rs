@@ -5,10 +5,17 @@
 //! - Split large text into overlapping chunks
 //! - Filter files by extension and exclude patterns
 
-use crate::config::IndexerConfig;
+use super::extract::{Extractors, TextExtractor};
+use super::types::{ChunkPreview, IndexReport};
+use crate::config::{ChunkUnit, IndexerConfig};
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use tokenizers::Tokenizer;
 use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Errors that can occur during file indexing.
 #[derive(Debug, Error)]
@@ -31,29 +38,212 @@ pub type Result<T> = std::result::Result<T, IndexerError>;
 #[derive(Debug, Clone)]
 pub struct Indexer {
     config: IndexerConfig,
+    tokenizer: Option<Arc<Tokenizer>>,
+    extractors: Extractors,
 }
 
 impl Indexer {
     /// Creates a new Indexer with the given configuration.
+    ///
+    /// With `chunk_unit: tokens`, the tokenizer at `tokenizer_path` is loaded.
+    /// If there is none, or it fails to load, chunks are measured in bytes.
     pub fn new(config: IndexerConfig) -> Self {
-        Self { config }
+        let tokenizer = match (config.chunk_unit, &config.tokenizer_path) {
+            (ChunkUnit::Tokens, Some(path)) => match Tokenizer::from_file(path) {
+                Ok(tokenizer) => Some(Arc::new(tokenizer)),
+                Err(e) => {
+                    eprintln!(
+                        "WARNING: Failed to load tokenizer from {}, chunking by bytes: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            },
+            (ChunkUnit::Tokens, None) => {
+                eprintln!("WARNING: No indexer.tokenizer_path configured, chunking by bytes");
+                None
+            }
+            (ChunkUnit::Bytes, _) => None,
+        };
+
+        Self {
+            config,
+            tokenizer,
+            extractors: Extractors::builtin(),
+        }
+    }
+
+    /// Measure chunks in tokens counted by `tokenizer`.
+    pub fn with_tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.config.chunk_unit = ChunkUnit::Tokens;
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self
+    }
+
+    /// Extract the text of files with the extractor's extensions using `extractor`.
+    pub fn with_extractor(mut self, extractor: Arc<dyn TextExtractor>) -> Self {
+        self.extractors.add(extractor);
+        self
     }
 
     /// Collects all indexable files from the specified directory.
     ///
     /// Walks the directory tree recursively, applying extension and exclude filters.
     pub async fn collect_files(&self, dir_path: impl AsRef<Path>) -> Result<Vec<IndexedFile>> {
-        collect_files(dir_path, &self.config).await
+        collect_files(dir_path, &self.config, &self.extractors).await
+    }
+
+    /// Collects all indexable files, reporting the files that were skipped or
+    /// could not be read.
+    ///
+    /// The returned report has the skipped and failed files filled in; files are
+    /// only added to `indexed` once they have actually been indexed.
+    pub async fn collect_files_with_report(
+        &self,
+        dir_path: impl AsRef<Path>,
+    ) -> Result<(Vec<IndexedFile>, IndexReport)> {
+        collect_files_with_report(dir_path, &self.config, &self.extractors).await
+    }
+
+    /// Whether indexing should stop at the first failing file.
+    pub fn abort_on_error(&self) -> bool {
+        self.config.abort_on_error
+    }
+
+    /// How many times a failed embedding is retried.
+    pub fn embed_retries(&self) -> usize {
+        self.config.embed_retries
+    }
+
+    /// Delay before the first retry of a failed embedding.
+    pub fn embed_initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.config.embed_initial_backoff_ms)
+    }
+
+    /// Longest delay between retries of a failed embedding.
+    pub fn embed_max_backoff(&self) -> Duration {
+        Duration::from_millis(self.config.embed_max_backoff_ms)
+    }
+
+    /// Whether chunks with identical content should only be indexed once per run.
+    pub fn dedup(&self) -> bool {
+        self.config.dedup
+    }
+
+    /// Size in bytes above which files are not indexed.
+    pub fn max_file_size(&self) -> u64 {
+        self.config.max_file_size
+    }
+
+    /// Whether `path` matches one of the exclude patterns.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        should_exclude(path, &self.config.exclude_patterns)
+    }
+
+    /// Whether a file at `path` passes the extension and exclude filters.
+    pub fn should_index(&self, path: &Path) -> bool {
+        !should_exclude(path, &self.config.exclude_patterns)
+            && is_indexable(path, &self.config.extensions)
+    }
+
+    /// Chunks `reader` incrementally with the configured size and overlap.
+    ///
+    /// Streaming chunks are always measured in bytes, whatever `chunk_unit` is.
+    pub fn streaming_chunker<R: AsyncRead + Unpin>(&self, reader: R) -> StreamingChunker<R> {
+        StreamingChunker::new(reader, self.config.chunk_size, self.config.chunk_overlap)
     }
 
     /// Chunks text according to the indexer's configuration.
     ///
     /// Splits text into overlapping chunks using the configured chunk_size and chunk_overlap.
     pub fn chunk_text(&self, text: &str) -> Vec<String> {
-        chunk_text(text, self.config.chunk_size, self.config.chunk_overlap)
+        self.chunk_text_with_spans(text)
+            .into_iter()
+            .map(|chunk| chunk.content)
+            .collect()
+    }
+
+    /// Chunks text according to the indexer's configuration, keeping the location
+    /// of each chunk within the original text.
+    pub fn chunk_text_with_spans(&self, text: &str) -> Vec<TextChunk> {
+        let (size, overlap) = (self.config.chunk_size, self.config.chunk_overlap);
+        match &self.tokenizer {
+            Some(tokenizer) if self.config.chunk_unit == ChunkUnit::Tokens => {
+                chunk_text_by_tokens(text, tokenizer, size, overlap)
+            }
+            _ => chunk_text_with_spans(text, size, overlap),
+        }
+    }
+
+    /// Reads the text of the file at `path` as it would be indexed, using the
+    /// extractor registered for its extension if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read, extraction fails, or a file
+    /// without an extractor looks binary (see [`decode_text`]).
+    pub async fn read_text(&self, path: &Path) -> Result<String> {
+        Ok(self.read_decoded(path).await?.content)
+    }
+
+    /// Like [`read_text`](Self::read_text), also returning the encoding the
+    /// file was decoded from.
+    pub async fn read_decoded(&self, path: &Path) -> Result<DecodedText> {
+        let bytes = fs::read(path).await?;
+        let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+
+        match self.extractors.for_path(path) {
+            Some(extractor) => Ok(DecodedText {
+                content: extractor
+                    .extract(&bytes)
+                    .map_err(|e| invalid(e.to_string()))?,
+                encoding: None,
+            }),
+            None => decode_text(path, bytes, self.config.detect_encoding)
+                .ok_or_else(|| invalid(format!("{} looks binary", path.display())).into()),
+        }
+    }
+
+    /// Chunks text like [`chunk_text_with_spans`](Self::chunk_text_with_spans),
+    /// counting the tokens in each chunk.
+    pub fn preview_chunks(&self, text: &str) -> Vec<ChunkPreview> {
+        self.chunk_text_with_spans(text)
+            .into_iter()
+            .map(|chunk| ChunkPreview {
+                tokens: self.count_tokens(&chunk.content),
+                content: chunk.content,
+                start_byte: chunk.start_byte,
+                end_byte: chunk.end_byte,
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+            })
+            .collect()
+    }
+
+    /// Number of tokens in `text`, estimated at about four bytes per token
+    /// without a tokenizer.
+    fn count_tokens(&self, text: &str) -> usize {
+        self.tokenizer
+            .as_ref()
+            .and_then(|tokenizer| tokenizer.encode(text, false).ok())
+            .map_or_else(|| text.len().div_ceil(4), |encoding| encoding.len())
     }
 }
 
+/// A chunk of text along with its location in the source it was cut from.
+///
+/// Line numbers are 1-based and inclusive. Byte offsets are 0-based, with
+/// `end_byte` exclusive, so `&source[start_byte..end_byte] == content`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    pub content: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
 /// Splits text into overlapping chunks for better context preservation.
 ///
 /// Text chunking is essential for RAG because:
@@ -66,17 +256,34 @@ impl Indexer {
 /// This function respects UTF-8 character boundaries by finding the nearest
 /// valid boundary when chunk sizes would split multi-byte characters.
 pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String> {
+    chunk_text_with_spans(text, chunk_size, overlap)
+        .into_iter()
+        .map(|chunk| chunk.content)
+        .collect()
+}
+
+/// Splits text into overlapping chunks, recording where each chunk came from.
+///
+/// Behaves exactly like [`chunk_text`], but each chunk also carries its byte
+/// offsets and the range of lines it spans, so retrieved context can be traced
+/// back to a location such as `file.rs:120-145`.
+pub fn chunk_text_with_spans(text: &str, chunk_size: usize, overlap: usize) -> Vec<TextChunk> {
     if text.is_empty() {
         eprintln!("WARNING: chunk_text called with empty text");
         return vec![];
     }
 
+    let chunk_size = chunk_size.max(1);
     if text.len() <= chunk_size {
-        return vec![text.to_string()];
+        return vec![make_chunk(text, 0, text.len(), 1)];
     }
 
+    let overlap = clamp_overlap(chunk_size, overlap);
     let mut chunks = Vec::new();
     let mut start = 0;
+    // Line number of `start`, advanced incrementally so the text is only scanned once.
+    let mut line = 1;
+    let mut line_offset = 0;
 
     while start < text.len() {
         let mut end = (start + chunk_size).min(text.len());
@@ -86,14 +293,16 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
             end -= 1;
         }
 
-        let chunk = text[start..end].to_string();
-        if chunk.is_empty() {
+        line += count_newlines(&text[line_offset..start]);
+        line_offset = start;
+
+        if start == end {
             eprintln!(
                 "WARNING: Empty chunk created at start={}, end={}",
                 start, end
             );
         } else {
-            chunks.push(chunk);
+            chunks.push(make_chunk(text, start, end, line));
         }
 
         if end == text.len() {
@@ -113,17 +322,461 @@ pub fn chunk_text(text: &str, chunk_size: usize, overlap: usize) -> Vec<String>
     chunks
 }
 
+/// Limits `overlap` to one less than `chunk_size`, so that each chunk starts
+/// after the previous one and chunking always makes progress.
+fn clamp_overlap(chunk_size: usize, overlap: usize) -> usize {
+    if overlap < chunk_size {
+        return overlap;
+    }
+    eprintln!(
+        "WARNING: chunk_overlap ({}) must be smaller than chunk_size ({}), using {}",
+        overlap,
+        chunk_size,
+        chunk_size - 1
+    );
+    chunk_size - 1
+}
+
+/// Splits text into overlapping chunks of at most `max_tokens` tokens, as
+/// counted by `tokenizer`, with `overlap` tokens shared between neighbors.
+///
+/// Chunks start and end on token boundaries. The text between two tokens (such
+/// as whitespace) goes to the chunk before it, so chunks without overlap cover
+/// the text exactly. Falls back to [`chunk_text_with_spans`] if the text cannot
+/// be tokenized.
+pub fn chunk_text_by_tokens(
+    text: &str,
+    tokenizer: &Tokenizer,
+    max_tokens: usize,
+    overlap: usize,
+) -> Vec<TextChunk> {
+    if text.is_empty() {
+        return vec![];
+    }
+
+    let encoding = match tokenizer.encode(text, false) {
+        Ok(encoding) => encoding,
+        Err(e) => {
+            eprintln!("WARNING: Failed to tokenize text, chunking by bytes: {}", e);
+            return chunk_text_with_spans(text, max_tokens, overlap);
+        }
+    };
+    let offsets = encoding.get_offsets();
+
+    let max_tokens = max_tokens.max(1);
+    if offsets.len() <= max_tokens {
+        return vec![make_chunk(text, 0, text.len(), 1)];
+    }
+
+    let step = max_tokens - clamp_overlap(max_tokens, overlap);
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    let mut line = 1;
+    let mut line_offset = 0;
+
+    loop {
+        let last = (first + max_tokens).min(offsets.len());
+        let start = if first == 0 {
+            0
+        } else {
+            floor_char_boundary(text, offsets[first].0)
+        };
+        let end = if last == offsets.len() {
+            text.len()
+        } else {
+            floor_char_boundary(text, offsets[last].0)
+        };
+
+        line += count_newlines(&text[line_offset..start]);
+        line_offset = start;
+        if start < end {
+            chunks.push(make_chunk(text, start, end, line));
+        }
+
+        if last == offsets.len() {
+            break;
+        }
+        first += step;
+    }
+
+    chunks
+}
+
+/// Number of bytes [`StreamingChunker`] reads from its source at a time by default.
+const STREAM_READ_SIZE: usize = 64 * 1024;
+
+/// Splits a byte stream into the same chunks as [`chunk_text_with_spans`]
+/// without reading it all into memory.
+///
+/// The source is read in windows as chunks are requested, and bytes are
+/// dropped once no later chunk needs them, so at most about
+/// `chunk_size + read_size` bytes are held at once. Byte offsets and line
+/// numbers are relative to the start of the stream.
+///
+/// # Example
+///
+/// ```no_run
+/// # use nucleus_core::rag::StreamingChunker;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let file = tokio::fs::File::open("huge.log").await?;
+/// let mut chunker = StreamingChunker::new(file, 512, 50);
+/// while let Some(chunk) = chunker.next_chunk().await? {
+///     println!("lines {}-{}", chunk.start_line, chunk.end_line);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct StreamingChunker<R> {
+    reader: R,
+    chunk_size: usize,
+    step: usize,
+    read_size: usize,
+    /// Bytes read but not yet dropped, starting at stream offset `buf_start`
+    buf: Vec<u8>,
+    buf_start: usize,
+    /// Stream offset of the next chunk
+    start: usize,
+    /// Line number of `start`
+    line: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> StreamingChunker<R> {
+    pub fn new(reader: R, chunk_size: usize, overlap: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Self {
+            reader,
+            chunk_size,
+            step: chunk_size - clamp_overlap(chunk_size, overlap),
+            read_size: STREAM_READ_SIZE,
+            buf: Vec::new(),
+            buf_start: 0,
+            start: 0,
+            line: 1,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Read `read_size` bytes from the source at a time.
+    pub fn with_read_size(mut self, read_size: usize) -> Self {
+        self.read_size = read_size.max(1);
+        self
+    }
+
+    /// Returns the next chunk, or `None` once the stream is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or the stream is not valid UTF-8.
+    pub async fn next_chunk(&mut self) -> Result<Option<TextChunk>> {
+        while !self.done {
+            // One byte past the chunk tells whether its end splits a character
+            self.fill(self.start + self.chunk_size + 1).await?;
+            let offset = self.start - self.buf_start;
+            if offset >= self.buf.len() {
+                self.done = true;
+                break;
+            }
+
+            let mut end = (offset + self.chunk_size).min(self.buf.len());
+            while end > offset && end < self.buf.len() && is_utf8_continuation(self.buf[end]) {
+                end -= 1;
+            }
+
+            let chunk = if end > offset {
+                let text = std::str::from_utf8(&self.buf[offset..end]).map_err(|e| {
+                    IndexerError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+                })?;
+                let mut chunk = make_chunk(text, 0, text.len(), self.line);
+                chunk.start_byte += self.start;
+                chunk.end_byte += self.start;
+                Some(chunk)
+            } else {
+                eprintln!(
+                    "WARNING: Empty chunk created at start={}, end={}",
+                    self.start, self.start
+                );
+                None
+            };
+
+            if end == self.buf.len() {
+                self.done = true;
+            } else {
+                self.advance().await?;
+            }
+
+            if chunk.is_some() {
+                return Ok(chunk);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Moves `start` to the next chunk, on a character boundary, and drops the
+    /// bytes before it.
+    async fn advance(&mut self) -> Result<()> {
+        let mut next = self.start + self.step;
+        loop {
+            self.fill(next + 1).await?;
+            match self.buf.get(next - self.buf_start) {
+                Some(&byte) if is_utf8_continuation(byte) => next += 1,
+                _ => break,
+            }
+        }
+
+        let consumed = (next - self.buf_start).min(self.buf.len());
+        self.line += self.buf[self.start - self.buf_start..consumed]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        self.buf.drain(..consumed);
+        self.buf_start += consumed;
+        self.start = next;
+        Ok(())
+    }
+
+    /// Reads until the buffer extends to stream offset `until`, or the source ends.
+    async fn fill(&mut self, until: usize) -> Result<()> {
+        while !self.eof && self.buf_start + self.buf.len() < until {
+            let len = self.buf.len();
+            self.buf.resize(len + self.read_size, 0);
+            let read = self.reader.read(&mut self.buf[len..]).await;
+            let read = read.inspect_err(|_| self.buf.truncate(len))?;
+            self.buf.truncate(len + read);
+            self.eof = read == 0;
+        }
+        Ok(())
+    }
+}
+
+fn is_utf8_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+/// The nearest character boundary at or before `index`.
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn make_chunk(text: &str, start: usize, end: usize, start_line: usize) -> TextChunk {
+    let content = &text[start..end];
+    // A trailing newline terminates the last line rather than starting a new one.
+    let end_line = start_line + count_newlines(content.strip_suffix('\n').unwrap_or(content));
+
+    TextChunk {
+        content: content.to_string(),
+        start_byte: start,
+        end_byte: end,
+        start_line,
+        end_line,
+    }
+}
+
+/// SHA-256 of a chunk's content, used to detect duplicate chunks.
+pub fn content_hash(text: &str) -> [u8; 32] {
+    Sha256::digest(text.as_bytes()).into()
+}
+
+/// Id of the `chunk_index`th chunk of `source`: the hex SHA-256 of the source
+/// followed by `:<chunk_index>`.
+///
+/// Ids only depend on where a chunk came from, so reindexing a file overwrites
+/// its chunks instead of duplicating them.
+pub fn chunk_id(source: &str, chunk_index: usize) -> String {
+    let hash: String = Sha256::digest(source.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}:{}", hash, chunk_index)
+}
+
+fn count_newlines(text: &str) -> usize {
+    text.bytes().filter(|&b| b == b'\n').count()
+}
+
 /// A file that has been collected and read for indexing.
 #[derive(Debug, Clone)]
 pub struct IndexedFile {
     pub path: PathBuf,
     pub content: String,
+    /// The encoding `content` was decoded from, `None` for UTF-8
+    pub encoding: Option<&'static str>,
+}
+
+/// Text decoded from a file, see [`decode_text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedText {
+    pub content: String,
+    /// Name of the encoding the text was decoded from (e.g. `windows-1252`),
+    /// `None` for UTF-8
+    pub encoding: Option<&'static str>,
+}
+
+/// Share of invalid characters above which a file with a UTF-16 byte order
+/// mark is treated as binary rather than decoded lossily.
+const MAX_INVALID_RATIO: f64 = 0.1;
+
+/// Share of control characters (other than whitespace and escapes) above
+/// which a file is treated as binary. Text has next to none, while they make
+/// up about a tenth of random bytes.
+const MAX_CONTROL_RATIO: f64 = 0.05;
+
+/// Decodes the bytes of a text file, or returns `None` if they look binary:
+/// they contain NUL bytes or many other control characters.
+///
+/// A byte order mark is stripped and selects the encoding, so UTF-16 files
+/// with one are decoded. Valid UTF-8 is used as is. A file that is mostly
+/// UTF-8 with some invalid bytes has them replaced with U+FFFD, with a
+/// warning. Otherwise, with `detect_encoding` set and the `encoding` feature
+/// enabled, its encoding is guessed (e.g. Latin-1) and used if it decodes the
+/// file cleanly, falling back to replacing invalid bytes however many there
+/// are, since text in another encoding can be mostly invalid UTF-8.
+pub(crate) fn decode_text(
+    path: &Path,
+    bytes: Vec<u8>,
+    detect_encoding: bool,
+) -> Option<DecodedText> {
+    if let Some(decoded) = decode_utf16_with_bom(&bytes) {
+        return decoded;
+    }
+    let bytes = match bytes.strip_prefix(b"\xef\xbb\xbf") {
+        Some(rest) => rest.to_vec(),
+        None => bytes,
+    };
+    if looks_binary(&bytes) {
+        return None;
+    }
+    let bytes = match String::from_utf8(bytes) {
+        Ok(content) => {
+            return Some(DecodedText {
+                content,
+                encoding: None,
+            })
+        }
+        Err(e) => e.into_bytes(),
+    };
+
+    let lossy = String::from_utf8_lossy(&bytes);
+    let has_utf8 = lossy
+        .chars()
+        .any(|c| !c.is_ascii() && c != char::REPLACEMENT_CHARACTER);
+    if detect_encoding && !has_utf8 {
+        if let Some(decoded) = decode_detected(&bytes) {
+            return Some(decoded);
+        }
+    }
+
+    let invalid = lossy
+        .chars()
+        .filter(|&c| c == char::REPLACEMENT_CHARACTER)
+        .count();
+    eprintln!(
+        "WARNING: {} isn't valid UTF-8, replaced {} invalid sequences",
+        path.display(),
+        invalid
+    );
+    Some(DecodedText {
+        content: lossy.into_owned(),
+        encoding: None,
+    })
+}
+
+/// Decodes text starting with a UTF-16 byte order mark: `None` if there is
+/// none, `Some(None)` if the decoded text looks binary.
+fn decode_utf16_with_bom(bytes: &[u8]) -> Option<Option<DecodedText>> {
+    let (rest, encoding, unit): (_, _, fn([u8; 2]) -> u16) =
+        if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+            (rest, "UTF-16LE", u16::from_le_bytes)
+        } else if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+            (rest, "UTF-16BE", u16::from_be_bytes)
+        } else {
+            return None;
+        };
+
+    let units = rest.chunks(2).map(|pair| match *pair {
+        [a, b] => unit([a, b]),
+        // A dangling byte can't be decoded
+        _ => 0xfffd,
+    });
+    let content: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+
+    let chars = content.chars().count();
+    let invalid = content
+        .chars()
+        .filter(|&c| c == char::REPLACEMENT_CHARACTER)
+        .count();
+    let controls = content.chars().filter(|&c| is_binary_control(c)).count();
+    if content.contains('\0')
+        || invalid as f64 > chars as f64 * MAX_INVALID_RATIO
+        || controls as f64 > chars as f64 * MAX_CONTROL_RATIO
+    {
+        return Some(None);
+    }
+    Some(Some(DecodedText {
+        content,
+        encoding: Some(encoding),
+    }))
+}
+
+/// Whether `bytes` look binary rather than text in an ASCII-compatible
+/// encoding: they contain a NUL or many other control characters.
+fn looks_binary(bytes: &[u8]) -> bool {
+    if bytes.contains(&0) {
+        return true;
+    }
+    let controls = bytes
+        .iter()
+        .filter(|&&byte| is_binary_control(char::from(byte)))
+        .count();
+    controls as f64 > bytes.len() as f64 * MAX_CONTROL_RATIO
+}
+
+/// ASCII control characters that don't turn up in text, unlike tabs, line
+/// breaks, form feeds and escape sequences.
+fn is_binary_control(c: char) -> bool {
+    c.is_ascii_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b')
+}
+
+/// Decodes `bytes` in their guessed encoding, if that decodes them cleanly.
+#[cfg(feature = "encoding")]
+fn decode_detected(bytes: &[u8]) -> Option<DecodedText> {
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, true);
+    let (content, malformed) = encoding.decode_without_bom_handling(bytes);
+    (!malformed).then(|| DecodedText {
+        content: content.into_owned(),
+        encoding: Some(encoding.name()),
+    })
+}
+
+/// Warns, once, that encodings can't be guessed without the `encoding` feature.
+#[cfg(not(feature = "encoding"))]
+fn decode_detected(_bytes: &[u8]) -> Option<DecodedText> {
+    static WARNED: std::sync::Once = std::sync::Once::new();
+    WARNED.call_once(|| {
+        eprintln!(
+            "WARNING: indexer.detect_encoding needs the `encoding` feature, \
+             replacing invalid UTF-8 instead"
+        )
+    });
+    None
 }
 
 /// Recursively collects all indexable files from a directory.
 ///
 /// Walks the directory tree starting from `dir_path`, filtering files based on
-/// the provided configuration. Binary files and unreadable files are silently skipped.
+/// the provided configuration. Binary files and unreadable files are silently skipped;
+/// use [`collect_files_with_report`] to find out which.
 ///
 /// # Filtering
 ///
@@ -132,22 +785,50 @@ pub struct IndexedFile {
 ///   If empty, all readable text files are indexed.
 /// - **Exclude patterns**: Directories or files matching patterns in `config.exclude_patterns`
 ///   are skipped (e.g., "node_modules", ".git").
+/// - **Size**: Files larger than `config.max_file_size` bytes are skipped.
 ///
 /// This function is internal to the RAG system. Use [`Rag::index_directory`](crate::rag::Rag::index_directory)
 /// for public-facing directory indexing.
 pub(crate) async fn collect_files(
     dir_path: impl AsRef<Path>,
     config: &IndexerConfig,
+    extractors: &Extractors,
 ) -> Result<Vec<IndexedFile>> {
-    let mut files = Vec::new();
-    collect_files_recursive(dir_path.as_ref(), &mut files, config).await?;
+    let (files, _) = collect_files_with_report(dir_path, config, extractors).await?;
     Ok(files)
 }
 
+/// Like [`collect_files`], but records skipped and unreadable files in a report.
+///
+/// Files with an extractor for their extension have their text extracted;
+/// files it fails on, or that yield no text, are skipped. Other files are
+/// decoded with [`decode_text`], skipping the ones that look binary.
+/// Files that cannot be read are recorded as errors, or abort collection when
+/// `config.abort_on_error` is set.
+pub(crate) async fn collect_files_with_report(
+    dir_path: impl AsRef<Path>,
+    config: &IndexerConfig,
+    extractors: &Extractors,
+) -> Result<(Vec<IndexedFile>, IndexReport)> {
+    let mut files = Vec::new();
+    let mut report = IndexReport::default();
+    collect_files_recursive(
+        dir_path.as_ref(),
+        &mut files,
+        &mut report,
+        config,
+        extractors,
+    )
+    .await?;
+    Ok((files, report))
+}
+
 fn collect_files_recursive<'a>(
     dir: &'a Path,
     files: &'a mut Vec<IndexedFile>,
+    report: &'a mut IndexReport,
     config: &'a IndexerConfig,
+    extractors: &'a Extractors,
 ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
     Box::pin(async move {
         let mut entries = fs::read_dir(dir).await?;
@@ -160,13 +841,47 @@ fn collect_files_recursive<'a>(
             }
 
             if path.is_dir() {
-                collect_files_recursive(&path, files, config).await?;
+                collect_files_recursive(&path, files, report, config, extractors).await?;
             } else if is_indexable(&path, &config.extensions) {
-                if let Ok(content) = fs::read_to_string(&path).await {
-                    files.push(IndexedFile {
-                        path: path.clone(),
-                        content,
-                    });
+                if let Ok(metadata) = fs::metadata(&path).await {
+                    if metadata.len() > config.max_file_size {
+                        report.skipped.push(path);
+                        continue;
+                    }
+                }
+
+                let bytes = match fs::read(&path).await {
+                    Ok(bytes) => bytes,
+                    Err(e) if config.abort_on_error => return Err(e.into()),
+                    Err(e) => {
+                        report.errors.push((path, e.to_string()));
+                        continue;
+                    }
+                };
+
+                if let Some(extractor) = extractors.for_path(&path) {
+                    match extractor.extract(&bytes) {
+                        Ok(content) if !content.trim().is_empty() => files.push(IndexedFile {
+                            path,
+                            content,
+                            encoding: None,
+                        }),
+                        Ok(_) => report.skipped.push(path),
+                        Err(e) => {
+                            eprintln!("WARNING: {}: {}", path.display(), e);
+                            report.skipped.push(path);
+                        }
+                    }
+                    continue;
+                }
+
+                match decode_text(&path, bytes, config.detect_encoding) {
+                    Some(decoded) => files.push(IndexedFile {
+                        path,
+                        content: decoded.content,
+                        encoding: decoded.encoding,
+                    }),
+                    None => report.skipped.push(path),
                 }
             }
         }
@@ -210,6 +925,18 @@ fn should_exclude(path: &Path, patterns: &[String]) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chunk_id_is_deterministic() {
+        let id = chunk_id("src/main.rs", 3);
+        assert_eq!(id, chunk_id("src/main.rs", 3));
+        assert_ne!(id, chunk_id("src/main.rs", 4));
+        assert_ne!(id, chunk_id("src/lib.rs", 3));
+
+        let (hash, index) = id.split_once(':').unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(index, "3");
+    }
+
     #[test]
     fn test_chunk_text_small() {
         let text = "Hello";
@@ -228,6 +955,140 @@ mod tests {
         assert_eq!(chunks[1], "89ABCDEF");
     }
 
+    #[test]
+    fn test_chunk_text_clamps_overlap_to_chunk_size() {
+        // An overlap as large as the chunk would never advance; it is reduced
+        // to chunk_size - 1 so every chunk starts one byte further
+        let chunks = chunk_text("abcdef", 4, 4);
+        assert_eq!(chunks, ["abcd", "bcde", "cdef"]);
+
+        let chunks = chunk_text("abcdef", 0, 0);
+        assert_eq!(chunks.concat(), "abcdef");
+    }
+
+    /// Collects every chunk from a [`StreamingChunker`] over `text`.
+    async fn stream_chunks(text: &str, chunk_size: usize, overlap: usize) -> Vec<TextChunk> {
+        let mut chunker =
+            StreamingChunker::new(text.as_bytes(), chunk_size, overlap).with_read_size(7);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = chunker.next_chunk().await.unwrap() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    #[tokio::test]
+    async fn test_streaming_chunker_matches_chunk_text_with_spans() {
+        let text = "fn main() {\n    println!(\"héllo wörld\");\n}\n\n// ✓ done\n".repeat(20);
+
+        for (chunk_size, overlap) in [(16, 0), (16, 5), (33, 10), (5000, 50)] {
+            assert_eq!(
+                stream_chunks(&text, chunk_size, overlap).await,
+                chunk_text_with_spans(&text, chunk_size, overlap),
+                "chunk_size {}, overlap {}",
+                chunk_size,
+                overlap
+            );
+        }
+        assert!(stream_chunks("", 16, 0).await.is_empty());
+    }
+
+    #[test]
+    fn test_chunk_text_with_spans_line_ranges() {
+        let text = "line1\nline2\nline3\nline4\n";
+        let chunks = chunk_text_with_spans(text, 12, 0);
+
+        assert_eq!(chunks.len(), 2);
+
+        assert_eq!(chunks[0].content, "line1\nline2\n");
+        assert_eq!((chunks[0].start_line, chunks[0].end_line), (1, 2));
+        assert_eq!((chunks[0].start_byte, chunks[0].end_byte), (0, 12));
+
+        assert_eq!(chunks[1].content, "line3\nline4\n");
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (3, 4));
+        assert_eq!((chunks[1].start_byte, chunks[1].end_byte), (12, 24));
+    }
+
+    #[test]
+    fn test_chunk_text_with_spans_overlap() {
+        let text = "aaaa\nbbbb\ncccc\ndddd";
+        let chunks = chunk_text_with_spans(text, 10, 4);
+
+        for chunk in &chunks {
+            assert_eq!(&text[chunk.start_byte..chunk.end_byte], chunk.content);
+            let expected_start = 1 + text[..chunk.start_byte].matches('\n').count();
+            assert_eq!(chunk.start_line, expected_start);
+        }
+
+        // The second chunk starts mid-way through line 2 and ends on line 4
+        assert_eq!(chunks[1].content, "bbb\ncccc\nd");
+        assert_eq!((chunks[1].start_line, chunks[1].end_line), (2, 4));
+    }
+
+    /// Word-level tokenizer with whitespace pre-tokenization, so every word
+    /// and run of punctuation is exactly one token.
+    fn word_tokenizer() -> Tokenizer {
+        let json = r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": { "type": "Whitespace" },
+            "post_processor": null,
+            "decoder": null,
+            "model": { "type": "WordLevel", "vocab": { "[UNK]": 0 }, "unk_token": "[UNK]" }
+        }"#;
+        json.parse().unwrap()
+    }
+
+    #[test]
+    fn test_chunk_text_by_tokens_respects_limit() {
+        let tokenizer = word_tokenizer();
+        let text =
+            "fn main() {\n    let greeting = \"hello\";\n    println!(\"{}\", greeting);\n}\n"
+                .repeat(4);
+        let count = |text: &str| tokenizer.encode(text, false).unwrap().len();
+
+        let chunks = chunk_text_by_tokens(&text, &tokenizer, 8, 2);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(
+                count(&chunk.content) <= 8,
+                "too many tokens: {:?}",
+                chunk.content
+            );
+            assert_eq!(&text[chunk.start_byte..chunk.end_byte], chunk.content);
+            let expected_start = 1 + text[..chunk.start_byte].matches('\n').count();
+            assert_eq!(chunk.start_line, expected_start);
+        }
+
+        // Without overlap, the chunks cover the text exactly
+        let chunks = chunk_text_by_tokens(&text, &tokenizer, 8, 0);
+        let joined: String = chunks.iter().map(|c| c.content.as_str()).collect();
+        assert_eq!(joined, text);
+    }
+
+    #[test]
+    fn test_token_chunking_falls_back_to_bytes() {
+        let config = IndexerConfig {
+            chunk_size: 12,
+            chunk_overlap: 0,
+            chunk_unit: ChunkUnit::Tokens,
+            ..IndexerConfig::default()
+        };
+        let text = "line1\nline2\nline3\nline4\n";
+
+        let without_tokenizer = Indexer::new(config.clone());
+        assert_eq!(
+            without_tokenizer.chunk_text(text),
+            vec!["line1\nline2\n", "line3\nline4\n"]
+        );
+
+        let with_tokenizer = Indexer::new(config).with_tokenizer(word_tokenizer());
+        assert_eq!(with_tokenizer.chunk_text(text), vec![text]);
+    }
+
     #[test]
     fn test_is_indexable() {
         let extensions = vec!["rs".to_string(), "md".to_string()];
@@ -263,4 +1124,45 @@ mod tests {
         assert!(should_exclude(Path::new("target/debug/main"), &patterns));
         assert!(!should_exclude(Path::new("src/main.rs"), &patterns));
     }
+
+    #[test]
+    fn test_decode_text_handles_byte_order_marks() {
+        let path = Path::new("notes.txt");
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("Déjà vu\n".encode_utf16().flat_map(u16::to_le_bytes));
+        let decoded = decode_text(path, utf16, false).unwrap();
+        assert_eq!(decoded.content, "Déjà vu\n");
+        assert_eq!(decoded.encoding, Some("UTF-16LE"));
+
+        let mut utf16 = vec![0xfe, 0xff];
+        utf16.extend("fn main() {}".encode_utf16().flat_map(u16::to_be_bytes));
+        let decoded = decode_text(path, utf16, false).unwrap();
+        assert_eq!(decoded.content, "fn main() {}");
+        assert_eq!(decoded.encoding, Some("UTF-16BE"));
+
+        let decoded = decode_text(path, b"\xef\xbb\xbfplain".to_vec(), false).unwrap();
+        assert_eq!(decoded.content, "plain");
+        assert_eq!(decoded.encoding, None);
+    }
+
+    #[test]
+    fn test_decode_text_rejects_binary_without_nul_bytes() {
+        let path = Path::new("blob.bin");
+        let binary: Vec<u8> = (1..=255).cycle().take(4096).collect();
+        assert_eq!(decode_text(path, binary.clone(), true), None);
+        assert_eq!(decode_text(path, binary, false), None);
+
+        let text = b"col1\tcol2\r\n\x1b[1mbold\x1b[0m\x0c\n".to_vec();
+        assert!(decode_text(path, text, true).is_some());
+    }
+
+    #[test]
+    fn test_decode_text_keeps_mostly_invalid_text() {
+        let path = Path::new("menu.txt");
+        // "crème brûlée" in Latin-1: a fifth of the characters are invalid UTF-8
+        let latin1 = b"cr\xe8me br\xfbl\xe9e".to_vec();
+        let decoded = decode_text(path, latin1, false).unwrap();
+        assert_eq!(decoded.content, "cr\u{fffd}me br\u{fffd}l\u{fffd}e");
+        assert_eq!(decoded.encoding, None);
+    }
 }
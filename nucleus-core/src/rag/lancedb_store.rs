@@ -4,11 +4,11 @@
 
 use crate::config::StorageConfig;
 
-use super::store::VectorStore;
-use super::types::{Document, SearchResult};
+use super::store::{eviction_cutoff, rank_results, SimilarityMetric, VectorStore};
+use super::types::{Document, MatchExplanation, SearchResult, INDEXED_AT};
 use anyhow::{Context, Result};
 use arrow_array::{
-    array::{ArrayRef, FixedSizeListArray, Float32Array, StringArray},
+    array::{ArrayRef, FixedSizeListArray, Float32Array, StringArray, UInt64Array},
     Array, RecordBatch, RecordBatchIterator,
 };
 use arrow_schema::DataType;
@@ -16,9 +16,55 @@ use arrow_schema::Field;
 use async_trait::async_trait;
 use futures::stream::TryStreamExt;
 use lancedb::arrow::arrow_schema::Schema;
-use lancedb::query::{ExecutableQuery, QueryBase};
-use lancedb::{connect, Connection, Table};
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::table::NewColumnTransform;
+use lancedb::{connect, Connection, DistanceType, Table};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Numeric metadata keys: where a chunk sits in its source file, and when it
+/// was indexed.
+///
+/// Each key is stored in its own nullable `UInt64` column so documents added
+/// without this information (e.g. via `add_knowledge`) remain valid.
+const NUMERIC_COLUMNS: [&str; 5] = [
+    "start_line",
+    "end_line",
+    "start_byte",
+    "end_byte",
+    INDEXED_AT,
+];
+
+/// Column holding the metadata without a column of its own (e.g. tags), as a
+/// JSON object of strings. Null when there is none.
+const METADATA_COLUMN: &str = "metadata";
+
+/// Whether `key` is stored in its own column, which holds `value` only if it
+/// is numeric for the numeric columns.
+fn has_column(key: &str, value: &str) -> bool {
+    key == "source" || (NUMERIC_COLUMNS.contains(&key) && value.parse::<u64>().is_ok())
+}
+
+/// LanceDB distance function used to search with `metric`.
+fn distance_type(metric: SimilarityMetric) -> DistanceType {
+    match metric {
+        SimilarityMetric::Cosine => DistanceType::Cosine,
+        SimilarityMetric::DotProduct => DistanceType::Dot,
+        SimilarityMetric::Euclidean => DistanceType::L2,
+    }
+}
+
+/// Converts a LanceDB `_distance` into a [`SearchResult`] score for `metric`.
+///
+/// LanceDB reports cosine and dot distances as `1 - similarity`, and L2 as
+/// the squared euclidean distance.
+fn score_from_distance(metric: SimilarityMetric, distance: f32) -> f32 {
+    match metric {
+        SimilarityMetric::Cosine | SimilarityMetric::DotProduct => 1.0 - distance,
+        SimilarityMetric::Euclidean => distance.max(0.0).sqrt(),
+    }
+}
 
 /// LanceDB-based vector store for embedded deployment.
 ///
@@ -41,9 +87,13 @@ impl VectorStore for LanceDbStore {
         let schema_ref = batch.schema();
         let reader = RecordBatchIterator::new(vec![Ok(batch)], schema_ref);
 
-        self.table
-            .add(reader)
-            .execute()
+        // Upsert by id, so documents that are added again replace their old rows
+        let mut merge = self.table.merge_insert(&["id"]);
+        merge
+            .when_matched_update_all(None)
+            .when_not_matched_insert_all();
+        merge
+            .execute(Box::new(reader))
             .await
             .map_err(|e| anyhow::anyhow!("Failed to add documents to LanceDB: {:?}", e))?;
 
@@ -51,6 +101,11 @@ impl VectorStore for LanceDbStore {
     }
 
     async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+        self.search_with_k(query_embedding, self.storage_config.top_k)
+            .await
+    }
+
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>> {
         use tracing::{debug, info};
 
         debug!("LanceDB search: opening table '{}'", self.table.name());
@@ -59,12 +114,14 @@ impl VectorStore for LanceDbStore {
         debug!(
             "LanceDB search: querying with embedding of size {}, limit={}",
             query_embedding.len(),
-            self.storage_config.top_k
+            k
         );
+        let metric = self.storage_config.vector_db.metric;
         let results = table
             .query()
-            .limit(self.storage_config.top_k)
+            .limit(k)
             .nearest_to(query_embedding)?
+            .distance_type(distance_type(metric))
             .execute()
             .await
             .context("Failed to execute LanceDB query")?;
@@ -82,57 +139,27 @@ impl VectorStore for LanceDbStore {
             let num_rows = batch.num_rows();
             debug!("Processing batch with {} rows", num_rows);
 
-            let id_col = batch.column_by_name("id").context("Missing 'id' column")?;
-            let content_col = batch
-                .column_by_name("content")
-                .context("Missing 'content' column")?;
-            let source_col = batch
-                .column_by_name("source")
-                .context("Missing 'source' column")?;
             let distance_col = batch
                 .column_by_name("_distance")
                 .context("Missing '_distance' column")?;
-
-            let id_array = id_col
-                .as_any()
-                .downcast_ref::<StringArray>()
-                .context("Failed to cast 'id' to StringArray")?;
-            let content_array = content_col
-                .as_any()
-                .downcast_ref::<StringArray>()
-                .context("Failed to cast 'content' to StringArray")?;
-            let source_array = source_col
-                .as_any()
-                .downcast_ref::<StringArray>()
-                .context("Failed to cast 'source' to StringArray")?;
             let distance_array = distance_col
                 .as_any()
                 .downcast_ref::<Float32Array>()
                 .context("Failed to cast '_distance' to Float32Array")?;
 
-            for i in 0..num_rows {
-                let id = id_array.value(i).to_string();
-                let content = content_array.value(i).to_string();
-                let distance = distance_array.value(i);
-
-                let mut metadata = std::collections::HashMap::new();
-                if !source_col.is_null(i) {
-                    metadata.insert("source".to_string(), source_array.value(i).to_string());
-                }
-
-                let document = Document {
-                    id,
-                    content,
-                    embedding: vec![],
-                    metadata,
-                };
-
-                let score = 1.0 - distance;
-
-                search_results.push(SearchResult { document, score });
+            for (i, document) in Self::batch_documents(&batch)?.into_iter().enumerate() {
+                let score = score_from_distance(metric, distance_array.value(i));
+                search_results.push(SearchResult {
+                    document,
+                    score,
+                    explanation: Some(MatchExplanation::vector(metric, score)),
+                    snippet: None,
+                });
             }
         }
 
+        rank_results(&self.storage_config.vector_db, &mut search_results);
+
         info!(
             "LanceDB search complete: found {} results",
             search_results.len()
@@ -162,11 +189,14 @@ impl VectorStore for LanceDbStore {
     }
 
     async fn get_indexed_paths(&self) -> Result<Vec<String>> {
-        use std::collections::HashSet;
+        Ok(self.count_by_source().await?.into_keys().collect())
+    }
 
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
         let table = self.conn.open_table(self.table.name()).execute().await?;
         let results = table
             .query()
+            .select(Select::columns(&["source"]))
             .execute()
             .await
             .context("Failed to query all documents")?;
@@ -176,7 +206,7 @@ impl VectorStore for LanceDbStore {
             .await
             .context("Failed to collect query results")?;
 
-        let mut unique_paths = HashSet::new();
+        let mut counts = BTreeMap::new();
 
         for batch in batches {
             let source_col = batch
@@ -189,12 +219,53 @@ impl VectorStore for LanceDbStore {
 
             for i in 0..batch.num_rows() {
                 if !source_array.is_null(i) {
-                    unique_paths.insert(source_array.value(i).to_string());
+                    *counts.entry(source_array.value(i).to_string()).or_insert(0) += 1;
                 }
             }
         }
 
-        Ok(unique_paths.into_iter().collect())
+        Ok(counts)
+    }
+
+    async fn get_by_source(&self, source: &str) -> Result<Vec<Document>> {
+        let table = self.conn.open_table(self.table.name()).execute().await?;
+        let results = table
+            .query()
+            .only_if(format!("source = '{}'", source.replace('\'', "''")))
+            .execute()
+            .await
+            .context("Failed to query documents by source")?;
+
+        let batches: Vec<RecordBatch> = results
+            .try_collect()
+            .await
+            .context("Failed to collect query results")?;
+
+        let mut documents = Vec::new();
+        for batch in &batches {
+            documents.extend(Self::batch_documents(batch)?);
+        }
+
+        Ok(documents)
+    }
+
+    async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        let filter = format!("{} < {}", INDEXED_AT, eviction_cutoff(age));
+
+        let table = self.conn.open_table(self.table.name()).execute().await?;
+        let count = table.count_rows(Some(filter.clone())).await?;
+        if count > 0 {
+            table
+                .delete(&filter)
+                .await
+                .context("Failed to evict stale documents")?;
+        }
+
+        Ok(count)
+    }
+
+    fn vector_size(&self) -> u64 {
+        self.vector_size
     }
 
     async fn remove_by_source(&self, source_path: &str) -> Result<usize> {
@@ -258,8 +329,78 @@ impl VectorStore for LanceDbStore {
 }
 
 impl LanceDbStore {
+    /// Reads the documents (without embeddings) stored in the rows of `batch`.
+    fn batch_documents(batch: &RecordBatch) -> Result<Vec<Document>> {
+        let id_col = batch.column_by_name("id").context("Missing 'id' column")?;
+        let content_col = batch
+            .column_by_name("content")
+            .context("Missing 'content' column")?;
+        let source_col = batch
+            .column_by_name("source")
+            .context("Missing 'source' column")?;
+
+        let id_array = id_col
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .context("Failed to cast 'id' to StringArray")?;
+        let content_array = content_col
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .context("Failed to cast 'content' to StringArray")?;
+        let source_array = source_col
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .context("Failed to cast 'source' to StringArray")?;
+
+        // Absent from batches of tables not yet migrated
+        let extra_array = batch
+            .column_by_name(METADATA_COLUMN)
+            .map(|col| {
+                col.as_any()
+                    .downcast_ref::<StringArray>()
+                    .context("Failed to cast 'metadata' to StringArray")
+            })
+            .transpose()?;
+
+        let mut documents = Vec::with_capacity(batch.num_rows());
+        for i in 0..batch.num_rows() {
+            let mut metadata = HashMap::new();
+            if let Some(extra) = extra_array.filter(|array| !array.is_null(i)) {
+                let extra: HashMap<String, String> = serde_json::from_str(extra.value(i))
+                    .with_context(|| {
+                        format!("Invalid metadata for document {}", id_array.value(i))
+                    })?;
+                metadata.extend(extra);
+            }
+            if !source_col.is_null(i) {
+                metadata.insert("source".to_string(), source_array.value(i).to_string());
+            }
+            for name in NUMERIC_COLUMNS {
+                let Some(col) = batch.column_by_name(name) else {
+                    continue;
+                };
+                let values = col
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .with_context(|| format!("Failed to cast '{}' to UInt64Array", name))?;
+                if !values.is_null(i) {
+                    metadata.insert(name.to_string(), values.value(i).to_string());
+                }
+            }
+
+            documents.push(Document {
+                id: id_array.value(i).to_string(),
+                content: content_array.value(i).to_string(),
+                embedding: vec![],
+                metadata,
+            });
+        }
+
+        Ok(documents)
+    }
+
     fn create_schema(vector_size: u64) -> Arc<Schema> {
-        Arc::new(Schema::new(vec![
+        let mut fields = vec![
             Field::new("id", DataType::Utf8, false),
             Field::new("content", DataType::Utf8, false),
             Field::new(
@@ -271,7 +412,70 @@ impl LanceDbStore {
                 false,
             ),
             Field::new("source", DataType::Utf8, true),
-        ]))
+        ];
+        fields.extend(Self::metadata_fields());
+
+        Arc::new(Schema::new(fields))
+    }
+
+    /// The nullable metadata columns, which older tables may be missing.
+    fn metadata_fields() -> Vec<Field> {
+        let mut fields: Vec<Field> = NUMERIC_COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::UInt64, true))
+            .collect();
+        fields.push(Field::new(METADATA_COLUMN, DataType::Utf8, true));
+        fields
+    }
+
+    /// Checks that an existing table stores vectors of the expected dimension.
+    ///
+    /// A table written with a different embedding model can't be searched with
+    /// the current one, so this fails early instead of on the first query.
+    async fn check_vector_size(table: &Table, vector_size: u64) -> Result<()> {
+        let schema = table.schema().await?;
+        let field = schema
+            .field_with_name("vector")
+            .context("LanceDB table has no vector column")?;
+
+        match field.data_type() {
+            DataType::FixedSizeList(_, size) if *size as u64 == vector_size => Ok(()),
+            DataType::FixedSizeList(_, size) => Err(anyhow::anyhow!(
+                "LanceDB table '{}' stores {}-dimensional embeddings but the embedder produces {}; \
+                 clear the knowledge base or use a different collection",
+                table.name(),
+                size,
+                vector_size
+            )),
+            other => Err(anyhow::anyhow!(
+                "LanceDB table '{}' has an unexpected vector column type {:?}",
+                table.name(),
+                other
+            )),
+        }
+    }
+
+    /// Adds any metadata columns missing from a table created by an older version.
+    ///
+    /// Existing rows get null values, which are read back as absent metadata.
+    async fn migrate_schema(table: &Table) -> Result<()> {
+        let schema = table.schema().await?;
+        let missing: Vec<Field> = Self::metadata_fields()
+            .into_iter()
+            .filter(|field| schema.field_with_name(field.name()).is_err())
+            .collect();
+
+        if !missing.is_empty() {
+            table
+                .add_columns(
+                    NewColumnTransform::AllNulls(Arc::new(Schema::new(missing))),
+                    None,
+                )
+                .await
+                .context("Failed to add metadata columns to LanceDB table")?;
+        }
+
+        Ok(())
     }
 
     fn create_record_batch(&self, documents: &[Document]) -> Result<RecordBatch> {
@@ -304,6 +508,29 @@ impl LanceDbStore {
         let id_array = StringArray::from(ids);
         let content_array = StringArray::from(contents);
         let source_array = StringArray::from(sources);
+        let numeric_arrays = NUMERIC_COLUMNS.iter().map(|name| {
+            let values: Vec<Option<u64>> = documents
+                .iter()
+                .map(|doc| doc.metadata.get(*name).and_then(|v| v.parse().ok()))
+                .collect();
+            Arc::new(UInt64Array::from(values)) as ArrayRef
+        });
+        let extra: Vec<Option<String>> = documents
+            .iter()
+            .map(|doc| {
+                let extra: BTreeMap<&String, &String> = doc
+                    .metadata
+                    .iter()
+                    .filter(|(key, value)| !has_column(key, value))
+                    .collect();
+                if extra.is_empty() {
+                    Ok(None)
+                } else {
+                    serde_json::to_string(&extra).map(Some)
+                }
+            })
+            .collect::<serde_json::Result<_>>()
+            .context("Failed to serialize document metadata")?;
 
         let vector_values = Float32Array::from(all_vector_values);
         let vector_array = FixedSizeListArray::new(
@@ -313,16 +540,16 @@ impl LanceDbStore {
             None,
         );
 
-        RecordBatch::try_new(
-            schema,
-            vec![
-                Arc::new(id_array) as ArrayRef,
-                Arc::new(content_array) as ArrayRef,
-                Arc::new(vector_array) as ArrayRef,
-                Arc::new(source_array) as ArrayRef,
-            ],
-        )
-        .context("Failed to create record batch")
+        let mut columns = vec![
+            Arc::new(id_array) as ArrayRef,
+            Arc::new(content_array) as ArrayRef,
+            Arc::new(vector_array) as ArrayRef,
+            Arc::new(source_array) as ArrayRef,
+        ];
+        columns.extend(numeric_arrays);
+        columns.push(Arc::new(StringArray::from(extra)) as ArrayRef);
+
+        RecordBatch::try_new(schema, columns).context("Failed to create record batch")
     }
 
     /// Creates a new LanceDB store and ensures the table exists.
@@ -342,10 +569,14 @@ impl LanceDbStore {
         let collection_name = &storage_config.vector_db.collection_name;
 
         let table = if table_names.contains(&collection_name.to_string()) {
-            conn.open_table(collection_name)
+            let table = conn
+                .open_table(collection_name)
                 .execute()
                 .await
-                .context("Failed to open LanceDB table")?
+                .context("Failed to open LanceDB table")?;
+            Self::check_vector_size(&table, vector_size).await?;
+            Self::migrate_schema(&table).await?;
+            table
         } else {
             let schema = Self::create_schema(vector_size);
 
@@ -363,3 +594,236 @@ impl LanceDbStore {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_location_metadata_round_trip() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().to_str().unwrap();
+        let store = LanceDbStore::new(StorageConfig::default(), path, 3)
+            .await
+            .unwrap();
+
+        let doc = Document::new("main.rs_chunk_0", "fn main() {}", vec![1.0, 0.0, 0.0])
+            .with_metadata("source", "src/main.rs")
+            .with_metadata("start_line", "120")
+            .with_metadata("end_line", "145")
+            .with_metadata("start_byte", "4096")
+            .with_metadata("end_byte", "4608");
+        let plain = Document::new("note_0", "no location", vec![0.0, 1.0, 0.0])
+            .with_metadata("source", "user_input");
+        store.add(vec![doc, plain]).await.unwrap();
+
+        let results = store.search(&[1.0, 0.0, 0.0]).await.unwrap();
+        let found = &results
+            .iter()
+            .find(|r| r.document.id == "main.rs_chunk_0")
+            .unwrap()
+            .document;
+
+        assert_eq!(found.metadata["start_line"], "120");
+        assert_eq!(found.metadata["end_line"], "145");
+        assert_eq!(found.metadata["start_byte"], "4096");
+        assert_eq!(found.metadata["end_byte"], "4608");
+        assert_eq!(found.location().as_deref(), Some("src/main.rs:120-145"));
+
+        let plain = &results
+            .iter()
+            .find(|r| r.document.id == "note_0")
+            .unwrap()
+            .document;
+        assert!(!plain.metadata.contains_key("start_line"));
+        assert_eq!(plain.location().as_deref(), Some("user_input"));
+    }
+
+    #[tokio::test]
+    async fn test_arbitrary_metadata_round_trip() {
+        let temp = tempdir().unwrap();
+        let store = LanceDbStore::new(StorageConfig::default(), temp.path().to_str().unwrap(), 3)
+            .await
+            .unwrap();
+
+        let doc = Document::new("lib.rs_chunk_2", "pub fn parse() {}", vec![1.0, 0.0, 0.0])
+            .with_metadata("source", "src/lib.rs")
+            .with_metadata("start_line", "40")
+            .with_metadata("language", "rust")
+            .with_metadata("tags", "parser,public")
+            .with_metadata("symbol", "parse")
+            .with_metadata("end_line", "not a number");
+        let expected = doc.metadata.clone();
+        store.add(vec![doc]).await.unwrap();
+
+        let results = store.search(&[1.0, 0.0, 0.0]).await.unwrap();
+        assert_eq!(results[0].document.metadata, expected);
+
+        let by_source = store.get_by_source("src/lib.rs").await.unwrap();
+        assert_eq!(by_source[0].metadata, expected);
+    }
+
+    #[tokio::test]
+    async fn test_reopen_checks_vector_size() {
+        let temp = tempdir().unwrap();
+        let path = temp.path().to_str().unwrap();
+        let store = LanceDbStore::new(StorageConfig::default(), path, 3)
+            .await
+            .unwrap();
+        store
+            .add(vec![Document::new("doc_0", "kept", vec![1.0, 0.0, 0.0])])
+            .await
+            .unwrap();
+        drop(store);
+
+        let err = LanceDbStore::new(StorageConfig::default(), path, 4)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("3-dimensional"), "{err}");
+
+        let store = LanceDbStore::new(StorageConfig::default(), path, 3)
+            .await
+            .unwrap();
+        assert_eq!(store.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_by_configured_metric() {
+        let docs = || {
+            vec![
+                Document::new("aligned_far", "", vec![10.0, 0.0, 0.0]),
+                Document::new("close", "", vec![0.9, 0.3, 0.0]),
+                Document::new("opposite", "", vec![-1.0, 0.0, 0.0]),
+            ]
+        };
+
+        for (metric, expected) in [
+            (
+                SimilarityMetric::Cosine,
+                ["aligned_far", "close", "opposite"],
+            ),
+            (
+                SimilarityMetric::DotProduct,
+                ["aligned_far", "close", "opposite"],
+            ),
+            (
+                SimilarityMetric::Euclidean,
+                ["close", "opposite", "aligned_far"],
+            ),
+        ] {
+            let temp = tempdir().unwrap();
+            let mut config = StorageConfig::default();
+            config.vector_db.metric = metric;
+            let store = LanceDbStore::new(config, temp.path().to_str().unwrap(), 3)
+                .await
+                .unwrap();
+            store.add(docs()).await.unwrap();
+
+            let results = store.search(&[1.0, 0.0, 0.0]).await.unwrap();
+            let ids: Vec<&str> = results.iter().map(|r| r.document.id.as_str()).collect();
+            assert_eq!(ids, expected, "ranking for {:?}", metric);
+        }
+
+        assert!((score_from_distance(SimilarityMetric::Euclidean, 25.0) - 5.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn test_normalized_scores_are_between_zero_and_one() {
+        let vectors = [
+            ("aligned_far", [10.0, 0.0, 0.0]),
+            ("close", [0.9, 0.3, 0.0]),
+            ("orthogonal", [0.0, 2.0, 0.0]),
+            ("opposite", [-1.0, 0.0, 0.0]),
+            ("opposite_far", [-20.0, 5.0, 0.0]),
+        ];
+        let query = [1.0, 0.0, 0.0];
+
+        for metric in [
+            SimilarityMetric::Cosine,
+            SimilarityMetric::DotProduct,
+            SimilarityMetric::Euclidean,
+        ] {
+            let temp = tempdir().unwrap();
+            let mut config = StorageConfig::default();
+            config.vector_db.metric = metric;
+            config.vector_db.normalize_scores = true;
+            let store = LanceDbStore::new(config, temp.path().to_str().unwrap(), 3)
+                .await
+                .unwrap();
+            store
+                .add(
+                    vectors
+                        .iter()
+                        .map(|(id, v)| Document::new(*id, "", v.to_vec()))
+                        .collect(),
+                )
+                .await
+                .unwrap();
+
+            let mut expected: Vec<(&str, f32)> = vectors
+                .iter()
+                .map(|(id, v)| (*id, metric.score(&query, v)))
+                .collect();
+            expected.sort_by(|a, b| {
+                let order = a.1.total_cmp(&b.1);
+                if metric.higher_is_better() {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+            let expected: Vec<&str> = expected.into_iter().map(|(id, _)| id).collect();
+
+            let results = store.search(&query).await.unwrap();
+            let ids: Vec<&str> = results.iter().map(|r| r.document.id.as_str()).collect();
+            assert_eq!(ids, expected, "ranking for {:?}", metric);
+            for result in &results {
+                assert!(
+                    (0.0..=1.0).contains(&result.score),
+                    "{:?} score {} for {}",
+                    metric,
+                    result.score,
+                    result.document.id
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_evict_older_than_removes_only_stale_documents() {
+        use std::time::SystemTime;
+
+        let temp = tempdir().unwrap();
+        let store = LanceDbStore::new(StorageConfig::default(), temp.path().to_str().unwrap(), 3)
+            .await
+            .unwrap();
+
+        let now = SystemTime::now();
+        let hours_ago = |hours: u64| now - Duration::from_secs(hours * 3600);
+        store
+            .add(vec![
+                Document::new("fresh", "", vec![1.0, 0.0, 0.0]).with_indexed_at(now),
+                Document::new("yesterday", "", vec![0.0, 1.0, 0.0]).with_indexed_at(hours_ago(24)),
+                Document::new("last_week", "", vec![0.0, 0.0, 1.0])
+                    .with_indexed_at(hours_ago(24 * 7)),
+                Document::new("undated", "", vec![1.0, 1.0, 0.0]),
+            ])
+            .await
+            .unwrap();
+
+        let evicted = store
+            .evict_older_than(Duration::from_secs(12 * 3600))
+            .await
+            .unwrap();
+        assert_eq!(evicted, 2);
+
+        let results = store.search(&[1.0, 0.0, 0.0]).await.unwrap();
+        let mut ids: Vec<&str> = results.iter().map(|r| r.document.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["fresh", "undated"]);
+        let fresh = results.iter().find(|r| r.document.id == "fresh").unwrap();
+        assert!(fresh.document.indexed_at().is_some());
+    }
+}
@@ -0,0 +1,222 @@
+//! In-memory vector store.
+//!
+//! Used in place of LanceDB when the embedded storage path can't be written
+//! to. Nothing is persisted: the knowledge base is lost when the process exits.
+
+use super::store::{eviction_cutoff, rank_results, VectorStore};
+use super::types::{Document, MatchExplanation, SearchResult};
+use crate::config::StorageConfig;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Vector store keeping every document in memory and searching them exhaustively.
+pub struct MemoryStore {
+    storage_config: StorageConfig,
+    documents: RwLock<Vec<Document>>,
+    vector_size: u64,
+}
+
+impl MemoryStore {
+    pub fn new(storage_config: StorageConfig, vector_size: u64) -> Self {
+        Self {
+            storage_config,
+            documents: RwLock::new(Vec::new()),
+            vector_size,
+        }
+    }
+}
+
+fn source(document: &Document) -> Option<&str> {
+    document.metadata.get("source").map(String::as_str)
+}
+
+#[async_trait]
+impl VectorStore for MemoryStore {
+    async fn add(&self, documents: Vec<Document>) -> Result<()> {
+        let mut stored = self.documents.write().unwrap();
+        for document in documents {
+            match stored.iter_mut().find(|d| d.id == document.id) {
+                Some(existing) => *existing = document,
+                None => stored.push(document),
+            }
+        }
+        Ok(())
+    }
+
+    async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+        self.search_with_k(query_embedding, self.storage_config.top_k)
+            .await
+    }
+
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        let metric = self.storage_config.vector_db.metric;
+        let mut results: Vec<SearchResult> = self
+            .documents
+            .read()
+            .unwrap()
+            .iter()
+            .map(|document| {
+                let score = metric.score(query_embedding, &document.embedding);
+                SearchResult {
+                    document: document.clone(),
+                    score,
+                    explanation: Some(MatchExplanation::vector(metric, score)),
+                    snippet: None,
+                }
+            })
+            .collect();
+
+        rank_results(&self.storage_config.vector_db, &mut results);
+        results.truncate(k);
+        Ok(results)
+    }
+
+    async fn count(&self) -> Result<usize> {
+        Ok(self.documents.read().unwrap().len())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.documents.write().unwrap().clear();
+        Ok(())
+    }
+
+    async fn get_indexed_paths(&self) -> Result<Vec<String>> {
+        let documents = self.documents.read().unwrap();
+        let paths: BTreeSet<&str> = documents.iter().filter_map(source).collect();
+        Ok(paths.into_iter().map(String::from).collect())
+    }
+
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
+        for source in self.documents.read().unwrap().iter().filter_map(source) {
+            *counts.entry(source.to_string()).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    async fn get_by_source(&self, source_path: &str) -> Result<Vec<Document>> {
+        Ok(self
+            .documents
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|document| source(document) == Some(source_path))
+            .map(|document| Document {
+                embedding: Vec::new(),
+                ..document.clone()
+            })
+            .collect())
+    }
+
+    async fn remove_by_source(&self, source_path: &str) -> Result<usize> {
+        let normalized_path = Path::new(source_path).to_string_lossy().replace('\\', "/");
+        let directory = format!("{}/", normalized_path);
+
+        let mut documents = self.documents.write().unwrap();
+        let before = documents.len();
+        documents.retain(|document| {
+            let Some(source) = source(document) else {
+                return true;
+            };
+            let source = source.replace('\\', "/");
+            source != normalized_path && !source.starts_with(&directory)
+        });
+
+        Ok(before - documents.len())
+    }
+
+    async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        let cutoff = UNIX_EPOCH + Duration::from_secs(eviction_cutoff(age));
+
+        let mut documents = self.documents.write().unwrap();
+        let before = documents.len();
+        documents.retain(|document| !document.indexed_at().is_some_and(|at| at < cutoff));
+
+        Ok(before - documents.len())
+    }
+
+    fn vector_size(&self) -> u64 {
+        self.vector_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rag::SimilarityMetric;
+
+    async fn top_match(metric: SimilarityMetric) -> SearchResult {
+        let mut storage_config = StorageConfig::default();
+        storage_config.vector_db.metric = metric;
+
+        let store = MemoryStore::new(storage_config, 2);
+        store
+            .add(vec![
+                Document::new("near", "near", vec![3.0, 4.0]),
+                Document::new("far", "far", vec![30.0, 40.0]),
+            ])
+            .await
+            .unwrap();
+
+        store.search(&[0.0, 0.0]).await.unwrap().remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_search_explains_vector_matches() {
+        let result = top_match(SimilarityMetric::Euclidean).await;
+        assert_eq!(
+            result.explanation,
+            Some(MatchExplanation {
+                metric: SimilarityMetric::Euclidean,
+                distance: 5.0,
+                matched_terms: Vec::new(),
+            })
+        );
+
+        let result = top_match(SimilarityMetric::DotProduct).await;
+        let explanation = result.explanation.unwrap();
+        assert_eq!(explanation.metric, SimilarityMetric::DotProduct);
+        assert_eq!(explanation.distance, 1.0 - result.score);
+    }
+
+    #[tokio::test]
+    async fn test_search_with_k_overrides_top_k() {
+        let storage_config = StorageConfig {
+            top_k: 2,
+            ..StorageConfig::default()
+        };
+        let store = MemoryStore::new(storage_config, 2);
+        let documents = (0..5)
+            .map(|i| Document::new(format!("doc{}", i), "content", vec![1.0, i as f32]))
+            .collect();
+        store.add(documents).await.unwrap();
+
+        assert_eq!(store.search(&[1.0, 0.0]).await.unwrap().len(), 2);
+        assert_eq!(store.search_with_k(&[1.0, 0.0], 3).await.unwrap().len(), 3);
+        assert_eq!(store.search_with_k(&[1.0, 0.0], 10).await.unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_count_by_source() {
+        let store = MemoryStore::new(StorageConfig::default(), 2);
+        let documents = [("b.md", 0), ("a.md", 1), ("b.md", 2)]
+            .into_iter()
+            .map(|(source, i)| {
+                Document::new(format!("doc{}", i), "content", vec![1.0, 0.0])
+                    .with_metadata("source", source)
+            })
+            .chain([Document::new("unsourced", "content", vec![1.0, 0.0])])
+            .collect();
+        store.add(documents).await.unwrap();
+
+        let counts = store.count_by_source().await.unwrap();
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            [("a.md".to_string(), 1), ("b.md".to_string(), 2)]
+        );
+    }
+}
@@ -38,25 +38,47 @@
 //!    - Context is added to the LLM prompt
 //!    - LLM generates response using the context
 
+mod cache;
 mod embedder;
+mod extract;
 mod indexer;
 mod lancedb_store;
+mod memory_store;
 mod qdrant_store;
+mod retry;
+mod rewrite;
+mod snippet;
 mod store;
 mod types;
 pub mod utils;
+mod watch;
 
+#[cfg(feature = "docx")]
+pub use extract::DocxExtractor;
+#[cfg(feature = "pdf")]
+pub use extract::PdfExtractor;
+pub use extract::{ExtractError, TextExtractor};
+pub use indexer::{chunk_id, StreamingChunker, TextChunk};
 #[allow(unused)]
-pub use types::{Document, SearchResult};
+pub use types::{
+    ChunkPreview, ContextTemplate, Document, EmbedProgress, IndexProgress, IndexReport,
+    IndexedSource, MatchExplanation, SearchResult,
+};
 
-use crate::config::Config;
+use crate::config::{Config, ExpansionConfig, SnippetConfig, VectorDbConfig};
 use crate::provider::Provider;
+use cache::{InvalidatingStore, RetrievalCache};
 use embedder::Embedder;
 use indexer::Indexer;
-use std::path::Path;
+use rewrite::QueryRewriter;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+pub use store::SimilarityMetric;
 use store::{create_vector_store, VectorStore};
 use thiserror::Error;
+pub use watch::WATCH_DEBOUNCE;
 
 #[derive(Debug, Error)]
 pub enum RagError {
@@ -68,10 +90,81 @@ pub enum RagError {
 
     #[error("Failed to retrieve context: {0}")]
     Retrieval(String),
+
+    #[error("Failed to watch directory: {0}")]
+    Watch(#[from] notify::Error),
 }
 
 pub type Result<T> = std::result::Result<T, RagError>;
 
+/// Chunks embedded per request when indexing.
+const EMBED_BATCH_SIZE: usize = 32;
+
+/// Records that `path` failed to index, keeping only its first error.
+fn record_error(report: &mut IndexReport, path: PathBuf, error: String) {
+    if !report.errors.iter().any(|(p, _)| *p == path) {
+        report.errors.push((path, error));
+    }
+}
+
+/// A chunk waiting to be embedded.
+struct PendingChunk {
+    id: String,
+    chunk: TextChunk,
+    /// File the chunk came from
+    source: String,
+    /// Index of the chunk in its file
+    chunk_idx: usize,
+    /// The file's encoding, if it isn't UTF-8
+    encoding: Option<&'static str>,
+    /// Directory the file was indexed from
+    root: Option<String>,
+}
+
+impl PendingChunk {
+    fn into_document(self, embedding: Vec<f32>) -> Document {
+        chunk_document(
+            self.id,
+            self.chunk,
+            embedding,
+            self.source,
+            self.chunk_idx,
+            self.encoding,
+            self.root.as_deref(),
+        )
+    }
+}
+
+/// Builds a document for a chunk of a file, recording where in the file it
+/// came from, the file's encoding if it isn't UTF-8 and the directory it was
+/// indexed from (`root`), if any.
+fn chunk_document(
+    id: String,
+    chunk: TextChunk,
+    embedding: Vec<f32>,
+    source: impl Into<String>,
+    chunk_idx: usize,
+    encoding: Option<&str>,
+    root: Option<&str>,
+) -> Document {
+    let document = Document::new(id, chunk.content, embedding)
+        .with_metadata("source", source)
+        .with_metadata("chunk", chunk_idx.to_string())
+        .with_metadata("start_line", chunk.start_line.to_string())
+        .with_metadata("end_line", chunk.end_line.to_string())
+        .with_metadata("start_byte", chunk.start_byte.to_string())
+        .with_metadata("end_byte", chunk.end_byte.to_string())
+        .with_indexed_at(SystemTime::now());
+    let document = match encoding {
+        Some(encoding) => document.with_metadata("encoding", encoding),
+        None => document,
+    };
+    match root {
+        Some(root) => document.with_metadata("root", root),
+        None => document,
+    }
+}
+
 /// The main RAG manager orchestrating all components.
 ///
 /// The manager ties together the embedder, vector store, and indexer to provide
@@ -90,11 +183,23 @@ pub type Result<T> = std::result::Result<T, RagError>;
 /// - `rag.chunk_size`: Size of text chunks in bytes
 /// - `rag.chunk_overlap`: Overlap between chunks in bytes
 /// - `storage.top_k`: Number of results to return from searches
+/// - `storage.rag_context_count`: Number of those results used as context
+/// - `rag.expansion`: Whether to add neighboring chunks to search results
+/// - `rag.cache`: How many recent retrievals to reuse for repeated queries
+/// - `rag.snippets`: Whether results carry excerpts highlighting the query terms
 #[derive(Clone)]
 pub struct RagEngine {
     embedder: Embedder,
     store: Arc<dyn VectorStore>,
     indexer: Indexer,
+    context_template: ContextTemplate,
+    expansion: ExpansionConfig,
+    snippets: SnippetConfig,
+    source_weights: BTreeMap<String, f32>,
+    rewriter: Option<Arc<QueryRewriter>>,
+    vector_db: VectorDbConfig,
+    context_count: Option<usize>,
+    cache: Arc<RetrievalCache>,
 }
 
 impl RagEngine {
@@ -113,18 +218,25 @@ impl RagEngine {
     /// ```
     pub async fn new(config: &Config, provider: Arc<dyn Provider>) -> Result<Self> {
         let rag = config.rag.clone().unwrap();
+        let rewriter = rag.query_rewrite.enabled.then(|| {
+            Arc::new(QueryRewriter::new(
+                Arc::clone(&provider),
+                config.llm.model.clone(),
+                rag.query_rewrite.clone(),
+            ))
+        });
         let embedder = Embedder::new(provider, rag.embedding_model.clone());
 
+        let embedding_dim =
+            detect_embedding_dim(&embedder, rag.embedding_model.embedding_dim).await;
         let store = create_vector_store(
-            config.storage.clone(),
-            rag
-                .embedding_model
-                .embedding_dim
-                .try_into()
-                .unwrap_or_default(),
+            config.resolved_storage(),
+            embedding_dim.try_into().unwrap_or_default(),
         )
         .await
         .map_err(|e| RagError::Retrieval(e.to_string()))?;
+        let cache = Arc::new(RetrievalCache::new(&rag.cache));
+        let store = Arc::new(InvalidatingStore::new(store, Arc::clone(&cache)));
 
         let mut indexer_config = rag.indexer.clone();
 
@@ -136,8 +248,25 @@ impl RagEngine {
             embedder,
             store,
             indexer,
+            context_template: rag.context_template.clone(),
+            expansion: rag.expansion.clone(),
+            snippets: rag.snippets.clone(),
+            source_weights: rag.source_weights.clone(),
+            rewriter,
+            vector_db: config.storage.vector_db.clone(),
+            context_count: config.storage.rag_context_count,
+            cache,
         })
     }
+
+    /// Indexes files with `extractor`'s extensions using the text it extracts,
+    /// e.g. to support a document format without a built-in extractor.
+    ///
+    /// It takes precedence over built-in extractors for the same extensions.
+    pub fn with_extractor(mut self, extractor: impl TextExtractor + 'static) -> Self {
+        self.indexer = self.indexer.with_extractor(Arc::new(extractor));
+        self
+    }
     /// Adds a single piece of text to the knowledge base.
     ///
     /// The text is embedded and stored as a single document. For large texts,
@@ -158,7 +287,9 @@ impl RagEngine {
 
         let count = self.store.count().await.unwrap_or(0);
         let id = format!("{}_{}", source, count);
-        let document = Document::new(id, content, embedding).with_metadata("source", source);
+        let document = Document::new(id, content, embedding)
+            .with_metadata("source", source)
+            .with_indexed_at(SystemTime::now());
 
         self.store
             .add(vec![document])
@@ -170,7 +301,7 @@ impl RagEngine {
     async fn process_batch(
         &self,
         chunk_batch: &mut Vec<String>,
-        chunk_metadata: &mut Vec<(String, String, String, usize)>,
+        chunk_metadata: &mut Vec<PendingChunk>,
     ) -> Result<()> {
         use tracing::info;
 
@@ -178,17 +309,13 @@ impl RagEngine {
         let chunk_refs: Vec<&str> = chunk_batch.iter().map(|s| s.as_str()).collect();
 
         info!("Calling embed_batch for {} texts", chunk_refs.len());
-        let embeddings = self.embedder.embed_batch(&chunk_refs).await?;
+        let embeddings = self.embed_with_retries(&chunk_refs).await?;
         info!("Received {} embeddings", embeddings.len());
 
         let documents: Vec<Document> = embeddings
             .into_iter()
             .zip(chunk_metadata.drain(..))
-            .map(|(embedding, (id, content, source, chunk_idx))| {
-                Document::new(id, content, embedding)
-                    .with_metadata("source", source)
-                    .with_metadata("chunk", chunk_idx.to_string())
-            })
+            .map(|(embedding, pending)| pending.into_document(embedding))
             .collect();
 
         self.store
@@ -201,6 +328,28 @@ impl RagEngine {
         Ok(())
     }
 
+    /// Embeds `texts`, trying again up to `indexer.embed_retries` times if it
+    /// fails, backing off exponentially between attempts.
+    async fn embed_with_retries(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let mut retries = 0;
+        loop {
+            match self.embedder.embed_batch(texts).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(e) if retries < self.indexer.embed_retries() => {
+                    retries += 1;
+                    let backoff = retry::backoff(
+                        self.indexer.embed_initial_backoff(),
+                        self.indexer.embed_max_backoff(),
+                        retries,
+                    );
+                    tracing::warn!(retries, "Retrying failed embedding in {:?}: {}", backoff, e);
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
     /// Recursively indexes all code files in a directory.
     ///
     /// Walks the directory tree, collecting indexable files (see [`indexer`] for
@@ -209,7 +358,9 @@ impl RagEngine {
     /// 2. Each chunk is embedded
     /// 3. Chunks are stored with file path and chunk index metadata
     ///
-    /// Progress is printed to stdout as files are indexed.
+    /// Progress is printed to stdout as files are indexed. Use
+    /// [`index_directory_report`](Self::index_directory_report) to find out which
+    /// files were skipped or failed.
     ///
     /// # Arguments
     ///
@@ -223,10 +374,88 @@ impl RagEngine {
     ///
     /// Returns an error if:
     /// - The directory doesn't exist or isn't accessible
-    /// - Embedding generation fails for any chunk
+    /// - A file fails to index and `indexer.abort_on_error` is set
     ///
     pub async fn index_directory(&self, dir_path: &Path) -> Result<usize> {
-        let files = self.indexer.collect_files(dir_path).await?;
+        Ok(self.index_directory_report(dir_path).await?.indexed_count())
+    }
+
+    /// Recursively indexes all code files in a directory, reporting the outcome
+    /// for every file.
+    ///
+    /// Binary, empty and oversized (see `indexer.max_file_size`) files are
+    /// recorded as skipped. Unreadable files and files whose chunks failed to
+    /// embed or store are recorded as errors, and indexing carries on with the
+    /// remaining files - unless `indexer.abort_on_error` is set, in which case
+    /// the first failure is returned as an error.
+    ///
+    /// Failed embeddings are retried `indexer.embed_retries` times. A chunk
+    /// that still fails only fails its own file; the rest of its batch is stored.
+    ///
+    /// With `indexer.dedup` set, a chunk whose content hash matches a chunk
+    /// already indexed in this run is not stored again.
+    ///
+    /// Chunks stored for a file by an earlier run are removed before its new
+    /// chunks are queued, so a file that shrank leaves nothing stale behind.
+    /// This also clears points stored by Qdrant before chunk ids became
+    /// deterministic, which are otherwise left next to their replacements.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory doesn't exist or isn't accessible, or on
+    /// the first failure when `indexer.abort_on_error` is set.
+    pub async fn index_directory_report(&self, dir_path: &Path) -> Result<IndexReport> {
+        self.index_directory_with_progress(dir_path, |_| {}).await
+    }
+
+    /// Like [`index_directory_report`](Self::index_directory_report), calling
+    /// `on_progress` after each file has been processed.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use nucleus_core::rag::RagEngine;
+    /// # use std::path::Path;
+    /// # async fn example(engine: RagEngine) {
+    /// let report = engine
+    ///     .index_directory_with_progress(Path::new("./src"), |progress| {
+    ///         println!("{}", progress);
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub async fn index_directory_with_progress<F>(
+        &self,
+        dir_path: &Path,
+        on_progress: F,
+    ) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+    {
+        self.index_directory_with_embed_progress(dir_path, on_progress, |_| {})
+            .await
+    }
+
+    /// Like [`index_directory_with_progress`](Self::index_directory_with_progress),
+    /// also calling `on_embedded` after each batch of chunks is embedded.
+    ///
+    /// Files are chunked one at a time as they are indexed, so the total
+    /// number of chunks grows until the last file is reached (see
+    /// [`EmbedProgress`]).
+    pub async fn index_directory_with_embed_progress<F, E>(
+        &self,
+        dir_path: &Path,
+        mut on_progress: F,
+        mut on_embedded: E,
+    ) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+        E: FnMut(&EmbedProgress) + Send,
+    {
+        let (files, mut report) = self.indexer.collect_files_with_report(dir_path).await?;
+        let total = files.len();
+        let root = dir_path.to_string_lossy().to_string();
 
         use tracing::{debug, info};
         info!("Found {} files to index", files.len());
@@ -235,55 +464,256 @@ impl RagEngine {
         }
         info!("Starting indexing...");
 
-        let mut indexed_count = 0;
+        let mut queued = Vec::new();
+        let mut seen_hashes = HashSet::new();
 
-        const BATCH_SIZE: usize = 32;
         let mut chunk_batch = Vec::new();
         let mut chunk_metadata = Vec::new();
+        let mut embedded_chunks = 0;
+        let mut total_chunks = 0;
+
+        for (processed, file) in files.into_iter().enumerate() {
+            let progress = IndexProgress {
+                processed: processed + 1,
+                total,
+                current: file.path.clone(),
+            };
+            let all_chunked = processed + 1 == total;
+
+            let source = file.path.to_string_lossy().to_string();
+            if let Err(e) = self.store.remove_by_source(&source).await {
+                if self.indexer.abort_on_error() {
+                    return Err(RagError::Retrieval(e.to_string()));
+                }
+                record_error(&mut report, file.path, e.to_string());
+                on_progress(&progress);
+                continue;
+            }
 
-        for file in files {
             if file.content.is_empty() {
                 eprintln!("WARNING: File has empty content: {}", file.path.display());
+                report.skipped.push(file.path);
+                on_progress(&progress);
                 continue;
             }
 
-            let chunks = self.indexer.chunk_text(&file.content);
-
+            let chunks = self.indexer.chunk_text_with_spans(&file.content);
             if chunks.is_empty() {
                 eprintln!(
                     "WARNING: No chunks created for file: {}",
                     file.path.display()
                 );
+                report.skipped.push(file.path);
+                on_progress(&progress);
                 continue;
             }
-
+            let mut unique = Vec::with_capacity(chunks.len());
             for (i, chunk) in chunks.into_iter().enumerate() {
-                chunk_batch.push(chunk.clone());
-                chunk_metadata.push((
-                    format!("{}_chunk_{}", file.path.display(), i),
+                if self.indexer.dedup()
+                    && !seen_hashes.insert(indexer::content_hash(&chunk.content))
+                {
+                    report.duplicate_chunks += 1;
+                    continue;
+                }
+                unique.push((i, chunk));
+            }
+            total_chunks += unique.len();
+
+            for (i, chunk) in unique {
+                chunk_batch.push(chunk.content.clone());
+                chunk_metadata.push(PendingChunk {
+                    id: indexer::chunk_id(&source, i),
                     chunk,
-                    file.path.to_string_lossy().to_string(),
-                    i,
-                ));
+                    source: source.clone(),
+                    chunk_idx: i,
+                    encoding: file.encoding,
+                    root: Some(root.clone()),
+                });
 
-                // Process batch when it reaches BATCH_SIZE
-                if chunk_batch.len() >= BATCH_SIZE {
-                    self.process_batch(&mut chunk_batch, &mut chunk_metadata)
+                // Process batch when it reaches EMBED_BATCH_SIZE
+                if chunk_batch.len() >= EMBED_BATCH_SIZE {
+                    embedded_chunks += chunk_batch.len();
+                    self.flush_batch(&mut chunk_batch, &mut chunk_metadata, &mut report)
                         .await?;
+                    on_embedded(&EmbedProgress {
+                        embedded_chunks,
+                        total_chunks,
+                        all_chunked,
+                    });
                 }
             }
 
-            indexed_count += 1;
             println!("✓ Indexed: {}", file.path.display());
+            queued.push(file.path);
+            on_progress(&progress);
         }
 
         // Process remaining chunks
         if !chunk_batch.is_empty() {
+            embedded_chunks += chunk_batch.len();
+            self.flush_batch(&mut chunk_batch, &mut chunk_metadata, &mut report)
+                .await?;
+            on_embedded(&EmbedProgress {
+                embedded_chunks,
+                total_chunks,
+                all_chunked: true,
+            });
+        }
+
+        // A file only counts as indexed if none of its batches failed
+        let failed: HashSet<PathBuf> = report.errors.iter().map(|(p, _)| p.clone()).collect();
+        report.indexed = queued
+            .into_iter()
+            .filter(|path| !failed.contains(path))
+            .collect();
+
+        Ok(report)
+    }
+
+    /// Indexes a single file without reading it into memory at once, replacing
+    /// any chunks previously stored for it.
+    ///
+    /// The file is chunked as it is read (see [`StreamingChunker`]) and the
+    /// chunks are embedded and stored in batches, so memory use stays bounded
+    /// however large the file is. Chunks are always measured in bytes. Like
+    /// [`index_file`](Self::index_file), the chunks keep the directory the file
+    /// was first indexed from.
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or isn't valid UTF-8, if the
+    /// old chunks can't be removed, or if a batch fails to embed or store.
+    /// Batches stored before the failure are kept.
+    pub async fn index_file_streaming(&self, path: &Path) -> Result<usize> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(indexer::IndexerError::from)?;
+        let mut chunker = self.indexer.streaming_chunker(file);
+        let source = path.to_string_lossy().to_string();
+
+        let root = self.stored_root(&source).await?;
+        self.store
+            .remove_by_source(&source)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+
+        let mut seen_hashes = HashSet::new();
+        let mut chunk_batch = Vec::new();
+        let mut chunk_metadata = Vec::new();
+        let mut chunk_idx = 0;
+        let mut stored = 0;
+
+        while let Some(chunk) = chunker.next_chunk().await? {
+            let i = chunk_idx;
+            chunk_idx += 1;
+            if self.indexer.dedup() && !seen_hashes.insert(indexer::content_hash(&chunk.content)) {
+                continue;
+            }
+
+            chunk_batch.push(chunk.content.clone());
+            chunk_metadata.push(PendingChunk {
+                id: indexer::chunk_id(&source, i),
+                chunk,
+                source: source.clone(),
+                chunk_idx: i,
+                encoding: None,
+                root: root.clone(),
+            });
+
+            if chunk_batch.len() >= EMBED_BATCH_SIZE {
+                stored += chunk_batch.len();
+                self.process_batch(&mut chunk_batch, &mut chunk_metadata)
+                    .await?;
+            }
+        }
+
+        if !chunk_batch.is_empty() {
+            stored += chunk_batch.len();
             self.process_batch(&mut chunk_batch, &mut chunk_metadata)
                 .await?;
         }
 
-        Ok(indexed_count)
+        Ok(stored)
+    }
+
+    /// Processes a batch, recording a failure against every file with chunks in
+    /// it rather than failing the whole run (unless `abort_on_error` is set).
+    ///
+    /// If the batch fails to embed, its chunks are embedded one at a time so a
+    /// single bad chunk only costs its own file, and the rest are still stored.
+    async fn flush_batch(
+        &self,
+        chunk_batch: &mut Vec<String>,
+        chunk_metadata: &mut Vec<PendingChunk>,
+        report: &mut IndexReport,
+    ) -> Result<()> {
+        let mut sources: Vec<String> = chunk_metadata
+            .iter()
+            .map(|pending| pending.source.clone())
+            .collect();
+        sources.dedup();
+
+        let e = match self.process_batch(chunk_batch, chunk_metadata).await {
+            Ok(()) => return Ok(()),
+            Err(e) if self.indexer.abort_on_error() => return Err(e),
+            Err(RagError::Embedder(_)) if chunk_batch.len() > 1 => {
+                return self
+                    .flush_chunks_individually(chunk_batch, chunk_metadata, report)
+                    .await;
+            }
+            Err(e) => e,
+        };
+
+        eprintln!("WARNING: Failed to index batch: {}", e);
+        chunk_batch.clear();
+        chunk_metadata.clear();
+        for source in sources {
+            record_error(report, PathBuf::from(source), e.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Embeds the chunks of a batch that failed to embed one by one, recording
+    /// the chunks that still fail and storing the others.
+    async fn flush_chunks_individually(
+        &self,
+        chunk_batch: &mut Vec<String>,
+        chunk_metadata: &mut Vec<PendingChunk>,
+        report: &mut IndexReport,
+    ) -> Result<()> {
+        let mut documents = Vec::new();
+        for (text, pending) in chunk_batch.drain(..).zip(chunk_metadata.drain(..)) {
+            match self.embed_with_retries(&[text.as_str()]).await {
+                Ok(mut embeddings) => documents.push(pending.into_document(embeddings.remove(0))),
+                Err(e) => {
+                    eprintln!("WARNING: Failed to embed {}: {}", pending.id, e);
+                    record_error(
+                        report,
+                        PathBuf::from(pending.source),
+                        format!("chunk {}: {}", pending.chunk_idx, e),
+                    );
+                }
+            }
+        }
+
+        let mut sources: Vec<String> = documents
+            .iter()
+            .filter_map(|document| document.metadata.get("source").cloned())
+            .collect();
+        sources.dedup();
+        if let Err(e) = self.store.add(documents).await {
+            eprintln!("WARNING: Failed to index batch: {}", e);
+            for source in sources {
+                record_error(report, PathBuf::from(source), e.to_string());
+            }
+        }
+
+        Ok(())
     }
 
     /// Indexes multiple directories in batch.
@@ -328,9 +758,14 @@ impl RagEngine {
         Ok(total_count)
     }
 
-    /// Indexes a single file directly.
+    /// Indexes a single file directly, replacing any chunks previously stored
+    /// for it.
     ///
-    /// This is useful for indexing individual files outside of directory traversal.
+    /// This is useful for indexing individual files outside of directory
+    /// traversal, such as a file that was just edited. The indexer's extension
+    /// and exclude filters don't apply. The chunks are embedded in batches
+    /// before the old ones are removed, so a file that fails to embed keeps
+    /// its previous chunks.
     ///
     /// # Arguments
     ///
@@ -345,27 +780,46 @@ impl RagEngine {
     /// Returns an error if:
     /// - The file cannot be read
     /// - Embedding generation fails
+    /// - The old chunks can't be removed or the new ones stored
     ///
     pub async fn index_file(&self, file_path: &str) -> Result<usize> {
-        use tokio::fs;
-
-        let content = fs::read_to_string(file_path)
-            .await
-            .map_err(|e| RagError::Indexer(indexer::IndexerError::Io(e)))?;
-
-        let chunks = self.indexer.chunk_text(&content);
+        let decoded = self.indexer.read_decoded(Path::new(file_path)).await?;
+        let chunks = self.indexer.chunk_text_with_spans(&decoded.content);
         let chunk_count = chunks.len();
 
-        for (i, chunk) in chunks.into_iter().enumerate() {
-            let embedding = self.embedder.embed(&chunk).await?;
+        let root = self.stored_root(file_path).await?;
 
-            let id = format!("{}_chunk_{}", file_path, i);
-            let document = Document::new(id, chunk, embedding)
-                .with_metadata("source", file_path)
-                .with_metadata("chunk", i.to_string());
+        let mut embeddings = Vec::with_capacity(chunk_count);
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.content.as_str()).collect();
+        for batch in texts.chunks(EMBED_BATCH_SIZE) {
+            embeddings.extend(self.embed_with_retries(batch).await?);
+        }
+        let documents: Vec<Document> = chunks
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(i, (chunk, embedding))| {
+                let id = indexer::chunk_id(file_path, i);
+                let encoding = decoded.encoding;
+                chunk_document(
+                    id,
+                    chunk,
+                    embedding,
+                    file_path,
+                    i,
+                    encoding,
+                    root.as_deref(),
+                )
+            })
+            .collect();
 
+        self.store
+            .remove_by_source(file_path)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+        if !documents.is_empty() {
             self.store
-                .add(vec![document])
+                .add(documents)
                 .await
                 .map_err(|e| RagError::Retrieval(e.to_string()))?;
         }
@@ -374,6 +828,152 @@ impl RagEngine {
         Ok(chunk_count)
     }
 
+    /// The directory `source` was first indexed from, which source weights are
+    /// matched relative to, so that indexing it again keeps it.
+    async fn stored_root(&self, source: &str) -> Result<Option<String>> {
+        Ok(self
+            .store
+            .get_by_source(source)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?
+            .into_iter()
+            .find_map(|document| document.metadata.get("root").cloned()))
+    }
+
+    /// Brings the knowledge base up to date with a single file.
+    ///
+    /// Chunks previously stored for `path` are removed, then the file is indexed
+    /// again if it still exists and passes the indexer's extension and exclude
+    /// filters.
+    ///
+    /// # Returns
+    ///
+    /// The number of chunks stored for the file, `0` if it was removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the old chunks can't be removed, or if the file can't
+    /// be read or embedded.
+    pub async fn reindex_file(&self, path: &Path) -> Result<usize> {
+        let source = path.to_string_lossy();
+        let indexable = path.is_file()
+            && self.indexer.should_index(path)
+            && tokio::fs::metadata(path)
+                .await
+                .map_err(|e| RagError::Indexer(indexer::IndexerError::Io(e)))?
+                .len()
+                <= self.indexer.max_file_size();
+
+        if !indexable {
+            self.store
+                .remove_by_source(&source)
+                .await
+                .map_err(|e| RagError::Retrieval(e.to_string()))?;
+            return Ok(0);
+        }
+        self.index_file(&source).await
+    }
+
+    /// Rebuilds the knowledge base from the files it was indexed from, with the
+    /// current chunk settings and embedding model, calling `on_progress` after
+    /// each file.
+    ///
+    /// Each file is rebuilt on its own with [`reindex_file`](Self::reindex_file),
+    /// so the rest of the knowledge base stays searchable meanwhile. Files that
+    /// no longer exist on disk are dropped and listed in the report's
+    /// `missing`, and files that no longer pass the indexer's filters are
+    /// dropped and recorded as skipped. Sources that never were files (like
+    /// text added with [`add_knowledge`](Self::add_knowledge)) can't be read
+    /// again: their stored chunks are embedded again with the current model
+    /// and listed in `kept`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sources can't be listed or a missing file's
+    /// chunks can't be removed, or on the first failing source when
+    /// `indexer.abort_on_error` is set.
+    pub async fn reindex_all_with_progress<F>(&self, mut on_progress: F) -> Result<IndexReport>
+    where
+        F: FnMut(&IndexProgress) + Send,
+    {
+        let mut sources = self.get_indexed_paths().await?;
+        sources.sort();
+
+        let mut report = IndexReport::default();
+        let mut files = Vec::new();
+        for source in sources {
+            let path = PathBuf::from(&source);
+            if path.is_file() {
+                files.push(path);
+                continue;
+            }
+
+            // Only chunks of files record the lines they came from
+            let documents = self
+                .store
+                .get_by_source(&source)
+                .await
+                .map_err(|e| RagError::Retrieval(e.to_string()))?;
+            let from_file = documents
+                .iter()
+                .any(|document| document.metadata.contains_key("start_line"));
+            if !from_file {
+                match self.reembed(&source, documents).await {
+                    Ok(()) => report.kept.push(source),
+                    Err(e) if self.indexer.abort_on_error() => return Err(e),
+                    Err(e) => record_error(&mut report, path, e.to_string()),
+                }
+                continue;
+            }
+
+            eprintln!("WARNING: Source no longer exists: {}", path.display());
+            self.store
+                .remove_by_source(&source)
+                .await
+                .map_err(|e| RagError::Retrieval(e.to_string()))?;
+            report.missing.push(path);
+        }
+
+        let total = files.len();
+        for (processed, path) in files.into_iter().enumerate() {
+            match self.reindex_file(&path).await {
+                Ok(0) => report.skipped.push(path.clone()),
+                Ok(_) => report.indexed.push(path.clone()),
+                Err(e) if self.indexer.abort_on_error() => return Err(e),
+                Err(e) => record_error(&mut report, path.clone(), e.to_string()),
+            }
+            on_progress(&IndexProgress {
+                processed: processed + 1,
+                total,
+                current: path,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Replaces the stored chunks of `source` with `documents` embedded again
+    /// with the current model.
+    async fn reembed(&self, source: &str, mut documents: Vec<Document>) -> Result<()> {
+        let texts: Vec<&str> = documents.iter().map(|d| d.content.as_str()).collect();
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH_SIZE) {
+            embeddings.extend(self.embed_with_retries(batch).await?);
+        }
+        for (document, embedding) in documents.iter_mut().zip(embeddings) {
+            document.embedding = embedding;
+        }
+
+        self.store
+            .remove_by_source(source)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+        self.store
+            .add(documents)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))
+    }
+
     /// Retrieves relevant context from the knowledge base for a query.
     ///
     /// Converts the query to an embedding, searches for the top-k most similar
@@ -388,13 +988,13 @@ impl RagEngine {
     /// A formatted string containing the most relevant document chunks, or an
     /// empty string if the knowledge base is empty or no relevant documents exist.
     ///
-    /// The format is:
+    /// The format is set by `rag.context_template`, and defaults to:
     /// ```text
     ///
     /// Relevant context from your knowledge base:
     ///
-    /// [1] <first most relevant chunk>
-    /// [2] <second most relevant chunk>
+    /// [1] (<source>) <first most relevant chunk>
+    /// [2] (<source>) <second most relevant chunk>
     /// ...
     /// ```
     ///
@@ -403,38 +1003,244 @@ impl RagEngine {
     /// Returns an error if embedding generation fails.
     ///
     pub async fn retrieve_context(&self, query: &str) -> Result<String> {
+        let results = self.retrieve(query).await?;
+        Ok(self.assemble_context(&results))
+    }
+
+    /// Retrieves the most relevant documents for a query, with their scores.
+    ///
+    /// This is the raw form of [`retrieve_context`](Self::retrieve_context), useful
+    /// for inspecting exactly what was retrieved and how well it matched.
+    ///
+    /// # Returns
+    ///
+    /// The best `storage.rag_context_count` of the top-k search results (all of
+    /// them if unset), or an empty vector if the knowledge base is empty.
+    /// Results of recent queries are reused (see `rag.cache`) until the
+    /// knowledge base changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if embedding generation or the vector search fails.
+    pub async fn retrieve(&self, query: &str) -> Result<Vec<SearchResult>> {
         use tracing::{debug, info};
 
         let count = self.store.count().await.unwrap_or(0);
         debug!("Knowledge base count: {}", count);
         if count == 0 {
             debug!("Knowledge base is empty, returning empty context");
-            return Ok(String::new());
+            return Ok(Vec::new());
+        }
+
+        if let Some(results) = self.cache.get(query) {
+            debug!("Reusing cached results for: {}", query);
+            return Ok(results);
         }
 
+        let generation = self.cache.generation();
         debug!("Generating query embedding for: {}", query);
-        let query_embedding = self.embedder.embed(query).await?;
+        let query_embedding = self.query_embedding(query).await?;
         debug!(
             "Query embedding generated, dimension: {}",
             query_embedding.len()
         );
 
         debug!("Searching vector store...");
-        let results = self
+        let mut results = self
             .store
             .search(&query_embedding)
             .await
             .map_err(|e| RagError::Retrieval(e.to_string()))?;
 
         info!("Found {} results from RAG search", results.len());
+        self.apply_source_weights(&mut results);
+        if let Some(count) = self.context_count {
+            results.truncate(count);
+        }
+
+        if self.expansion.enabled {
+            results = self.expand(results).await?;
+        }
+        self.annotate_matches(query, &mut results);
+        self.cache.insert(query, results.clone(), generation);
+        Ok(results)
+    }
+
+    /// Embeds `query` for the search. With `rag.query_rewrite` enabled, the
+    /// model's hypothetical answer is embedded instead, or averaged with the
+    /// query if `include_query` is set. If rewriting fails, the query is
+    /// embedded as is.
+    async fn query_embedding(&self, query: &str) -> Result<Vec<f32>> {
+        use tracing::{debug, warn};
+
+        let Some(rewriter) = &self.rewriter else {
+            return Ok(self.embedder.embed(query).await?);
+        };
+        let rewrite = match rewriter.rewrite(query).await {
+            Ok(rewrite) if !rewrite.is_empty() => rewrite,
+            Ok(_) => return Ok(self.embedder.embed(query).await?),
+            Err(e) => {
+                warn!(
+                    "Query rewrite failed, searching with the query itself: {}",
+                    e
+                );
+                return Ok(self.embedder.embed(query).await?);
+            }
+        };
+        debug!("Rewrote query as: {}", rewrite);
+
+        if !rewriter.include_query() {
+            return Ok(self.embedder.embed(&rewrite).await?);
+        }
+        let embeddings = self.embedder.embed_batch(&[query, &rewrite]).await?;
+        let dim = embeddings.first().map_or(0, Vec::len);
+        Ok((0..dim)
+            .map(|i| embeddings.iter().map(|e| e[i]).sum::<f32>() / embeddings.len() as f32)
+            .collect())
+    }
+
+    /// Ranks results again by their score scaled by their `rag.source_weights`
+    /// entry, so a favored source can overtake closer matches among the
+    /// `top_k` candidates.
+    ///
+    /// Weights scale scores [normalized](SimilarityMetric::normalize) to
+    /// `0..=1`, so that they work the same way for every metric and a weight
+    /// above 1 always favors a source. With `vector_db.normalize_scores` the
+    /// weighted scores are reported; otherwise results keep their raw scores.
+    fn apply_source_weights(&self, results: &mut [SearchResult]) {
+        if self.source_weights.is_empty() {
+            return;
+        }
+
+        if self.vector_db.normalize_scores {
+            for result in results.iter_mut() {
+                result.score = self.weighted_score(result);
+            }
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        } else {
+            results.sort_by(|a, b| self.weighted_score(b).total_cmp(&self.weighted_score(a)));
+        }
+    }
+
+    /// The normalized score of `result` scaled by the weight of its source.
+    fn weighted_score(&self, result: &SearchResult) -> f32 {
+        let score = if self.vector_db.normalize_scores {
+            result.score
+        } else {
+            self.vector_db.metric.normalize(result.score)
+        };
+        let metadata = &result.document.metadata;
+        let weight = metadata.get("source").and_then(|source| {
+            let root = metadata.get("root").map(String::as_str);
+            source_weight(&self.source_weights, source, root)
+        });
+        score * weight.unwrap_or(1.0)
+    }
+
+    /// Records the query terms each result contains and, with `rag.snippets`
+    /// enabled, a snippet highlighting them.
+    fn annotate_matches(&self, query: &str, results: &mut [SearchResult]) {
+        let terms = snippet::query_terms(query);
+        for result in results {
+            let content = &result.document.content;
+            if self.snippets.enabled {
+                result.snippet =
+                    snippet::snippet(content, &terms, self.snippets.window, &self.snippets.marker);
+            }
+            if let Some(explanation) = &mut result.explanation {
+                explanation.matched_terms = snippet::matched_terms(content, &terms);
+            }
+        }
+    }
+
+    /// Adds the chunks adjacent to each result in its source file, up to the
+    /// `rag.expansion` budget.
+    ///
+    /// Neighbors are found by ordering a file's chunks by line range, and are
+    /// taken nearest first, following chunks before preceding ones. They keep
+    /// the score of the hit they were found from and are appended after the
+    /// original results, so the ranking of actual matches is unchanged.
+    async fn expand(&self, mut results: Vec<SearchResult>) -> Result<Vec<SearchResult>> {
+        use tracing::debug;
+
+        let mut seen: HashSet<String> = results.iter().map(|r| r.document.id.clone()).collect();
+        let mut chunks_by_source: HashMap<String, Vec<Document>> = HashMap::new();
+        let mut expanded = Vec::new();
+        let mut budget = self.expansion.max_chunks;
+
+        for hit in &results {
+            if budget == 0 {
+                break;
+            }
+            let Some(source) = hit.document.metadata.get("source") else {
+                continue;
+            };
+
+            if !chunks_by_source.contains_key(source) {
+                let mut chunks = self
+                    .store
+                    .get_by_source(source)
+                    .await
+                    .map_err(|e| RagError::Retrieval(e.to_string()))?;
+                chunks.sort_by_key(|doc| line_metadata(doc, "start_line"));
+                chunks_by_source.insert(source.clone(), chunks);
+            }
+            let chunks = &chunks_by_source[source];
+
+            let Some(position) = chunks.iter().position(|doc| doc.id == hit.document.id) else {
+                continue;
+            };
+
+            for distance in 1..=self.expansion.neighbors {
+                let candidates = [
+                    chunks.get(position + distance),
+                    position.checked_sub(distance).and_then(|i| chunks.get(i)),
+                ];
+                for neighbor in candidates.into_iter().flatten() {
+                    if budget == 0 {
+                        break;
+                    }
+                    if seen.insert(neighbor.id.clone()) {
+                        expanded.push(SearchResult {
+                            document: neighbor.clone(),
+                            score: hit.score,
+                            explanation: None,
+                            snippet: None,
+                        });
+                        budget -= 1;
+                    }
+                }
+            }
+        }
+
+        debug!("Expanded results with {} neighbor chunks", expanded.len());
+        results.extend(expanded);
+        Ok(results)
+    }
+
+    /// Formats search results as context to be added to an LLM prompt, using the
+    /// default [`ContextTemplate`].
+    ///
+    /// See [`retrieve_context`](Self::retrieve_context) for the format. Returns an
+    /// empty string if there are no results.
+    pub fn format_context(results: &[SearchResult]) -> String {
+        Self::format_context_with(results, &ContextTemplate::default())
+    }
+
+    /// Formats search results with the `rag.context_template` from the config.
+    pub fn assemble_context(&self, results: &[SearchResult]) -> String {
+        Self::format_context_with(results, &self.context_template)
+    }
+
+    /// Formats search results as context using the given template.
+    pub fn format_context_with(results: &[SearchResult], template: &ContextTemplate) -> String {
+        use tracing::{debug, info};
 
         if results.is_empty() {
             debug!("No results found, returning empty context");
-            return Ok(String::new());
+            return String::new();
         }
 
-        let mut context = String::from("\n\nRelevant context from your knowledge base:\n");
-
         for (i, result) in results.iter().enumerate() {
             debug!(
                 "Result {}: score={}, source={:?}",
@@ -442,11 +1248,25 @@ impl RagEngine {
                 result.score,
                 result.document.metadata.get("source")
             );
-            context.push_str(&format!("\n[{}] {}\n", i + 1, result.document.content));
         }
 
+        let context = template.render(results);
         info!("Generated context with {} results", results.len());
-        Ok(context)
+        context
+    }
+
+    /// Whether the indexer's exclude patterns match `path`, so indexing a
+    /// directory would skip it.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.indexer.is_excluded(path)
+    }
+
+    /// Returns the dimension of the embeddings stored in the knowledge base.
+    ///
+    /// This is the dimension detected from the embedding model at startup,
+    /// which may differ from `rag.embedding_model.embedding_dim` in the config.
+    pub fn embedding_dim(&self) -> usize {
+        self.store.vector_size() as usize
     }
 
     /// Returns the total number of documents (chunks) in the knowledge base.
@@ -466,17 +1286,90 @@ impl RagEngine {
         Ok(())
     }
 
-    /// Returns all unique file paths that have been indexed in the knowledge base.
+    /// Removes documents indexed more than `age` ago, keeping a rolling window
+    /// of fresh content.
     ///
-    /// This method queries Qdrant to retrieve all unique source file paths
-    /// from indexed documents. Useful for displaying indexing status in UIs.
-    pub async fn get_indexed_paths(&self) -> Result<Vec<String>> {
+    /// Documents indexed before timestamps were recorded are kept.
+    ///
+    /// # Returns
+    ///
+    /// The number of document chunks removed.
+    pub async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        self.store
+            .evict_older_than(age)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))
+    }
+
+    /// Returns all unique file paths that have been indexed in the knowledge base.
+    ///
+    /// This method queries Qdrant to retrieve all unique source file paths
+    /// from indexed documents. Useful for displaying indexing status in UIs.
+    pub async fn get_indexed_paths(&self) -> Result<Vec<String>> {
         self.store
             .get_indexed_paths()
             .await
             .map_err(|e| RagError::Retrieval(e.to_string()))
     }
 
+    /// Returns every indexed source with its number of chunks, sorted by path.
+    pub async fn indexed_sources(&self) -> Result<Vec<IndexedSource>> {
+        let counts = self
+            .store
+            .count_by_source()
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+
+        Ok(counts
+            .into_iter()
+            .map(|(source, chunks)| IndexedSource { source, chunks })
+            .collect())
+    }
+
+    /// Splits the file at `path` with the configured chunker, without embedding
+    /// or storing anything, to check how it would be indexed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read as text.
+    pub async fn preview_chunks(&self, path: &Path) -> Result<Vec<ChunkPreview>> {
+        let text = self.indexer.read_text(path).await?;
+        Ok(self.indexer.preview_chunks(&text))
+    }
+
+    /// Checks that the vector store works end to end: stores `embedding` as a
+    /// probe document, searches for it and removes it again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any step fails, or if the search doesn't find the probe.
+    pub async fn probe_store(&self, embedding: Vec<f32>) -> Result<()> {
+        const PROBE_SOURCE: &str = "nucleus://selftest";
+
+        let id = chunk_id(PROBE_SOURCE, 0);
+        let document = Document::new(id.clone(), "nucleus self-test probe", embedding.clone())
+            .with_metadata("source", PROBE_SOURCE);
+        self.store
+            .add(vec![document])
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+
+        // Remove the probe even if the search fails
+        let found = self.store.search(&embedding).await;
+        self.store
+            .remove_by_source(PROBE_SOURCE)
+            .await
+            .map_err(|e| RagError::Retrieval(e.to_string()))?;
+
+        let found = found.map_err(|e| RagError::Retrieval(e.to_string()))?;
+        if !found.iter().any(|result| result.document.id == id) {
+            return Err(RagError::Retrieval(
+                "the probe document was stored but not found by search".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Removes documents from the knowledge base by source path.
     ///
     /// This method removes all documents that match the given source path.
@@ -525,3 +1418,920 @@ impl RagEngine {
         Ok(removed)
     }
 }
+
+/// Reads a line number recorded in a document's metadata, if any.
+fn line_metadata(document: &Document, key: &str) -> Option<usize> {
+    document.metadata.get(key).and_then(|v| v.parse().ok())
+}
+
+/// Finds the embedding model's actual output dimension by embedding a probe string.
+///
+/// The detected dimension is authoritative, and a mismatch with `configured` is
+/// logged. If the probe fails (e.g. the embedding backend is unreachable), the
+/// configured dimension is used instead.
+async fn detect_embedding_dim(embedder: &Embedder, configured: usize) -> usize {
+    use tracing::{debug, warn};
+
+    match embedder.embed("nucleus embedding dimension probe").await {
+        Ok(embedding) if !embedding.is_empty() => {
+            if embedding.len() != configured {
+                warn!(
+                    detected = embedding.len(),
+                    configured,
+                    "Embedding dimension differs from config, using the detected dimension"
+                );
+            } else {
+                debug!("Detected embedding dimension {}", embedding.len());
+            }
+            embedding.len()
+        }
+        Ok(_) => {
+            warn!(
+                "Embedding probe returned an empty vector, using configured dimension {}",
+                configured
+            );
+            configured
+        }
+        Err(e) => {
+            warn!(
+                "Could not detect embedding dimension ({}), using configured dimension {}",
+                e, configured
+            );
+            configured
+        }
+    }
+}
+
+/// The weight of the longest prefix in `weights` that `source` starts with,
+/// compared by whole path components. Sources indexed from a directory are
+/// taken relative to it (`root`).
+fn source_weight(weights: &BTreeMap<String, f32>, source: &str, root: Option<&str>) -> Option<f32> {
+    let source = Path::new(source);
+    let relative = root
+        .and_then(|root| source.strip_prefix(root).ok())
+        .unwrap_or(source);
+    weights
+        .iter()
+        .filter(|(prefix, _)| relative.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, &weight)| weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test_config, MockProvider, TEST_EMBEDDING_DIM};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_repeated_query_reuses_cached_results() {
+        use std::sync::atomic::Ordering;
+
+        let data = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new(""));
+        let engine = RagEngine::new(&test_config(data.path()), provider.clone())
+            .await
+            .unwrap();
+        engine
+            .add_knowledge("The server listens on a unix socket", "notes.md")
+            .await
+            .unwrap();
+        let embeds = || provider.embed_calls.load(Ordering::SeqCst);
+
+        let query = "Where does the server listen?";
+        let before = embeds();
+        let first = engine.retrieve(query).await.unwrap();
+        assert_eq!(embeds(), before + 1);
+
+        let second = engine
+            .retrieve("  where does the server LISTEN? ")
+            .await
+            .unwrap();
+        assert_eq!(embeds(), before + 1);
+        assert_eq!(second.len(), first.len());
+        assert_eq!(second[0].document.id, first[0].document.id);
+
+        // Changing the knowledge base invalidates cached results
+        engine
+            .add_knowledge("Requests are JSON lines", "protocol.md")
+            .await
+            .unwrap();
+        let after_add = embeds();
+        let third = engine.retrieve(query).await.unwrap();
+        assert_eq!(embeds(), after_add + 1);
+        assert_eq!(third.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_index_directory_report_buckets() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        // Default patterns exclude paths containing "tmp", which tempdirs do
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let good = dir.path().join("good.rs");
+        let notes = dir.path().join("notes.md");
+        let binary = dir.path().join("image.dat");
+        let empty = dir.path().join("empty.txt");
+        let unreadable = dir.path().join("dangling.rs");
+        std::fs::write(&good, "fn main() {}\n").unwrap();
+        std::fs::write(&notes, "# Notes\n").unwrap();
+        std::fs::write(&binary, [0x89, b'P', b'N', b'G', 0x00, 0xff]).unwrap();
+        std::fs::write(&empty, "").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("missing"), &unreadable).unwrap();
+
+        let mut report = engine.index_directory_report(dir.path()).await.unwrap();
+        report.indexed.sort();
+        report.skipped.sort();
+
+        assert_eq!(report.indexed, vec![good, notes]);
+        assert_eq!(report.skipped, vec![empty, binary]);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].0, unreadable);
+        assert_eq!(engine.count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_index_directory_skips_oversized_files() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.max_file_size = 64;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small.rs");
+        let bundle = dir.path().join("bundle.min.js");
+        std::fs::write(&small, "fn main() {}\n").unwrap();
+        std::fs::write(&bundle, "var a=1;".repeat(100)).unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+
+        assert_eq!(report.indexed, vec![small]);
+        assert_eq!(report.skipped, vec![bundle]);
+        assert_eq!(engine.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_keeps_chunk_ids_stable() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.chunk_size = 16;
+        indexer.chunk_overlap = 0;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "first line here\nsecond line here\nthird line\n").unwrap();
+        let source = path.to_string_lossy().to_string();
+
+        let chunk_ids = || async {
+            let mut ids: Vec<String> = engine
+                .store
+                .get_by_source(&source)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|document| document.id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        engine.index_directory(dir.path()).await.unwrap();
+        let count = engine.count().await;
+        let first = chunk_ids().await;
+        assert!(count > 1);
+        assert!(first.contains(&chunk_id(&source, 0)));
+
+        engine.index_directory(dir.path()).await.unwrap();
+        assert_eq!(engine.count().await, count);
+        assert_eq!(chunk_ids().await, first);
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_a_shrunk_file_drops_its_old_chunks() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.chunk_size = 16;
+        indexer.chunk_overlap = 0;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "first line here\nsecond line here\nthird line\n").unwrap();
+        let source = path.to_string_lossy().to_string();
+
+        engine.index_directory(dir.path()).await.unwrap();
+        assert!(engine.count().await > 1);
+
+        std::fs::write(&path, "only line\n").unwrap();
+        engine.index_directory(dir.path()).await.unwrap();
+
+        let documents = engine.store.get_by_source(&source).await.unwrap();
+        assert_eq!(engine.count().await, 1);
+        assert_eq!(documents[0].id, chunk_id(&source, 0));
+        assert_eq!(documents[0].content, "only line\n");
+    }
+
+    #[tokio::test]
+    async fn test_reindex_all_embeds_kept_sources_again() {
+        use std::sync::atomic::Ordering;
+
+        let data = tempdir().unwrap();
+        let config = test_config(data.path());
+        let provider = Arc::new(MockProvider::new(""));
+        let engine = RagEngine::new(&config, provider.clone()).await.unwrap();
+
+        engine
+            .add_knowledge("The build runs on Fridays", "user_input")
+            .await
+            .unwrap();
+        let embedded = provider.embed_calls.load(Ordering::SeqCst);
+
+        let report = engine.reindex_all_with_progress(|_| {}).await.unwrap();
+        assert_eq!(report.kept, vec!["user_input".to_string()]);
+        assert_eq!(provider.embed_calls.load(Ordering::SeqCst), embedded + 1);
+
+        let documents = engine.store.get_by_source("user_input").await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].content, "The build runs on Fridays");
+    }
+
+    struct NoteExtractor;
+
+    impl TextExtractor for NoteExtractor {
+        fn extensions(&self) -> &[&str] {
+            &["note"]
+        }
+
+        fn extract(&self, bytes: &[u8]) -> std::result::Result<String, ExtractError> {
+            match bytes.strip_prefix(b"NOTE\0") {
+                Some(text) => Ok(String::from_utf8_lossy(text).into_owned()),
+                None => Err(ExtractError("missing NOTE header".to_string())),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_directory_uses_registered_extractor() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap()
+            .with_extractor(NoteExtractor);
+
+        let dir = tempdir().unwrap();
+        let note = dir.path().join("meeting.note");
+        let corrupt = dir.path().join("corrupt.note");
+        std::fs::write(&note, b"NOTE\0Ship the extractor on Friday").unwrap();
+        std::fs::write(&corrupt, b"\0\x01\x02").unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+
+        assert_eq!(report.indexed, vec![note]);
+        assert_eq!(report.skipped, vec![corrupt]);
+        assert_eq!(engine.count().await, 1);
+        let context = engine.retrieve_context("extractor").await.unwrap();
+        assert!(context.contains("Ship the extractor on Friday"));
+    }
+
+    #[cfg(feature = "pdf")]
+    #[tokio::test]
+    async fn test_index_directory_extracts_pdf_text() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let manual = dir.path().join("manual.pdf");
+        std::fs::write(
+            &manual,
+            include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/sample.pdf"
+            )),
+        )
+        .unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+
+        assert_eq!(report.indexed, vec![manual]);
+        assert!(engine.count().await > 0);
+        let context = engine.retrieve_context("manuals").await.unwrap();
+        assert!(context.contains("Nucleus indexes PDF manuals"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_index_directory_aborts_on_error() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.abort_on_error = true;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("good.rs"), "fn main() {}\n").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("dangling.rs"))
+            .unwrap();
+
+        assert!(engine.index_directory_report(dir.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_index_directory_continues_past_failing_chunk() {
+        tokio::time::pause();
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.embed_retries = 1;
+
+        // The first embed is the dimension probe, so the second chunk fails
+        let provider = Arc::new(MockProvider::new("").with_failing_embed(3));
+        let engine = RagEngine::new(&config, provider.clone()).await.unwrap();
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "fn b() {}\n").unwrap();
+        std::fs::write(dir.path().join("c.rs"), "fn c() {}\n").unwrap();
+
+        let start = tokio::time::Instant::now();
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+
+        assert_eq!(report.errors.len(), 1);
+        let (failed, error) = &report.errors[0];
+        assert!(error.starts_with("chunk 0: "), "{}", error);
+        assert!(error.contains("out of memory"), "{}", error);
+        assert_eq!(report.indexed_count(), 2);
+        assert!(!report.indexed.contains(failed));
+        assert_eq!(engine.count().await, 2);
+        // The probe, the batch up to the failing chunk and its retry, then
+        // each chunk alone with the failing one retried
+        assert_eq!(provider.embed_calls.load(Ordering::SeqCst), 1 + 2 + 2 + 4);
+        // Both retries waited at least half of the initial backoff
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[cfg(feature = "encoding")]
+    #[tokio::test]
+    async fn test_index_directory_decodes_non_utf8_files() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        // "Le café de la rue est très célèbre pour sa crème brûlée" in Latin-1
+        let latin1 = dir.path().join("menu.txt");
+        let mut bytes = b"Le caf\xe9 de la rue est tr\xe8s c\xe9l\xe8bre ".to_vec();
+        bytes.extend_from_slice(b"pour sa cr\xe8me br\xfbl\xe9e.\n");
+        std::fs::write(&latin1, &bytes).unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+        assert_eq!(report.indexed_count(), 1);
+        assert!(report.errors.is_empty());
+
+        let chunks = engine
+            .store
+            .get_by_source(&latin1.to_string_lossy())
+            .await
+            .unwrap();
+        assert!(chunks[0]
+            .content
+            .starts_with("Le café de la rue est très célèbre"));
+        assert_eq!(chunks[0].metadata["encoding"], "windows-1252");
+    }
+
+    #[tokio::test]
+    async fn test_index_directory_replaces_invalid_utf8() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.detect_encoding = false;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        // "crème brûlée" in Latin-1, with far more invalid UTF-8 than text has
+        let latin1 = dir.path().join("dessert.txt");
+        std::fs::write(&latin1, b"cr\xe8me br\xfbl\xe9e\n").unwrap();
+        // UTF-8 with a stray byte
+        let damaged = dir.path().join("notes.md");
+        let mut bytes = "# Déjà vu\n".as_bytes().to_vec();
+        bytes.extend_from_slice(b"broken \xff byte\n");
+        std::fs::write(&damaged, &bytes).unwrap();
+        let binary = dir.path().join("blob.bin");
+        std::fs::write(&binary, [0x89, 0x50, 0x00, 0x01, 0xff, 0xfe]).unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+        assert_eq!(report.indexed_count(), 2);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.skipped, vec![binary]);
+
+        let chunks = engine
+            .store
+            .get_by_source(&latin1.to_string_lossy())
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].content, "cr\u{fffd}me br\u{fffd}l\u{fffd}e\n");
+        assert!(!chunks[0].metadata.contains_key("encoding"));
+
+        let chunks = engine
+            .store
+            .get_by_source(&damaged.to_string_lossy())
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].content, "# Déjà vu\nbroken \u{fffd} byte\n");
+        assert!(!chunks[0].metadata.contains_key("encoding"));
+    }
+
+    #[tokio::test]
+    async fn test_index_directory_dedups_identical_chunks() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        indexer.exclude_patterns = Vec::new();
+        indexer.dedup = true;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("vendor_copy");
+        std::fs::create_dir(&nested).unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "pub fn shared() {}\n").unwrap();
+        std::fs::write(nested.join("lib.rs"), "pub fn shared() {}\n").unwrap();
+        std::fs::write(dir.path().join("copy.rs"), "pub fn shared() {}\n").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let report = engine.index_directory_report(dir.path()).await.unwrap();
+
+        assert_eq!(report.indexed_count(), 4);
+        assert_eq!(report.duplicate_chunks, 2);
+        assert_eq!(engine.count().await, 2);
+
+        let contents: HashSet<String> = engine
+            .retrieve("pub fn shared() {}")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.document.content)
+            .collect();
+        assert_eq!(
+            contents,
+            HashSet::from([
+                "pub fn shared() {}\n".to_string(),
+                "fn main() {}\n".to_string()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embedding_dim_is_detected_from_provider() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().embedding_model.embedding_dim = 8;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        assert_eq!(engine.embedding_dim(), TEST_EMBEDDING_DIM);
+
+        // Inserts succeed despite the misconfigured dimension
+        engine
+            .add_knowledge("some notes", "notes.md")
+            .await
+            .unwrap();
+        assert_eq!(engine.count().await, 1);
+    }
+
+    fn search_result(source: &str, lines: (u32, u32), content: &str, score: f32) -> SearchResult {
+        let document = Document::new(source, content, Vec::new())
+            .with_metadata("source", source)
+            .with_metadata("start_line", lines.0.to_string())
+            .with_metadata("end_line", lines.1.to_string());
+        SearchResult {
+            document,
+            score,
+            explanation: None,
+            snippet: None,
+        }
+    }
+
+    #[test]
+    fn test_format_context_default_template() {
+        let results = vec![
+            search_result("src/main.rs", (1, 3), "fn main() {}", 0.9),
+            SearchResult {
+                document: Document::new("note", "remember the milk", Vec::new()),
+                score: 0.5,
+                explanation: None,
+                snippet: None,
+            },
+        ];
+
+        assert_eq!(
+            RagEngine::format_context(&results),
+            "\n\nRelevant context from your knowledge base:\n\
+             \n[1] (src/main.rs:1-3) fn main() {}\n\
+             \n[2] remember the milk\n"
+        );
+        assert_eq!(RagEngine::format_context(&[]), "");
+    }
+
+    #[test]
+    fn test_format_context_custom_template() {
+        let template = ContextTemplate {
+            wrapper: "Answer using these files:\n{chunks}End of files.".to_string(),
+            chunk: "--- {source} (score {score}) ---\n```\n{content}\n```\n".to_string(),
+        };
+        let results = vec![
+            search_result("src/lib.rs", (10, 12), "pub mod config;", 0.875),
+            search_result("README.md", (4, 4), "# Nucleus", 0.5),
+        ];
+
+        assert_eq!(
+            RagEngine::format_context_with(&results, &template),
+            "Answer using these files:\n\
+             --- src/lib.rs:10-12 (score 0.875) ---\n```\npub mod config;\n```\n\
+             --- README.md:4 (score 0.500) ---\n```\n# Nucleus\n```\n\
+             End of files."
+        );
+    }
+
+    #[test]
+    fn test_format_context_leaves_out_unknown_source() {
+        let note = SearchResult {
+            document: Document::new("note", "remember the milk", Vec::new()),
+            score: 0.5,
+            explanation: None,
+            snippet: None,
+        };
+        let render = |chunk: &str| {
+            let template = ContextTemplate {
+                wrapper: "{chunks}".to_string(),
+                chunk: chunk.to_string(),
+            };
+            RagEngine::format_context_with(std::slice::from_ref(&note), &template)
+        };
+
+        assert_eq!(
+            render("[{index}] <{source}> {content}"),
+            "[1] remember the milk"
+        );
+        assert_eq!(render("{content} [{source}]"), "remember the milk");
+        assert_eq!(
+            render("--- {source} ---\n{content}"),
+            "--- ---\nremember the milk"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rag_context_count_bounds_context_not_fetch() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.storage.top_k = 4;
+        config.storage.rag_context_count = Some(2);
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        for note in ["alpha", "bravo", "charlie", "delta"] {
+            engine.add_knowledge(note, "notes.md").await.unwrap();
+        }
+
+        let query = engine.embedder.embed("alpha").await.unwrap();
+        assert_eq!(engine.store.search(&query).await.unwrap().len(), 4);
+
+        assert_eq!(engine.retrieve("alpha").await.unwrap().len(), 2);
+        let context = engine.retrieve_context("alpha").await.unwrap();
+        assert!(context.contains("[2] "));
+        assert!(!context.contains("[3] "));
+    }
+
+    #[tokio::test]
+    async fn test_expansion_adds_following_chunk() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.storage.top_k = 1;
+        let rag = config.rag.as_mut().unwrap();
+        rag.indexer.exclude_patterns = Vec::new();
+        rag.indexer.chunk_size = 5;
+        rag.indexer.chunk_overlap = 0;
+        rag.expansion = ExpansionConfig {
+            enabled: true,
+            neighbors: 1,
+            max_chunks: 1,
+        };
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "aaaa\nbbbb\ncccc\ndddd\n").unwrap();
+        engine.index_directory_report(dir.path()).await.unwrap();
+        assert_eq!(engine.count().await, 4);
+
+        let results = engine.retrieve("bbbb\n").await.unwrap();
+        let contents: Vec<&str> = results
+            .iter()
+            .map(|r| r.document.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["bbbb\n", "cccc\n"]);
+        let location = results[1].document.location().unwrap();
+        assert!(location.ends_with("lib.rs:3"), "{}", location);
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_highlights_query_terms_in_snippets() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let rag = config.rag.as_mut().unwrap();
+        rag.snippets.enabled = true;
+        rag.snippets.window = 40;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        let content = format!(
+            "{} The scheduler runs queued requests by priority. {}",
+            "Setup notes and unrelated details.".repeat(3),
+            "More unrelated details follow here.".repeat(3)
+        );
+        engine.add_knowledge(&content, "notes").await.unwrap();
+
+        let results = engine
+            .retrieve("How does the scheduler work?")
+            .await
+            .unwrap();
+        let snippet = results[0].snippet.as_deref().unwrap();
+        assert!(snippet.contains("The **scheduler** runs"), "{}", snippet);
+        assert!(
+            snippet.starts_with('…') && snippet.ends_with('…'),
+            "{}",
+            snippet
+        );
+        assert_eq!(
+            results[0].explanation.as_ref().unwrap().matched_terms,
+            vec!["scheduler"]
+        );
+        assert_eq!(results[0].document.content, content);
+    }
+
+    #[tokio::test]
+    async fn test_query_rewrite_searches_with_hypothetical_answer() {
+        let expansion = "Tokio schedules tasks on a pool of worker threads";
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().query_rewrite.enabled = true;
+
+        let provider = Arc::new(MockProvider::new(expansion));
+        let engine = RagEngine::new(&config, provider.clone()).await.unwrap();
+        engine.add_knowledge("tokio?", "faq").await.unwrap();
+        engine.add_knowledge(expansion, "guide").await.unwrap();
+
+        let results = engine.retrieve("tokio?").await.unwrap();
+        assert_eq!(results[0].document.metadata["source"], "guide");
+        assert!(
+            (results[0].score - 1.0).abs() < 1e-4,
+            "{}",
+            results[0].score
+        );
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].messages[0].content.contains("tokio?"));
+    }
+
+    #[tokio::test]
+    async fn test_source_weights_outrank_closer_matches() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let rag = config.rag.as_mut().unwrap();
+        rag.indexer.exclude_patterns = Vec::new();
+        rag.source_weights.insert("docs/".to_string(), 1.5);
+        rag.source_weights.insert("tests/".to_string(), 0.5);
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        let repo = tempdir().unwrap();
+        let docs = repo.path().join("docs/retry.md");
+        let tests = repo.path().join("tests/retry_test.md");
+        std::fs::create_dir_all(docs.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(tests.parent().unwrap()).unwrap();
+        std::fs::write(
+            &docs,
+            "The retry policy backs off exponentially between attempts.",
+        )
+        .unwrap();
+        std::fs::write(&tests, "retry policy").unwrap();
+        engine.index_directory(repo.path()).await.unwrap();
+
+        let results = engine.retrieve("retry policy").await.unwrap();
+        let source = |result: &SearchResult| result.document.metadata["source"].clone();
+        assert_eq!(source(&results[0]), docs.to_string_lossy());
+        assert_eq!(source(&results[1]), tests.to_string_lossy());
+        // Raw scores are reported as they are, unweighted
+        let metric = config.vector_db.metric;
+        assert!(
+            metric.normalize(results[1].score) > metric.normalize(results[0].score),
+            "the test file should be the closer match before weighting"
+        );
+    }
+
+    #[test]
+    fn test_source_weight_prefers_longest_prefix() {
+        let weights = BTreeMap::from([("docs/".to_string(), 1.5), ("docs/old/".to_string(), 0.2)]);
+        assert_eq!(source_weight(&weights, "docs/guide.md", None), Some(1.5));
+        assert_eq!(
+            source_weight(&weights, "/repo/docs/old/guide.md", Some("/repo")),
+            Some(0.2)
+        );
+        assert_eq!(source_weight(&weights, "src/docs.rs", None), None);
+        assert_eq!(source_weight(&weights, "docs-old/guide.md", None), None);
+
+        // Only the part below the indexed directory is matched
+        assert_eq!(source_weight(&weights, "/repo/docs/guide.md", None), None);
+        assert_eq!(
+            source_weight(&weights, "/home/docs/src/main.rs", Some("/home/docs")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_index_file_streaming_stores_every_chunk() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let rag = config.rag.as_mut().unwrap();
+        rag.indexer.chunk_size = 1024;
+        rag.indexer.chunk_overlap = 128;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("large.log");
+        let content: String = (0..20_000)
+            .map(|i| format!("{} request handled in {}ms\n", i, i % 97))
+            .collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let expected = indexer::chunk_text_with_spans(&content, 1024, 128).len();
+        assert!(expected > 32, "spans several batches");
+
+        let stored = engine.index_file_streaming(&path).await.unwrap();
+        assert_eq!(stored, expected);
+        assert_eq!(engine.count().await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_index_file_streaming_replaces_previous_chunks() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let rag = config.rag.as_mut().unwrap();
+        rag.indexer.exclude_patterns = Vec::new();
+        rag.indexer.chunk_size = 1024;
+        rag.indexer.chunk_overlap = 128;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.log");
+        let long: String = (0..500).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, &long).unwrap();
+        engine.index_directory(dir.path()).await.unwrap();
+        assert!(engine.count().await > 1);
+
+        std::fs::write(&path, "a single line\n").unwrap();
+        let stored = engine.index_file_streaming(&path).await.unwrap();
+        assert_eq!(stored, 1);
+        assert_eq!(engine.count().await, 1);
+
+        let source = path.to_string_lossy();
+        let documents = engine.store.get_by_source(&source).await.unwrap();
+        assert_eq!(
+            documents[0].metadata.get("root").map(String::as_str),
+            Some(&*dir.path().to_string_lossy())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_index_file_keeps_previous_chunks_when_embedding_fails() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        config.rag.as_mut().unwrap().indexer.embed_retries = 0;
+
+        // The first embed is the dimension probe, so indexing the file again fails
+        let provider = Arc::new(MockProvider::new("").with_failing_embed(3));
+        let engine = RagEngine::new(&config, provider).await.unwrap();
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        let source = path.to_string_lossy();
+        std::fs::write(&path, "Deploys run on Fridays\n").unwrap();
+        assert_eq!(engine.index_file(&source).await.unwrap(), 1);
+
+        std::fs::write(&path, "Deploys run on Mondays\n").unwrap();
+        assert!(engine.index_file(&source).await.is_err());
+
+        let documents = engine.store.get_by_source(&source).await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert!(documents[0].content.contains("Fridays"));
+    }
+
+    #[tokio::test]
+    async fn test_embed_progress_counts_up_to_total_chunks() {
+        let data = tempdir().unwrap();
+        let mut config = test_config(data.path());
+        let rag = config.rag.as_mut().unwrap();
+        rag.indexer.exclude_patterns = Vec::new();
+        rag.indexer.chunk_size = 1024;
+        rag.indexer.chunk_overlap = 128;
+
+        let engine = RagEngine::new(&config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let content: String = (0..5_000)
+            .map(|i| format!("{} request handled in {}ms\n", i, i % 97))
+            .collect();
+        std::fs::write(dir.path().join("server.log"), &content).unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+        let mut reports = Vec::new();
+        engine
+            .index_directory_with_embed_progress(
+                dir.path(),
+                |_| {},
+                |progress| reports.push(*progress),
+            )
+            .await
+            .unwrap();
+
+        let total = engine.count().await;
+        assert!(total > 64, "spans several batches");
+        assert!(reports.len() >= 3);
+        assert!(reports.windows(2).all(|pair| {
+            pair[0].embedded_chunks < pair[1].embedded_chunks
+                && pair[0].total_chunks <= pair[1].total_chunks
+        }));
+        assert!(reports.iter().all(|p| p.embedded_chunks <= p.total_chunks));
+        let last = reports.last().unwrap();
+        assert_eq!((last.embedded_chunks, last.total_chunks), (total, total));
+        assert!(last.all_chunked);
+        assert!(reports[0].to_string().starts_with("embedded 32/"));
+
+        let partial = EmbedProgress {
+            embedded_chunks: 32,
+            total_chunks: 40,
+            all_chunked: false,
+        };
+        assert_eq!(partial.to_string(), "embedded 32/40+ chunks");
+    }
+}
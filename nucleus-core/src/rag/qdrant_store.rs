@@ -3,24 +3,65 @@
 //! This module provides integration with Qdrant, a high-performance vector database
 //! that offers automatic deduplication, persistence, and scalability.
 
-use super::store::VectorStore;
-use super::types::{Document, SearchResult};
+use super::store::{eviction_cutoff, rank_results, SimilarityMetric, VectorStore};
+use super::types::{Document, MatchExplanation, SearchResult, INDEXED_AT};
 use crate::config::{StorageConfig, StorageMode};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use qdrant_client::{
     qdrant::{
-        vectors_config::Config, CreateCollectionBuilder, DeletePointsBuilder, Distance,
-        PointStruct, ScrollPointsBuilder, SearchPointsBuilder, UpsertPointsBuilder,
+        vectors_config::Config, Condition, CreateCollectionBuilder, DeletePointsBuilder, Distance,
+        Filter, PointStruct, ScrollPointsBuilder, SearchPointsBuilder, UpsertPointsBuilder, Value,
         VectorParamsBuilder, VectorsConfig,
     },
     Qdrant,
 };
 use serde_json::json;
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+
+/// Qdrant distance function for `metric`.
+///
+/// Qdrant already scores and orders results the way [`SimilarityMetric`]
+/// describes: similarities descending, euclidean distances ascending.
+fn distance(metric: SimilarityMetric) -> Distance {
+    match metric {
+        SimilarityMetric::Cosine => Distance::Cosine,
+        SimilarityMetric::DotProduct => Distance::Dot,
+        SimilarityMetric::Euclidean => Distance::Euclid,
+    }
+}
+
+/// Rebuilds a document (without its embedding) from a point's payload.
+fn payload_document(payload: HashMap<String, Value>) -> Document {
+    let content = payload
+        .get("content")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+
+    // Get the original ID from metadata
+    let id = payload
+        .get("id")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_default();
+
+    let metadata: HashMap<String, String> = payload
+        .iter()
+        .filter(|(k, _)| k.as_str() != "content" && k.as_str() != "id")
+        .filter_map(|(k, v)| v.as_str().map(|s| (k.clone(), s.to_string())))
+        .collect();
+
+    Document {
+        id,
+        content,
+        embedding: vec![],
+        metadata,
+    }
+}
 
 /// Qdrant-based vector store for document embeddings.
 ///
@@ -53,9 +94,11 @@ impl VectorStore for QdrantStore {
         let points: Vec<PointStruct> = documents
             .into_iter()
             .map(|document| {
-                let mut hasher = DefaultHasher::new();
-                document.id.hash(&mut hasher);
-                let numeric_id = hasher.finish();
+                // Derived from a stable hash so re-added documents upsert the same point.
+                // Points from before this scheme are removed when their file is
+                // indexed again (see `RagEngine::index_directory_report`)
+                let digest = Sha256::digest(document.id.as_bytes());
+                let numeric_id = u64::from_le_bytes(digest[..8].try_into().unwrap());
 
                 let payload: HashMap<String, serde_json::Value> = document
                     .metadata
@@ -84,63 +127,41 @@ impl VectorStore for QdrantStore {
     /// # Arguments
     ///
     /// * `query_embedding` - The embedding vector to search for
-    /// * `top_k` - Maximum number of results to return
     ///
     /// # Returns
     ///
     /// A vector of search results, sorted by descending similarity score.
     async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+        self.search_with_k(query_embedding, self.storage_config.top_k)
+            .await
+    }
+
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>> {
         let search_result = self
             .client
             .search_points(
-                SearchPointsBuilder::new(
-                    &self.collection_name,
-                    query_embedding.to_vec(),
-                    self.storage_config.top_k as u64,
-                )
-                .with_payload(true),
+                SearchPointsBuilder::new(&self.collection_name, query_embedding.to_vec(), k as u64)
+                    .with_payload(true),
             )
             .await
             .context("Failed to search points")?;
 
-        let results = search_result
+        let metric = self.storage_config.vector_db.metric;
+        let mut results: Vec<SearchResult> = search_result
             .result
             .into_iter()
             .map(|point| {
-                let payload = point.payload;
-                let content = payload
-                    .get("content")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .unwrap_or_default();
-
-                // Get the original ID from metadata
-                let id = payload
-                    .get("id")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .unwrap_or_default();
-
-                let metadata: HashMap<String, String> = payload
-                    .iter()
-                    .filter(|(k, _)| k.as_str() != "content" && k.as_str() != "id")
-                    .filter_map(|(k, v)| v.as_str().map(|s| (k.clone(), s.to_string())))
-                    .collect();
-
-                let document = Document {
-                    id,
-                    content,
-                    embedding: vec![], // Don't return embeddings in search results
-                    metadata,
-                };
-
+                let document = payload_document(point.payload);
                 SearchResult {
                     document,
                     score: point.score,
+                    explanation: Some(MatchExplanation::vector(metric, point.score)),
+                    snippet: None,
                 }
             })
             .collect();
 
+        rank_results(&self.storage_config.vector_db, &mut results);
         Ok(results)
     }
 
@@ -177,9 +198,11 @@ impl VectorStore for QdrantStore {
     /// Scrolls through all documents in the collection and extracts unique
     /// source paths from the metadata.
     async fn get_indexed_paths(&self) -> Result<Vec<String>> {
-        use std::collections::HashSet;
+        Ok(self.count_by_source().await?.into_keys().collect())
+    }
 
-        let mut unique_paths = HashSet::new();
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
+        let mut counts = BTreeMap::new();
         let mut offset: Option<qdrant_client::qdrant::PointId> = None;
 
         // Scroll through all points in batches
@@ -198,13 +221,10 @@ impl VectorStore for QdrantStore {
                 .await
                 .context("Failed to scroll points")?;
 
-            // Extract source paths from this batch
+            // Count the source paths in this batch
             for point in &scroll_result.result {
-                let payload = &point.payload;
-                if let Some(source_value) = payload.get("source") {
-                    if let Some(source_str) = source_value.as_str() {
-                        unique_paths.insert(source_str.to_string());
-                    }
+                if let Some(source) = point.payload.get("source").and_then(|v| v.as_str()) {
+                    *counts.entry(source.to_string()).or_insert(0) += 1;
                 }
             }
 
@@ -216,7 +236,108 @@ impl VectorStore for QdrantStore {
             }
         }
 
-        Ok(unique_paths.into_iter().collect())
+        Ok(counts)
+    }
+
+    /// Returns every document whose source is exactly `source`.
+    async fn get_by_source(&self, source: &str) -> Result<Vec<Document>> {
+        let mut documents = Vec::new();
+        let mut offset: Option<qdrant_client::qdrant::PointId> = None;
+
+        loop {
+            let mut builder = ScrollPointsBuilder::new(&self.collection_name)
+                .filter(Filter::must([Condition::matches(
+                    "source",
+                    source.to_string(),
+                )]))
+                .limit(100)
+                .with_payload(true);
+
+            if let Some(off) = offset {
+                builder = builder.offset(off);
+            }
+
+            let scroll_result = self
+                .client
+                .scroll(builder)
+                .await
+                .context("Failed to scroll points")?;
+
+            documents.extend(
+                scroll_result
+                    .result
+                    .into_iter()
+                    .map(|point| payload_document(point.payload)),
+            );
+
+            match scroll_result.next_page_offset {
+                Some(next_offset) => offset = Some(next_offset),
+                None => break,
+            }
+        }
+
+        Ok(documents)
+    }
+
+    /// Removes all documents indexed more than `age` ago.
+    ///
+    /// Metadata is stored as strings, so points are scrolled and their
+    /// `indexed_at` compared here rather than with a range filter.
+    async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        let cutoff = eviction_cutoff(age);
+        let mut points_to_delete = Vec::new();
+        let mut offset: Option<qdrant_client::qdrant::PointId> = None;
+
+        loop {
+            let mut builder = ScrollPointsBuilder::new(&self.collection_name)
+                .limit(100)
+                .with_payload(true);
+
+            if let Some(off) = offset {
+                builder = builder.offset(off);
+            }
+
+            let scroll_result = self
+                .client
+                .scroll(builder)
+                .await
+                .context("Failed to scroll points")?;
+
+            for point in scroll_result.result {
+                let indexed_at = point
+                    .payload
+                    .get(INDEXED_AT)
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| v.parse::<u64>().ok());
+                if let (Some(point_id), Some(indexed_at)) = (point.id, indexed_at) {
+                    if indexed_at < cutoff {
+                        points_to_delete.push(point_id);
+                    }
+                }
+            }
+
+            match scroll_result.next_page_offset {
+                Some(next_offset) => offset = Some(next_offset),
+                None => break,
+            }
+        }
+
+        let count = points_to_delete.len();
+
+        if !points_to_delete.is_empty() {
+            self.client
+                .delete_points(
+                    DeletePointsBuilder::new(&self.collection_name).points(points_to_delete),
+                )
+                .await
+                .context("Failed to delete points")?;
+        }
+
+        Ok(count)
+    }
+
+    fn vector_size(&self) -> u64 {
+        self.vector_size
     }
 
     /// Removes all documents with a matching source path.
@@ -344,8 +465,11 @@ impl QdrantStore {
                     CreateCollectionBuilder::new(&self.collection_name).vectors_config(
                         VectorsConfig {
                             config: Some(Config::Params(
-                                VectorParamsBuilder::new(self.vector_size, Distance::Cosine)
-                                    .build(),
+                                VectorParamsBuilder::new(
+                                    self.vector_size,
+                                    distance(self.storage_config.vector_db.metric),
+                                )
+                                .build(),
                             )),
                         },
                     ),
@@ -0,0 +1,334 @@
+//! Retrying of transient failures against remote vector stores.
+
+use super::store::VectorStore;
+use super::types::{Document, SearchResult};
+use crate::config::StoreRetryConfig;
+use anyhow::Result;
+use async_trait::async_trait;
+use qdrant_client::QdrantError;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Code;
+use tracing::warn;
+
+/// Vector store that retries adds, searches and counts failing with a transient
+/// error, following `vector_db.retry`.
+///
+/// Retrying an add is safe, as documents with the same id replace each other.
+pub(super) struct RetryingStore {
+    inner: Arc<dyn VectorStore>,
+    config: StoreRetryConfig,
+}
+
+impl RetryingStore {
+    pub(super) fn new(inner: Arc<dyn VectorStore>, config: StoreRetryConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that isn't
+    /// transient, or runs out of retries.
+    async fn retry<T, F, Fut>(&self, operation: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let initial_backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let max_backoff = Duration::from_millis(self.config.max_backoff_ms);
+        let mut retries = 0;
+
+        loop {
+            match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retries < self.config.max_retries && is_transient(&e) => {
+                    retries += 1;
+                    let backoff = backoff(initial_backoff, max_backoff, retries);
+                    warn!(
+                        operation,
+                        retries,
+                        "Vector store operation failed, retrying in {:?}: {:#}",
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Delay before retry number `retry` (counting from 1): `initial`, doubled for
+/// each retry after the first and capped at `max`, with up to half of it taken
+/// off at random so that clients failing together don't retry in lockstep.
+pub(super) fn backoff(initial: Duration, max: Duration, retry: usize) -> Duration {
+    let doublings = retry.saturating_sub(1).min(31) as u32;
+    let delay = initial.saturating_mul(1 << doublings).min(max);
+    delay.mul_f64(1.0 - random_fraction() / 2.0)
+}
+
+/// A random number in `[0, 1)`.
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Whether `error` is a connection failure or timeout, which may succeed if
+/// tried again, rather than a problem with the request itself.
+///
+/// Any cause in the chain counts: gRPC statuses, including those inside
+/// Qdrant errors, I/O errors from the network stack and HTTP client errors.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+            return is_transient_code(status.code());
+        }
+        if let Some(e) = cause.downcast_ref::<QdrantError>() {
+            return match e {
+                QdrantError::ResponseError { status } => is_transient_code(status.code()),
+                QdrantError::Io(e) => is_transient_io(e),
+                _ => false,
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return is_transient_io(e);
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_timeout() || e.is_connect();
+        }
+        false
+    })
+}
+
+/// gRPC status codes of requests that didn't reach the server, timed out or
+/// were turned away for now.
+fn is_transient_code(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted | Code::Aborted
+    )
+}
+
+fn is_transient_io(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+    )
+}
+
+#[async_trait]
+impl VectorStore for RetryingStore {
+    async fn add(&self, documents: Vec<Document>) -> Result<()> {
+        self.retry("add", || self.inner.add(documents.clone()))
+            .await
+    }
+
+    async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+        self.retry("search", || self.inner.search(query_embedding))
+            .await
+    }
+
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>> {
+        self.retry("search", || self.inner.search_with_k(query_embedding, k))
+            .await
+    }
+
+    async fn count(&self) -> Result<usize> {
+        self.retry("count", || self.inner.count()).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn get_indexed_paths(&self) -> Result<Vec<String>> {
+        self.inner.get_indexed_paths().await
+    }
+
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
+        self.inner.count_by_source().await
+    }
+
+    async fn get_by_source(&self, source: &str) -> Result<Vec<Document>> {
+        self.inner.get_by_source(source).await
+    }
+
+    async fn remove_by_source(&self, source_path: &str) -> Result<usize> {
+        self.inner.remove_by_source(source_path).await
+    }
+
+    async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+        self.inner.evict_older_than(age).await
+    }
+
+    fn vector_size(&self) -> u64 {
+        self.inner.vector_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory_store::MemoryStore;
+    use super::*;
+    use crate::config::StorageConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store whose adds, searches and counts fail with `error` a number of
+    /// times before reaching a memory store.
+    struct FlakyStore {
+        inner: MemoryStore,
+        failures: AtomicUsize,
+        attempts: AtomicUsize,
+        error: fn() -> anyhow::Error,
+    }
+
+    impl FlakyStore {
+        fn new(failures: usize, error: fn() -> anyhow::Error) -> Self {
+            Self {
+                inner: MemoryStore::new(StorageConfig::default(), 2),
+                failures: AtomicUsize::new(failures),
+                attempts: AtomicUsize::new(0),
+                error,
+            }
+        }
+
+        fn fail(&self) -> Result<()> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let remaining = self.failures.load(Ordering::SeqCst);
+            if remaining == 0 {
+                return Ok(());
+            }
+            self.failures.store(remaining - 1, Ordering::SeqCst);
+            Err((self.error)())
+        }
+    }
+
+    #[async_trait]
+    impl VectorStore for FlakyStore {
+        async fn add(&self, documents: Vec<Document>) -> Result<()> {
+            self.fail()?;
+            self.inner.add(documents).await
+        }
+
+        async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>> {
+            self.fail()?;
+            self.inner.search(query_embedding).await
+        }
+
+        async fn search_with_k(
+            &self,
+            query_embedding: &[f32],
+            k: usize,
+        ) -> Result<Vec<SearchResult>> {
+            self.fail()?;
+            self.inner.search_with_k(query_embedding, k).await
+        }
+
+        async fn count(&self) -> Result<usize> {
+            self.fail()?;
+            self.inner.count().await
+        }
+
+        async fn clear(&self) -> Result<()> {
+            self.inner.clear().await
+        }
+
+        async fn get_indexed_paths(&self) -> Result<Vec<String>> {
+            self.inner.get_indexed_paths().await
+        }
+
+        async fn count_by_source(&self) -> Result<BTreeMap<String, usize>> {
+            self.inner.count_by_source().await
+        }
+
+        async fn get_by_source(&self, source: &str) -> Result<Vec<Document>> {
+            self.inner.get_by_source(source).await
+        }
+
+        async fn remove_by_source(&self, source_path: &str) -> Result<usize> {
+            self.inner.remove_by_source(source_path).await
+        }
+
+        async fn evict_older_than(&self, age: Duration) -> Result<usize> {
+            self.inner.evict_older_than(age).await
+        }
+
+        fn vector_size(&self) -> u64 {
+            self.inner.vector_size()
+        }
+    }
+
+    fn retry_config() -> StoreRetryConfig {
+        StoreRetryConfig {
+            max_retries: 3,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_are_retried() {
+        let flaky = Arc::new(FlakyStore::new(2, || {
+            std::io::Error::from(ErrorKind::ConnectionReset).into()
+        }));
+        let store = RetryingStore::new(flaky.clone(), retry_config());
+
+        let document = Document::new("a", "content", vec![1.0, 0.0]);
+        store.add(vec![document]).await.unwrap();
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+
+        flaky.failures.store(2, Ordering::SeqCst);
+        assert_eq!(store.count().await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_with_jitter() {
+        let initial = Duration::from_millis(100);
+        let max = Duration::from_millis(1000);
+        let expected = [100, 200, 400, 800, 1000, 1000];
+        for (retry, full) in [1, 2, 3, 4, 5, 60].into_iter().zip(expected) {
+            let full = Duration::from_millis(full);
+            let delay = backoff(initial, max, retry);
+            assert!(delay >= full / 2 && delay <= full, "retry {}", retry);
+        }
+    }
+
+    #[test]
+    fn test_grpc_statuses_are_classified_by_code() {
+        let unavailable = anyhow::Error::new(tonic::Status::unavailable("transport error"))
+            .context("Failed to search points");
+        assert!(is_transient(&unavailable));
+
+        let response = QdrantError::ResponseError {
+            status: tonic::Status::deadline_exceeded("Timeout expired"),
+        };
+        assert!(is_transient(&anyhow::Error::new(response)));
+
+        // Mentioning "unavailable" doesn't make an invalid request transient
+        let invalid = QdrantError::ResponseError {
+            status: tonic::Status::invalid_argument("payload index unavailable for field"),
+        };
+        assert!(!is_transient(&anyhow::Error::new(invalid)));
+    }
+
+    #[tokio::test]
+    async fn test_other_failures_are_not_retried() {
+        let flaky = Arc::new(FlakyStore::new(2, || {
+            anyhow::anyhow!("Wrong input: vector dimension error: expected dim 768, got 2")
+        }));
+        let store = RetryingStore::new(flaky.clone(), retry_config());
+
+        assert!(store.search(&[1.0, 0.0]).await.is_err());
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,81 @@
+//! Rewriting queries into hypothetical answers before retrieval (HyDE).
+
+use super::cache::QueryCache;
+use crate::config::QueryRewriteConfig;
+use crate::provider::{ChatRequest, Message, Provider, ProviderError};
+use std::sync::Arc;
+
+/// Asks the model for a passage answering a query, to embed in its place.
+pub(super) struct QueryRewriter {
+    provider: Arc<dyn Provider>,
+    model: String,
+    config: QueryRewriteConfig,
+    /// Recent rewrites, keyed by query.
+    cache: QueryCache<String>,
+}
+
+impl QueryRewriter {
+    pub(super) fn new(
+        provider: Arc<dyn Provider>,
+        model: impl Into<String>,
+        config: QueryRewriteConfig,
+    ) -> Self {
+        Self {
+            provider,
+            model: model.into(),
+            cache: QueryCache::new(config.cache_capacity, None),
+            config,
+        }
+    }
+
+    /// Whether the query itself should be embedded along with its rewrite.
+    pub(super) fn include_query(&self) -> bool {
+        self.config.include_query
+    }
+
+    /// The hypothetical passage for `query`, reused if it was rewritten recently.
+    pub(super) async fn rewrite(&self, query: &str) -> Result<String, ProviderError> {
+        let key = query.trim();
+        if let Some(rewrite) = self.cache.get(key) {
+            return Ok(rewrite);
+        }
+
+        let prompt = self.config.prompt.replace("{query}", key);
+        let request = ChatRequest::new(self.model.clone(), vec![Message::user(None, prompt)])
+            .with_temperature(0.0);
+        let rewrite = self
+            .provider
+            .chat_once(request)
+            .await?
+            .content
+            .trim()
+            .to_string();
+
+        self.cache.insert(key, rewrite.clone());
+        Ok(rewrite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockProvider;
+
+    #[tokio::test]
+    async fn test_rewrites_are_cached() {
+        let provider = Arc::new(MockProvider::new("  A passage.  "));
+        let config = QueryRewriteConfig {
+            enabled: true,
+            prompt: "Answer: {query}".to_string(),
+            ..QueryRewriteConfig::default()
+        };
+        let rewriter = QueryRewriter::new(provider.clone(), "model", config);
+
+        assert_eq!(rewriter.rewrite("what is x").await.unwrap(), "A passage.");
+        assert_eq!(rewriter.rewrite(" what is x ").await.unwrap(), "A passage.");
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].messages[0].content, "Answer: what is x");
+    }
+}
@@ -0,0 +1,202 @@
+//! Keyword matching of queries against retrieved chunks, and snippets that
+//! show the matches at a glance.
+
+/// Common words that say nothing about what a query is looking for.
+const STOPWORDS: [&str; 32] = [
+    "about", "and", "are", "can", "did", "does", "for", "from", "had", "has", "have", "how",
+    "into", "its", "not", "that", "the", "their", "then", "there", "these", "this", "use", "was",
+    "what", "when", "where", "which", "who", "why", "will", "with",
+];
+
+/// Marks the omitted text at either end of a snippet.
+const ELLIPSIS: &str = "…";
+
+/// Splits a query into the lowercase terms worth matching, in order and without
+/// duplicates. Words shorter than three characters and stopwords are dropped.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for word in query.split(|c: char| !c.is_alphanumeric() && c != '_') {
+        let word = word.to_lowercase();
+        if word.chars().count() >= 3
+            && !STOPWORDS.contains(&word.as_str())
+            && !terms.contains(&word)
+        {
+            terms.push(word);
+        }
+    }
+    terms
+}
+
+/// The `terms` that occur in `content`.
+pub fn matched_terms(content: &str, terms: &[String]) -> Vec<String> {
+    terms
+        .iter()
+        .filter(|term| !find_matches(content, std::slice::from_ref(term)).is_empty())
+        .cloned()
+        .collect()
+}
+
+/// A window of about `window` bytes of `content`, centered on the first match
+/// of any of `terms`, with every match in it wrapped in `marker`.
+///
+/// Whitespace is collapsed to single spaces, and text cut off at either end is
+/// replaced by an ellipsis. Returns `None` if none of the terms occur.
+pub fn snippet(content: &str, terms: &[String], window: usize, marker: &str) -> Option<String> {
+    let matches = find_matches(content, terms);
+    let &(first_start, first_end) = matches.first()?;
+
+    let center = (first_start + first_end) / 2;
+    let mut start = center.saturating_sub(window / 2);
+    let mut end = (start + window).min(content.len());
+    start = end.saturating_sub(window).min(start);
+    start = word_start(content, start.min(first_start));
+    end = word_end(content, end.max(first_end));
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+    let mut position = start;
+    for &(match_start, match_end) in &matches {
+        if match_start < start || match_end > end {
+            continue;
+        }
+        snippet.push_str(&content[position..match_start]);
+        snippet.push_str(marker);
+        snippet.push_str(&content[match_start..match_end]);
+        snippet.push_str(marker);
+        position = match_end;
+    }
+    snippet.push_str(&content[position..end]);
+    if end < content.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+
+    Some(snippet.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Byte ranges of the occurrences of `terms` in `content`, in order and not
+/// overlapping. Terms, which are lowercase, match case-insensitively (in any
+/// script) at the start of a word.
+fn find_matches(content: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut position = 0;
+
+    while position < content.len() {
+        let at_word_start = position == 0
+            || !content[..position]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let matched = at_word_start
+            .then(|| {
+                terms
+                    .iter()
+                    .filter_map(|term| lowercase_prefix_len(&content[position..], term))
+                    .max()
+            })
+            .flatten();
+
+        match matched {
+            Some(len) => {
+                matches.push((position, position + len));
+                position += len;
+            }
+            None => position += content[position..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    matches
+}
+
+/// Byte length of the start of `text` that lowercases to `term`, if any.
+fn lowercase_prefix_len(text: &str, term: &str) -> Option<usize> {
+    if term.is_empty() {
+        return None;
+    }
+    let mut remaining = term;
+    for (index, c) in text.char_indices() {
+        if remaining.is_empty() {
+            return Some(index);
+        }
+        for lower in c.to_lowercase() {
+            remaining = remaining.strip_prefix(lower)?;
+        }
+    }
+    remaining.is_empty().then_some(text.len())
+}
+
+/// Moves `index` back to the start of the word it falls in.
+fn word_start(content: &str, mut index: usize) -> usize {
+    while !content.is_char_boundary(index) {
+        index -= 1;
+    }
+    content[..index].rfind(char::is_whitespace).map_or(0, |i| {
+        i + content[i..].chars().next().map_or(1, char::len_utf8)
+    })
+}
+
+/// Moves `index` forward to the end of the word it falls in.
+fn word_end(content: &str, mut index: usize) -> usize {
+    while !content.is_char_boundary(index) {
+        index += 1;
+    }
+    content[index..]
+        .find(char::is_whitespace)
+        .map_or(content.len(), |i| index + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_terms_drop_stopwords_and_short_words() {
+        assert_eq!(
+            query_terms("How does the Tokio runtime spawn a task? tokio"),
+            vec!["tokio", "runtime", "spawn", "task"]
+        );
+    }
+
+    #[test]
+    fn test_snippet_centers_on_and_marks_matched_term() {
+        let before = "alpha beta gamma delta ".repeat(10);
+        let after = " epsilon zeta eta theta".repeat(10);
+        let content = format!("{}the Tokio runtime{}", before, after);
+
+        let snippet = snippet(&content, &query_terms("tokio"), 60, "**").unwrap();
+
+        assert!(
+            snippet.starts_with(ELLIPSIS) && snippet.ends_with(ELLIPSIS),
+            "{snippet}"
+        );
+        assert!(snippet.contains("the **Tokio** runtime"), "{snippet}");
+        let (head, tail) = snippet.split_once("**Tokio**").unwrap();
+        let offset = head.len() as i64 - tail.len() as i64;
+        assert!(offset.abs() <= 12, "term is off center: {snippet}");
+        assert!(snippet.len() < 60 + 2 * ELLIPSIS.len() + 24, "{snippet}");
+    }
+
+    #[test]
+    fn test_snippet_marks_every_term_in_window() {
+        let terms = query_terms("spawn tasks");
+        let snippet = snippet("Spawns tasks\nonto the runtime", &terms, 200, "**").unwrap();
+        assert_eq!(snippet, "**Spawn**s **tasks** onto the runtime");
+
+        assert!(super::snippet("no match here", &terms, 200, "**").is_none());
+        assert!(super::snippet("respawn", &terms, 200, "**").is_none());
+    }
+
+    #[test]
+    fn test_terms_match_case_insensitively_beyond_ascii() {
+        let terms = query_terms("ÉCOLE straße ΣΟΦΙΑ");
+        assert_eq!(terms, vec!["école", "straße", "σοφια"]);
+
+        let snippet = snippet("Die STRASSE? Nein, die Straße zur École", &terms, 200, "*");
+        assert_eq!(
+            snippet.unwrap(),
+            "Die STRASSE? Nein, die *Straße* zur *École*"
+        );
+        assert_eq!(matched_terms("ΣΟΦΙΑ ΚΑΙ ΓΝΩΣΗ", &terms), vec!["σοφια"]);
+    }
+}
@@ -3,12 +3,114 @@
 //! This module provides a unified interface for different vector database implementations.
 
 use super::lancedb_store::LanceDbStore;
+use super::memory_store::MemoryStore;
 use super::qdrant_store::QdrantStore;
+use super::retry::RetryingStore;
 use super::types::{Document, SearchResult};
-use crate::config::{StorageConfig, StorageMode};
+use crate::config::{StorageConfig, StorageMode, VectorDbConfig};
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// How embeddings are compared when searching the vector store.
+///
+/// Cosine similarity suits most text embedding models. Models trained for
+/// maximum inner product search rank better with [`DotProduct`](Self::DotProduct),
+/// and [`Euclidean`](Self::Euclidean) distance suits models whose embeddings are
+/// not normalized.
+///
+/// For `Cosine` and `DotProduct`, [`SearchResult::score`] is a similarity and
+/// results are sorted by descending score. For `Euclidean` it is a distance and
+/// results are sorted by ascending score. With `vector_db.normalize_scores`
+/// enabled, scores of every metric are [normalized](Self::normalize) to `0..=1`
+/// and sorted by descending score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarityMetric {
+    #[default]
+    Cosine,
+    DotProduct,
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Scores `b` against `a`: a similarity for `Cosine` and `DotProduct`, a
+    /// distance for `Euclidean`.
+    pub fn score(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine => {
+                let norm_a = dot(a, a).sqrt();
+                let norm_b = dot(b, b).sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    0.0
+                } else {
+                    dot(a, b) / (norm_a * norm_b)
+                }
+            }
+            Self::DotProduct => dot(a, b),
+            Self::Euclidean => a
+                .iter()
+                .zip(b)
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    /// Whether a higher score means a closer match.
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(self, Self::Euclidean)
+    }
+
+    /// Maps a score under this metric to `0..=1`, higher meaning a closer match,
+    /// preserving the order of matches:
+    /// - `Cosine`: `(1 + similarity) / 2`, so orthogonal vectors score 0.5
+    /// - `DotProduct`: the logistic function of the product
+    /// - `Euclidean`: `1 / (1 + distance)`
+    pub fn normalize(&self, score: f32) -> f32 {
+        let normalized = match self {
+            Self::Cosine => (1.0 + score) / 2.0,
+            Self::DotProduct => 1.0 / (1.0 + (-score).exp()),
+            Self::Euclidean => 1.0 / (1.0 + score.max(0.0)),
+        };
+        normalized.clamp(0.0, 1.0)
+    }
+
+    /// Sorts results best match first.
+    pub fn sort(&self, results: &mut [SearchResult]) {
+        results.sort_by(|a, b| {
+            let order = a.score.total_cmp(&b.score);
+            if self.higher_is_better() {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+}
+
+/// Sorts results best match first, normalizing their scores if
+/// `vector_db.normalize_scores` is enabled.
+pub(super) fn rank_results(config: &VectorDbConfig, results: &mut [SearchResult]) {
+    if !config.normalize_scores {
+        config.metric.sort(results);
+        return;
+    }
+
+    for result in results.iter_mut() {
+        result.score = config.metric.normalize(result.score);
+    }
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
 
 /// Unified interface for vector database operations.
 ///
@@ -18,18 +120,23 @@ use std::sync::Arc;
 pub trait VectorStore: Send + Sync {
     /// Adds or updates multiple documents in the store.
     async fn add(&self, documents: Vec<Document>) -> Result<()>;
-    /// Searches for the most similar documents using vector similarity.
+    /// Searches for the most similar documents using vector similarity,
+    /// returning up to the configured `storage.top_k` results.
     ///
     /// # Arguments
     ///
     /// * `query_embedding` - The embedding vector to search for
-    /// * `top_k` - Maximum number of results to return
     ///
     /// # Returns
     ///
-    /// A vector of search results, sorted by descending similarity score.
+    /// A vector of search results, best match first (see [`SimilarityMetric`]
+    /// for how scores are ordered).
     async fn search(&self, query_embedding: &[f32]) -> Result<Vec<SearchResult>>;
 
+    /// Like [`search`](Self::search), but returning up to `k` results instead
+    /// of the configured `storage.top_k`.
+    async fn search_with_k(&self, query_embedding: &[f32], k: usize) -> Result<Vec<SearchResult>>;
+
     /// Returns the total number of documents in the store.
     async fn count(&self) -> Result<usize>;
 
@@ -39,6 +146,13 @@ pub trait VectorStore: Send + Sync {
     /// Returns all unique source file paths that have been indexed.
     async fn get_indexed_paths(&self) -> Result<Vec<String>>;
 
+    /// Returns the number of documents of every indexed source, sorted by
+    /// source, in a single pass over the store.
+    async fn count_by_source(&self) -> Result<BTreeMap<String, usize>>;
+
+    /// Returns every document whose source is exactly `source`, without embeddings.
+    async fn get_by_source(&self, source: &str) -> Result<Vec<Document>>;
+
     /// Removes all documents with a matching source path.
     ///
     /// # Arguments
@@ -49,12 +163,38 @@ pub trait VectorStore: Send + Sync {
     ///
     /// The number of documents removed.
     async fn remove_by_source(&self, source_path: &str) -> Result<usize>;
+
+    /// Removes all documents indexed more than `age` ago.
+    ///
+    /// Documents without an [`indexed_at`](Document::indexed_at) timestamp are
+    /// kept, as their age is unknown.
+    ///
+    /// # Returns
+    ///
+    /// The number of documents removed.
+    async fn evict_older_than(&self, age: Duration) -> Result<usize>;
+
+    /// Returns the dimension of the embedding vectors the store holds.
+    fn vector_size(&self) -> u64;
+}
+
+/// The `indexed_at` value (seconds since the Unix epoch) before which a
+/// document is older than `age`.
+pub(super) fn eviction_cutoff(age: Duration) -> u64 {
+    SystemTime::now()
+        .checked_sub(age)
+        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |cutoff| cutoff.as_secs())
 }
 
 /// Creates a vector store instance based on the storage mode.
 ///
 /// - `Embedded` mode uses LanceDB for zero-setup, in-process storage
-/// - `Grpc` mode uses Qdrant for remote server connectivity
+/// - `Grpc` mode uses Qdrant for remote server connectivity, retrying
+///   transient failures as set by `vector_db.retry`
+///
+/// With `memory_fallback` set, an embedded path that can't be written to is
+/// replaced by an in-memory store rather than failing.
 ///
 /// # Arguments
 ///
@@ -70,12 +210,137 @@ pub async fn create_vector_store(
 ) -> Result<Arc<dyn VectorStore>> {
     match storage_config.storage_mode.clone() {
         StorageMode::Embedded { path } => {
+            if storage_config.memory_fallback && !is_writable(Path::new(&path)) {
+                warn!(
+                    path = %path,
+                    "Vector store path is not writable, using an in-memory store; \
+                     the knowledge base will not be persisted"
+                );
+                return Ok(Arc::new(MemoryStore::new(storage_config, vector_size)));
+            }
+
             let store = LanceDbStore::new(storage_config, &path, vector_size.into()).await?;
             Ok(Arc::new(store))
         }
         StorageMode::Grpc { .. } => {
+            let retry = storage_config.vector_db.retry.clone();
             let store = QdrantStore::new(storage_config, vector_size).await?;
-            Ok(Arc::new(store))
+            Ok(Arc::new(RetryingStore::new(Arc::new(store), retry)))
         }
     }
 }
+
+/// Whether a directory can be created at `path` and written to.
+fn is_writable(path: &Path) -> bool {
+    if std::fs::create_dir_all(path).is_err() {
+        return false;
+    }
+
+    let probe = path.join(".nucleus_write_test");
+    let writable = std::fs::write(&probe, b"").is_ok();
+    let _ = std::fs::remove_file(&probe);
+    writable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scores every fixture against the query and returns their ids, best match first.
+    fn rank(metric: SimilarityMetric, query: &[f32]) -> Vec<String> {
+        let fixtures: [(&str, [f32; 2]); 3] = [
+            // Same direction as the query, but far away
+            ("aligned_far", [10.0, 0.0]),
+            // Slightly off direction, but very close
+            ("close", [0.9, 0.3]),
+            // Opposite direction
+            ("opposite", [-1.0, 0.0]),
+        ];
+
+        let mut results: Vec<SearchResult> = fixtures
+            .iter()
+            .map(|(id, embedding)| SearchResult {
+                document: Document::new(*id, "", embedding.to_vec()),
+                score: metric.score(query, embedding),
+                explanation: None,
+                snippet: None,
+            })
+            .collect();
+        metric.sort(&mut results);
+
+        results.into_iter().map(|r| r.document.id).collect()
+    }
+
+    #[test]
+    fn test_cosine_ranks_by_direction() {
+        assert_eq!(
+            rank(SimilarityMetric::Cosine, &[1.0, 0.0]),
+            vec!["aligned_far", "close", "opposite"]
+        );
+    }
+
+    #[test]
+    fn test_dot_product_ranks_by_projection() {
+        assert_eq!(
+            rank(SimilarityMetric::DotProduct, &[1.0, 0.0]),
+            vec!["aligned_far", "close", "opposite"]
+        );
+        // Unlike cosine, magnitude matters: a long vector slightly off-axis
+        // beats a short aligned one
+        let long_off_axis = SimilarityMetric::DotProduct.score(&[1.0, 0.0], &[5.0, 5.0]);
+        let short_aligned = SimilarityMetric::DotProduct.score(&[1.0, 0.0], &[1.0, 0.0]);
+        assert!(long_off_axis > short_aligned);
+    }
+
+    #[test]
+    fn test_euclidean_ranks_by_ascending_distance() {
+        assert_eq!(
+            rank(SimilarityMetric::Euclidean, &[1.0, 0.0]),
+            vec!["close", "opposite", "aligned_far"]
+        );
+        assert_eq!(
+            SimilarityMetric::Euclidean.score(&[0.0, 0.0], &[3.0, 4.0]),
+            5.0
+        );
+    }
+
+    #[test]
+    fn test_cosine_is_default() {
+        assert_eq!(SimilarityMetric::default(), SimilarityMetric::Cosine);
+        let metric: SimilarityMetric = serde_json::from_str("\"dot_product\"").unwrap();
+        assert_eq!(metric, SimilarityMetric::DotProduct);
+    }
+
+    #[tokio::test]
+    async fn test_read_only_path_falls_back_to_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory can't be created beneath a file, even with root privileges
+        let blocker = dir.path().join("blocker");
+        std::fs::write(&blocker, "").unwrap();
+        let path = blocker.join("vectors");
+
+        let mut storage_config = StorageConfig {
+            storage_mode: StorageMode::Embedded {
+                path: path.to_string_lossy().to_string(),
+            },
+            ..StorageConfig::default()
+        };
+        assert!(create_vector_store(storage_config.clone(), 2)
+            .await
+            .is_err());
+
+        storage_config.memory_fallback = true;
+        let store = create_vector_store(storage_config, 2).await.unwrap();
+        store
+            .add(vec![Document::new("a", "kept in memory", vec![1.0, 0.0])
+                .with_metadata("source", "notes.md")])
+            .await
+            .unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(store.count().await.unwrap(), 1);
+        let results = store.search(&[1.0, 0.0]).await.unwrap();
+        assert_eq!(results[0].document.content, "kept in memory");
+        assert_eq!(store.remove_by_source("notes.md").await.unwrap(), 1);
+    }
+}
@@ -1,5 +1,13 @@
+use super::store::SimilarityMetric;
+use crate::prompt::render_template;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata key holding when a document was indexed, in seconds since the Unix epoch.
+pub const INDEXED_AT: &str = "indexed_at";
 
 /// A document stored in the vector database.
 ///
@@ -40,6 +48,39 @@ impl Document {
         self.metadata.insert(key.into(), value.into());
         self
     }
+
+    /// Records when the document was indexed, used to evict stale documents.
+    pub fn with_indexed_at(self, time: SystemTime) -> Self {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+        self.with_metadata(INDEXED_AT, secs.to_string())
+    }
+
+    /// When the document was indexed, if recorded.
+    pub fn indexed_at(&self) -> Option<SystemTime> {
+        let secs: u64 = self.metadata.get(INDEXED_AT)?.parse().ok()?;
+        Some(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Returns the location this document was cut from, e.g. `src/main.rs:120-145`.
+    ///
+    /// Uses the `source`, `start_line` and `end_line` metadata recorded at index
+    /// time. Returns just the source if no line range is known, or `None` if the
+    /// document has no source at all.
+    pub fn location(&self) -> Option<String> {
+        let source = self.metadata.get("source")?;
+
+        match (
+            self.metadata.get("start_line"),
+            self.metadata.get("end_line"),
+        ) {
+            (Some(start), Some(end)) if start == end => Some(format!("{}:{}", source, start)),
+            (Some(start), Some(end)) => Some(format!("{}:{}-{}", source, start, end)),
+            _ => Some(source.clone()),
+        }
+    }
 }
 
 /// A search result containing a document and its similarity score.
@@ -59,4 +100,253 @@ impl Document {
 pub struct SearchResult {
     pub document: Document,
     pub score: f32,
+    /// Why the document matched, if the search recorded it.
+    pub explanation: Option<MatchExplanation>,
+    /// Short excerpt of the document around the query terms it contains, with
+    /// the terms marked. Only set when `rag.snippets` is enabled.
+    pub snippet: Option<String>,
+}
+
+/// Details of how a search result was matched, to help tune retrieval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchExplanation {
+    /// Metric the query and document embeddings were compared with.
+    pub metric: SimilarityMetric,
+    /// Distance between the embeddings: `1 - score` for cosine and dot product,
+    /// the euclidean distance itself for euclidean.
+    pub distance: f32,
+    /// Query terms found in the document, filled in by
+    /// [`RagEngine::retrieve`](super::RagEngine::retrieve); stores leave them empty.
+    pub matched_terms: Vec<String>,
+}
+
+impl MatchExplanation {
+    /// Explains a vector search match with the given `score` under `metric`.
+    pub fn vector(metric: SimilarityMetric, score: f32) -> Self {
+        let distance = if metric.higher_is_better() {
+            1.0 - score
+        } else {
+            score
+        };
+
+        Self {
+            metric,
+            distance,
+            matched_terms: Vec::new(),
+        }
+    }
+}
+
+/// Outcome of indexing a directory, file by file.
+///
+/// Every file found while walking the directory ends up in exactly one bucket:
+/// - `indexed` - embedded and stored
+/// - `skipped` - intentionally not indexed (binary, empty or oversized files,
+///   and files whose text couldn't be extracted)
+/// - `errors` - could not be indexed, with the reason (unreadable, embedding failed)
+///
+/// With `indexer.dedup` enabled, `duplicate_chunks` counts the chunks that were
+/// not stored because an identical chunk had already been indexed. When
+/// reindexing the knowledge base, `missing` lists the files that no longer
+/// exist on disk and `kept` the sources that aren't files, which are embedded
+/// again from their stored chunks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexReport {
+    pub indexed: Vec<PathBuf>,
+    pub skipped: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, String)>,
+    pub duplicate_chunks: usize,
+    pub missing: Vec<PathBuf>,
+    pub kept: Vec<String>,
+}
+
+impl IndexReport {
+    /// Number of files successfully indexed.
+    pub fn indexed_count(&self) -> usize {
+        self.indexed.len()
+    }
+
+    /// Whether any file failed to index.
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// Folds another report (e.g. for a second directory) into this one.
+    pub fn merge(&mut self, other: IndexReport) {
+        self.indexed.extend(other.indexed);
+        self.skipped.extend(other.skipped);
+        self.errors.extend(other.errors);
+        self.duplicate_chunks += other.duplicate_chunks;
+        self.missing.extend(other.missing);
+        self.kept.extend(other.kept);
+    }
+}
+
+/// Progress of a directory being indexed, reported after each file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexProgress {
+    /// Number of files processed so far, including skipped and failed ones
+    pub processed: usize,
+    /// Number of files found in the directory
+    pub total: usize,
+    /// The file that was just processed
+    pub current: PathBuf,
+}
+
+impl std::fmt::Display for IndexProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "indexed {}/{} files, current: {}",
+            self.processed,
+            self.total,
+            self.current.display()
+        )
+    }
+}
+
+/// Progress of embedding the chunks of a directory being indexed, reported
+/// after each batch.
+///
+/// Files are chunked as they are indexed, so until `all_chunked` is set the
+/// total only counts the chunks of the files reached so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbedProgress {
+    /// Number of chunks embedded so far, including ones that failed to embed
+    pub embedded_chunks: usize,
+    /// Number of chunks to embed found so far, not counting duplicates
+    /// skipped by `indexer.dedup`
+    pub total_chunks: usize,
+    /// Whether every file has been chunked, so `total_chunks` is final
+    pub all_chunked: bool,
+}
+
+impl std::fmt::Display for EmbedProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "embedded {}/{}{} chunks",
+            self.embedded_chunks,
+            self.total_chunks,
+            if self.all_chunked { "" } else { "+" }
+        )
+    }
+}
+
+/// A source file in the knowledge base and how many chunks it was split into.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedSource {
+    pub source: String,
+    pub chunks: usize,
+}
+
+/// A chunk a file would be split into when indexed, for checking chunk settings.
+///
+/// Byte offsets are 0-based with `end_byte` exclusive; lines are 1-based and
+/// inclusive. `tokens` is counted with the indexer's tokenizer, or estimated at
+/// about four bytes per token without one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkPreview {
+    pub content: String,
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub tokens: usize,
+}
+
+/// Templates used to turn search results into the context given to the model.
+///
+/// `chunk` is rendered once per result, with these placeholders:
+/// - `{index}` - the 1-based rank of the result
+/// - `{source}` - where the chunk came from, e.g. `src/main.rs:120-145`. If it
+///   wasn't recorded, the placeholder is left out, along with brackets right
+///   around it (`()`, `[]` or `<>`) and a space next to it
+/// - `{content}` - the chunk text
+/// - `{score}` - the similarity score, with three decimals
+///
+/// The rendered chunks are concatenated and substituted for `{chunks}` in
+/// `wrapper`. Other placeholders are left as is.
+///
+/// # Example
+///
+/// ```yaml
+/// rag:
+///   context_template:
+///     wrapper: "Use these files to answer:\n{chunks}"
+///     chunk: "\n--- {source} ---\n```\n{content}\n```\n"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextTemplate {
+    pub wrapper: String,
+    pub chunk: String,
+}
+
+impl Default for ContextTemplate {
+    fn default() -> Self {
+        Self {
+            wrapper: "\n\nRelevant context from your knowledge base:\n{chunks}".to_string(),
+            chunk: "\n[{index}] ({source}) {content}\n".to_string(),
+        }
+    }
+}
+
+/// `template` with each `{source}` placeholder removed, along with brackets
+/// right around it and a space after it (or, failing that, before it).
+fn without_source(template: &str) -> String {
+    const PLACEHOLDER: &str = "{source}";
+    let mut template = template.to_string();
+    while let Some(mut start) = template.find(PLACEHOLDER) {
+        let mut end = start + PLACEHOLDER.len();
+        let before = template[..start].chars().next_back();
+        let after = template[end..].chars().next();
+        if matches!(
+            (before, after),
+            (Some('('), Some(')')) | (Some('['), Some(']')) | (Some('<'), Some('>'))
+        ) {
+            start -= 1;
+            end += 1;
+        }
+        if template[end..].starts_with(' ') {
+            end += 1;
+        } else if template[..start].ends_with(' ') {
+            start -= 1;
+        }
+        template.replace_range(start..end, "");
+    }
+    template
+}
+
+impl ContextTemplate {
+    /// Renders `results` with this template. Returns an empty string if there
+    /// are no results.
+    pub fn render(&self, results: &[SearchResult]) -> String {
+        if results.is_empty() {
+            return String::new();
+        }
+
+        let chunks: String = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let location = result.document.location();
+                let chunk = match location {
+                    Some(_) => Cow::Borrowed(&self.chunk),
+                    None => Cow::Owned(without_source(&self.chunk)),
+                };
+                render_template(&chunk, |name| match name {
+                    "index" => Some((i + 1).to_string()),
+                    "source" => Some(location.clone().unwrap_or_default()),
+                    "content" => Some(result.document.content.clone()),
+                    "score" => Some(format!("{:.3}", result.score)),
+                    _ => None,
+                })
+            })
+            .collect();
+
+        render_template(&self.wrapper, |name| {
+            (name == "chunks").then(|| chunks.clone())
+        })
+    }
 }
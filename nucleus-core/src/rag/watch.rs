@@ -0,0 +1,105 @@
+//! Keeping the knowledge base in sync with a directory as files change.
+
+use super::{RagEngine, Result};
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// How long a watched directory has to be quiet before changed files are
+/// reindexed.
+///
+/// Editors often write a file several times in quick succession when saving,
+/// so changes are collected until no event has arrived for this long.
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Paths touched by a burst of file system events.
+#[derive(Default)]
+struct PendingChanges {
+    /// Every path created, modified or removed.
+    changed: BTreeSet<PathBuf>,
+    /// Paths that appeared in the directory, so new directories get indexed.
+    created: BTreeSet<PathBuf>,
+}
+
+impl PendingChanges {
+    fn record(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                warn!(error = %e, "File watcher error");
+                return;
+            }
+        };
+
+        match event.kind {
+            EventKind::Access(_) | EventKind::Other => return,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+                self.created.extend(event.paths.iter().cloned());
+            }
+            _ => {}
+        }
+        self.changed.extend(event.paths);
+    }
+}
+
+impl RagEngine {
+    /// Watches `dir` and keeps the knowledge base in sync with it until the
+    /// returned future is dropped.
+    ///
+    /// Created and modified files are reindexed with
+    /// [`reindex_file`](Self::reindex_file), deleted files have their chunks
+    /// removed, and new directories are indexed as a whole. Events are debounced
+    /// by [`WATCH_DEBOUNCE`], so a file saved several times in a row is only
+    /// reindexed once.
+    ///
+    /// Files that fail to index are logged and skipped; watching carries on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` can't be watched.
+    pub async fn watch(&self, dir: &Path) -> Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+        info!(dir = %dir.display(), "Watching for changes");
+
+        while let Some(event) = rx.recv().await {
+            let mut pending = PendingChanges::default();
+            pending.record(event);
+
+            while let Ok(Some(event)) = tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {
+                pending.record(event);
+            }
+
+            self.apply_changes(pending).await;
+        }
+
+        Ok(())
+    }
+
+    async fn apply_changes(&self, pending: PendingChanges) {
+        for path in &pending.changed {
+            if path.is_dir() {
+                // Modified directories only matter for their files, which get
+                // their own events
+                if pending.created.contains(path) {
+                    if let Err(e) = self.index_directory(path).await {
+                        warn!(dir = %path.display(), error = %e, "Failed to index new directory");
+                    }
+                }
+                continue;
+            }
+
+            match self.reindex_file(path).await {
+                Ok(chunks) => info!(file = %path.display(), chunks, "Reindexed"),
+                Err(e) => warn!(file = %path.display(), error = %e, "Failed to reindex"),
+            }
+        }
+    }
+}
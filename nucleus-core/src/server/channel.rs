@@ -0,0 +1,211 @@
+//! Bounded channel carrying response chunks from the handler to a connection.
+
+use super::types::StreamChunk;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use tokio::sync::mpsc::{self, error::SendError};
+
+/// Observes every chunk sent, see [`ChunkSender::inspect`].
+type Inspector = Arc<dyn Fn(&StreamChunk) + Send + Sync>;
+
+/// Creates a channel buffering up to `capacity` chunks (at least one).
+///
+/// Once the buffer is full, [`ChunkSender::send`] waits for the receiver to
+/// take a chunk, so a model generating faster than the client reads is held
+/// back instead of queueing its whole response in memory.
+pub fn chunk_channel(capacity: usize) -> (ChunkSender, ChunkReceiver) {
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+    let sender = ChunkSender {
+        sender,
+        inspect: None,
+    };
+    (sender, ChunkReceiver { receiver })
+}
+
+/// Sending half of a [`chunk_channel`].
+#[derive(Clone)]
+pub struct ChunkSender {
+    sender: mpsc::Sender<StreamChunk>,
+    inspect: Option<Inspector>,
+}
+
+impl ChunkSender {
+    /// Calls `inspect` with every chunk sent through the returned sender,
+    /// before it is buffered.
+    pub fn inspect(mut self, inspect: impl Fn(&StreamChunk) + Send + Sync + 'static) -> Self {
+        self.inspect = Some(Arc::new(inspect));
+        self
+    }
+
+    /// Sends a chunk, waiting while the buffer is full. Fails once the
+    /// receiver is dropped.
+    pub async fn send(&self, chunk: StreamChunk) -> Result<(), SendError<StreamChunk>> {
+        if let Some(inspect) = &self.inspect {
+            inspect(&chunk);
+        }
+        self.sender.send(chunk).await
+    }
+
+    /// Runs `future`, sending the chunks its synchronous callbacks push onto
+    /// `queue`, such as those of a provider streaming a response.
+    ///
+    /// The future is only polled while `queue` is empty, so once the buffer is
+    /// full it waits for the client along with everything it would generate.
+    pub async fn drive<F: Future>(&self, queue: &ChunkQueue, future: F) -> F::Output {
+        tokio::pin!(future);
+        loop {
+            self.flush(queue).await;
+            let output = std::future::poll_fn(|cx| match future.as_mut().poll(cx) {
+                Poll::Ready(output) => Poll::Ready(Some(output)),
+                Poll::Pending if queue.is_empty() => Poll::Pending,
+                Poll::Pending => Poll::Ready(None),
+            })
+            .await;
+            if let Some(output) = output {
+                self.flush(queue).await;
+                return output;
+            }
+        }
+    }
+
+    /// Sends every chunk in `queue`, dropping them if the receiver is gone.
+    pub async fn flush(&self, queue: &ChunkQueue) {
+        while let Some(chunk) = queue.pop() {
+            let _ = self.send(chunk).await;
+        }
+    }
+}
+
+/// Chunks pushed by synchronous callbacks, waiting to be sent by
+/// [`ChunkSender::drive`] or [`ChunkSender::flush`].
+#[derive(Clone, Default)]
+pub struct ChunkQueue {
+    chunks: Arc<Mutex<VecDeque<StreamChunk>>>,
+}
+
+impl ChunkQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, chunk: StreamChunk) {
+        self.chunks.lock().unwrap().push_back(chunk);
+    }
+
+    fn pop(&self) -> Option<StreamChunk> {
+        self.chunks.lock().unwrap().pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chunks.lock().unwrap().is_empty()
+    }
+}
+
+/// Receiving half of a [`chunk_channel`]. Dropping it wakes senders waiting
+/// for room, whose sends then fail.
+pub struct ChunkReceiver {
+    receiver: mpsc::Receiver<StreamChunk>,
+}
+
+impl ChunkReceiver {
+    /// Receives the next chunk, making room for another. Returns `None` once
+    /// every sender is dropped and the buffer is empty.
+    pub async fn recv(&mut self) -> Option<StreamChunk> {
+        self.receiver.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Reads every chunk slowly, returning them and the most chunks that were
+    /// ever sent but not yet received.
+    async fn read_slowly(
+        mut receiver: ChunkReceiver,
+        sent: Arc<AtomicUsize>,
+    ) -> (Vec<String>, usize) {
+        let mut received = Vec::new();
+        let mut most_buffered = 0;
+        while let Some(chunk) = receiver.recv().await {
+            received.push(chunk.content);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            most_buffered = most_buffered.max(sent.load(Ordering::SeqCst) - received.len());
+        }
+        (received, most_buffered)
+    }
+
+    #[tokio::test]
+    async fn test_slow_reader_bounds_buffer_without_losing_chunks() {
+        let (sender, receiver) = chunk_channel(4);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sender = {
+            let sent = Arc::clone(&sent);
+            sender.inspect(move |_| {
+                sent.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+
+        let producer = tokio::spawn(async move {
+            for i in 0..50 {
+                sender
+                    .send(StreamChunk::chunk(i.to_string()))
+                    .await
+                    .unwrap();
+            }
+        });
+        let (received, most_buffered) = read_slowly(receiver, sent).await;
+        producer.await.unwrap();
+
+        let expected: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        assert_eq!(received, expected);
+        // The inspector counts the chunk a sender is waiting to buffer too
+        assert!(most_buffered <= 5, "buffered {} chunks", most_buffered);
+    }
+
+    #[tokio::test]
+    async fn test_drive_pauses_the_future_while_the_buffer_is_full() {
+        let (sender, receiver) = chunk_channel(4);
+        let queued = Arc::new(AtomicUsize::new(0));
+
+        let producer = {
+            let queued = Arc::clone(&queued);
+            tokio::spawn(async move {
+                let queue = ChunkQueue::new();
+                let generate = async {
+                    for i in 0..50 {
+                        queue.push(StreamChunk::chunk(i.to_string()));
+                        queued.fetch_add(1, Ordering::SeqCst);
+                        tokio::task::yield_now().await;
+                    }
+                };
+                sender.drive(&queue, generate).await;
+            })
+        };
+        let (received, most_buffered) = read_slowly(receiver, queued).await;
+        producer.await.unwrap();
+
+        let expected: Vec<String> = (0..50).map(|i| i.to_string()).collect();
+        assert_eq!(received, expected);
+        assert!(most_buffered <= 5, "buffered {} chunks", most_buffered);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_receiver_releases_waiting_sender() {
+        let (sender, receiver) = chunk_channel(1);
+        sender
+            .send(StreamChunk::chunk("fills the buffer"))
+            .await
+            .unwrap();
+
+        let waiting = tokio::spawn(async move { sender.send(StreamChunk::chunk("waits")).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(receiver);
+
+        assert!(waiting.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Client for talking to a running nucleus server over IPC.
+
+use super::transport::{Result, TransportError};
+use super::types::{
+    ChunkType, ErrorCode, Priority, Request, RequestType, SelfTestReport, StreamChunk,
+};
+use super::SOCKET_PATH;
+use crate::models::LocalModel;
+use crate::rag::IndexedSource;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// Client for the server's IPC socket.
+///
+/// Each request opens a new connection, matching the server's one request per
+/// connection protocol.
+pub struct AiClient {
+    socket_path: String,
+    auth_token: Option<String>,
+}
+
+impl Default for AiClient {
+    fn default() -> Self {
+        Self::new(SOCKET_PATH)
+    }
+}
+
+impl AiClient {
+    pub fn new(socket_path: impl Into<String>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+            auth_token: None,
+        }
+    }
+
+    /// Sends `token` with every request that doesn't carry its own, for servers
+    /// configured with `server.auth_token`.
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Sends a request and collects every chunk of the response.
+    pub async fn send(&self, request: &Request) -> Result<Vec<StreamChunk>> {
+        self.send_with(request, |_| {}).await
+    }
+
+    /// Sends a request, calling `on_chunk` for each chunk as it arrives, and
+    /// collects every chunk of the response.
+    pub async fn send_with<F>(&self, request: &Request, mut on_chunk: F) -> Result<Vec<StreamChunk>>
+    where
+        F: FnMut(&StreamChunk),
+    {
+        let mut stream = UnixStream::connect(&self.socket_path).await?;
+
+        let mut line = match (&request.auth_token, &self.auth_token) {
+            (None, Some(token)) => serde_json::to_string(&Request {
+                auth_token: Some(token.clone()),
+                ..request.clone()
+            })?,
+            _ => serde_json::to_string(request)?,
+        };
+        line.push('\n');
+        stream.write_all(line.as_bytes()).await?;
+        stream.flush().await?;
+
+        let mut chunks = Vec::new();
+        let mut lines = BufReader::new(stream).lines();
+        while let Some(line) = lines.next_line().await? {
+            let chunk = serde_json::from_str(&line)?;
+            on_chunk(&chunk);
+            chunks.push(chunk);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Indexes the single file at `path` into the server's knowledge base,
+    /// replacing the chunks stored for it before.
+    ///
+    /// Returns the server's summary of the indexed chunks.
+    pub async fn index_file(&self, path: &Path) -> Result<String> {
+        let path = std::path::absolute(path)?;
+        let request = Request {
+            request_type: RequestType::IndexFile,
+            content: path.to_string_lossy().to_string(),
+            priority: Priority::Low,
+            ..Default::default()
+        };
+
+        let last = self
+            .send(&request)
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(last.content),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Rebuilds the server's knowledge base from its indexed files with the
+    /// server's current settings, calling `on_progress` as each file is
+    /// processed.
+    ///
+    /// Returns the server's summary, which lists sources that no longer exist.
+    pub async fn reindex<F>(&self, mut on_progress: F) -> Result<String>
+    where
+        F: FnMut(&StreamChunk),
+    {
+        let request = Request {
+            request_type: RequestType::Reindex,
+            priority: Priority::Low,
+            ..Default::default()
+        };
+
+        let last = self
+            .send_with(&request, |chunk| {
+                if chunk.chunk_type == ChunkType::Progress {
+                    on_progress(chunk);
+                }
+            })
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(last.content),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Indexes `dir` into the server's knowledge base, calling `on_progress` as
+    /// each file is processed and each batch of chunks is embedded.
+    ///
+    /// Returns the server's summary of the indexed files.
+    pub async fn index_directory<F>(&self, dir: &Path, mut on_progress: F) -> Result<String>
+    where
+        F: FnMut(&StreamChunk),
+    {
+        let dir = dir.to_string_lossy().to_string();
+        let request = Request {
+            request_type: RequestType::Index,
+            content: dir.clone(),
+            pwd: Some(dir),
+            priority: Priority::Low,
+            ..Default::default()
+        };
+
+        let last = self
+            .send_with(&request, |chunk| {
+                if chunk.chunk_type == ChunkType::Progress {
+                    on_progress(chunk);
+                }
+            })
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(last.content),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Embeds `text` with the server's embedding model.
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let request = Request {
+            request_type: RequestType::Embed,
+            content: text.to_string(),
+            ..Default::default()
+        };
+
+        let last = self
+            .send(&request)
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(serde_json::from_str(&last.content)?),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Lists the sources in the server's knowledge base with their chunk counts.
+    pub async fn list_sources(&self) -> Result<Vec<IndexedSource>> {
+        let request = Request {
+            request_type: RequestType::Sources,
+            ..Default::default()
+        };
+
+        let last = self
+            .send(&request)
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(serde_json::from_str(&last.content)?),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Lists the models found in the server's `server.models_dir`.
+    pub async fn list_local_models(&self) -> Result<Vec<LocalModel>> {
+        let request = Request {
+            request_type: RequestType::ListLocalModels,
+            ..Default::default()
+        };
+
+        let last = self
+            .send(&request)
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(serde_json::from_str(&last.content)?),
+            _ => Err(server_error(last)),
+        }
+    }
+
+    /// Runs the server's end-to-end self-test.
+    pub async fn self_test(&self) -> Result<SelfTestReport> {
+        let request = Request {
+            request_type: RequestType::SelfTest,
+            ..Default::default()
+        };
+
+        let last = self
+            .send(&request)
+            .await?
+            .pop()
+            .ok_or_else(|| TransportError::Server("empty response".to_string()))?;
+
+        match last.chunk_type {
+            ChunkType::Done => Ok(serde_json::from_str(&last.content)?),
+            _ => Err(server_error(last)),
+        }
+    }
+}
+
+/// Turns an error chunk into an error, keeping its [`ErrorCode`] when there is
+/// a specific one so callers can decide how to present it.
+fn server_error(chunk: StreamChunk) -> TransportError {
+    let message = chunk
+        .error
+        .unwrap_or_else(|| "unexpected response".to_string());
+
+    match chunk.error_code {
+        Some(code) if code != ErrorCode::Internal => TransportError::Rejected { code, message },
+        _ => TransportError::Server(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{handle_connection, handler::RequestHandler};
+    use super::*;
+    use crate::testing::{test_config, MockProvider, TEST_EMBEDDING_DIM};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_embed_through_server() {
+        let temp = tempdir().unwrap();
+        let handler = Arc::new(
+            RequestHandler::new(test_config(temp.path()), Arc::new(MockProvider::new("")))
+                .await
+                .unwrap(),
+        );
+
+        let socket_path = temp.path().join("nucleus.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, handler, Duration::from_secs(30))
+                .await
+                .unwrap();
+        });
+
+        let client = AiClient::new(socket_path.to_string_lossy());
+        let embedding = client.embed("hello nucleus").await.unwrap();
+
+        assert_eq!(embedding.len(), TEST_EMBEDDING_DIM);
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_server_error_keeps_the_error_code() {
+        let chunk = StreamChunk::error("model crashed").with_error_code(ErrorCode::Timeout);
+        assert!(matches!(
+            server_error(chunk),
+            TransportError::Rejected {
+                code: ErrorCode::Timeout,
+                message,
+            } if message == "model crashed"
+        ));
+
+        let chunk = StreamChunk::error("oops").with_error_code(ErrorCode::Internal);
+        assert!(
+            matches!(server_error(chunk), TransportError::Server(message) if message == "oops")
+        );
+    }
+}
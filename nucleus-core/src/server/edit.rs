@@ -0,0 +1,136 @@
+//! Prompting for and reading the unified diffs of edit requests.
+
+use super::types::EditedFile;
+
+/// Appended to the system prompt of edit requests.
+pub(super) const EDIT_INSTRUCTIONS: &str = "\n\n\
+You are editing files. Briefly explain the change, then give it as a single \
+unified diff in a ```diff block, with `--- a/<path>` and `+++ b/<path>` \
+headers (paths relative to the working directory) and `@@` hunks with three \
+lines of context. Use `/dev/null` as the old path for new files.";
+
+/// Plugin that applies the diff of an edit request when `server.apply_edits` is set.
+pub(super) const APPLY_PLUGIN: &str = "apply_patch";
+
+/// The unified diff in a model response: the first ```diff or ```patch block,
+/// or else everything from the first `---` file header.
+pub(super) fn extract_diff(response: &str) -> Option<String> {
+    let mut lines = response.lines();
+    while let Some(line) = lines.next() {
+        let fence = line.trim();
+        if fence == "```diff" || fence == "```patch" {
+            let body: Vec<&str> = lines.take_while(|l| l.trim() != "```").collect();
+            return non_empty(body.join("\n"));
+        }
+    }
+
+    let lines: Vec<&str> = response.lines().collect();
+    let start = lines
+        .windows(2)
+        .position(|pair| pair[0].starts_with("--- ") && pair[1].starts_with("+++ "))?;
+    non_empty(lines[start..].join("\n"))
+}
+
+fn non_empty(diff: String) -> Option<String> {
+    if diff.trim().is_empty() {
+        None
+    } else {
+        Some(diff + "\n")
+    }
+}
+
+/// The files a unified diff changes, in diff order, with the number of lines
+/// it adds to and removes from each.
+pub(super) fn changed_files(diff: &str) -> Vec<EditedFile> {
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut files: Vec<EditedFile> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        let next = lines.get(i + 1).copied().unwrap_or_default();
+        if let (Some(old), Some(new)) = (line.strip_prefix("--- "), next.strip_prefix("+++ ")) {
+            let path = if header_path(new) == "/dev/null" {
+                header_path(old)
+            } else {
+                header_path(new)
+            };
+            let path = path
+                .strip_prefix("a/")
+                .or_else(|| path.strip_prefix("b/"))
+                .unwrap_or(path);
+            files.push(EditedFile {
+                path: path.to_string(),
+                added: 0,
+                removed: 0,
+            });
+            i += 2;
+            continue;
+        }
+
+        if let Some(file) = files.last_mut() {
+            if line.starts_with('+') {
+                file.added += 1;
+            } else if line.starts_with('-') {
+                file.removed += 1;
+            }
+        }
+        i += 1;
+    }
+
+    files
+}
+
+/// Path of a `---`/`+++` header, without the timestamp some tools append.
+fn header_path(header: &str) -> &str {
+    header.split('\t').next().unwrap_or(header).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_is_extracted_and_summarized_per_file() {
+        let response = "Renamed the greeting and added a file.\n\n\
+            ```diff\n\
+            --- a/src/main.rs\n\
+            +++ b/src/main.rs\n\
+            @@ -1,3 +1,3 @@\n \
+            fn main() {\n\
+            -    println!(\"hi\");\n\
+            +    println!(\"hello\");\n \
+            }\n\
+            --- /dev/null\n\
+            +++ b/NOTES.md\n\
+            @@ -0,0 +1,2 @@\n\
+            +# Notes\n\
+            +--- not a header\n\
+            ```\n\
+            Done.";
+
+        let diff = extract_diff(response).unwrap();
+        assert!(diff.starts_with("--- a/src/main.rs\n"));
+        assert!(!diff.contains("```"));
+        assert!(!diff.contains("Done."));
+
+        let files = changed_files(&diff);
+        assert_eq!(
+            files,
+            vec![
+                EditedFile {
+                    path: "src/main.rs".to_string(),
+                    added: 1,
+                    removed: 1,
+                },
+                EditedFile {
+                    path: "NOTES.md".to_string(),
+                    added: 2,
+                    removed: 0,
+                },
+            ]
+        );
+
+        assert_eq!(extract_diff("No changes are needed."), None);
+    }
+}
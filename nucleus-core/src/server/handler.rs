@@ -1,67 +1,430 @@
-use super::types::{Request, RequestType, StreamChunk};
-use crate::{config::Config, provider::Provider, rag};
-use std::{path::Path, sync::Arc};
-use tokio::sync::mpsc;
-
-pub type ChunkSender = mpsc::UnboundedSender<StreamChunk>;
+use super::channel::{chunk_channel, ChunkQueue, ChunkReceiver, ChunkSender};
+use super::edit::{changed_files, extract_diff, APPLY_PLUGIN, EDIT_INSTRUCTIONS};
+use super::metrics::Metrics;
+use super::scheduler::Scheduler;
+use super::thinking::ThinkingFilter;
+use super::types::{
+    ChunkType, EditReport, ErrorCode, OutputFormat, Request, RequestType, SearchMatch,
+    SelfTestReport, SelfTestStage, StreamChunk,
+};
+use crate::{
+    chat::{
+        estimate_tokens, inline_context, run_tool_loop, tool_definitions, ToolEvent, ToolLoopError,
+        TOOL_LIMIT_MARKER,
+    },
+    config::Config,
+    models::scan_models_dir,
+    prompt::{render_prompt, PromptVars},
+    provider::{Message, Provider, ProviderError},
+    rag,
+};
+use nucleus_plugin::PluginRegistry;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::warn;
 
 /// Handles different request types and sends responses via channel.
 pub struct RequestHandler {
     config: Config,
     provider: Arc<dyn Provider>,
     rag_manager: rag::RagEngine,
+    scheduler: Scheduler,
+    metrics: Arc<Metrics>,
+    registry: Option<Arc<PluginRegistry>>,
 }
 
 impl RequestHandler {
     pub async fn new(config: Config, provider: Arc<dyn Provider>) -> Result<Self, rag::RagError> {
         let rag_manager = rag::RagEngine::new(&config, provider.clone()).await?;
+        let scheduler = Scheduler::new(config.server.max_concurrent_requests);
 
         Ok(Self {
             config,
             provider,
             rag_manager,
+            scheduler,
+            metrics: Arc::new(Metrics::default()),
+            registry: None,
         })
     }
 
+    /// Runs the tools the model calls during chat requests with the plugins in
+    /// `registry`, streaming a "tool_call" chunk before each call and a
+    /// "tool_result" chunk after it, and applies edits with its `apply_patch`
+    /// plugin.
+    ///
+    /// Chat requests only get tools when `server.tools_enabled` is set, since
+    /// any client could otherwise use the registry's permissions. Without a
+    /// registry, tool calls are ignored.
+    pub fn with_registry(mut self, registry: Arc<PluginRegistry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Metrics updated by every request this handler serves.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
     /// Routes request to appropriate handler based on type.
+    ///
+    /// When `server.auth_token` is set, requests without the matching token are
+    /// rejected with a permission denied error before anything else happens.
+    ///
+    /// Apart from stats, requests wait for a slot from the scheduler first, so
+    /// at most `server.max_concurrent_requests` run at once.
+    ///
+    /// Every chunk passes through [`Metrics`] on its way to `sender`.
     pub async fn handle(&self, request: Request, sender: ChunkSender) {
+        self.metrics.record_request();
+        let is_chat = matches!(request.request_type, RequestType::Chat | RequestType::Edit);
+        let started = Instant::now();
+
+        let chunks = Arc::new(AtomicU64::new(0));
+        let sender = {
+            let metrics = Arc::clone(&self.metrics);
+            let chunks = Arc::clone(&chunks);
+            sender.inspect(move |chunk| match chunk.chunk_type {
+                ChunkType::Chunk => {
+                    chunks.fetch_add(1, Ordering::Relaxed);
+                }
+                ChunkType::Error => metrics.record_error(),
+                ChunkType::Done
+                | ChunkType::ToolCall
+                | ChunkType::ToolResult
+                | ChunkType::Progress => {}
+            })
+        };
+
+        self.dispatch(request, sender).await;
+        if is_chat {
+            let chunks = chunks.load(Ordering::Relaxed);
+            self.metrics.record_generation(chunks, started.elapsed());
+        }
+    }
+
+    /// A channel for the chunks of one response, buffering up to
+    /// `server.stream_buffer_size` chunks before generation waits for the client.
+    pub fn chunk_channel(&self) -> (ChunkSender, ChunkReceiver) {
+        chunk_channel(self.config.server.stream_buffer_size)
+    }
+
+    async fn dispatch(&self, request: Request, sender: ChunkSender) {
+        if let Some(expected) = &self.config.server.auth_token {
+            if !token_matches(expected, request.auth_token.as_deref()) {
+                let _ = sender
+                    .send(
+                        StreamChunk::error("Missing or invalid auth token")
+                            .with_error_code(ErrorCode::PermissionDenied),
+                    )
+                    .await;
+                return;
+            }
+        }
+
+        let _permit = match request.request_type {
+            RequestType::Stats | RequestType::Sources | RequestType::ListLocalModels => None,
+            _ => Some(self.scheduler.acquire(request.priority).await),
+        };
+
         match request.request_type {
             RequestType::Chat | RequestType::Edit => self.handle_chat(request, sender).await,
             RequestType::Add => self.handle_add(request, sender).await,
             RequestType::Index => self.handle_index(request, sender).await,
-            RequestType::Stats => self.handle_stats(sender).await,
+            RequestType::IndexFile => self.handle_index_file(request, sender).await,
+            RequestType::Reindex => self.handle_reindex(sender).await,
+            RequestType::Stats => self.handle_stats(request.format, sender).await,
+            RequestType::Embed => self.handle_embed(request, sender).await,
+            RequestType::Sources => self.handle_sources(request.format, sender).await,
+            RequestType::Search => self.handle_search(request, sender).await,
+            RequestType::SelfTest => self.handle_self_test(sender).await,
+            RequestType::ListLocalModels => {
+                self.handle_list_local_models(request.format, sender).await
+            }
         }
     }
 
-    async fn handle_chat(&self, request: Request, sender: ChunkSender) {
+    async fn handle_chat(&self, mut request: Request, sender: ChunkSender) {
         use crate::provider::ChatRequest;
 
-        let messages = self.build_messages(request);
+        if let Some(images) = request.images.take() {
+            match load_images(&images, request.pwd.as_deref()).await {
+                Ok(images) => request.images = Some(images),
+                Err(e) => {
+                    let _ = sender
+                        .send(StreamChunk::error(e).with_error_code(ErrorCode::InvalidRequest))
+                        .await;
+                    return;
+                }
+            }
+        }
 
-        let chat_request = ChatRequest::new(&self.config.llm.model, messages)
-            .with_temperature(self.config.llm.temperature);
+        let model = request
+            .model
+            .take()
+            .unwrap_or_else(|| self.config.llm.model.clone());
+        if model != self.config.llm.model && !self.provider.supports_model_switching() {
+            let _ = sender
+                .send(
+                    StreamChunk::error(format!(
+                        "The active provider can't switch models per request (loaded model: {})",
+                        self.config.llm.model
+                    ))
+                    .with_error_code(ErrorCode::Unsupported),
+                )
+                .await;
+            return;
+        }
+        let temperature = request.temperature.unwrap_or(self.config.llm.temperature);
 
+        let is_edit = request.request_type == RequestType::Edit;
+        let edit_dir = match (is_edit, request.pwd.clone()) {
+            (false, _) => None,
+            (true, Some(pwd)) => Some(pwd),
+            (true, None) => {
+                let _ = sender
+                    .send(
+                        StreamChunk::error("Edit requests require a working directory (pwd)")
+                            .with_error_code(ErrorCode::InvalidRequest),
+                    )
+                    .await;
+                return;
+            }
+        };
+        if let Some(pwd) = &edit_dir {
+            let files = request.files.take().unwrap_or_default();
+            match edit_file_contents(&files, pwd).await {
+                Ok(contents) => request.content = format!("{}{}", contents, request.content),
+                Err(e) => {
+                    let _ = sender
+                        .send(StreamChunk::error(e).with_error_code(ErrorCode::InvalidRequest))
+                        .await;
+                    return;
+                }
+            }
+        }
+        let n = request.n.unwrap_or(1);
+        let use_rag = request.use_rag.unwrap_or(false);
+        let mut messages = self.build_messages(request);
+        if is_edit {
+            messages[0].content.push_str(EDIT_INSTRUCTIONS);
+        }
+        if use_rag {
+            self.attach_context(&mut messages).await;
+        }
+
+        if let Err(e) = fit_to_context(&mut messages, self.config.llm.context_length) {
+            let _ = sender
+                .send(StreamChunk::error(e).with_error_code(ErrorCode::ContextOverflow))
+                .await;
+            return;
+        }
+        inline_context(&mut messages);
+
+        let mut chat_request = ChatRequest::new(model, messages).with_temperature(temperature);
+
+        if let Some(pwd) = edit_dir {
+            return self.handle_edit(chat_request, &pwd, sender).await;
+        }
+        if n > 1 {
+            return self.handle_chat_n(chat_request.with_n(n), n, sender).await;
+        }
+
+        let registry = self.tool_registry();
+        if let Some(registry) = registry {
+            let tools = tool_definitions(registry).await;
+            if !tools.is_empty() {
+                chat_request.tools = Some(tools);
+            }
+        }
+
+        let queue = ChunkQueue::new();
         let mut full_response = String::new();
+        let mut filter = ThinkingFilter::new(self.config.server.strip_thinking);
+        let mut send_text = |text: String| {
+            if !text.is_empty() {
+                full_response.push_str(&text);
+                queue.push(StreamChunk::chunk(&text));
+            }
+        };
 
-        let result = self
-            .provider
-            .chat(
-                chat_request,
-                Box::new(|response| {
-                    if !response.message.content.is_empty() {
-                        full_response.push_str(&response.message.content);
-                        let _ = sender.send(StreamChunk::chunk(&response.message.content));
+        let conversation = run_tool_loop(
+            self.provider.as_ref(),
+            registry,
+            &self.config.llm,
+            chat_request,
+            None,
+            &mut |text: &str| send_text(filter.push(text)),
+            &mut |event: ToolEvent| {
+                let chunk = match event {
+                    ToolEvent::Call { name, arguments } => {
+                        StreamChunk::tool_call(name, arguments.clone())
                     }
-                }),
-            )
-            .await;
+                    ToolEvent::Result { name, result, .. } => {
+                        StreamChunk::tool_result(name, result.to_string())
+                    }
+                };
+                queue.push(chunk);
+            },
+        );
+        let result = sender.drive(&queue, conversation).await;
 
         match result {
+            Ok(conversation) if conversation.tool_limit_reached => {
+                let separator = if conversation.response == TOOL_LIMIT_MARKER {
+                    ""
+                } else {
+                    "\n\n"
+                };
+                send_text(format!("{}{}", separator, TOOL_LIMIT_MARKER));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let chunk = match &e {
+                    ToolLoopError::Provider(error) => {
+                        StreamChunk::error(error.to_string()).with_error_code(error.into())
+                    }
+                    ToolLoopError::Tool { source, .. } => {
+                        StreamChunk::error(e.to_string()).with_error_code(source.into())
+                    }
+                    ToolLoopError::NoResponse => StreamChunk::error(e.to_string()),
+                };
+                let _ = sender.send(chunk).await;
+                return;
+            }
+        }
+
+        send_text(filter.finish());
+        sender.flush(&queue).await;
+        let _ = sender.send(StreamChunk::done(&full_response)).await;
+    }
+
+    /// The registry whose tools chat requests may call: none unless
+    /// `server.tools_enabled` is set.
+    fn tool_registry(&self) -> Option<&PluginRegistry> {
+        if self.config.server.tools_enabled {
+            self.registry.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Streams the response to an edit request like a chat without tools, then
+    /// sends an [`EditReport`] of the diff it proposes, applied under `pwd`
+    /// when `server.apply_edits` is set.
+    async fn handle_edit(
+        &self,
+        chat_request: crate::provider::ChatRequest,
+        pwd: &str,
+        sender: ChunkSender,
+    ) {
+        let queue = ChunkQueue::new();
+        let mut response = String::new();
+        let mut filter = ThinkingFilter::new(self.config.server.strip_thinking);
+        let mut send_text = |text: String| {
+            if !text.is_empty() {
+                response.push_str(&text);
+                queue.push(StreamChunk::chunk(&text));
+            }
+        };
+
+        let chat = self.provider.chat(
+            chat_request,
+            Box::new(|r| send_text(filter.push(&r.message.content))),
+        );
+        if let Err(e) = sender.drive(&queue, chat).await {
+            let _ = sender
+                .send(StreamChunk::error(e.to_string()).with_error_code((&e).into()))
+                .await;
+            return;
+        }
+        send_text(filter.finish());
+        sender.flush(&queue).await;
+
+        let report = self.apply_edit(response, pwd).await;
+        let chunk = match serde_json::to_string(&report) {
+            Ok(content) => StreamChunk::done(content),
+            Err(e) => StreamChunk::error(e.to_string()),
+        };
+        let _ = sender.send(chunk).await;
+    }
+
+    /// Reports the diff in `response`, applying it to the files under `pwd` if
+    /// edits are enabled.
+    ///
+    /// Whether the diff may be written is up to the plugin registry, so a
+    /// registry without write permission reports the denial as the error.
+    async fn apply_edit(&self, response: String, pwd: &str) -> EditReport {
+        let diff = extract_diff(&response);
+        let mut report = EditReport {
+            files: diff.as_deref().map(changed_files).unwrap_or_default(),
+            diff,
+            response,
+            applied: false,
+            error: None,
+        };
+
+        if !self.config.server.apply_edits {
+            return report;
+        }
+        let (Some(diff), Some(registry)) = (&report.diff, &self.registry) else {
+            return report;
+        };
+        let input = serde_json::json!({ "patch": diff, "pwd": pwd });
+        match registry.execute(APPLY_PLUGIN, input).await {
+            Ok(_) => report.applied = true,
+            Err(e) => {
+                warn!("Failed to apply edit: {}", e);
+                report.error = Some(e.to_string());
+            }
+        }
+        report
+    }
+
+    /// Streams several completions, labeling each chunk with its completion index.
+    async fn handle_chat_n(
+        &self,
+        chat_request: crate::provider::ChatRequest,
+        n: usize,
+        sender: ChunkSender,
+    ) {
+        let mut completions = vec![String::new(); n];
+        let mut filters: Vec<ThinkingFilter> = (0..n)
+            .map(|_| ThinkingFilter::new(self.config.server.strip_thinking))
+            .collect();
+        let queue = ChunkQueue::new();
+        let mut send_text = |index: usize, text: String| {
+            if !text.is_empty() {
+                completions[index].push_str(&text);
+                queue.push(StreamChunk::chunk(&text).with_index(index));
+            }
+        };
+
+        let chat = self.provider.chat_n(
+            chat_request,
+            Box::new(|index, response| {
+                send_text(index, filters[index].push(&response.message.content))
+            }),
+        );
+
+        match sender.drive(&queue, chat).await {
             Ok(_) => {
-                let _ = sender.send(StreamChunk::done(&full_response));
+                for (index, filter) in filters.iter_mut().enumerate() {
+                    send_text(index, filter.finish());
+                }
+                sender.flush(&queue).await;
+                let _ = sender.send(StreamChunk::done_n(completions)).await;
             }
             Err(e) => {
-                let _ = sender.send(StreamChunk::error(e.to_string()));
+                let _ = sender
+                    .send(StreamChunk::error(e.to_string()).with_error_code((&e).into()))
+                    .await;
             }
         }
     }
@@ -73,56 +436,1601 @@ impl RequestHandler {
             .await
         {
             Ok(_) => {
-                let _ = sender.send(StreamChunk::done("Added to knowledge base"));
+                let _ = sender
+                    .send(StreamChunk::done("Added to knowledge base"))
+                    .await;
             }
             Err(e) => {
-                let _ = sender.send(StreamChunk::error(format!("Failed to add: {}", e)));
+                let _ = sender
+                    .send(StreamChunk::error(format!("Failed to add: {}", e)))
+                    .await;
             }
         }
     }
 
     async fn handle_index(&self, request: Request, sender: ChunkSender) {
-        let dir = request.pwd.clone().expect("Invalid directory");
+        let Some(dir) = request.pwd.clone() else {
+            let _ = sender
+                .send(
+                    StreamChunk::error("Index requests require a directory (pwd)")
+                        .with_error_code(ErrorCode::InvalidRequest),
+                )
+                .await;
+            return;
+        };
         let path_dir = Path::new(&dir);
-        match self.rag_manager.index_directory(&path_dir).await {
-            Ok(count) => {
-                let _ = sender.send(StreamChunk::done(format!(
+        let queue = ChunkQueue::new();
+        let indexing = self.rag_manager.index_directory_with_embed_progress(
+            path_dir,
+            |progress| queue.push(StreamChunk::progress(progress.to_string())),
+            |progress| queue.push(StreamChunk::embed_progress(progress)),
+        );
+        match sender.drive(&queue, indexing).await {
+            Ok(report) => {
+                let mut message = format!(
                     "Indexed {} files from: {}",
-                    count, request.content
-                )));
+                    report.indexed_count(),
+                    request.content
+                );
+                if !report.skipped.is_empty() {
+                    message.push_str(&format!("\nSkipped {} files", report.skipped.len()));
+                }
+                for (path, error) in &report.errors {
+                    message.push_str(&format!("\nFailed: {}: {}", path.display(), error));
+                }
+                let _ = sender.send(StreamChunk::done(message)).await;
+            }
+            Err(e) => {
+                let _ = sender
+                    .send(StreamChunk::error(format!("Failed to index: {}", e)))
+                    .await;
+            }
+        }
+    }
+
+    /// Indexes the single file named by `content`, resolved against `pwd`,
+    /// replacing the chunks stored for it before.
+    ///
+    /// The file must be under `pwd` and not match the indexer's exclude
+    /// patterns (checked below `pwd`), like the files of an index request.
+    async fn handle_index_file(&self, request: Request, sender: ChunkSender) {
+        let invalid = |message: String| {
+            sender.send(StreamChunk::error(message).with_error_code(ErrorCode::InvalidRequest))
+        };
+        if request.content.trim().is_empty() {
+            let _ = invalid("Index file requests require a file path".to_string()).await;
+            return;
+        }
+        let Some(pwd) = request.pwd.as_deref() else {
+            let _ = invalid("Index file requests require a directory (pwd)".to_string()).await;
+            return;
+        };
+        let path = match resolve_within(Path::new(pwd), request.content.trim()).await {
+            Ok(path) => path,
+            Err(e) => {
+                let _ = invalid(e).await;
+                return;
+            }
+        };
+        let relative = match tokio::fs::canonicalize(pwd).await {
+            Ok(dir) => path.strip_prefix(&dir).unwrap_or(&path).to_path_buf(),
+            Err(_) => path.clone(),
+        };
+        if self.rag_manager.is_excluded(&relative) {
+            let _ = invalid(format!(
+                "{} matches rag.indexer.exclude_patterns",
+                relative.display()
+            ))
+            .await;
+            return;
+        }
+
+        // Stored under the path an index request of `pwd` would give it
+        let source = Path::new(pwd).join(&relative);
+        let chunk = match self.rag_manager.index_file(&source.to_string_lossy()).await {
+            Ok(chunks) => StreamChunk::done(format!(
+                "Indexed {} chunks from: {}",
+                chunks,
+                source.display()
+            )),
+            Err(e) => StreamChunk::error(format!("Failed to index {}: {}", source.display(), e)),
+        };
+        let _ = sender.send(chunk).await;
+    }
+
+    /// Rebuilds the knowledge base from its sources, streaming progress after
+    /// each file, then a summary listing the files that no longer exist and
+    /// the sources that aren't files, which are embedded again from their
+    /// stored chunks.
+    async fn handle_reindex(&self, sender: ChunkSender) {
+        let queue = ChunkQueue::new();
+        let reindexing = self.rag_manager.reindex_all_with_progress(|progress| {
+            queue.push(StreamChunk::progress(progress.to_string()))
+        });
+        match sender.drive(&queue, reindexing).await {
+            Ok(report) => {
+                let mut message = format!("Reindexed {} files", report.indexed_count());
+                if !report.skipped.is_empty() {
+                    message.push_str(&format!("\nSkipped {} files", report.skipped.len()));
+                }
+                for path in &report.missing {
+                    message.push_str(&format!("\nMissing: {}", path.display()));
+                }
+                for source in &report.kept {
+                    message.push_str(&format!("\nKept: {}", source));
+                }
+                for (path, error) in &report.errors {
+                    message.push_str(&format!("\nFailed: {}: {}", path.display(), error));
+                }
+                let _ = sender.send(StreamChunk::done(message)).await;
             }
             Err(e) => {
-                let _ = sender.send(StreamChunk::error(format!("Failed to index: {}", e)));
+                let _ = sender
+                    .send(StreamChunk::error(format!("Failed to reindex: {}", e)))
+                    .await;
             }
         }
     }
 
-    async fn handle_stats(&self, sender: ChunkSender) {
+    async fn handle_stats(&self, format: OutputFormat, sender: ChunkSender) {
         let count = self.rag_manager.count().await;
-        let _ = sender.send(StreamChunk::done(format!(
-            "Knowledge base contains {} documents",
-            count
-        )));
+        let content = match format {
+            OutputFormat::Text => format!("Knowledge base contains {} documents", count),
+            OutputFormat::Jsonl => serde_json::json!({
+                "documents": count,
+                "accelerator": self.provider.accelerator(),
+            })
+            .to_string(),
+        };
+        let _ = sender.send(StreamChunk::done(content)).await;
     }
 
-    fn build_messages(&self, request: Request) -> Vec<crate::provider::Message> {
-        use crate::provider::Message;
+    /// Sends the indexed sources and their chunk counts in a done chunk, as a
+    /// JSON array or one JSON object per source for [`OutputFormat::Jsonl`].
+    async fn handle_sources(&self, format: OutputFormat, sender: ChunkSender) {
+        let chunk = match self.rag_manager.indexed_sources().await {
+            Ok(sources) => match to_output(&sources, format) {
+                Ok(content) => StreamChunk::done(content),
+                Err(e) => StreamChunk::error(e.to_string()),
+            },
+            Err(e) => StreamChunk::error(format!("Failed to list sources: {}", e)),
+        };
+        let _ = sender.send(chunk).await;
+    }
 
-        let mut messages = vec![Message::system(None, &self.config.system_prompt)];
+    /// Sends the knowledge base matches for `content` in a done chunk, as a
+    /// JSON array of [`SearchMatch`] or one JSON object per match for
+    /// [`OutputFormat::Jsonl`].
+    async fn handle_search(&self, request: Request, sender: ChunkSender) {
+        if request.content.trim().is_empty() {
+            let _ = sender
+                .send(
+                    StreamChunk::error("Search requests require a query")
+                        .with_error_code(ErrorCode::InvalidRequest),
+                )
+                .await;
+            return;
+        }
+        let chunk = match self.rag_manager.retrieve(&request.content).await {
+            Ok(results) => {
+                let matches: Vec<SearchMatch> = results.iter().map(SearchMatch::from).collect();
+                match to_output(&matches, request.format) {
+                    Ok(content) => StreamChunk::done(content),
+                    Err(e) => StreamChunk::error(e.to_string()),
+                }
+            }
+            Err(e) => StreamChunk::error(format!("Failed to search: {}", e)),
+        };
+        let _ = sender.send(chunk).await;
+    }
+
+    /// Sends the models found in `server.models_dir` in a done chunk, as a JSON
+    /// array or one JSON object per model for [`OutputFormat::Jsonl`].
+    async fn handle_list_local_models(&self, format: OutputFormat, sender: ChunkSender) {
+        let models_dir = self.config.server.models_dir.clone();
+        let chunk = match tokio::task::spawn_blocking(move || scan_models_dir(models_dir)).await {
+            Ok(models) => match to_output(&models, format) {
+                Ok(content) => StreamChunk::done(content),
+                Err(e) => StreamChunk::error(e.to_string()),
+            },
+            Err(e) => StreamChunk::error(format!("Failed to scan for models: {}", e)),
+        };
+        let _ = sender.send(chunk).await;
+    }
+
+    /// Runs a quick end-to-end check and sends a [`SelfTestReport`] as JSON in
+    /// a done chunk, with the outcome and timing of each stage: embedding a
+    /// probe string, storing and finding it in the vector store, and
+    /// generating a first token.
+    async fn handle_self_test(&self, sender: ChunkSender) {
+        const PROBE: &str = "nucleus self-test probe";
+        let mut stages = Vec::new();
+
+        let started = Instant::now();
+        let embedding = match &self.config.rag {
+            Some(rag) => self
+                .provider
+                .embed(PROBE, &rag.embedding_model)
+                .await
+                .map_err(|e| e.to_string()),
+            None => Err("no embedding model configured (rag.embedding_model)".to_string()),
+        };
+        stages.push(stage("embed", started.elapsed(), embedding.as_ref().err()));
+
+        let started = Instant::now();
+        let stored = match embedding {
+            Ok(embedding) => self
+                .rag_manager
+                .probe_store(embedding)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(_) => Err("skipped, as embedding failed".to_string()),
+        };
+        stages.push(stage("store", started.elapsed(), stored.as_ref().err()));
+
+        let started = Instant::now();
+        let generated = self.generate_first_token().await;
+        stages.push(stage(
+            "generate",
+            started.elapsed(),
+            generated.as_ref().err(),
+        ));
+
+        let report = SelfTestReport::new(stages);
+        let chunk = match serde_json::to_string(&report) {
+            Ok(content) => StreamChunk::done(content),
+            Err(e) => StreamChunk::error(e.to_string()),
+        };
+        let _ = sender.send(chunk).await;
+    }
+
+    /// Starts a reply to a trivial prompt and stops once the first token arrives.
+    async fn generate_first_token(&self) -> Result<(), String> {
+        use crate::provider::ChatRequest;
+        use std::sync::atomic::AtomicBool;
+        use tokio::sync::Notify;
+
+        let request = ChatRequest::new(
+            self.config.llm.model.clone(),
+            vec![Message::user(None, "Reply with OK.")],
+        )
+        .with_temperature(0.0);
+
+        let generated = AtomicBool::new(false);
+        let first_token = Notify::new();
+        let generation = self.provider.chat(
+            request,
+            Box::new(|response| {
+                if !response.content.is_empty() && !generated.swap(true, Ordering::SeqCst) {
+                    first_token.notify_one();
+                }
+            }),
+        );
+
+        // Dropping the generation once a token arrives cancels the rest
+        tokio::select! {
+            result = generation => result.map_err(|e| e.to_string())?,
+            () = first_token.notified() => {}
+        }
+
+        if generated.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err("the model generated no tokens".to_string())
+        }
+    }
+
+    /// Embeds `texts` (or `content`) and sends the vectors as JSON in a done chunk.
+    async fn handle_embed(&self, request: Request, sender: ChunkSender) {
+        let Some(rag) = self.config.rag.as_ref() else {
+            let _ = sender
+                .send(
+                    StreamChunk::error(
+                        "Embedding requires an embedding model to be configured (rag.embedding_model)",
+                    )
+                    .with_error_code(ErrorCode::Unsupported),
+                )
+                .await;
+            return;
+        };
+        let model = &rag.embedding_model;
+
+        let result = match &request.texts {
+            Some(texts) => {
+                let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+                self.provider
+                    .embed_batch(&texts, model)
+                    .await
+                    .map(|embeddings| serde_json::json!(embeddings))
+            }
+            None => self
+                .provider
+                .embed(&request.content, model)
+                .await
+                .map(|embedding| serde_json::json!(embedding)),
+        };
+
+        match result {
+            Ok(vectors) => {
+                let _ = sender.send(StreamChunk::done(vectors.to_string())).await;
+            }
+            Err(ProviderError::Unsupported(reason)) => {
+                let _ = sender
+                    .send(
+                        StreamChunk::error(format!(
+                            "The active provider does not support embeddings: {}",
+                            reason
+                        ))
+                        .with_error_code(ErrorCode::Unsupported),
+                    )
+                    .await;
+            }
+            Err(e) => {
+                let _ = sender
+                    .send(
+                        StreamChunk::error(format!("Failed to embed: {}", e))
+                            .with_error_code((&e).into()),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Attaches context retrieved from the knowledge base to the user message,
+    /// the last of `messages`.
+    ///
+    /// Retrieval failures are logged and the message is sent without context,
+    /// so a broken knowledge base never blocks a conversation.
+    async fn attach_context(&self, messages: &mut [Message]) {
+        let Some(message) = messages.last_mut() else {
+            return;
+        };
+        match self.rag_manager.retrieve_context(&message.content).await {
+            Ok(context) if !context.is_empty() => message.context = Some(context),
+            Ok(_) => {}
+            Err(e) => warn!("Could not retrieve RAG context: {}", e),
+        }
+    }
+
+    fn build_messages(&self, request: Request) -> Vec<Message> {
+        let vars = PromptVars::new(request.pwd.as_deref());
+        let system_prompt = render_prompt(&self.config.system_prompt, &vars);
+        let mut messages = vec![Message::system(None, system_prompt)];
 
         if let Some(history) = request.history {
             for msg in history {
-                messages.push(Message {
-                    role: "user".to_string(),
-                    context: None,
-                    content: msg.content.clone(),
-                    images: None,
-                    tool_calls: None,
-                });
+                messages.push(Message::user(None, msg.content.clone()));
             }
         }
 
-        messages.push(Message::user(None, &request.content));
+        let mut user_message = Message::user(None, &request.content);
+        user_message.images = request.images;
+        messages.push(user_message);
         messages
     }
 }
+
+/// Trims `messages` until they fit in `context_length` tokens, so oversized
+/// prompts fail here with a clear error rather than deep in the provider.
+///
+/// RAG context is dropped first, then history from the oldest message on. The
+/// system prompt and the final user message are always kept; if they alone are
+/// too large, an error is returned. A `context_length` of 0 disables the check.
+fn fit_to_context(messages: &mut Vec<Message>, context_length: usize) -> Result<(), String> {
+    if context_length == 0 {
+        return Ok(());
+    }
+
+    for i in 0..messages.len() {
+        if estimate_tokens(messages) <= context_length {
+            return Ok(());
+        }
+        messages[i].context = None;
+    }
+
+    // Everything between the system prompt and the final user message is history
+    while estimate_tokens(messages) > context_length && messages.len() > 2 {
+        messages.remove(1);
+    }
+
+    let estimated = estimate_tokens(messages);
+    if estimated > context_length {
+        return Err(format!(
+            "Prompt is about {} tokens, which exceeds the context length of {}",
+            estimated, context_length
+        ));
+    }
+    Ok(())
+}
+
+/// The current contents of the files an edit request names, as a block to put
+/// before the user's message.
+async fn edit_file_contents(files: &[String], pwd: &str) -> Result<String, String> {
+    let mut contents = String::new();
+    for file in files {
+        let path = resolve_within(Path::new(pwd), file).await?;
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+        contents.push_str(&format!(
+            "Current contents of {}:\n```\n{}\n```\n\n",
+            file,
+            content.trim_end_matches('\n')
+        ));
+    }
+    Ok(contents)
+}
+
+/// Resolves `path` against `dir`, rejecting paths that lead outside `dir`
+/// once `..` and symlinks are followed. The path must exist.
+async fn resolve_within(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let dir = tokio::fs::canonicalize(dir)
+        .await
+        .map_err(|e| format!("Invalid directory {}: {}", dir.display(), e))?;
+    let resolved = tokio::fs::canonicalize(dir.join(path))
+        .await
+        .map_err(|e| format!("Failed to resolve {}: {}", path, e))?;
+    if !resolved.starts_with(&dir) {
+        return Err(format!("{} is outside {}", path, dir.display()));
+    }
+    Ok(resolved)
+}
+
+/// Resolves request images to the base64 data providers expect.
+///
+/// Entries naming an existing file under `pwd` are read and encoded, provided
+/// they hold a PNG, JPEG, GIF, WebP or BMP image; paths leading outside `pwd`
+/// are rejected, and without a `pwd` no files are read. Anything else is taken
+/// to be base64 data already, with any `data:...;base64,` prefix removed.
+async fn load_images(images: &[String], pwd: Option<&str>) -> Result<Vec<String>, String> {
+    use base64::Engine;
+
+    let mut loaded = Vec::with_capacity(images.len());
+    for image in images {
+        if let Some((_, data)) = image
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+        {
+            loaded.push(data.to_string());
+            continue;
+        }
+
+        let Some(pwd) = pwd.filter(|pwd| Path::new(pwd).join(image).is_file()) else {
+            loaded.push(image.clone());
+            continue;
+        };
+        let path = resolve_within(Path::new(pwd), image).await?;
+        let bytes = tokio::fs::read(&path)
+            .await
+            .map_err(|e| format!("Failed to read image {}: {}", image, e))?;
+        if !is_image(&bytes) {
+            return Err(format!(
+                "{} is not a PNG, JPEG, GIF, WebP or BMP image",
+                image
+            ));
+        }
+        loaded.push(base64::engine::general_purpose::STANDARD.encode(bytes));
+    }
+
+    Ok(loaded)
+}
+
+/// Whether `bytes` start with the signature of an image format vision models read.
+fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\x89PNG\r\n\x1a\n")
+        || bytes.starts_with(b"\xff\xd8\xff")
+        || bytes.starts_with(b"GIF87a")
+        || bytes.starts_with(b"GIF89a")
+        || (bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP"))
+        || bytes.starts_with(b"BM")
+}
+
+/// Outcome of a self-test stage that took `elapsed` and failed with `error`, if any.
+fn stage(name: &str, elapsed: Duration, error: Option<&String>) -> SelfTestStage {
+    SelfTestStage {
+        name: name.to_string(),
+        passed: error.is_none(),
+        millis: elapsed.as_millis() as u64,
+        error: error.cloned(),
+    }
+}
+
+/// Compares the request's token against the configured one in constant time,
+/// so response timing doesn't reveal how much of a guess was right.
+fn token_matches(expected: &str, given: Option<&str>) -> bool {
+    let Some(given) = given else {
+        return false;
+    };
+    if expected.len() != given.len() {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(given.bytes())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// Serializes `items` as a JSON array, or as one JSON object per line.
+fn to_output<T: serde::Serialize>(items: &[T], format: OutputFormat) -> serde_json::Result<String> {
+    match format {
+        OutputFormat::Text => serde_json::to_string(items),
+        OutputFormat::Jsonl => {
+            let lines = items
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<serde_json::Result<Vec<_>>>()?;
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::types::{ChunkType, Priority};
+    use super::*;
+    use crate::rag::IndexedSource;
+    use crate::testing::{test_config, MockProvider, RecordingPatchPlugin};
+    use tempfile::tempdir;
+
+    fn chat_request(temperature: Option<f64>, model: Option<&str>) -> Request {
+        Request {
+            request_type: RequestType::Chat,
+            content: "Hello".to_string(),
+            temperature,
+            model: model.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    async fn last_chunk(handler: &RequestHandler, request: Request) -> StreamChunk {
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut last = None;
+        while let Some(chunk) = receiver.recv().await {
+            last = Some(chunk);
+        }
+        last.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chat_request_overrides_temperature_and_model() {
+        let temp = tempdir().unwrap();
+        let config = test_config(temp.path());
+        let provider = Arc::new(MockProvider::new("Hi"));
+        let handler = RequestHandler::new(config.clone(), provider.clone())
+            .await
+            .unwrap();
+
+        let overridden = chat_request(Some(0.1), Some("other-model"));
+        assert_eq!(
+            last_chunk(&handler, overridden).await.chunk_type,
+            ChunkType::Done
+        );
+        let defaults = chat_request(None, None);
+        assert_eq!(
+            last_chunk(&handler, defaults).await.chunk_type,
+            ChunkType::Done
+        );
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests[0].temperature, 0.1);
+        assert_eq!(requests[0].model, "other-model");
+        assert_eq!(requests[1].temperature, config.llm.temperature);
+        assert_eq!(requests[1].model, config.llm.model);
+    }
+
+    #[tokio::test]
+    async fn test_use_rag_toggles_retrieval_per_request() {
+        use std::sync::atomic::Ordering;
+
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("Hi"));
+        let handler = RequestHandler::new(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap();
+        let fact = "The server listens on port 7878";
+        handler
+            .rag_manager
+            .add_knowledge(fact, "notes.md")
+            .await
+            .unwrap();
+
+        let embed_calls = provider.embed_calls.load(Ordering::SeqCst);
+        let without = Request {
+            use_rag: Some(false),
+            ..chat_request(None, None)
+        };
+        assert_eq!(
+            last_chunk(&handler, without).await.chunk_type,
+            ChunkType::Done
+        );
+        assert_eq!(
+            provider.embed_calls.load(Ordering::SeqCst),
+            embed_calls,
+            "no query embedding without RAG"
+        );
+
+        let with = Request {
+            use_rag: Some(true),
+            ..chat_request(None, None)
+        };
+        assert_eq!(last_chunk(&handler, with).await.chunk_type, ChunkType::Done);
+        assert!(provider.embed_calls.load(Ordering::SeqCst) > embed_calls);
+
+        let requests = provider.requests.lock().unwrap();
+        let user_message = |i: usize| requests[i].messages.last().unwrap().content.clone();
+        assert_eq!(user_message(0), "Hello");
+        assert!(user_message(1).contains(fact));
+        assert!(user_message(1).ends_with("Hello"));
+    }
+
+    struct SearchPlugin;
+
+    #[async_trait::async_trait]
+    impl nucleus_plugin::Plugin for SearchPlugin {
+        fn name(&self) -> &str {
+            "search"
+        }
+
+        fn description(&self) -> &str {
+            "Searches for a query"
+        }
+
+        fn parameter_schema(&self) -> serde_json::Value {
+            serde_json::json!({ "type": "object", "properties": { "query": { "type": "string" } } })
+        }
+
+        fn required_permission(&self) -> nucleus_plugin::Permission {
+            nucleus_plugin::Permission::NONE
+        }
+
+        async fn execute(
+            &self,
+            input: serde_json::Value,
+        ) -> nucleus_plugin::Result<nucleus_plugin::PluginOutput> {
+            let query = input["query"].as_str().unwrap_or_default();
+            Ok(nucleus_plugin::PluginOutput::new(format!(
+                "3 results for {}",
+                query
+            )))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_stream_around_execution() {
+        let temp = tempdir().unwrap();
+        let arguments = serde_json::json!({ "query": "tokio" });
+        let provider = Arc::new(
+            MockProvider::streaming(vec!["Found ".to_string(), "them".to_string()], None)
+                .with_tool_call("search", arguments.clone()),
+        );
+        let mut registry = PluginRegistry::new(nucleus_plugin::Permission::NONE);
+        assert!(registry.register(SearchPlugin).await);
+        let mut config = test_config(temp.path());
+        config.server.tools_enabled = true;
+        let handler = RequestHandler::new(config, provider.clone())
+            .await
+            .unwrap()
+            .with_registry(Arc::new(registry));
+
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(chat_request(None, None), sender).await;
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk);
+        }
+
+        let types: Vec<_> = chunks.iter().map(|c| c.chunk_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                ChunkType::ToolCall,
+                ChunkType::ToolResult,
+                ChunkType::Chunk,
+                ChunkType::Chunk,
+                ChunkType::Done
+            ]
+        );
+        assert_eq!(chunks[0].tool.as_deref(), Some("search"));
+        assert_eq!(chunks[0].arguments, Some(arguments));
+        assert_eq!(chunks[1].tool.as_deref(), Some("search"));
+        assert_eq!(chunks[1].content, "3 results for tokio");
+        assert_eq!(chunks[4].content, "Found them");
+
+        let json = serde_json::to_value(&chunks[0]).unwrap();
+        assert_eq!(json["type"], "tool_call");
+
+        // The tool result is given back to the model for the final answer
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let tool_message = requests[1].messages.last().unwrap();
+        assert_eq!(tool_message.role, "tool");
+        assert_eq!(tool_message.content, "3 results for tokio");
+    }
+
+    #[tokio::test]
+    async fn test_tools_are_off_by_default() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(
+            MockProvider::new("Hi").with_tool_call("search", serde_json::json!({ "query": "x" })),
+        );
+        let mut registry = PluginRegistry::new(nucleus_plugin::Permission::NONE);
+        assert!(registry.register(SearchPlugin).await);
+        let handler = RequestHandler::new(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap()
+            .with_registry(Arc::new(registry));
+
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(chat_request(None, None), sender).await;
+        while let Some(chunk) = receiver.recv().await {
+            assert_ne!(chunk.chunk_type, ChunkType::ToolCall);
+        }
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].tools.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_edit_applies_proposed_diff_with_write_permission() {
+        let temp = tempdir().unwrap();
+        let target = temp.path().join("greeting.txt");
+        std::fs::write(&target, "hi\n").unwrap();
+        let reply = "Made it friendlier.\n\n```diff\n--- a/greeting.txt\n+++ b/greeting.txt\n\
+            @@ -1 +1 @@\n-hi\n+hello\n```\n";
+
+        let mut config = test_config(temp.path());
+        config.server.apply_edits = true;
+        let edit = |registry_permission| {
+            let config = config.clone();
+            let root = temp.path().to_path_buf();
+            async move {
+                let provider = Arc::new(MockProvider::new(reply));
+                let mut registry = PluginRegistry::new(registry_permission);
+                let plugin = RecordingPatchPlugin::default();
+                let calls = Arc::clone(&plugin.calls);
+                assert!(registry.register(plugin).await);
+                let handler = RequestHandler::new(config, provider.clone())
+                    .await
+                    .unwrap()
+                    .with_registry(Arc::new(registry));
+
+                let mut request = chat_request(None, None);
+                request.request_type = RequestType::Edit;
+                request.pwd = Some(root.to_string_lossy().to_string());
+                request.files = Some(vec!["greeting.txt".to_string()]);
+                let chunk = last_chunk(&handler, request).await;
+                assert_eq!(chunk.chunk_type, ChunkType::Done);
+                let messages = provider.requests.lock().unwrap()[0].messages.clone();
+                assert!(messages[0].content.contains("unified diff"));
+                let user = &messages.last().unwrap().content;
+                assert!(user.starts_with("Current contents of greeting.txt:\n```\nhi\n```"));
+                let report = serde_json::from_str::<EditReport>(&chunk.content).unwrap();
+                let calls = calls.lock().unwrap().clone();
+                (report, calls)
+            }
+        };
+
+        let (report, calls) = edit(nucleus_plugin::Permission::READ_ONLY).await;
+        assert!(!report.applied);
+        assert!(report.error.unwrap().contains("not granted"));
+        assert!(calls.is_empty());
+
+        let (report, calls) = edit(nucleus_plugin::Permission::READ_WRITE).await;
+        assert!(report.applied, "{:?}", report.error);
+        assert_eq!(report.response, reply);
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].path, "greeting.txt");
+        assert_eq!((report.files[0].added, report.files[0].removed), (1, 1));
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0]["patch"].as_str(), report.diff.as_deref());
+        assert_eq!(calls[0]["pwd"], &*temp.path().to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn test_edit_is_confined_to_pwd() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(temp.path().join("secret.txt"), "token\n").unwrap();
+        let reply = "```diff\n--- a/../secret.txt\n+++ b/../secret.txt\n\
+            @@ -1 +1 @@\n-token\n+leaked\n```\n";
+
+        let mut config = test_config(temp.path());
+        config.server.apply_edits = true;
+        let mut registry = PluginRegistry::new(nucleus_plugin::Permission::READ_WRITE);
+        let plugin = RecordingPatchPlugin::default();
+        let calls = Arc::clone(&plugin.calls);
+        assert!(registry.register(plugin).await);
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new(reply)))
+            .await
+            .unwrap()
+            .with_registry(Arc::new(registry));
+
+        let mut request = chat_request(None, None);
+        request.request_type = RequestType::Edit;
+        let chunk = last_chunk(&handler, request.clone()).await;
+        assert_eq!(chunk.error_code, Some(ErrorCode::InvalidRequest));
+
+        request.pwd = Some(project.to_string_lossy().to_string());
+        request.files = Some(vec!["../secret.txt".to_string()]);
+        let chunk = last_chunk(&handler, request.clone()).await;
+        assert_eq!(chunk.error_code, Some(ErrorCode::InvalidRequest));
+        assert!(chunk.error.unwrap().contains("outside"));
+
+        // The plugin confines the diff to the pwd it is given
+        request.files = None;
+        let chunk = last_chunk(&handler, request).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done);
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0]["pwd"], &*project.to_string_lossy());
+    }
+
+    #[tokio::test]
+    async fn test_model_override_needs_model_switching() {
+        let temp = tempdir().unwrap();
+        let config = test_config(temp.path());
+        let provider = Arc::new(MockProvider::new("Hi").with_fixed_model());
+        let handler = RequestHandler::new(config.clone(), provider.clone())
+            .await
+            .unwrap();
+
+        let chunk = last_chunk(&handler, chat_request(None, Some("other-model"))).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Error);
+        assert_eq!(chunk.error_code, Some(ErrorCode::Unsupported));
+        assert!(provider.requests.lock().unwrap().is_empty());
+
+        // Naming the loaded model is not a switch
+        let chunk = last_chunk(&handler, chat_request(None, Some(&config.llm.model))).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done);
+    }
+
+    #[tokio::test]
+    async fn test_chat_images_reach_provider() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("A red pixel"));
+        let handler = RequestHandler::new(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap();
+
+        std::fs::write(temp.path().join("pixel.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "What is in these images?".to_string(),
+            pwd: Some(temp.path().to_string_lossy().to_string()),
+            images: Some(vec![
+                "pixel.png".to_string(),
+                "data:image/png;base64,aGVsbG8=".to_string(),
+                "d29ybGQ=".to_string(),
+            ]),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut last = None;
+        while let Some(chunk) = receiver.recv().await {
+            last = Some(chunk);
+        }
+        assert_eq!(last.unwrap().chunk_type, ChunkType::Done);
+
+        let requests = provider.requests.lock().unwrap();
+        let user_message = requests[0].messages.last().unwrap();
+        assert_eq!(user_message.role, "user");
+        assert_eq!(
+            user_message.images.as_deref(),
+            Some(
+                &[
+                    "iVBORw0KGgo=".to_string(),
+                    "aGVsbG8=".to_string(),
+                    "d29ybGQ=".to_string()
+                ][..]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_images_must_be_images_under_pwd() {
+        let temp = tempdir().unwrap();
+        let project = temp.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(temp.path().join("id_rsa"), "-----BEGIN KEY-----").unwrap();
+        std::fs::write(project.join("notes.txt"), "not an image").unwrap();
+        let provider = Arc::new(MockProvider::new("Hi"));
+        let handler = RequestHandler::new(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap();
+
+        for image in ["../id_rsa", "notes.txt"] {
+            let request = Request {
+                pwd: Some(project.to_string_lossy().to_string()),
+                images: Some(vec![image.to_string()]),
+                ..chat_request(None, None)
+            };
+            let chunk = last_chunk(&handler, request).await;
+            assert_eq!(
+                chunk.error_code,
+                Some(ErrorCode::InvalidRequest),
+                "{}",
+                image
+            );
+        }
+        assert!(provider.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_n_labels_each_completion() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("candidate"));
+        let handler = RequestHandler::new(test_config(temp.path()), provider)
+            .await
+            .unwrap();
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "Suggest a name".to_string(),
+            n: Some(3),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk);
+        }
+
+        let done = chunks.pop().unwrap();
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        let completions = done.completions.unwrap();
+        assert_eq!(completions.len(), 3);
+        assert!(completions.iter().all(|c| c.starts_with("candidate #")));
+
+        let mut distinct = completions.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3, "completions should differ by seed");
+        assert_eq!(done.content, completions[0]);
+
+        for (index, completion) in completions.iter().enumerate() {
+            let streamed: String = chunks
+                .iter()
+                .filter(|chunk| chunk.index == Some(index))
+                .map(|chunk| chunk.content.as_str())
+                .collect();
+            assert_eq!(&streamed, completion);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_chat_is_served_before_queued_index() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.server.max_concurrent_requests = 1;
+
+        let provider = Arc::new(MockProvider::streaming(
+            vec!["busy".to_string()],
+            Some(std::time::Duration::from_millis(100)),
+        ));
+        let handler = Arc::new(RequestHandler::new(config, provider).await.unwrap());
+
+        let request = |request_type, priority| Request {
+            request_type,
+            content: "work".to_string(),
+            pwd: Some(temp.path().to_string_lossy().to_string()),
+            priority,
+            ..Default::default()
+        };
+        let finished = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let submit = |name: &'static str, request: Request| {
+            let handler = Arc::clone(&handler);
+            let finished = Arc::clone(&finished);
+            tokio::spawn(async move {
+                let (sender, mut receiver) = handler.chunk_channel();
+                handler.handle(request, sender).await;
+                while receiver.recv().await.is_some() {}
+                finished.lock().unwrap().push(name);
+            })
+        };
+
+        // Occupy the only slot, then queue a low-priority index before a high-priority chat
+        let pause = || tokio::time::sleep(std::time::Duration::from_millis(20));
+        let running = submit("running", request(RequestType::Chat, Priority::Normal));
+        pause().await;
+        let index = submit("index", request(RequestType::Index, Priority::Low));
+        pause().await;
+        let chat = submit("chat", request(RequestType::Chat, Priority::High));
+
+        for task in [running, index, chat] {
+            task.await.unwrap();
+        }
+        assert_eq!(*finished.lock().unwrap(), vec!["running", "chat", "index"]);
+    }
+
+    #[tokio::test]
+    async fn test_strip_thinking_hides_reasoning_split_across_chunks() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.server.strip_thinking = true;
+        let chunks = ["<thi", "nk>secret", " plan</th", "ink>\n\nThe ", "answer"];
+        let provider = Arc::new(MockProvider::streaming(
+            chunks.iter().map(|chunk| chunk.to_string()).collect(),
+            None,
+        ));
+        let handler = RequestHandler::new(config, provider).await.unwrap();
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "Hello?".to_string(),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut streamed = String::new();
+        while let Ok(chunk) = receiver.try_recv() {
+            assert!(!chunk.content.contains("secret"), "{:?}", chunk);
+            assert!(!chunk.content.contains("plan"), "{:?}", chunk);
+            assert!(!chunk.content.contains('<'), "{:?}", chunk);
+            match chunk.chunk_type {
+                ChunkType::Chunk => streamed.push_str(&chunk.content),
+                ChunkType::Done => assert_eq!(chunk.content, "The answer"),
+                ChunkType::Error => panic!("unexpected error: {:?}", chunk.error),
+            }
+        }
+        assert_eq!(streamed, "The answer");
+    }
+
+    #[tokio::test]
+    async fn test_provider_timeout_sets_error_code() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(
+            MockProvider::new("").with_chat_error(ProviderError::Timeout(
+                "No response chunk received after 30 seconds".to_string(),
+            )),
+        );
+        let handler = RequestHandler::new(test_config(temp.path()), provider)
+            .await
+            .unwrap();
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "Hello?".to_string(),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let chunk = receiver.recv().await.unwrap();
+        assert_eq!(chunk.chunk_type, ChunkType::Error);
+        assert_eq!(chunk.error_code, Some(ErrorCode::Timeout));
+        assert!(chunk.error.unwrap().contains("30 seconds"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_while_model_downloads_get_loading_status() {
+        use crate::provider::BackgroundProvider;
+
+        let temp = tempdir().unwrap();
+        let (finish_download, download_finished) = tokio::sync::oneshot::channel::<()>();
+        let provider = Arc::new(BackgroundProvider::spawn(|progress| async move {
+            progress.set(0.35);
+            let _ = download_finished.await;
+            Ok(Arc::new(MockProvider::new("Loaded!")) as Arc<dyn Provider>)
+        }));
+        while provider.progress().is_none() {
+            tokio::task::yield_now().await;
+        }
+        let handler = RequestHandler::new(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap();
+
+        let request = |request_type| Request {
+            request_type,
+            content: "Hello?".to_string(),
+            ..Default::default()
+        };
+        let last_chunk = |request| {
+            let handler = &handler;
+            async move {
+                let (sender, mut receiver) = handler.chunk_channel();
+                tokio::time::timeout(
+                    std::time::Duration::from_secs(5),
+                    handler.handle(request, sender),
+                )
+                .await
+                .expect("request hung while the model was downloading");
+                let mut last = None;
+                while let Ok(chunk) = receiver.try_recv() {
+                    last = Some(chunk);
+                }
+                last.unwrap()
+            }
+        };
+
+        for request_type in [RequestType::Chat, RequestType::Embed] {
+            let chunk = last_chunk(request(request_type)).await;
+            assert_eq!(chunk.chunk_type, ChunkType::Error);
+            assert_eq!(chunk.error_code, Some(ErrorCode::ModelLoading));
+            assert!(chunk.error.unwrap().contains("35% complete"));
+        }
+
+        finish_download.send(()).unwrap();
+        provider.wait_ready().await.unwrap();
+
+        let chunk = last_chunk(request(RequestType::Chat)).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done);
+        assert_eq!(chunk.content, "Loaded!");
+    }
+
+    #[tokio::test]
+    async fn test_search_returns_matches_with_snippets() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.rag.as_mut().unwrap().snippets.enabled = true;
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        handler
+            .rag_manager
+            .add_knowledge("The scheduler runs queued requests by priority.", "notes")
+            .await
+            .unwrap();
+
+        let request = Request {
+            request_type: RequestType::Search,
+            content: "How does the scheduler work?".to_string(),
+            ..Default::default()
+        };
+        let chunk = last_chunk(&handler, request).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done, "{:?}", chunk.error);
+
+        let matches: Vec<SearchMatch> = serde_json::from_str(&chunk.content).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].location.as_deref(), Some("notes"));
+        assert_eq!(
+            matches[0].snippet.as_deref(),
+            Some("The **scheduler** runs queued requests by priority.")
+        );
+        assert_eq!(matches[0].matched_terms, vec!["scheduler"]);
+    }
+
+    #[tokio::test]
+    async fn test_index_file_stays_under_pwd_and_honours_excludes() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = vec!["secrets".to_string()];
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(project.join("secrets")).unwrap();
+        std::fs::write(project.join("notes.md"), "Deploys run on Fridays\n").unwrap();
+        std::fs::write(project.join("secrets/keys.md"), "hunter2\n").unwrap();
+        std::fs::write(dir.path().join("outside.md"), "Not in the project\n").unwrap();
+
+        let index_file = |content: &str, pwd: Option<&Path>| Request {
+            request_type: RequestType::IndexFile,
+            content: content.to_string(),
+            pwd: pwd.map(|pwd| pwd.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let chunk = last_chunk(&handler, index_file("notes.md", Some(&project))).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done, "{:?}", chunk.error);
+
+        let absolute = project.join("notes.md").to_string_lossy().to_string();
+        for (content, pwd) in [
+            ("../outside.md", Some(project.as_path())),
+            ("secrets/keys.md", Some(project.as_path())),
+            (absolute.as_str(), None),
+        ] {
+            let chunk = last_chunk(&handler, index_file(content, pwd)).await;
+            assert_eq!(chunk.chunk_type, ChunkType::Error, "{}", content);
+            assert_eq!(chunk.error_code, Some(ErrorCode::InvalidRequest));
+        }
+        assert_eq!(handler.rag_manager.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_index_file_uses_the_source_of_an_index_request() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.md"), "Deploys run on Fridays\n").unwrap();
+        // Not canonical, as a client may send it
+        let pwd = dir.path().join(".").to_string_lossy().to_string();
+
+        for (request_type, content) in [
+            (RequestType::Index, "project"),
+            (RequestType::IndexFile, "notes.md"),
+        ] {
+            let request = Request {
+                request_type,
+                content: content.to_string(),
+                pwd: Some(pwd.clone()),
+                ..Default::default()
+            };
+            let chunk = last_chunk(&handler, request).await;
+            assert_eq!(chunk.chunk_type, ChunkType::Done, "{:?}", chunk.error);
+        }
+
+        // The file's chunks were replaced rather than stored a second time
+        assert_eq!(handler.rag_manager.count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_index_streams_progress_before_done() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        // Default patterns exclude paths containing "tmp", which tempdirs do
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        for name in ["a.rs", "b.rs", "c.rs"] {
+            std::fs::write(dir.path().join(name), format!("// {}\n", name)).unwrap();
+        }
+
+        let request = Request {
+            request_type: RequestType::Index,
+            content: "project".to_string(),
+            pwd: Some(dir.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk);
+        }
+
+        let (done, progress) = chunks.split_last().unwrap();
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        assert!(done.content.starts_with("Indexed 3 files"));
+
+        // One line per file, then one for the single batch of chunks embedded
+        let (embedded, files) = progress.split_last().unwrap();
+        assert_eq!(embedded.chunk_type, ChunkType::Progress);
+        assert_eq!(embedded.content, "embedded 3/3 chunks");
+        assert_eq!(embedded.embedded_chunks, Some(3));
+        assert_eq!(embedded.total_chunks, Some(3));
+        assert_eq!(files.len(), 3);
+        for (i, chunk) in files.iter().enumerate() {
+            assert_eq!(chunk.chunk_type, ChunkType::Progress);
+            assert!(chunk
+                .content
+                .starts_with(&format!("indexed {}/3 files, current: ", i + 1)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reindex_rebuilds_with_current_chunk_settings() {
+        let temp = tempdir().unwrap();
+        let config_with_chunk_size = |chunk_size| {
+            let mut config = test_config(temp.path());
+            let indexer = &mut config.rag.as_mut().unwrap().indexer;
+            indexer.exclude_patterns = Vec::new();
+            indexer.chunk_size = chunk_size;
+            indexer.chunk_overlap = 0;
+            config
+        };
+
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.rs");
+        let second = dir.path().join("second.rs");
+        let removed = dir.path().join("removed.rs");
+        for path in [&first, &second, &removed] {
+            std::fs::write(path, "// a line of about thirty bytes\n".repeat(3)).unwrap();
+        }
+
+        let handler =
+            RequestHandler::new(config_with_chunk_size(32), Arc::new(MockProvider::new("")))
+                .await
+                .unwrap();
+        handler
+            .rag_manager
+            .index_directory(dir.path())
+            .await
+            .unwrap();
+        handler
+            .rag_manager
+            .add_knowledge("The build runs on Fridays", "user_input")
+            .await
+            .unwrap();
+        assert_eq!(handler.rag_manager.count().await, 10);
+        drop(handler);
+        std::fs::remove_file(&removed).unwrap();
+
+        // Chunks now fit a whole file
+        let handler =
+            RequestHandler::new(config_with_chunk_size(512), Arc::new(MockProvider::new("")))
+                .await
+                .unwrap();
+        let request = Request {
+            request_type: RequestType::Reindex,
+            priority: Priority::Low,
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk);
+        }
+        let (done, progress) = chunks.split_last().unwrap();
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        assert_eq!(
+            done.content,
+            format!(
+                "Reindexed 2 files\nMissing: {}\nKept: user_input",
+                removed.display()
+            )
+        );
+        assert_eq!(progress.len(), 2);
+        assert!(progress[1]
+            .content
+            .starts_with("indexed 2/2 files, current: "));
+
+        let mut sources = handler.rag_manager.indexed_sources().await.unwrap();
+        sources.sort_by(|a, b| a.source.cmp(&b.source));
+        let sources: Vec<(String, usize)> =
+            sources.into_iter().map(|s| (s.source, s.chunks)).collect();
+        assert_eq!(
+            sources,
+            vec![
+                (first.to_string_lossy().to_string(), 1),
+                (second.to_string_lossy().to_string(), 1),
+                ("user_input".to_string(), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sources_lists_chunk_counts_per_source() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        let indexer = &mut config.rag.as_mut().unwrap().indexer;
+        // Default patterns exclude paths containing "tmp", which tempdirs do
+        indexer.exclude_patterns = Vec::new();
+        indexer.chunk_size = 32;
+        indexer.chunk_overlap = 0;
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let short_dir = tempdir().unwrap();
+        let short = short_dir.path().join("short.rs");
+        std::fs::write(&short, "fn short() {}\n").unwrap();
+
+        let long_dir = tempdir().unwrap();
+        let long = long_dir.path().join("long.rs");
+        std::fs::write(&long, "// a line of about thirty bytes\n".repeat(3)).unwrap();
+
+        for dir in [&short_dir, &long_dir] {
+            let path = dir.path().to_string_lossy().to_string();
+            handler
+                .rag_manager
+                .index_directory(Path::new(&path))
+                .await
+                .unwrap();
+        }
+
+        let request = Request {
+            request_type: RequestType::Sources,
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let done = receiver.recv().await.unwrap();
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        let sources: Vec<IndexedSource> = serde_json::from_str(&done.content).unwrap();
+
+        let count = |path: &Path| {
+            sources
+                .iter()
+                .find(|s| s.source == path.to_string_lossy())
+                .map(|s| s.chunks)
+        };
+        assert_eq!(sources.len(), 2);
+        assert_eq!(count(&short), Some(1));
+        assert_eq!(count(&long), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_output_has_one_object_per_line() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.rag.as_mut().unwrap().indexer.exclude_patterns = Vec::new();
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(dir.path().join("b.rs"), "fn b() {}\n").unwrap();
+        handler
+            .rag_manager
+            .index_directory(dir.path())
+            .await
+            .unwrap();
+
+        let respond = |request_type| {
+            let request = Request {
+                request_type,
+                format: OutputFormat::Jsonl,
+                ..Default::default()
+            };
+            let (sender, mut receiver) = handler.chunk_channel();
+            let handler = &handler;
+            async move {
+                handler.handle(request, sender).await;
+                receiver.recv().await.unwrap()
+            }
+        };
+
+        let done = respond(RequestType::Sources).await;
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        let sources: Vec<IndexedSource> = done
+            .content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(sources.len(), 2);
+        assert!(sources.iter().all(|s| s.chunks == 1));
+
+        let done = respond(RequestType::Stats).await;
+        assert_eq!(done.content.lines().count(), 1);
+        let stats: serde_json::Value = serde_json::from_str(&done.content).unwrap();
+        assert_eq!(stats["documents"], 2);
+        assert_eq!(stats["accelerator"], "none");
+    }
+
+    #[tokio::test]
+    async fn test_self_test_reports_every_stage_passing() {
+        let temp = tempdir().unwrap();
+        let handler =
+            RequestHandler::new(test_config(temp.path()), Arc::new(MockProvider::new("OK")))
+                .await
+                .unwrap();
+        let request = Request {
+            request_type: RequestType::SelfTest,
+            ..chat_request(None, None)
+        };
+
+        let done = last_chunk(&handler, request).await;
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        let report: SelfTestReport = serde_json::from_str(&done.content).unwrap();
+        let names: Vec<&str> = report.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["embed", "store", "generate"]);
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(handler.rag_manager.count().await, 0, "the probe is removed");
+
+        let handler =
+            RequestHandler::new(test_config(temp.path()), Arc::new(MockProvider::new("")))
+                .await
+                .unwrap();
+        let request = Request {
+            request_type: RequestType::SelfTest,
+            ..chat_request(None, None)
+        };
+        let report: SelfTestReport =
+            serde_json::from_str(&last_chunk(&handler, request).await.content).unwrap();
+        assert!(!report.passed);
+        assert!(report.stages[2]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("no tokens"));
+    }
+
+    async fn stats_with_token(config: Config, auth_token: Option<&str>) -> StreamChunk {
+        let handler = RequestHandler::new(config, Arc::new(MockProvider::new("")))
+            .await
+            .unwrap();
+        let request = Request {
+            request_type: RequestType::Stats,
+            auth_token: auth_token.map(str::to_string),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+        receiver.recv().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_accepted() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.server.auth_token = Some("s3cret".to_string());
+
+        let chunk = stats_with_token(config, Some("s3cret")).await;
+        assert_eq!(chunk.chunk_type, ChunkType::Done);
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_rejected() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.server.auth_token = Some("s3cret".to_string());
+
+        for token in [None, Some("wrong"), Some("s3cre")] {
+            let chunk = stats_with_token(config.clone(), token).await;
+            assert_eq!(chunk.chunk_type, ChunkType::Error);
+            assert_eq!(chunk.error_code, Some(ErrorCode::PermissionDenied));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_auth_token_configured_accepts_any_request() {
+        let temp = tempdir().unwrap();
+        let config = test_config(temp.path());
+
+        for token in [None, Some("anything")] {
+            let chunk = stats_with_token(config.clone(), token).await;
+            assert_eq!(chunk.chunk_type, ChunkType::Done);
+        }
+    }
+
+    #[test]
+    fn test_fit_to_context_drops_rag_context_before_history() {
+        let messages = vec![
+            Message::system(None, "sys"),
+            Message::user(None, "h".repeat(40)),
+            Message::user(Some("c".repeat(400)), "q".repeat(40)),
+        ];
+
+        // Dropping the RAG context is enough, so history stays
+        let mut trimmed = messages.clone();
+        fit_to_context(&mut trimmed, 40).unwrap();
+        assert_eq!(trimmed.len(), 3);
+        assert!(trimmed[2].context.is_none());
+
+        // Then history goes, but never the system prompt or the question
+        let mut trimmed = messages.clone();
+        fit_to_context(&mut trimmed, 15).unwrap();
+        let contents: Vec<_> = trimmed.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["sys".to_string(), "q".repeat(40)]);
+
+        let mut trimmed = messages;
+        assert!(fit_to_context(&mut trimmed, 5).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_prompt_is_rejected_before_provider() {
+        let temp = tempdir().unwrap();
+        let mut config = test_config(temp.path());
+        config.llm.context_length = 64;
+        let provider = Arc::new(MockProvider::new("unreachable"));
+        let handler = RequestHandler::new(config, provider.clone()).await.unwrap();
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "word ".repeat(1000),
+            ..Default::default()
+        };
+        let (sender, mut receiver) = handler.chunk_channel();
+        handler.handle(request, sender).await;
+
+        let chunk = receiver.recv().await.unwrap();
+        assert_eq!(chunk.chunk_type, ChunkType::Error);
+        assert_eq!(chunk.error_code, Some(ErrorCode::ContextOverflow));
+        assert!(provider.requests.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+//! HTTP transport serving the chat protocol as Server-Sent Events.
+//!
+//! Clients `POST` a JSON [`Request`](super::Request) to `/chat` and receive the
+//! response as an SSE stream. Each [`StreamChunk`] is sent as a `data:` frame
+//! containing its JSON encoding; the final chunk is sent as a `done` (or
+//! `error`) event, after which the connection is closed. Tool calls made while
+//! generating are sent as `tool_call` and `tool_result` events.
+//!
+//! ```text
+//! data: {"type":"chunk","content":"Hel"}
+//!
+//! data: {"type":"chunk","content":"lo"}
+//!
+//! event: done
+//! data: {"type":"done","content":"Hello"}
+//! ```
+
+use super::channel::ChunkReceiver;
+use super::transport::{Result, TransportError};
+use super::types::{ChunkType, StreamChunk};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Path of the chat endpoint.
+pub const CHAT_PATH: &str = "/chat";
+
+/// Largest request body accepted, to avoid unbounded allocations.
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// HTTP transport listening on a TCP address (e.g. `127.0.0.1:8080`).
+pub struct HttpTransport {
+    addr: String,
+}
+
+impl HttpTransport {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into() }
+    }
+
+    /// The address this transport binds to.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Binds to the TCP address and returns a listener.
+    pub async fn bind(&self) -> Result<TcpListener> {
+        Ok(TcpListener::bind(&self.addr).await?)
+    }
+}
+
+/// A parsed HTTP request line and body.
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// Reads an HTTP/1.1 request from the stream.
+///
+/// Only `Content-Length` bodies are supported; chunked request bodies are rejected.
+pub async fn read_request(stream: &mut TcpStream) -> Result<HttpRequest> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| TransportError::Http("missing request line".to_string()))?
+        .to_string();
+    let path = parts
+        .next()
+        .ok_or_else(|| TransportError::Http("missing request path".to_string()))?
+        .to_string();
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(TransportError::Http(
+                "unexpected end of headers".to_string(),
+            ));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().map_err(|_| {
+                    TransportError::Http(format!("invalid Content-Length: {}", value))
+                })?;
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(TransportError::Http(format!(
+                    "unsupported Transfer-Encoding: {}",
+                    value
+                )));
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(TransportError::Http(format!(
+            "request body too large: {} bytes",
+            content_length
+        )));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(HttpRequest { method, path, body })
+}
+
+/// Encodes a chunk as an SSE frame.
+pub fn sse_frame(chunk: &StreamChunk) -> Result<String> {
+    let data = serde_json::to_string(chunk)?;
+
+    Ok(match chunk.chunk_type {
+        ChunkType::Chunk => format!("data: {}\n\n", data),
+        ChunkType::Done => format!("event: done\ndata: {}\n\n", data),
+        ChunkType::Error => format!("event: error\ndata: {}\n\n", data),
+        ChunkType::ToolCall => format!("event: tool_call\ndata: {}\n\n", data),
+        ChunkType::ToolResult => format!("event: tool_result\ndata: {}\n\n", data),
+        ChunkType::Progress => format!("event: progress\ndata: {}\n\n", data),
+    })
+}
+
+/// A Server-Sent Event: its `event` name, if given, and its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Splits an SSE body into events.
+///
+/// A blank line ends an event. An event's `data:` lines are joined with
+/// newlines, comment lines (starting with `:`) and unknown fields are ignored,
+/// and events without any data are dropped.
+pub fn parse_sse(body: &str) -> Vec<SseEvent> {
+    let mut events = Vec::new();
+    let mut event = None;
+    let mut data: Option<String> = None;
+
+    // A trailing event without a final blank line is still dispatched
+    for line in body.lines().chain(std::iter::once("")) {
+        if line.is_empty() {
+            if let Some(data) = data.take() {
+                events.push(SseEvent {
+                    event: event.take(),
+                    data,
+                });
+            }
+            event = None;
+            continue;
+        }
+        if line.starts_with(':') {
+            continue;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = Some(value.to_string()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Decodes an SSE body produced by [`write_sse`] back into its chunks, along
+/// with each event's name.
+///
+/// Events whose data is `[DONE]` are skipped.
+pub fn decode_sse(body: &str) -> Result<Vec<(Option<String>, StreamChunk)>> {
+    parse_sse(body)
+        .into_iter()
+        .filter(|event| event.data != "[DONE]")
+        .map(|event| Ok((event.event, serde_json::from_str(&event.data)?)))
+        .collect()
+}
+
+/// Writes the SSE response headers followed by a frame per stream chunk.
+pub async fn write_sse(stream: &mut TcpStream, mut receiver: ChunkReceiver) -> Result<()> {
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\n\
+              Connection: close\r\n\r\n",
+        )
+        .await?;
+    stream.flush().await?;
+
+    while let Some(chunk) = receiver.recv().await {
+        stream.write_all(sse_frame(&chunk)?.as_bytes()).await?;
+        stream.flush().await?;
+    }
+
+    Ok(())
+}
+
+/// Writes a plain-text response with the given status, e.g. `"404 Not Found"`.
+pub async fn write_status(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        message.len(),
+        message
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{handle_http_connection, handler::RequestHandler, Request, RequestType};
+    use super::*;
+    use crate::testing::{test_config, MockProvider};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_chat_over_sse() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("Hello from nucleus"));
+        let handler = Arc::new(
+            RequestHandler::new(test_config(temp.path()), provider)
+                .await
+                .unwrap(),
+        );
+
+        let listener = HttpTransport::new("127.0.0.1:0").bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_http_connection(stream, handler).await.unwrap();
+        });
+
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "Hi".to_string(),
+            ..Default::default()
+        };
+        let response = reqwest::Client::new()
+            .post(format!("http://{}{}", addr, CHAT_PATH))
+            .json(&request)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.headers()["content-type"].to_str().unwrap(),
+            "text/event-stream"
+        );
+
+        let frames = decode_sse(&response.text().await.unwrap()).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        let (event, chunk) = &frames[0];
+        assert_eq!(*event, None);
+        assert_eq!(chunk.chunk_type, ChunkType::Chunk);
+        assert_eq!(chunk.content, "Hello from nucleus");
+
+        let (event, chunk) = &frames[1];
+        assert_eq!(event.as_deref(), Some("done"));
+        assert_eq!(chunk.chunk_type, ChunkType::Done);
+        assert_eq!(chunk.content, "Hello from nucleus");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path_returns_not_found() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new("unused"));
+        let handler = Arc::new(
+            RequestHandler::new(test_config(temp.path()), provider)
+                .await
+                .unwrap(),
+        );
+
+        let listener = HttpTransport::new("127.0.0.1:0").bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_http_connection(stream, handler).await.unwrap();
+        });
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}/missing", addr))
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_parse_sse_joins_multi_line_data() {
+        let body = "event: done\ndata: {\ndata:   \"type\": \"done\",\ndata:   \"content\": \"Hi\"\ndata: }\n\n";
+
+        let events = parse_sse(body);
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: Some("done".to_string()),
+                data: "{\n  \"type\": \"done\",\n  \"content\": \"Hi\"\n}".to_string(),
+            }]
+        );
+
+        let chunks = decode_sse(body).unwrap();
+        assert_eq!(chunks[0].1.chunk_type, ChunkType::Done);
+        assert_eq!(chunks[0].1.content, "Hi");
+    }
+
+    #[test]
+    fn test_decode_sse_skips_comments_and_done_marker() {
+        let body = concat!(
+            ": keepalive\n\n",
+            "data: {\"type\":\"chunk\",\n",
+            ": comment inside an event\n",
+            "data: \"content\":\"Hel\"}\n\n",
+            ":\n",
+            "data:{\"type\":\"chunk\",\"content\":\"lo\"}\r\n\r\n",
+            "data: [DONE]\n\n",
+        );
+
+        let chunks = decode_sse(body).unwrap();
+        let contents: Vec<&str> = chunks.iter().map(|(_, c)| c.content.as_str()).collect();
+        assert_eq!(contents, ["Hel", "lo"]);
+        assert!(chunks.iter().all(|(event, _)| event.is_none()));
+    }
+}
@@ -0,0 +1,334 @@
+//! MCP (Model Context Protocol) server exposing nucleus plugins to other clients.
+//!
+//! Editors and assistants that speak MCP (e.g. Claude Desktop or Zed) can launch
+//! nucleus as a subprocess and use its plugins as tools. Messages are JSON-RPC
+//! 2.0, one per line, over stdin/stdout. The supported methods are:
+//!
+//! - `initialize`: returns the server info and its capabilities
+//! - `tools/list`: lists every plugin in the [`PluginRegistry`]
+//! - `tools/call`: executes a plugin with the given arguments
+//!
+//! Only plugins the registry's permissions allowed to register are exposed, so
+//! a registry created with [`Permission::READ_ONLY`](nucleus_plugin::Permission)
+//! never offers `exec` or `write_file`.
+
+use nucleus_plugin::PluginRegistry;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// MCP protocol revision implemented by the server.
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves the plugins of a [`PluginRegistry`] over MCP.
+///
+/// # Examples
+///
+/// ```no_run
+/// use nucleus_core::server::McpServer;
+/// use nucleus_plugin::{Permission, PluginRegistry};
+///
+/// # async fn example() -> std::io::Result<()> {
+/// let registry = PluginRegistry::new(Permission::READ_ONLY);
+/// McpServer::new(registry).serve_stdio().await
+/// # }
+/// ```
+pub struct McpServer {
+    registry: Arc<PluginRegistry>,
+}
+
+impl McpServer {
+    pub fn new(registry: impl Into<Arc<PluginRegistry>>) -> Self {
+        Self {
+            registry: registry.into(),
+        }
+    }
+
+    /// Serves requests from stdin until it is closed.
+    pub async fn serve_stdio(&self) -> std::io::Result<()> {
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serves newline-delimited JSON-RPC messages from `reader` until EOF,
+    /// writing responses to `writer`.
+    pub async fn serve<R, W>(&self, reader: R, mut writer: W) -> std::io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle_message(message).await,
+                Err(e) => Some(error_response(
+                    Value::Null,
+                    PARSE_ERROR,
+                    format!("Parse error: {}", e),
+                )),
+            };
+
+            if let Some(response) = response {
+                writer.write_all(response.to_string().as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                writer.flush().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a single JSON-RPC message.
+    ///
+    /// Returns `None` for notifications, which get no response.
+    pub async fn handle_message(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned()?;
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": "nucleus",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(self.list_tools().await),
+            "tools/call" => self.call_tool(params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    async fn list_tools(&self) -> Value {
+        let mut tools = Vec::new();
+        for plugin in self.registry.all() {
+            let plugin = plugin.lock().await;
+            tools.push(json!({
+                "name": plugin.name(),
+                "description": plugin.description(),
+                "inputSchema": plugin.parameter_schema(),
+            }));
+        }
+        tools.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+        json!({ "tools": tools })
+    }
+
+    /// Executes a plugin. Plugin failures are reported as tool errors
+    /// (`isError`) rather than protocol errors, as MCP prescribes, and
+    /// structured plugin output is returned as `structuredContent`, see
+    /// [`structured_content`].
+    async fn call_tool(&self, params: Value) -> Result<Value, (i64, String)> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+        if self.registry.get(name).is_none() {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        }
+
+        let arguments = params
+            .get("arguments")
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+
+        Ok(match self.registry.execute(name, arguments).await {
+            Ok(output) => {
+                let mut result = json!({
+                    "content": [{ "type": "text", "text": output.content }],
+                    "isError": false,
+                });
+                if let Some(data) = output.data {
+                    result["structuredContent"] = structured_content(data);
+                }
+                result
+            }
+            Err(e) => json!({
+                "content": [{ "type": "text", "text": e.to_string() }],
+                "isError": true,
+            }),
+        })
+    }
+}
+
+/// MCP requires `structuredContent` to be an object, so other plugin data is
+/// wrapped in one: arrays as `{"results": [...]}`, anything else as
+/// `{"result": ...}`.
+fn structured_content(data: Value) -> Value {
+    match data {
+        Value::Object(_) => data,
+        Value::Array(_) => json!({ "results": data }),
+        _ => json!({ "result": data }),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use nucleus_plugin::{Permission, Plugin, PluginOutput};
+
+    struct EchoPlugin;
+
+    #[async_trait]
+    impl Plugin for EchoPlugin {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn description(&self) -> &str {
+            "Echoes its input"
+        }
+
+        fn parameter_schema(&self) -> Value {
+            json!({ "type": "object", "properties": { "text": { "type": "string" } } })
+        }
+
+        fn required_permission(&self) -> Permission {
+            Permission::READ_ONLY
+        }
+
+        async fn execute(&self, input: Value) -> nucleus_plugin::Result<PluginOutput> {
+            Ok(PluginOutput::new(format!("echo: {}", input["text"])).with_data(input))
+        }
+    }
+
+    struct DangerousPlugin;
+
+    #[async_trait]
+    impl Plugin for DangerousPlugin {
+        fn name(&self) -> &str {
+            "dangerous"
+        }
+
+        fn description(&self) -> &str {
+            "Needs every permission"
+        }
+
+        fn parameter_schema(&self) -> Value {
+            json!({ "type": "object" })
+        }
+
+        fn required_permission(&self) -> Permission {
+            Permission::ALL
+        }
+
+        async fn execute(&self, _input: Value) -> nucleus_plugin::Result<PluginOutput> {
+            Ok(PluginOutput::new("ran"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scripted_session() {
+        let mut registry = PluginRegistry::new(Permission::READ_ONLY);
+        assert!(registry.register(EchoPlugin).await);
+        assert!(!registry.register(DangerousPlugin).await);
+        let server = McpServer::new(registry);
+
+        let script = [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list" }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 3,
+                "method": "tools/call",
+                "params": { "name": "echo", "arguments": { "text": "hi" } }
+            }),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 4,
+                "method": "tools/call",
+                "params": { "name": "dangerous", "arguments": {} }
+            }),
+        ]
+        .iter()
+        .map(|message| format!("{}\n", message))
+        .collect::<String>();
+
+        let mut output = Vec::new();
+        server.serve(script.as_bytes(), &mut output).await.unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 4, "notifications get no response");
+
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["protocolVersion"], PROTOCOL_VERSION);
+        assert_eq!(responses[0]["result"]["serverInfo"]["name"], "nucleus");
+
+        let tools = responses[1]["result"]["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0]["name"], "echo");
+        assert_eq!(tools[0]["inputSchema"]["type"], "object");
+
+        assert_eq!(responses[2]["id"], 3);
+        assert_eq!(responses[2]["result"]["isError"], false);
+        assert_eq!(responses[2]["result"]["content"][0]["text"], "echo: \"hi\"");
+        assert_eq!(
+            responses[2]["result"]["structuredContent"],
+            json!({ "text": "hi" })
+        );
+
+        assert_eq!(responses[3]["id"], 4);
+        assert_eq!(responses[3]["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_method_and_bad_json() {
+        let server = McpServer::new(PluginRegistry::new(Permission::NONE));
+
+        let mut output = Vec::new();
+        let script = "not json\n{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"resources/list\"}\n";
+        server.serve(script.as_bytes(), &mut output).await.unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["id"], 7);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_structured_content_is_always_an_object() {
+        assert_eq!(
+            structured_content(json!({ "text": "hi" })),
+            json!({ "text": "hi" })
+        );
+        assert_eq!(
+            structured_content(json!([{ "source": "a.md" }])),
+            json!({ "results": [{ "source": "a.md" }] })
+        );
+        assert_eq!(structured_content(json!(3)), json!({ "result": 3 }));
+    }
+}
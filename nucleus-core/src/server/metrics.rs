@@ -0,0 +1,232 @@
+//! Server metrics in the Prometheus text exposition format.
+//!
+//! The request handler updates the counters as requests flow through it. With
+//! the `metrics` feature enabled, [`Server::with_metrics`](super::Server::with_metrics)
+//! serves them over HTTP at `GET /metrics`:
+//!
+//! ```text
+//! # HELP nucleus_requests_total Requests handled.
+//! # TYPE nucleus_requests_total counter
+//! nucleus_requests_total 3
+//! ```
+
+use super::http;
+use super::transport::Result;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+/// Path of the metrics endpoint.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Counters and gauges describing the work a server has done.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    chunks: AtomicU64,
+    generation_micros: AtomicU64,
+    errors: AtomicU64,
+    active_connections: AtomicU64,
+}
+
+impl Metrics {
+    pub(super) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a chat response of `chunks` streamed chunks that took `elapsed`.
+    pub(super) fn record_generation(&self, chunks: u64, elapsed: Duration) {
+        self.chunks.fetch_add(chunks, Ordering::Relaxed);
+        self.generation_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a connection as active until the returned guard is dropped.
+    pub(super) fn connection(self: &Arc<Self>) -> ConnectionGuard {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(Arc::clone(self))
+    }
+
+    /// Average response chunks streamed per second across all chat requests.
+    pub fn chunks_per_second(&self) -> f64 {
+        let micros = self.generation_micros.load(Ordering::Relaxed);
+        if micros == 0 {
+            return 0.0;
+        }
+        self.chunks.load(Ordering::Relaxed) as f64 / (micros as f64 / 1_000_000.0)
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = [
+            (
+                "nucleus_requests_total",
+                "counter",
+                "Requests handled.",
+                self.requests.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "nucleus_chunks_streamed_total",
+                "counter",
+                "Response chunks streamed by chat requests.",
+                self.chunks.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "nucleus_chunks_per_second",
+                "gauge",
+                "Average response chunks streamed per second.",
+                self.chunks_per_second().to_string(),
+            ),
+            (
+                "nucleus_active_connections",
+                "gauge",
+                "Client connections currently open.",
+                self.active_connections.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "nucleus_errors_total",
+                "counter",
+                "Requests that ended in an error.",
+                self.errors.load(Ordering::Relaxed).to_string(),
+            ),
+        ];
+
+        let mut output = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+        output
+    }
+}
+
+/// Marks a connection as closed when dropped.
+pub(super) struct ConnectionGuard(Arc<Metrics>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Answers a single scrape of the metrics endpoint.
+pub(super) async fn handle_connection(mut stream: TcpStream, metrics: Arc<Metrics>) -> Result<()> {
+    let request = match http::read_request(&mut stream).await {
+        Ok(request) => request,
+        Err(e) => return http::write_status(&mut stream, "400 Bad Request", &e.to_string()).await,
+    };
+
+    if request.method != "GET" || request.path != METRICS_PATH {
+        return http::write_status(&mut stream, "404 Not Found", "Not found").await;
+    }
+
+    let body = metrics.render();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{handler::RequestHandler, Request, RequestType};
+    use super::*;
+    use crate::testing::{test_config, MockProvider};
+    use tempfile::tempdir;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_render_exposition_format() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_request();
+        metrics.record_generation(10, Duration::from_secs(2));
+        let connection = metrics.connection();
+
+        let output = metrics.render();
+        assert!(output.contains(
+            "# HELP nucleus_requests_total Requests handled.\n\
+             # TYPE nucleus_requests_total counter\n\
+             nucleus_requests_total 1\n"
+        ));
+        assert!(output.contains("nucleus_chunks_streamed_total 10\n"));
+        assert!(output.contains("nucleus_chunks_per_second 5\n"));
+        assert!(output.contains("nucleus_active_connections 1\n"));
+        assert!(output.contains("nucleus_errors_total 0\n"));
+
+        drop(connection);
+        assert!(metrics.render().contains("nucleus_active_connections 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_scrape_counts_handled_requests() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::streaming(
+            vec!["Hel".to_string(), "lo".to_string()],
+            None,
+        ));
+        let handler = RequestHandler::new(test_config(temp.path()), provider)
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = handler.metrics();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                handle_connection(stream, Arc::clone(&metrics))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let scrape = || async {
+            let response = reqwest::get(format!("http://{}{}", addr, METRICS_PATH))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.headers()["content-type"].to_str().unwrap(),
+                CONTENT_TYPE
+            );
+            response.text().await.unwrap()
+        };
+
+        let before = scrape().await;
+        assert!(before.contains("nucleus_requests_total 0\n"));
+        assert!(before.contains("nucleus_chunks_streamed_total 0\n"));
+
+        let (sender, _receiver) = handler.chunk_channel();
+        let request = Request {
+            request_type: RequestType::Chat,
+            content: "Hi".to_string(),
+            ..Default::default()
+        };
+        handler.handle(request, sender).await;
+
+        let after = scrape().await;
+        assert!(after.contains("nucleus_requests_total 1\n"));
+        assert!(after.contains("nucleus_chunks_streamed_total 2\n"));
+        assert!(after.contains("nucleus_errors_total 0\n"));
+
+        let missing = reqwest::get(format!("http://{}/missing", addr))
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -3,25 +3,61 @@
 //! The server is organized into separate concerns:
 //! - `types`: Protocol types for requests and responses
 //! - `handler`: Business logic for processing requests
+//! - `edit`: Diff prompting and parsing for edit requests
+//! - `channel`: Bounded buffer of response chunks waiting for a slow client
 //! - `transport`: IPC communication layer (Unix sockets on Unix, Named Pipes on Windows)
+//! - `http`: Optional HTTP listener streaming responses as Server-Sent Events
+//! - `websocket`: Optional WebSocket listener for interactive, cancellable chat
+//! - `metrics`: Request metrics, optionally served to Prometheus over HTTP
+//! - `scheduler`: Priority-aware limit on concurrently handled requests
+//! - `client`: IPC client for talking to a running server (Unix only)
+//! - `mcp`: MCP server exposing the plugin registry over stdio
 
+mod channel;
+#[cfg(unix)]
+mod client;
+mod edit;
 mod handler;
+mod http;
+mod mcp;
+mod metrics;
+mod scheduler;
+mod thinking;
 mod transport;
 mod types;
+mod websocket;
 
 // Re-export types for external use
 #[allow(unused)]
-pub use types::{ChunkType, Message, Request, RequestType, StreamChunk};
+pub use types::{
+    ChunkType, EditReport, EditedFile, ErrorCode, Message, OutputFormat, Priority, Request,
+    RequestType, SearchMatch, SelfTestReport, SelfTestStage, StreamChunk,
+};
+
+pub use transport::TransportError;
+
+pub use http::{decode_sse, parse_sse, SseEvent};
+
+pub use mcp::McpServer;
+
+pub use metrics::{Metrics, METRICS_PATH};
+
+#[cfg(unix)]
+pub use client::AiClient;
 
 use crate::{
     config::Config,
     detection,
-    provider::{create_provider, Provider},
+    provider::{create_provider, BackgroundProvider, MistralRsProvider, Provider},
 };
 use nucleus_plugin::PluginRegistry;
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::signal;
-use tokio::sync::mpsc;
 
 #[cfg(unix)]
 const SOCKET_PATH: &str = "/tmp/llm-workspace.sock";
@@ -31,8 +67,14 @@ const SOCKET_PATH: &str = r"\\.\pipe\llm-workspace";
 
 /// Main server coordinating transport and request handling.
 pub struct Server {
+    provider: Arc<dyn Provider>,
     handler: Arc<handler::RequestHandler>,
     transport: transport::IpcTransport,
+    http: Option<http::HttpTransport>,
+    websocket: Option<websocket::WebSocketTransport>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<http::HttpTransport>,
+    read_timeout: Duration,
 }
 
 impl Server {
@@ -41,6 +83,12 @@ impl Server {
     /// Initializes the provider based on configuration (ollama, mistralrs, or coreml).
     /// For Ollama provider, checks if Ollama is installed and running.
     /// Connects to vector storage based on config.
+    ///
+    /// With `server.background_load` set, the provider is created (and warmed up,
+    /// with `llm.warmup`) in a background task, see [`BackgroundProvider`].
+    ///
+    /// Tools the model calls during chat requests run with the plugins in
+    /// `registry`.
     pub async fn new(
         config: Config,
         registry: PluginRegistry,
@@ -50,32 +98,217 @@ impl Server {
         }
 
         let registry = Arc::new(registry);
-        let provider = create_provider(&config, registry).await?;
-        let handler = Arc::new(handler::RequestHandler::new(config, provider).await?);
+        let provider: Arc<dyn Provider> = if config.server.background_load {
+            let config = config.clone();
+            let registry = Arc::clone(&registry);
+            Arc::new(BackgroundProvider::spawn(move |progress| async move {
+                let download = if config.llm.provider.eq_ignore_ascii_case("mistralrs") {
+                    MistralRsProvider::report_download_progress(&config.llm.model, progress.clone())
+                } else {
+                    None
+                };
+                let provider = create_provider(&config, registry).await;
+                if let Some(download) = download {
+                    download.abort();
+                }
+                progress.clear();
+                let provider = provider?;
+                if config.llm.warmup {
+                    if let Err(e) = provider.warmup().await {
+                        eprintln!("Provider warmup error: {}", e);
+                    }
+                }
+                Ok(provider)
+            }))
+        } else {
+            create_provider(&config, Arc::clone(&registry)).await?
+        };
+        Self::build(config, provider, Some(registry)).await
+    }
+
+    /// Creates a server around an already constructed provider.
+    ///
+    /// With `llm.warmup` set, the provider loads its model before this returns.
+    /// A failed warmup is logged rather than returned, as the model may still
+    /// load on the first request.
+    pub async fn with_provider(
+        config: Config,
+        provider: Arc<dyn Provider>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::build(config, provider, None).await
+    }
+
+    async fn build(
+        config: Config,
+        provider: Arc<dyn Provider>,
+        registry: Option<Arc<PluginRegistry>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if config.llm.warmup {
+            println!("Warming up model {}...", config.llm.model);
+            if let Err(e) = provider.warmup().await {
+                eprintln!("Provider warmup error: {}", e);
+            }
+        }
+
+        let read_timeout = Duration::from_secs(config.server.request_read_timeout_secs);
+        let mut handler = handler::RequestHandler::new(config, provider.clone()).await?;
+        if let Some(registry) = registry {
+            handler = handler.with_registry(registry);
+        }
+        let handler = Arc::new(handler);
         let transport = transport::IpcTransport::new(SOCKET_PATH);
 
-        Ok(Self { handler, transport })
+        Ok(Self {
+            provider,
+            handler,
+            transport,
+            http: None,
+            websocket: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            read_timeout,
+        })
+    }
+
+    /// Listen on a different IPC socket (or named pipe) than the default.
+    pub fn with_socket_path(mut self, socket_path: impl Into<String>) -> Self {
+        self.transport = transport::IpcTransport::new(socket_path);
+        self
+    }
+
+    /// Additionally serve the chat protocol over HTTP at `addr` (e.g. `127.0.0.1:8080`).
+    ///
+    /// Clients `POST` a JSON request to `/chat` and receive the response as a
+    /// stream of Server-Sent Events. The IPC socket keeps running alongside it.
+    pub fn with_http(mut self, addr: impl Into<String>) -> Self {
+        self.http = Some(http::HttpTransport::new(addr));
+        self
+    }
+
+    /// Additionally serve the chat protocol over WebSocket at `addr` (e.g. `127.0.0.1:8081`).
+    ///
+    /// Connections stay open across requests, and an in-flight request can be
+    /// cancelled by sending `{"type": "cancel"}`.
+    pub fn with_websocket(mut self, addr: impl Into<String>) -> Self {
+        self.websocket = Some(websocket::WebSocketTransport::new(addr));
+        self
+    }
+
+    /// Additionally serve Prometheus metrics at `addr` (e.g. `127.0.0.1:9090`).
+    ///
+    /// Scrapers `GET` [`METRICS_PATH`] on their own listener, apart from the
+    /// IPC socket and any chat transports.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, addr: impl Into<String>) -> Self {
+        self.metrics = Some(http::HttpTransport::new(addr));
+        self
+    }
+
+    /// Metrics for the requests this server has handled.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.handler.metrics()
     }
 
-    /// Starts the server and listens for connections.
+    /// Starts the server and listens for connections until Ctrl-C.
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.start_until(signal::ctrl_c()).await
+    }
+
+    /// Starts the server and listens for connections until `shutdown` completes.
+    ///
+    /// On shutdown the IPC endpoint is removed and the provider is given a
+    /// chance to release its resources via [`Provider::shutdown`].
+    pub async fn start_until<F>(&self, shutdown: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: Future,
+    {
         let listener = self.transport.bind().await?;
 
-        println!("AI Server listening on {}", SOCKET_PATH);
+        println!("AI Server listening on {}", self.transport.socket_path());
+
+        let http_listener = match &self.http {
+            Some(http) => {
+                let listener = http.bind().await?;
+                println!(
+                    "HTTP (SSE) server listening on http://{}{}",
+                    http.addr(),
+                    http::CHAT_PATH
+                );
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let ws_listener = match &self.websocket {
+            Some(websocket) => {
+                let listener = websocket.bind().await?;
+                println!("WebSocket server listening on ws://{}", websocket.addr());
+                Some(listener)
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "metrics")]
+        let metrics_listener = match &self.metrics {
+            Some(metrics) => {
+                let listener = metrics.bind().await?;
+                println!(
+                    "Metrics server listening on http://{}{}",
+                    metrics.addr(),
+                    METRICS_PATH
+                );
+                Some(listener)
+            }
+            None => None,
+        };
+        #[cfg(not(feature = "metrics"))]
+        let metrics_listener: Option<TcpListener> = None;
+
+        let metrics = self.handler.metrics();
 
-        let shutdown = signal::ctrl_c();
         tokio::pin!(shutdown);
 
         loop {
             tokio::select! {
                 Ok((stream, _)) = listener.accept() => {
                     let handler = Arc::clone(&self.handler);
+                    let read_timeout = self.read_timeout;
+                    let connection = metrics.connection();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, handler).await {
+                        let _connection = connection;
+                        if let Err(e) = handle_connection(stream, handler, read_timeout).await {
                             eprintln!("Connection error: {}", e);
                         }
                     });
                 }
+                Some(Ok((stream, _))) = accept_optional(http_listener.as_ref()) => {
+                    let handler = Arc::clone(&self.handler);
+                    let connection = metrics.connection();
+                    tokio::spawn(async move {
+                        let _connection = connection;
+                        if let Err(e) = handle_http_connection(stream, handler).await {
+                            eprintln!("HTTP connection error: {}", e);
+                        }
+                    });
+                }
+                Some(Ok((stream, _))) = accept_optional(ws_listener.as_ref()) => {
+                    let handler = Arc::clone(&self.handler);
+                    let connection = metrics.connection();
+                    tokio::spawn(async move {
+                        let _connection = connection;
+                        if let Err(e) = websocket::handle_connection(stream, handler).await {
+                            eprintln!("WebSocket connection error: {}", e);
+                        }
+                    });
+                }
+                Some(Ok((stream, _))) = accept_optional(metrics_listener.as_ref()) => {
+                    let metrics = Arc::clone(&metrics);
+                    tokio::spawn(async move {
+                        if let Err(e) = metrics::handle_connection(stream, metrics).await {
+                            eprintln!("Metrics connection error: {}", e);
+                        }
+                    });
+                }
                 _ = &mut shutdown => {
                     println!("\nShutting down...");
                     self.transport.cleanup();
@@ -84,27 +317,258 @@ impl Server {
             }
         }
 
+        if let Err(e) = self.provider.shutdown().await {
+            eprintln!("Provider shutdown error: {}", e);
+        }
+
         Ok(())
     }
 }
 
 /// Handles a single client connection.
+///
+/// The connection is dropped if the client sends no request within `read_timeout`.
+///
+/// While the response streams, the client can send `{"type": "stop"}` on the
+/// same connection to halt generation. The response then ends with a `done`
+/// chunk holding the content streamed so far.
 async fn handle_connection(
-    mut stream: transport::IpcStream,
+    stream: transport::IpcStream,
+    handler: Arc<handler::RequestHandler>,
+    read_timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let request = transport::read_request_within(&mut reader, read_timeout).await?;
+
+    let (sender, mut receiver) = handler.chunk_channel();
+
+    let handle_task = tokio::spawn(async move {
+        handler.handle(request, sender).await;
+    });
+
+    let stop = transport::wait_for_stop(&mut reader);
+    tokio::pin!(stop);
+    let mut watching_for_stop = true;
+    let mut partial = String::new();
+
+    loop {
+        tokio::select! {
+            chunk = receiver.recv() => {
+                let Some(chunk) = chunk else { break };
+                transport::write_chunk(&mut writer, &chunk).await?;
+                match chunk.chunk_type {
+                    // Chunks of multiple completions can't be joined into one response
+                    ChunkType::Chunk if chunk.index.is_none() => partial.push_str(&chunk.content),
+                    ChunkType::Chunk
+                    | ChunkType::ToolCall
+                    | ChunkType::ToolResult
+                    | ChunkType::Progress => {}
+                    ChunkType::Done | ChunkType::Error => break,
+                }
+            }
+            stopped = &mut stop, if watching_for_stop => {
+                watching_for_stop = false;
+                if stopped {
+                    handle_task.abort();
+                    let done = StreamChunk::done(std::mem::take(&mut partial));
+                    transport::write_chunk(&mut writer, &done).await?;
+                    break;
+                }
+            }
+        }
+    }
+
+    // Wakes the handler if it is waiting for room to send more chunks
+    drop(receiver);
+    match handle_task.await {
+        Err(e) if !e.is_cancelled() => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Accepts a connection on an optional listener, or waits forever if it is disabled.
+async fn accept_optional(
+    listener: Option<&TcpListener>,
+) -> Option<std::io::Result<(TcpStream, SocketAddr)>> {
+    match listener {
+        Some(listener) => Some(listener.accept().await),
+        None => std::future::pending().await,
+    }
+}
+
+/// Handles a single HTTP client connection, streaming the response as SSE.
+async fn handle_http_connection(
+    mut stream: TcpStream,
     handler: Arc<handler::RequestHandler>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let request = transport::read_request(&mut stream).await?;
+    let http_request = match http::read_request(&mut stream).await {
+        Ok(request) => request,
+        Err(e) => {
+            http::write_status(&mut stream, "400 Bad Request", &e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    if http_request.method != "POST" || http_request.path != http::CHAT_PATH {
+        http::write_status(&mut stream, "404 Not Found", "Not found").await?;
+        return Ok(());
+    }
 
-    let (sender, receiver) = mpsc::unbounded_channel();
+    let request: Request = match serde_json::from_slice(&http_request.body) {
+        Ok(request) => request,
+        Err(e) => {
+            http::write_status(&mut stream, "400 Bad Request", &e.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let (sender, receiver) = handler.chunk_channel();
 
     let handle_task = tokio::spawn(async move {
         handler.handle(request, sender).await;
     });
 
-    let write_task =
-        tokio::spawn(async move { transport::write_chunks(&mut stream, receiver).await });
+    let write_task = tokio::spawn(async move { http::write_sse(&mut stream, receiver).await });
 
     let _ = tokio::try_join!(handle_task, write_task)?;
 
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testing::{test_config, MockProvider};
+    use std::sync::atomic::Ordering;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_provider_shutdown_on_server_shutdown() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::new(""));
+        let socket_path = temp.path().join("nucleus.sock");
+
+        let server = Server::with_provider(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap()
+            .with_socket_path(socket_path.to_string_lossy());
+
+        server.start_until(std::future::ready(())).await.unwrap();
+
+        assert_eq!(provider.shutdown_calls.load(Ordering::SeqCst), 1);
+        assert!(!socket_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_provider_warmup_on_startup() {
+        let temp = tempdir().unwrap();
+
+        let provider = Arc::new(MockProvider::new(""));
+        Server::with_provider(test_config(temp.path()), provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(provider.warmup_calls.load(Ordering::SeqCst), 0);
+
+        let mut config = test_config(temp.path());
+        config.llm.warmup = true;
+        let provider = Arc::new(MockProvider::new(""));
+        Server::with_provider(config, provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(provider.warmup_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_connection_is_closed_after_timeout() {
+        use tokio::io::AsyncReadExt;
+        use tokio::net::{UnixListener, UnixStream};
+
+        let temp = tempdir().unwrap();
+        let handler = Arc::new(
+            handler::RequestHandler::new(test_config(temp.path()), Arc::new(MockProvider::new("")))
+                .await
+                .unwrap(),
+        );
+
+        let socket_path = temp.path().join("nucleus.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, handler, Duration::from_millis(100))
+                .await
+                .map_err(|e| e.to_string())
+        });
+
+        // Connect, but never send a request
+        let mut client = UnixStream::connect(&socket_path).await.unwrap();
+        let mut buf = Vec::new();
+        let read = tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut buf))
+            .await
+            .expect("server should close the idle connection");
+        assert_eq!(read.unwrap(), 0);
+
+        let error = server.await.unwrap().unwrap_err();
+        assert!(error.contains("No request received"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_response_with_partial_content() {
+        use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, Lines};
+        use tokio::net::{UnixListener, UnixStream};
+
+        async fn next_chunk<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> StreamChunk {
+            let line = lines.next_line().await.unwrap().expect("connection closed");
+            serde_json::from_str(&line).unwrap()
+        }
+
+        let temp = tempdir().unwrap();
+        let chunks: Vec<String> = (0..20).map(|i| format!("word{} ", i)).collect();
+        let provider = Arc::new(MockProvider::streaming(
+            chunks,
+            Some(Duration::from_millis(50)),
+        ));
+        let handler = Arc::new(
+            handler::RequestHandler::new(test_config(temp.path()), provider)
+                .await
+                .unwrap(),
+        );
+
+        let socket_path = temp.path().join("nucleus.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, handler, Duration::from_secs(5))
+                .await
+                .map_err(|e| e.to_string())
+        });
+
+        let (reader, mut writer) = UnixStream::connect(&socket_path)
+            .await
+            .unwrap()
+            .into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(b"{\"type\":\"chat\",\"content\":\"Count\"}\n")
+            .await
+            .unwrap();
+        let first = next_chunk(&mut lines).await;
+        assert_eq!(first.chunk_type, ChunkType::Chunk);
+
+        writer.write_all(b"{\"type\":\"stop\"}\n").await.unwrap();
+        let mut streamed = first.content;
+        let done = loop {
+            let chunk = next_chunk(&mut lines).await;
+            match chunk.chunk_type {
+                ChunkType::Chunk => streamed.push_str(&chunk.content),
+                _ => break chunk,
+            }
+        };
+
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        assert_eq!(done.content, streamed);
+        assert!(streamed.split_whitespace().count() < 20, "{}", streamed);
+        assert_eq!(server.await.unwrap(), Ok(()));
+    }
+}
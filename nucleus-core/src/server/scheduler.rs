@@ -0,0 +1,201 @@
+//! Priority-aware limit on the number of requests handled at once.
+//!
+//! Requests acquire a [`Permit`] before they run. Below the limit a permit is
+//! granted immediately; otherwise the request queues until a running one
+//! finishes. Queued requests are started highest [`Priority`] first, and in
+//! arrival order within the same priority.
+
+use super::types::Priority;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+use tokio::sync::oneshot;
+
+/// Grants up to `limit` concurrent permits, queueing the rest by priority.
+pub struct Scheduler {
+    limit: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    running: usize,
+    next_seq: u64,
+    waiting: BinaryHeap<Waiter>,
+}
+
+/// A queued request, woken when a slot is handed to it.
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    wake: oneshot::Sender<()>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Max-heap: higher priority first, then the earlier arrival
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+/// A running slot, released when dropped.
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// A queued acquisition. If it is dropped after a slot was handed to it but
+/// before it noticed, the slot is passed on instead of being lost.
+struct Waiting<'a> {
+    scheduler: &'a Scheduler,
+    receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler allowing `limit` concurrent permits; 0 means unlimited.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(State {
+                running: 0,
+                next_seq: 0,
+                waiting: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    /// Waits for a slot, letting higher priority requests go first.
+    pub async fn acquire(&self, priority: Priority) -> Permit<'_> {
+        let receiver = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if self.limit == 0 || state.running < self.limit {
+                state.running += 1;
+                return Permit { scheduler: self };
+            }
+
+            let (wake, receiver) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                wake,
+            });
+            receiver
+        };
+
+        let mut waiting = Waiting {
+            scheduler: self,
+            receiver: Some(receiver),
+        };
+        if let Some(receiver) = waiting.receiver.as_mut() {
+            // The sender lives in the queue until it is woken, so this only
+            // completes once the slot has been handed over
+            let _ = receiver.await;
+        }
+        waiting.receiver = None;
+
+        Permit { scheduler: self }
+    }
+
+    /// Hands a finished request's slot to the next waiter, or frees it.
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_waiters_start_by_priority_then_arrival() {
+        let scheduler = Arc::new(Scheduler::new(1));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let running = scheduler.acquire(Priority::Normal).await;
+
+        let mut tasks = Vec::new();
+        for (name, priority) in [
+            ("low", Priority::Low),
+            ("normal-1", Priority::Normal),
+            ("high", Priority::High),
+            ("normal-2", Priority::Normal),
+        ] {
+            let scheduler = Arc::clone(&scheduler);
+            let order = Arc::clone(&order);
+            tasks.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            // Make sure each waiter is queued before the next arrives
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        drop(running);
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["high", "normal-1", "normal-2", "low"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_waiter_does_not_leak_slot() {
+        let scheduler = Scheduler::new(1);
+        let running = scheduler.acquire(Priority::Normal).await;
+
+        let abandoned =
+            tokio::time::timeout(Duration::from_millis(10), scheduler.acquire(Priority::High))
+                .await;
+        assert!(abandoned.is_err());
+
+        drop(running);
+        let acquired =
+            tokio::time::timeout(Duration::from_millis(100), scheduler.acquire(Priority::Low))
+                .await;
+        assert!(acquired.is_ok());
+    }
+}
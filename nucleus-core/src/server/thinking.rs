@@ -0,0 +1,132 @@
+//! Removal of reasoning (`<think>...</think>`) blocks from streamed responses.
+
+const OPEN_TAG: &str = "<think>";
+const CLOSE_TAG: &str = "</think>";
+
+/// Strips `<think>...</think>` blocks from a response as it streams in.
+///
+/// Tags may be split across chunks, so text that could be the start of a tag
+/// is held back until the next chunk shows whether it is one. Whitespace right
+/// after a block is dropped too, as models usually separate their reasoning
+/// from the answer with blank lines. When disabled, chunks pass through as is.
+#[derive(Debug, Default)]
+pub(super) struct ThinkingFilter {
+    enabled: bool,
+    pending: String,
+    in_block: bool,
+    after_block: bool,
+}
+
+impl ThinkingFilter {
+    pub(super) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the part of `chunk` (and of earlier held back text) that can be
+    /// shown now.
+    pub(super) fn push(&mut self, chunk: &str) -> String {
+        if !self.enabled {
+            return chunk.to_string();
+        }
+
+        self.pending.push_str(chunk);
+        let mut output = String::new();
+        loop {
+            let tag = if self.in_block { CLOSE_TAG } else { OPEN_TAG };
+            if let Some(start) = self.pending.find(tag) {
+                if !self.in_block {
+                    self.emit(&mut output, start);
+                }
+                self.pending.drain(..start + tag.len());
+                self.after_block = self.in_block;
+                self.in_block = !self.in_block;
+                continue;
+            }
+
+            let keep = partial_tag_len(&self.pending, tag);
+            let end = self.pending.len() - keep;
+            if self.in_block {
+                self.pending.drain(..end);
+            } else {
+                self.emit(&mut output, end);
+            }
+            return output;
+        }
+    }
+
+    /// Returns the text still held back once the response is complete. An
+    /// unterminated block is dropped.
+    pub(super) fn finish(&mut self) -> String {
+        let mut output = String::new();
+        if !self.in_block {
+            self.emit(&mut output, self.pending.len());
+        }
+        self.pending.clear();
+        output
+    }
+
+    /// Moves the first `end` bytes of the pending text to `output`.
+    fn emit(&mut self, output: &mut String, end: usize) {
+        let text: String = self.pending.drain(..end).collect();
+        let text = if self.after_block {
+            text.trim_start()
+        } else {
+            text.as_str()
+        };
+        if !text.is_empty() {
+            self.after_block = false;
+            output.push_str(text);
+        }
+    }
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&len| text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_all(chunks: &[&str]) -> Vec<String> {
+        let mut filter = ThinkingFilter::new(true);
+        let mut output: Vec<String> = chunks.iter().map(|chunk| filter.push(chunk)).collect();
+        output.push(filter.finish());
+        output
+    }
+
+    #[test]
+    fn test_blocks_split_across_chunks_are_removed() {
+        let output = filter_all(&[
+            "Sure",
+            "<th",
+            "ink>plan <",
+            "/thi",
+            "nk>\n\nThe",
+            " answer <",
+            "b>",
+        ]);
+        assert!(output.iter().all(|chunk| !chunk.contains("plan")));
+        assert_eq!(output.concat(), "SureThe answer <b>");
+
+        assert_eq!(
+            filter_all(&["a <thin", "king> b"]).concat(),
+            "a <thinking> b"
+        );
+        assert_eq!(filter_all(&["<think>never closed"]).concat(), "");
+    }
+
+    #[test]
+    fn test_disabled_filter_passes_chunks_through() {
+        let mut filter = ThinkingFilter::new(false);
+        assert_eq!(filter.push("<think>plan</think>"), "<think>plan</think>");
+        assert_eq!(filter.finish(), "");
+    }
+}
@@ -1,7 +1,9 @@
-use super::types::{Request, StreamChunk};
+use super::types::{ErrorCode, Request, StreamChunk};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::mpsc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 #[cfg(unix)]
 use std::path::Path;
@@ -18,10 +20,33 @@ pub enum TransportError {
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+
+    #[error("Malformed HTTP request: {0}")]
+    Http(String),
+
+    #[error("Server error: {0}")]
+    Server(String),
+
+    /// A server error with a specific category, which callers can present with
+    /// [`ErrorCode::friendly_message`]
+    #[error("Server error: {message}")]
+    Rejected { code: ErrorCode, message: String },
+
+    #[error("No request received within {0:?}, closing connection")]
+    IdleTimeout(Duration),
+
+    #[error("Request larger than {0} bytes")]
+    RequestTooLarge(usize),
+
+    #[error("Another server is already listening on {0}")]
+    InUse(String),
 }
 
 pub type Result<T> = std::result::Result<T, TransportError>;
 
+/// Largest request accepted, to avoid unbounded allocations.
+const MAX_REQUEST_SIZE: usize = 10 * 1024 * 1024;
+
 // Type aliases for platform-specific types
 #[cfg(unix)]
 pub type IpcStream = UnixStream;
@@ -65,10 +90,22 @@ impl IpcTransport {
         }
     }
 
+    /// The socket path (or pipe name) this transport binds to.
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+
     /// Binds to the IPC endpoint and returns a listener.
+    ///
+    /// A socket file left behind by a server that crashed is removed first. If
+    /// another server is still accepting connections on it, binding fails with
+    /// [`TransportError::InUse`] instead.
     #[cfg(unix)]
     pub async fn bind(&self) -> Result<IpcListener> {
         if Path::new(&self.socket_path).exists() {
+            if UnixStream::connect(&self.socket_path).await.is_ok() {
+                return Err(TransportError::InUse(self.socket_path.clone()));
+            }
             std::fs::remove_file(&self.socket_path)?;
         }
 
@@ -106,40 +143,220 @@ impl IpcTransport {
 }
 
 /// Reads a request from the stream.
-#[cfg(unix)]
-pub async fn read_request(stream: &mut IpcStream) -> Result<Request> {
-    let (reader, _) = stream.split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+///
+/// Requests are newline-terminated JSON and may arrive over any number of
+/// reads. A request the client ends by closing its side of the connection
+/// instead of with a newline is accepted too.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request> {
+    read_message(reader, MAX_REQUEST_SIZE).await
+}
 
-    reader.read_line(&mut line).await?;
-    let request = serde_json::from_str(&line)?;
+/// A message a client sends while its request is being handled.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ControlMessage {
+    /// Halt generation and finish the response with what was streamed so far
+    Stop,
+}
 
-    Ok(request)
+/// Waits for the client to send `{"type": "stop"}` after its request.
+///
+/// Other messages are ignored. Returns `false` if the client closes its side
+/// of the connection without asking to stop.
+pub async fn wait_for_stop<R: AsyncBufRead + Unpin>(reader: &mut R) -> bool {
+    loop {
+        match read_message(reader, MAX_REQUEST_SIZE).await {
+            Ok(ControlMessage::Stop) => return true,
+            Err(TransportError::Json(_)) => continue,
+            Err(_) => return false,
+        }
+    }
 }
 
-#[cfg(windows)]
-pub async fn read_request(stream: &mut IpcStream) -> Result<Request> {
-    let mut reader = BufReader::new(stream);
-    let mut line = String::new();
+/// Reads one newline-terminated JSON message of at most `max_size` bytes.
+async fn read_message<R, T>(reader: &mut R, max_size: usize) -> Result<T>
+where
+    R: AsyncBufRead + Unpin,
+    T: DeserializeOwned,
+{
+    // One byte over the limit leaves room for the terminating newline
+    let mut reader = reader.take(max_size as u64 + 1);
+    let mut message = Vec::new();
+    reader.read_until(b'\n', &mut message).await?;
 
-    reader.read_line(&mut line).await?;
-    let request = serde_json::from_str(&line)?;
+    if message.last() != Some(&b'\n') {
+        if message.len() > max_size {
+            return Err(TransportError::RequestTooLarge(max_size));
+        }
+        if message.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed before a request was received",
+            )
+            .into());
+        }
+    }
 
-    Ok(request)
+    Ok(serde_json::from_slice(&message)?)
 }
 
-/// Writes stream chunks to the client.
-pub async fn write_chunks(
-    stream: &mut IpcStream,
-    mut receiver: mpsc::UnboundedReceiver<StreamChunk>,
-) -> Result<()> {
-    while let Some(chunk) = receiver.recv().await {
-        let json = serde_json::to_string(&chunk)?;
-        stream.write_all(json.as_bytes()).await?;
-        stream.write_all(b"\n").await?;
-        stream.flush().await?;
+/// Reads a request from the stream, giving up if none arrives within `timeout`.
+/// A zero `timeout` waits indefinitely.
+///
+/// Keeps clients that connect but never send anything from tying up a task
+/// forever.
+pub async fn read_request_within<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    timeout: Duration,
+) -> Result<Request> {
+    if timeout.is_zero() {
+        return read_request(reader).await;
     }
+    tokio::time::timeout(timeout, read_request(reader))
+        .await
+        .map_err(|_| TransportError::IdleTimeout(timeout))?
+}
 
+/// Writes a stream chunk to the client as a line of JSON.
+pub async fn write_chunk<W: AsyncWrite + Unpin>(writer: &mut W, chunk: &StreamChunk) -> Result<()> {
+    let json = serde_json::to_string(chunk)?;
+    writer.write_all(json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::super::types::{Message, RequestType};
+    use super::*;
+    use tempfile::tempdir;
+    use tokio::io::BufReader;
+
+    fn request_with_history(turns: usize) -> Request {
+        let history = (0..turns)
+            .map(|i| Message {
+                role: "user".to_string(),
+                content: format!("turn {}: {}", i, "lorem ipsum ".repeat(100)),
+            })
+            .collect();
+        Request {
+            request_type: RequestType::Chat,
+            content: "Summarize".to_string(),
+            history: Some(history),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zero_read_timeout_waits_for_the_request() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let mut bytes = serde_json::to_vec(&request_with_history(1)).unwrap();
+            bytes.push(b'\n');
+            client.write_all(&bytes).await.unwrap();
+            client
+        });
+
+        let request = read_request_within(&mut BufReader::new(server), Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(request.content, "Summarize");
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_split_across_writes() {
+        let (mut server, mut client) = UnixStream::pair().unwrap();
+        let mut bytes = serde_json::to_vec(&request_with_history(200)).unwrap();
+        bytes.push(b'\n');
+        assert!(bytes.len() > 200_000);
+
+        let writer = tokio::spawn(async move {
+            for piece in bytes.chunks(64 * 1024) {
+                client.write_all(piece).await.unwrap();
+                client.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            client
+        });
+
+        let request = read_request(&mut BufReader::new(server)).await.unwrap();
+        let history = request.history.unwrap();
+        assert_eq!(history.len(), 200);
+        assert!(history[199].content.starts_with("turn 199: "));
+        assert_eq!(request.content, "Summarize");
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_framing_limits() {
+        let line = serde_json::to_string(&request_with_history(1)).unwrap();
+
+        // Closing the connection ends a request as well as a newline does
+        let request: Request = read_message(&mut line.as_bytes(), line.len())
+            .await
+            .unwrap();
+        assert_eq!(request.content, "Summarize");
+
+        let with_newline = format!("{}\n", line);
+        assert!(
+            read_message::<_, Request>(&mut with_newline.as_bytes(), line.len())
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            read_message::<_, Request>(&mut with_newline.as_bytes(), line.len() - 1).await,
+            Err(TransportError::RequestTooLarge(_))
+        ));
+        assert!(matches!(
+            read_message::<_, Request>(&mut &b""[..], line.len()).await,
+            Err(TransportError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_stop_skips_other_messages() {
+        let mut input = &b"not json\n{\"type\":\"cancel\"}\n{\"type\":\"stop\"}\n"[..];
+        assert!(wait_for_stop(&mut input).await);
+
+        let mut closed = &b"{\"type\":\"cancel\"}\n"[..];
+        assert!(!wait_for_stop(&mut closed).await);
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket() {
+        let temp = tempdir().unwrap();
+        let socket_path = temp.path().join("nucleus.sock");
+
+        // A listener dropped without cleanup leaves its socket file behind
+        drop(UnixListener::bind(&socket_path).unwrap());
+        assert!(socket_path.exists());
+
+        let transport = IpcTransport::new(socket_path.to_string_lossy());
+        let listener = transport.bind().await.unwrap();
+
+        let accept = tokio::spawn(async move { listener.accept().await.is_ok() });
+        UnixStream::connect(&socket_path).await.unwrap();
+        assert!(accept.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_bind_refuses_live_socket() {
+        let temp = tempdir().unwrap();
+        let socket_path = temp.path().join("nucleus.sock");
+        let live = UnixListener::bind(&socket_path).unwrap();
+
+        let transport = IpcTransport::new(socket_path.to_string_lossy());
+        assert!(matches!(
+            transport.bind().await,
+            Err(TransportError::InUse(_))
+        ));
+
+        // The running server keeps its socket
+        let accept = tokio::spawn(async move { live.accept().await.is_ok() });
+        UnixStream::connect(&socket_path).await.unwrap();
+        assert!(accept.await.unwrap());
+    }
+}
@@ -1,19 +1,105 @@
+use crate::provider::ProviderError;
+use crate::rag::{EmbedProgress, SearchResult};
+use nucleus_plugin::PluginError;
 use serde::{Deserialize, Serialize};
 
 /// Type of request being made to the server.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum RequestType {
     /// Chat with AI (streaming response)
+    #[default]
     Chat,
-    /// Edit mode with AI assistance (streaming response)
+    /// Propose file changes to the files under `pwd` as a unified diff,
+    /// applied when `server.apply_edits` is set (streaming response)
     Edit,
     /// Add content to knowledge base
     Add,
     /// Index a directory for RAG
     Index,
+    /// Index the file at `content` (relative to `pwd`, and under it), replacing
+    /// its chunks. Files the indexer's exclude patterns match are refused
+    #[serde(rename = "index_file")]
+    IndexFile,
+    /// Rebuild the knowledge base from its indexed files with the current
+    /// chunk settings and embedding model (streaming progress)
+    Reindex,
     /// Get knowledge base statistics
     Stats,
+    /// Embed text with the provider's embedding model (no chat)
+    Embed,
+    /// List indexed sources with their chunk counts
+    Sources,
+    /// Search the knowledge base for `content`, returning the best matches
+    /// with their scores and snippets (see [`SearchMatch`])
+    Search,
+    /// Check embedding, the vector store and generation end to end
+    SelfTest,
+    /// List the models found in `server.models_dir`
+    #[serde(rename = "list_local_models")]
+    ListLocalModels,
+}
+
+/// A knowledge base match returned by a search request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchMatch {
+    /// Where the chunk was cut from, e.g. `src/main.rs:120-145`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub score: f32,
+    /// Excerpt of the chunk around the query terms, with them marked; only
+    /// set when `rag.snippets` is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Query terms found in the chunk
+    #[serde(default)]
+    pub matched_terms: Vec<String>,
+    /// The whole chunk
+    pub content: String,
+}
+
+impl From<&SearchResult> for SearchMatch {
+    fn from(result: &SearchResult) -> Self {
+        Self {
+            location: result.document.location(),
+            score: result.score,
+            snippet: result.snippet.clone(),
+            matched_terms: result
+                .explanation
+                .as_ref()
+                .map(|explanation| explanation.matched_terms.clone())
+                .unwrap_or_default(),
+            content: result.document.content.clone(),
+        }
+    }
+}
+
+/// Scheduling priority of a request.
+///
+/// When the server is at its concurrency limit, queued requests are started
+/// highest priority first, and in arrival order within the same priority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Background work such as indexing
+    Low,
+    /// The default priority
+    #[default]
+    Normal,
+    /// Interactive requests that should preempt queued work
+    High,
+}
+
+/// How the content of a "done" chunk is formatted, for requests that return data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The request's usual format: a sentence for stats, a JSON array for
+    /// sources and local models
+    #[default]
+    Text,
+    /// One JSON object per line (NDJSON), for piping into tools like `jq`
+    Jsonl,
 }
 
 /// Type of streaming response chunk.
@@ -26,6 +112,89 @@ pub enum ChunkType {
     Done,
     /// An error occurred
     Error,
+    /// The model called a tool, which is now running
+    #[serde(rename = "tool_call")]
+    ToolCall,
+    /// A tool finished, with its output as the content
+    #[serde(rename = "tool_result")]
+    ToolResult,
+    /// Progress of a long-running request such as indexing
+    Progress,
+}
+
+/// Machine-readable category of an error chunk, so clients can react to
+/// specific failures instead of parsing the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The configured model is not available to the provider
+    ModelNotFound,
+    /// The model is still being downloaded or loaded
+    ModelLoading,
+    /// The provider or a request took too long
+    Timeout,
+    /// The operation is not allowed by the granted permissions
+    PermissionDenied,
+    /// A requested plugin/tool does not exist
+    PluginNotFound,
+    /// The active provider does not support the operation
+    Unsupported,
+    /// The request was malformed or referenced invalid input
+    InvalidRequest,
+    /// The request was cancelled by the client
+    Cancelled,
+    /// The prompt doesn't fit in the model's context window, even after trimming
+    ContextOverflow,
+    /// Any other failure
+    Internal,
+}
+
+impl ErrorCode {
+    /// A short, user-facing explanation of the error category.
+    pub fn friendly_message(&self) -> &'static str {
+        match self {
+            Self::ModelNotFound => {
+                "The model is not available. Check the configured model name or download it first"
+            }
+            Self::ModelLoading => "The model is still downloading. Try again once it's ready",
+            Self::Timeout => "The AI took too long to respond. Try again, or use a smaller model",
+            Self::PermissionDenied => "This action is not permitted with the current permissions",
+            Self::PluginNotFound => "The requested tool is not available",
+            Self::Unsupported => "The active provider does not support this request",
+            Self::InvalidRequest => "The request was invalid",
+            Self::Cancelled => "The request was cancelled",
+            Self::ContextOverflow => {
+                "The message is too long for the model's context window. Shorten it and try again"
+            }
+            Self::Internal => "Something went wrong on the server",
+        }
+    }
+}
+
+impl From<&ProviderError> for ErrorCode {
+    fn from(error: &ProviderError) -> Self {
+        match error {
+            ProviderError::ModelNotFound(_) | ProviderError::IncompatibleModel(_) => {
+                Self::ModelNotFound
+            }
+            ProviderError::ModelLoading(_) => Self::ModelLoading,
+            ProviderError::Timeout(_) => Self::Timeout,
+            ProviderError::Request(e) if e.is_timeout() => Self::Timeout,
+            ProviderError::Unsupported(_) => Self::Unsupported,
+            _ => Self::Internal,
+        }
+    }
+}
+
+impl From<&PluginError> for ErrorCode {
+    fn from(error: &PluginError) -> Self {
+        match error {
+            PluginError::PermissionDenied(_) => Self::PermissionDenied,
+            PluginError::NotFound(_) => Self::PluginNotFound,
+            PluginError::InvalidInput(_) => Self::InvalidRequest,
+            _ => Self::Internal,
+        }
+    }
 }
 
 /// A message in conversation history.
@@ -48,7 +217,7 @@ pub struct Message {
 }
 
 /// Request from client to server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Request {
     /// Type of request to perform.
     #[serde(rename = "type")]
@@ -59,7 +228,10 @@ pub struct Request {
     /// For chat/edit: the user's message
     /// For add: the text to add to knowledge base
     /// For index: the directory path to index
+    /// For index_file: the file to index, relative to `pwd` if given
     /// For stats: ignored
+    /// For embed: the text to embed (unless `texts` is given)
+    /// For selftest and list_local_models: ignored
     pub content: String,
 
     /// Optional working directory context.
@@ -73,6 +245,125 @@ pub struct Request {
     /// Allows maintaining context across multiple interactions.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub history: Option<Vec<Message>>,
+
+    /// Number of completions to generate for chat/edit requests (defaults to 1).
+    ///
+    /// When greater than 1, each chunk is labeled with the `index` of the
+    /// completion it belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<usize>,
+
+    /// Several texts to embed at once for embed requests, instead of `content`.
+    ///
+    /// The "done" chunk then holds an array of vectors, one per text, rather
+    /// than a single vector.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub texts: Option<Vec<String>>,
+
+    /// Images attached to the user message for chat/edit requests.
+    ///
+    /// Each entry is either base64-encoded image data (optionally as a
+    /// `data:image/...;base64,` URL) or the path of a PNG, JPEG, GIF, WebP or
+    /// BMP file inside `pwd`. Only vision-capable providers accept images.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<String>>,
+
+    /// Files an edit request changes, relative to `pwd`.
+    ///
+    /// Their current contents are included in the prompt, so the diff the
+    /// model proposes can quote their lines exactly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+
+    /// Sampling temperature for this chat/edit request, instead of `llm.temperature`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+
+    /// Model for this chat/edit request, instead of `llm.model`.
+    ///
+    /// Only providers that select the model per request (such as Ollama)
+    /// accept a different model; others answer with an unsupported error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Whether to augment this chat/edit request with context retrieved from
+    /// the knowledge base.
+    ///
+    /// `true` retrieves context for the message; `false`, or leaving it out,
+    /// sends the message as is without searching the knowledge base.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_rag: Option<bool>,
+
+    /// Scheduling priority (`high`, `normal` or `low`; defaults to normal).
+    #[serde(default)]
+    pub priority: Priority,
+
+    /// Output format for stats, sources and list_local_models requests
+    /// (`text` or `jsonl`; defaults to text).
+    #[serde(default)]
+    pub format: OutputFormat,
+
+    /// Shared secret, required when the server is configured with `server.auth_token`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+}
+
+/// Outcome of one stage of a self-test request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelfTestStage {
+    /// `embed`, `store` or `generate`
+    pub name: String,
+    pub passed: bool,
+    /// How long the stage took, in milliseconds
+    pub millis: u64,
+    /// Why the stage failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Result of a self-test request, sent as JSON in its "done" chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// Whether every stage passed
+    pub passed: bool,
+    pub stages: Vec<SelfTestStage>,
+}
+
+impl SelfTestReport {
+    pub fn new(stages: Vec<SelfTestStage>) -> Self {
+        Self {
+            passed: stages.iter().all(|stage| stage.passed),
+            stages,
+        }
+    }
+}
+
+/// Result of an edit request, sent as JSON in its "done" chunk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditReport {
+    /// The model's complete response
+    pub response: String,
+    /// The unified diff in the response, if it proposed any changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// Files the diff changes
+    #[serde(default)]
+    pub files: Vec<EditedFile>,
+    /// Whether the diff was applied to the files
+    pub applied: bool,
+    /// Why applying the diff failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A file changed by the diff of an edit request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditedFile {
+    pub path: String,
+    /// Lines the diff adds
+    pub added: usize,
+    /// Lines the diff removes
+    pub removed: usize,
 }
 
 /// Streaming response chunk sent to client.
@@ -87,13 +378,47 @@ pub struct StreamChunk {
     /// The content of this chunk.
     ///
     /// For "chunk" type: partial response text
-    /// For "done" type: complete response text
+    /// For "done" type: complete response text (for embed requests, the
+    /// embedding vector or vectors as JSON, for selftest requests a
+    /// [`SelfTestReport`] as JSON, for edit requests an [`EditReport`] as JSON)
     /// For "error" type: empty (error details in `error` field)
+    /// For "tool_call" type: empty (the call is in `tool` and `arguments`)
+    /// For "tool_result" type: the output of the tool, as given to the model
     pub content: String,
 
     /// Error message if chunk_type is "error".
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+
+    /// Category of the error if chunk_type is "error".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<ErrorCode>,
+
+    /// Index of the completion this chunk belongs to, for requests with `n > 1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+
+    /// Every completion in index order, on the "done" chunk of requests with `n > 1`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<Vec<String>>,
+
+    /// Name of the tool, for "tool_call" and "tool_result" chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+
+    /// Arguments the model passed to the tool, for "tool_call" chunks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<serde_json::Value>,
+
+    /// Number of chunks embedded so far, on embedding progress chunks of
+    /// index requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedded_chunks: Option<usize>,
+
+    /// Number of chunks to embed, on embedding progress chunks of index
+    /// requests. It grows as files are chunked, see [`EmbedProgress`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_chunks: Option<usize>,
 }
 
 impl StreamChunk {
@@ -102,6 +427,13 @@ impl StreamChunk {
             chunk_type: ChunkType::Chunk,
             content: content.into(),
             error: None,
+            error_code: None,
+            index: None,
+            completions: None,
+            tool: None,
+            arguments: None,
+            embedded_chunks: None,
+            total_chunks: None,
         }
     }
 
@@ -110,14 +442,132 @@ impl StreamChunk {
             chunk_type: ChunkType::Done,
             content: content.into(),
             error: None,
+            error_code: None,
+            index: None,
+            completions: None,
+            tool: None,
+            arguments: None,
+            embedded_chunks: None,
+            total_chunks: None,
         }
     }
 
+    /// Error chunk with the [`Internal`](ErrorCode::Internal) code; use
+    /// [`with_error_code`](Self::with_error_code) for a more specific one.
     pub fn error(error: impl Into<String>) -> Self {
         Self {
             chunk_type: ChunkType::Error,
             content: String::new(),
             error: Some(error.into()),
+            error_code: Some(ErrorCode::Internal),
+            index: None,
+            completions: None,
+            tool: None,
+            arguments: None,
+            embedded_chunks: None,
+            total_chunks: None,
+        }
+    }
+
+    /// Final chunk for a request with several completions.
+    ///
+    /// `content` holds the first completion, so clients that ignore
+    /// `completions` still receive a complete response.
+    pub fn done_n(completions: Vec<String>) -> Self {
+        Self {
+            content: completions.first().cloned().unwrap_or_default(),
+            completions: Some(completions),
+            ..Self::done(String::new())
+        }
+    }
+
+    /// Chunk announcing that the model called `tool` with `arguments`.
+    pub fn tool_call(tool: impl Into<String>, arguments: serde_json::Value) -> Self {
+        Self {
+            chunk_type: ChunkType::ToolCall,
+            tool: Some(tool.into()),
+            arguments: Some(arguments),
+            ..Self::chunk(String::new())
         }
     }
+
+    /// Chunk carrying the `output` of a finished call to `tool`.
+    pub fn tool_result(tool: impl Into<String>, output: impl Into<String>) -> Self {
+        Self {
+            chunk_type: ChunkType::ToolResult,
+            tool: Some(tool.into()),
+            ..Self::chunk(output)
+        }
+    }
+
+    /// Chunk reporting the progress of a long-running request.
+    pub fn progress(content: impl Into<String>) -> Self {
+        Self {
+            chunk_type: ChunkType::Progress,
+            ..Self::chunk(content)
+        }
+    }
+
+    /// Chunk reporting how many chunks of an index request have been embedded.
+    pub fn embed_progress(progress: &EmbedProgress) -> Self {
+        Self {
+            embedded_chunks: Some(progress.embedded_chunks),
+            total_chunks: Some(progress.total_chunks),
+            ..Self::progress(progress.to_string())
+        }
+    }
+
+    /// Sets the category of an error chunk.
+    pub fn with_error_code(mut self, code: ErrorCode) -> Self {
+        self.error_code = Some(code);
+        self
+    }
+
+    /// Labels the chunk with the index of the completion it belongs to.
+    pub fn with_index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_from_errors() {
+        let timeout = ProviderError::Timeout("no chunk after 30 seconds".to_string());
+        assert_eq!(ErrorCode::from(&timeout), ErrorCode::Timeout);
+        let missing = ProviderError::ModelNotFound("model 'qwen' not found".to_string());
+        assert_eq!(ErrorCode::from(&missing), ErrorCode::ModelNotFound);
+        let incompatible = ProviderError::IncompatibleModel("model.safetensors".to_string());
+        assert_eq!(ErrorCode::from(&incompatible), ErrorCode::ModelNotFound);
+        let other = ProviderError::Api("boom".to_string());
+        assert_eq!(ErrorCode::from(&other), ErrorCode::Internal);
+
+        let missing_plugin = PluginError::NotFound("grep".to_string());
+        assert_eq!(ErrorCode::from(&missing_plugin), ErrorCode::PluginNotFound);
+        let denied = PluginError::PermissionDenied("exec".to_string());
+        assert_eq!(ErrorCode::from(&denied), ErrorCode::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_missing_plugin_error_code() {
+        let registry = nucleus_plugin::PluginRegistry::new(nucleus_plugin::Permission::ALL);
+        let error = registry
+            .execute("does_not_exist", serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorCode::from(&error), ErrorCode::PluginNotFound);
+    }
+
+    #[test]
+    fn test_error_code_serialization() {
+        let chunk = StreamChunk::error("took too long").with_error_code(ErrorCode::Timeout);
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["error_code"], "timeout");
+
+        let done = serde_json::to_value(StreamChunk::done("ok")).unwrap();
+        assert!(done.get("error_code").is_none());
+    }
 }
@@ -0,0 +1,253 @@
+//! WebSocket transport for interactive, bidirectional chat.
+//!
+//! Unlike the IPC and HTTP transports, a WebSocket connection stays open across
+//! requests. Clients send JSON [`Request`]s as text frames and receive each
+//! [`StreamChunk`] as its own text frame. Requests on a connection are handled
+//! one at a time; a request is finished once a `done` or `error` chunk is sent.
+//!
+//! An in-flight request can be cancelled by sending:
+//!
+//! ```json
+//! {"type": "cancel"}
+//! ```
+//!
+//! The server stops generation, discards any pending chunks and replies with
+//! an `error` chunk whose message is [`CANCELLED`].
+
+use super::channel::ChunkReceiver;
+use super::handler::RequestHandler;
+use super::transport::Result;
+use super::types::{ChunkType, ErrorCode, Request, StreamChunk};
+use futures::{SinkExt, StreamExt};
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// Error message sent when a request is cancelled by the client.
+pub const CANCELLED: &str = "Request cancelled";
+
+/// WebSocket transport listening on a TCP address (e.g. `127.0.0.1:8081`).
+pub struct WebSocketTransport {
+    addr: String,
+}
+
+impl WebSocketTransport {
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self { addr: addr.into() }
+    }
+
+    /// The address this transport binds to.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Binds to the TCP address and returns a listener.
+    pub async fn bind(&self) -> Result<TcpListener> {
+        Ok(TcpListener::bind(&self.addr).await?)
+    }
+}
+
+/// A message sent by a WebSocket client.
+enum ClientMessage {
+    Request(Request),
+    Cancel,
+}
+
+impl ClientMessage {
+    fn parse(text: &str) -> serde_json::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(text)?;
+        if value.get("type").and_then(|t| t.as_str()) == Some("cancel") {
+            return Ok(Self::Cancel);
+        }
+        serde_json::from_value(value).map(Self::Request)
+    }
+}
+
+/// The request currently being handled on a connection.
+struct ActiveRequest {
+    task: JoinHandle<()>,
+    receiver: ChunkReceiver,
+}
+
+/// Receives the next chunk of the active request, or waits forever if there is none.
+async fn next_chunk(active: &mut Option<ActiveRequest>) -> Option<StreamChunk> {
+    match active {
+        Some(active) => active.receiver.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Handles a WebSocket connection until the client closes it.
+pub async fn handle_connection(
+    stream: TcpStream,
+    handler: Arc<RequestHandler>,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let ws = tokio_tungstenite::accept_async(stream).await?;
+    let (mut sink, mut source) = ws.split();
+    let mut active: Option<ActiveRequest> = None;
+
+    loop {
+        tokio::select! {
+            message = source.next() => {
+                let text = match message {
+                    Some(Ok(WsMessage::Text(text))) => text,
+                    Some(Ok(WsMessage::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => return Err(e.into()),
+                };
+
+                let reply = match ClientMessage::parse(text.as_str()) {
+                    Ok(ClientMessage::Request(request)) => {
+                        if active.is_some() {
+                            Some(
+                                StreamChunk::error("A request is already in progress")
+                                    .with_error_code(ErrorCode::InvalidRequest),
+                            )
+                        } else {
+                            let (sender, receiver) = handler.chunk_channel();
+                            let handler = Arc::clone(&handler);
+                            let task = tokio::spawn(async move {
+                                handler.handle(request, sender).await;
+                            });
+                            active = Some(ActiveRequest { task, receiver });
+                            None
+                        }
+                    }
+                    Ok(ClientMessage::Cancel) => match active.take() {
+                        Some(request) => {
+                            request.task.abort();
+                            Some(
+                                StreamChunk::error(CANCELLED)
+                                    .with_error_code(ErrorCode::Cancelled),
+                            )
+                        }
+                        None => Some(
+                            StreamChunk::error("No request in progress")
+                                .with_error_code(ErrorCode::InvalidRequest),
+                        ),
+                    },
+                    Err(e) => Some(
+                        StreamChunk::error(format!("Invalid message: {}", e))
+                            .with_error_code(ErrorCode::InvalidRequest),
+                    ),
+                };
+
+                if let Some(chunk) = reply {
+                    sink.send(WsMessage::text(serde_json::to_string(&chunk)?)).await?;
+                }
+            }
+            chunk = next_chunk(&mut active) => {
+                let Some(chunk) = chunk else {
+                    // The handler finished without a final chunk
+                    active = None;
+                    continue;
+                };
+
+                if matches!(chunk.chunk_type, ChunkType::Done | ChunkType::Error) {
+                    active = None;
+                }
+                sink.send(WsMessage::text(serde_json::to_string(&chunk)?)).await?;
+            }
+        }
+    }
+
+    if let Some(request) = active {
+        request.task.abort();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::RequestType;
+    use super::*;
+    use crate::testing::{test_config, MockProvider};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    type Client = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn send(client: &mut Client, value: serde_json::Value) {
+        client
+            .send(WsMessage::text(value.to_string()))
+            .await
+            .unwrap();
+    }
+
+    async fn recv_chunk(client: &mut Client) -> StreamChunk {
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                WsMessage::Text(text) => return serde_json::from_str(text.as_str()).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    fn chat_request(content: &str) -> serde_json::Value {
+        serde_json::to_value(Request {
+            request_type: RequestType::Chat,
+            content: content.to_string(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sequential_requests_and_cancellation() {
+        let temp = tempdir().unwrap();
+        let provider = Arc::new(MockProvider::streaming(
+            vec!["one ".to_string(), "two ".to_string(), "three".to_string()],
+            Some(Duration::from_millis(100)),
+        ));
+        let handler = Arc::new(
+            RequestHandler::new(test_config(temp.path()), provider)
+                .await
+                .unwrap(),
+        );
+
+        let listener = WebSocketTransport::new("127.0.0.1:0").bind().await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_connection(stream, handler).await.unwrap();
+        });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        // First request streams to completion
+        send(&mut client, chat_request("count to three")).await;
+        let mut streamed = Vec::new();
+        let done = loop {
+            let chunk = recv_chunk(&mut client).await;
+            match chunk.chunk_type {
+                ChunkType::Chunk => streamed.push(chunk.content),
+                _ => break chunk,
+            }
+        };
+        assert_eq!(streamed, vec!["one ", "two ", "three"]);
+        assert_eq!(done.chunk_type, ChunkType::Done);
+        assert_eq!(done.content, "one two three");
+
+        // Second request on the same connection is cancelled mid-stream
+        send(&mut client, chat_request("count again")).await;
+        let first = recv_chunk(&mut client).await;
+        assert_eq!(first.chunk_type, ChunkType::Chunk);
+        assert_eq!(first.content, "one ");
+
+        send(&mut client, serde_json::json!({ "type": "cancel" })).await;
+        let cancelled = recv_chunk(&mut client).await;
+        assert_eq!(cancelled.chunk_type, ChunkType::Error);
+        assert_eq!(cancelled.error.as_deref(), Some(CANCELLED));
+        assert_eq!(cancelled.error_code, Some(ErrorCode::Cancelled));
+
+        // Nothing from the cancelled request arrives afterwards
+        let next = tokio::time::timeout(Duration::from_millis(400), client.next()).await;
+        assert!(next.is_err(), "unexpected frame after cancel: {:?}", next);
+    }
+}
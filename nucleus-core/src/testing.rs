@@ -0,0 +1,290 @@
+//! Test helpers shared across nucleus-core unit tests.
+
+use crate::config::{Config, RagConfig, StorageConfig, StorageMode};
+use crate::models::EmbeddingModel;
+use crate::provider::{
+    ChatRequest, ChatResponse, Message, Provider, ProviderError, Result, ToolCall, ToolCallFunction,
+};
+use async_trait::async_trait;
+use nucleus_plugin::{Permission, Plugin, PluginOutput};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Embedding dimension used by [`MockProvider`] and [`test_config`].
+pub const TEST_EMBEDDING_DIM: usize = 16;
+
+/// Deterministic in-process provider for tests.
+///
+/// Chat requests are recorded and answered with a fixed reply, streamed as one
+/// or more chunks followed by a `done` response. Requests carrying a seed get
+/// an extra `" #<seed>"` chunk, so sampled completions can be told apart.
+/// Embeddings are a normalized byte histogram, so texts sharing characters
+/// land close together.
+pub struct MockProvider {
+    chunks: Vec<String>,
+    chunk_delay: Option<Duration>,
+    tool_calls: Mutex<Vec<ToolCall>>,
+    looping_tool_call: Option<ToolCall>,
+    chat_error: Mutex<Option<ProviderError>>,
+    model_switching: bool,
+    failing_embed: Option<usize>,
+    failed_text: Mutex<Option<String>>,
+    pub requests: Mutex<Vec<ChatRequest>>,
+    pub warmup_calls: AtomicUsize,
+    pub shutdown_calls: AtomicUsize,
+    pub embed_calls: AtomicUsize,
+}
+
+impl MockProvider {
+    /// Replies with `reply` as a single chunk.
+    pub fn new(reply: impl Into<String>) -> Self {
+        Self::streaming(vec![reply.into()], None)
+    }
+
+    /// Replies with each of `chunks` in turn, waiting `chunk_delay` before each one.
+    pub fn streaming(chunks: Vec<String>, chunk_delay: Option<Duration>) -> Self {
+        Self {
+            chunks,
+            chunk_delay,
+            tool_calls: Mutex::new(Vec::new()),
+            looping_tool_call: None,
+            chat_error: Mutex::new(None),
+            model_switching: true,
+            failing_embed: None,
+            failed_text: Mutex::new(None),
+            requests: Mutex::new(Vec::new()),
+            warmup_calls: AtomicUsize::new(0),
+            shutdown_calls: AtomicUsize::new(0),
+            embed_calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Answers the first chat request with a call to the `name` tool instead of
+    /// the reply; later requests get the reply as usual.
+    pub fn with_tool_call(self, name: impl Into<String>, arguments: serde_json::Value) -> Self {
+        {
+            let mut tool_calls = self.tool_calls.lock().unwrap();
+            let id = format!("call_{}", tool_calls.len());
+            tool_calls.push(ToolCall {
+                id: Some(id),
+                function: ToolCallFunction {
+                    name: name.into(),
+                    arguments,
+                },
+            });
+        }
+        self
+    }
+
+    /// Answers every chat request with a call to the `name` tool, so the
+    /// model never gives a final answer.
+    pub fn with_looping_tool_call(
+        mut self,
+        name: impl Into<String>,
+        arguments: serde_json::Value,
+    ) -> Self {
+        self.looping_tool_call = Some(ToolCall {
+            id: None,
+            function: ToolCallFunction {
+                name: name.into(),
+                arguments,
+            },
+        });
+        self
+    }
+
+    /// Fails the next chat request with `error`; later requests succeed.
+    pub fn with_chat_error(self, error: ProviderError) -> Self {
+        *self.chat_error.lock().unwrap() = Some(error);
+        self
+    }
+
+    /// Reports that the model can't be switched per request, like providers
+    /// that load a single model up front.
+    pub fn with_fixed_model(mut self) -> Self {
+        self.model_switching = false;
+        self
+    }
+
+    /// Fails the `nth` call to `embed` (counting from 1), and every later call
+    /// embedding the same text, like a chunk that always runs out of memory.
+    pub fn with_failing_embed(mut self, nth: usize) -> Self {
+        self.failing_embed = Some(nth);
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    async fn chat<'a>(
+        &'a self,
+        request: ChatRequest,
+        mut callback: Box<dyn FnMut(ChatResponse) + Send + 'a>,
+    ) -> Result<()> {
+        let model = request.model.clone();
+        let seed = request.seed.map(|seed| format!(" #{}", seed));
+        self.requests.lock().unwrap().push(request);
+
+        if let Some(error) = self.chat_error.lock().unwrap().take() {
+            return Err(error);
+        }
+
+        let mut tool_calls = std::mem::take(&mut *self.tool_calls.lock().unwrap());
+        tool_calls.extend(self.looping_tool_call.clone());
+        if !tool_calls.is_empty() {
+            let mut message = Message::assistant(None, "");
+            message.tool_calls = Some(tool_calls);
+            callback(ChatResponse {
+                model,
+                content: String::new(),
+                done: true,
+                message,
+            });
+            return Ok(());
+        }
+
+        for chunk in self.chunks.iter().chain(seed.as_ref()) {
+            if let Some(delay) = self.chunk_delay {
+                tokio::time::sleep(delay).await;
+            }
+            callback(ChatResponse {
+                model: model.clone(),
+                content: chunk.clone(),
+                done: false,
+                message: Message::assistant(None, chunk),
+            });
+        }
+        // Like Ollama, the final `done` response carries no content of its own.
+        callback(ChatResponse {
+            model,
+            content: String::new(),
+            done: true,
+            message: Message::assistant(None, ""),
+        });
+
+        Ok(())
+    }
+
+    fn supports_model_switching(&self) -> bool {
+        self.model_switching
+    }
+
+    async fn warmup(&self) -> Result<()> {
+        self.warmup_calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.shutdown_calls.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn embed(&self, text: &str, _model: &EmbeddingModel) -> Result<Vec<f32>> {
+        let call = self.embed_calls.fetch_add(1, Ordering::SeqCst) + 1;
+        {
+            let mut failed_text = self.failed_text.lock().unwrap();
+            if self.failing_embed == Some(call) {
+                *failed_text = Some(text.to_string());
+            }
+            if failed_text.as_deref() == Some(text) {
+                return Err(ProviderError::Other("out of memory".to_string()));
+            }
+        }
+
+        let mut embedding = vec![0.0f32; TEST_EMBEDDING_DIM];
+        for byte in text.bytes() {
+            embedding[byte as usize % TEST_EMBEDDING_DIM] += 1.0;
+        }
+
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            embedding.iter_mut().for_each(|v| *v /= norm);
+        }
+
+        Ok(embedding)
+    }
+}
+
+/// Config with RAG enabled and embedded storage rooted at `data_dir`.
+pub fn test_config(data_dir: &Path) -> Config {
+    let mut rag = RagConfig::default();
+    rag.embedding_model.embedding_dim = TEST_EMBEDDING_DIM;
+
+    let storage = StorageConfig {
+        storage_mode: StorageMode::Embedded {
+            path: data_dir.to_string_lossy().to_string(),
+        },
+        ..StorageConfig::default()
+    };
+
+    Config::default()
+        .with_data_dir(data_dir)
+        .with_rag_config(rag)
+        .with_storage_config(storage)
+}
+
+/// Answers a single HTTP request with the JSON `body`, standing in for a
+/// remote server such as Ollama or the Hugging Face Hub. Returns the base URL
+/// to request and a task resolving to the request line it received.
+pub async fn serve_once(body: &'static str) -> (String, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 4096];
+        let len = stream.read(&mut request).await.unwrap();
+        let request_line = String::from_utf8_lossy(&request[..len])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        request_line
+    });
+
+    (base_url, server)
+}
+
+/// Stand-in for the `apply_patch` plugin of nucleus-std, which needs write
+/// permission and records the input of each call instead of touching files.
+#[derive(Default)]
+pub struct RecordingPatchPlugin {
+    pub calls: Arc<Mutex<Vec<Value>>>,
+}
+
+#[async_trait]
+impl Plugin for RecordingPatchPlugin {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+
+    fn description(&self) -> &str {
+        "Records the patches it is asked to apply"
+    }
+
+    fn parameter_schema(&self) -> Value {
+        json!({ "type": "object", "properties": { "patch": { "type": "string" } } })
+    }
+
+    fn required_permission(&self) -> Permission {
+        Permission::READ_WRITE
+    }
+
+    async fn execute(&self, input: Value) -> nucleus_plugin::Result<PluginOutput> {
+        self.calls.lock().unwrap().push(input);
+        Ok(PluginOutput::new("applied"))
+    }
+}
@@ -14,6 +14,9 @@ pub enum PluginError {
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
 
+    #[error("Unknown plugin: {0}")]
+    NotFound(String),
+
     #[error("Plugin error: {0}")]
     Other(String),
 }
@@ -62,9 +65,17 @@ impl Permission {
 }
 
 /// Output from plugin execution.
+///
+/// `content` is what the model sees as the tool result. Plugins producing
+/// structured results (search matches, file listings, ...) can also attach
+/// them as `data`, so programmatic consumers don't have to parse `content`.
 #[derive(Debug, Clone)]
 pub struct PluginOutput {
+    /// Human-readable result, fed back to the model.
     pub content: String,
+    /// Structured form of the result, for callers other than the model.
+    pub data: Option<Value>,
+    /// Extra information about the execution.
     pub metadata: Option<Value>,
 }
 
@@ -72,10 +83,17 @@ impl PluginOutput {
     pub fn new(content: impl Into<String>) -> Self {
         Self {
             content: content.into(),
+            data: None,
             metadata: None,
         }
     }
 
+    /// Attaches the structured form of the result.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
     pub fn with_metadata(mut self, metadata: Value) -> Self {
         self.metadata = Some(metadata);
         self
@@ -1,7 +1,7 @@
 use crate::{Permission, Plugin, PluginError, PluginOutput};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError, RwLock};
 use tokio::sync::Mutex;
 
 /// Registry for managing plugins.
@@ -11,27 +11,83 @@ use tokio::sync::Mutex;
 /// - Looking up plugins by name
 /// - Executing plugins
 /// - Providing plugin specifications to the LLM
+/// - Asking an optional approval hook before running write/command plugins
+///
+/// Granted permissions can be changed at runtime with
+/// [`set_granted_permissions`](Self::set_granted_permissions). Registered
+/// plugins that are no longer allowed stay registered but are inactive: they
+/// are left out of lookups and specs, and can't be executed, until their
+/// permission is granted again.
 pub struct PluginRegistry {
-    plugins: HashMap<String, Arc<Mutex<dyn Plugin + Send + Sync>>>,
-    granted_permissions: Permission,
+    plugins: HashMap<String, RegisteredPlugin>,
+    granted_permissions: RwLock<Permission>,
+    approval_hook: Option<ApprovalHook>,
+}
+
+/// A registered plugin along with the permission it requires.
+struct RegisteredPlugin {
+    plugin: Arc<Mutex<dyn Plugin + Send + Sync>>,
+    required: Permission,
 }
 
+/// Callback deciding whether a plugin call may run, given the plugin name and its input.
+type ApprovalHook = Arc<dyn Fn(&str, &Value) -> bool + Send + Sync>;
+
 impl PluginRegistry {
     /// Create a new plugin registry with the given permissions.
     pub fn new(granted_permissions: Permission) -> Self {
         Self {
             plugins: HashMap::new(),
-            granted_permissions,
+            granted_permissions: RwLock::new(granted_permissions),
+            approval_hook: None,
         }
     }
 
+    /// Set a hook that is asked before every call to a plugin requiring write
+    /// or command permission.
+    ///
+    /// The hook receives the plugin name and its input; returning false makes
+    /// [`execute`](Self::execute) fail with [`PluginError::PermissionDenied`]
+    /// without running the plugin. Read-only plugins are never gated.
+    pub fn set_approval_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &Value) -> bool + Send + Sync + 'static,
+    {
+        self.approval_hook = Some(Arc::new(hook));
+    }
+
+    /// The currently granted permissions.
+    pub fn granted_permissions(&self) -> Permission {
+        *self
+            .granted_permissions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the granted permissions, enabling or disabling registered
+    /// plugins according to what they require.
+    pub fn set_granted_permissions(&self, permissions: Permission) {
+        *self
+            .granted_permissions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = permissions;
+    }
+
+    /// Registered plugins whose required permission is currently granted.
+    fn active(&self) -> impl Iterator<Item = (&String, &RegisteredPlugin)> {
+        let granted = self.granted_permissions();
+        self.plugins
+            .iter()
+            .filter(move |(_, entry)| granted.allows(&entry.required))
+    }
+
     /// Register a plugin if permissions allow.
     /// Returns true if the plugin was registered, false if denied by permissions.
     pub async fn register<T: Plugin + 'static>(&mut self, plugin: T) -> bool {
         let required = plugin.required_permission();
         let plugin = Arc::new(Mutex::new(plugin));
 
-        if !self.granted_permissions.allows(&required) {
+        if !self.granted_permissions().allows(&required) {
             return false;
         }
 
@@ -39,39 +95,71 @@ impl PluginRegistry {
             let locked_plugin = plugin.lock().await;
             locked_plugin.name().to_string()
         };
-        self.plugins.insert(plugin_name, plugin);
+        self.plugins
+            .insert(plugin_name, RegisteredPlugin { plugin, required });
         true
     }
 
-    /// Get the number plugins that exist in the registry
+    /// Whether a plugin named `name` is registered, active or not.
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.plugins.contains_key(name)
+    }
+
+    /// Get the number of active plugins in the registry
     pub fn get_count(&self) -> usize {
-        self.plugins.iter().count()
+        self.active().count()
     }
 
-    /// Get a plugin by name.
+    /// Get an active plugin by name.
     pub fn get(&self, name: &str) -> Option<&Arc<Mutex<dyn Plugin + Send + Sync>>> {
-        self.plugins.get(name)
+        self.plugins
+            .get(name)
+            .filter(|entry| self.granted_permissions().allows(&entry.required))
+            .map(|entry| &entry.plugin)
     }
 
-    /// Get all registered plugins.
+    /// Get all active plugins.
     pub fn all(&self) -> Vec<&Arc<Mutex<dyn Plugin + Send + Sync>>> {
-        self.plugins.values().collect()
+        self.active().map(|(_, entry)| &entry.plugin).collect()
     }
 
     /// Execute a plugin by name.
+    ///
+    /// Inactive plugins fail with [`PluginError::PermissionDenied`]. Write and
+    /// command plugins only run if the approval hook, when set, approves the call.
     pub async fn execute(&self, name: &str, input: Value) -> Result<PluginOutput, PluginError> {
-        let plugin = self
+        let entry = self
+            .plugins
             .get(name)
-            .ok_or_else(|| PluginError::Other(format!("Unknown plugin: {}", name)))?;
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+        let required = entry.required;
+
+        if !self.granted_permissions().allows(&required) {
+            return Err(PluginError::PermissionDenied(format!(
+                "Plugin '{}' requires permissions that are not granted",
+                name
+            )));
+        }
+
+        // The hook may block on the user, so ask before locking the plugin
+        if let Some(hook) = &self.approval_hook {
+            if (required.write || required.execute) && !hook(name, &input) {
+                return Err(PluginError::PermissionDenied(format!(
+                    "Call to '{}' was not approved",
+                    name
+                )));
+            }
+        }
 
-        plugin.lock().await.execute(input).await
+        let plugin = entry.plugin.lock().await;
+        plugin.execute(input).await
     }
 
     /// Get plugin specifications for the LLM.
     /// Returns a list of tool definitions in a format the LLM can understand.
     pub async fn plugin_specs(&self) -> Vec<Value> {
         let mut specs = Vec::new();
-        for plugin in self.plugins.values() {
+        for plugin in self.all() {
             let locked_plugin = plugin.lock().await;
             specs.push(serde_json::json!({
                 "name": locked_plugin.name(),
@@ -113,6 +201,173 @@ mod tests {
         }
     }
 
+    /// A plugin with a configurable name and permission that echoes its input.
+    struct NamedPlugin {
+        name: &'static str,
+        permission: Permission,
+    }
+
+    #[async_trait]
+    impl Plugin for NamedPlugin {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn description(&self) -> &str {
+            "A named test plugin"
+        }
+
+        fn parameter_schema(&self) -> Value {
+            serde_json::json!({})
+        }
+
+        fn required_permission(&self) -> Permission {
+            self.permission
+        }
+
+        async fn execute(&self, input: Value) -> crate::Result<PluginOutput> {
+            Ok(PluginOutput::new(input.to_string()))
+        }
+    }
+
+    /// A plugin returning a file listing both as text and as structured data.
+    struct ListingPlugin;
+
+    #[async_trait]
+    impl Plugin for ListingPlugin {
+        fn name(&self) -> &str {
+            "list_files"
+        }
+
+        fn description(&self) -> &str {
+            "Lists files"
+        }
+
+        fn parameter_schema(&self) -> Value {
+            serde_json::json!({})
+        }
+
+        fn required_permission(&self) -> Permission {
+            Permission::READ_ONLY
+        }
+
+        async fn execute(&self, _input: Value) -> crate::Result<PluginOutput> {
+            let files = serde_json::json!([
+                { "path": "src/main.rs", "size": 120 },
+                { "path": "Cargo.toml", "size": 45 },
+            ]);
+            Ok(PluginOutput::new("src/main.rs\nCargo.toml").with_data(files))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_structured_output_data() {
+        let mut registry = PluginRegistry::new(Permission::READ_ONLY);
+        registry.register(ListingPlugin).await;
+
+        let output = registry
+            .execute("list_files", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(output.content, "src/main.rs\nCargo.toml");
+        let data = output.data.unwrap();
+        assert_eq!(data[0]["path"], "src/main.rs");
+        assert_eq!(data[1]["size"], 45);
+        assert!(output.metadata.is_none());
+
+        let plain = PluginOutput::new("text only");
+        assert!(plain.data.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_approval_hook_gates_calls() {
+        let mut registry = PluginRegistry::new(Permission::ALL);
+        registry
+            .register(NamedPlugin {
+                name: "exec",
+                permission: Permission::ALL,
+            })
+            .await;
+        registry
+            .register(NamedPlugin {
+                name: "read_file",
+                permission: Permission::READ_ONLY,
+            })
+            .await;
+        registry.set_approval_hook(|name, _input| name != "exec");
+
+        let denied = registry
+            .execute("exec", serde_json::json!({ "command": "rm" }))
+            .await;
+        assert!(matches!(denied, Err(PluginError::PermissionDenied(_))));
+
+        let approved = registry
+            .execute("read_file", serde_json::json!({ "path": "a.txt" }))
+            .await
+            .unwrap();
+        assert!(approved.content.contains("a.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_denied_write_plugin_is_refused() {
+        let mut registry = PluginRegistry::new(Permission::READ_WRITE);
+        registry
+            .register(NamedPlugin {
+                name: "write_file",
+                permission: Permission::READ_WRITE,
+            })
+            .await;
+        registry
+            .register(NamedPlugin {
+                name: "read_file",
+                permission: Permission::READ_ONLY,
+            })
+            .await;
+        registry.set_approval_hook(|_name, _input| false);
+
+        let denied = registry
+            .execute("write_file", serde_json::json!({ "path": "a.txt" }))
+            .await;
+        assert!(matches!(denied, Err(PluginError::PermissionDenied(_))));
+
+        // Read-only plugins are never gated
+        assert!(registry
+            .execute("read_file", serde_json::json!({ "path": "a.txt" }))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_downgrading_permissions_disables_plugins() {
+        let mut registry = PluginRegistry::new(Permission::READ_WRITE);
+        registry
+            .register(NamedPlugin {
+                name: "read_file",
+                permission: Permission::READ_ONLY,
+            })
+            .await;
+        registry
+            .register(NamedPlugin {
+                name: "write_file",
+                permission: Permission::READ_WRITE,
+            })
+            .await;
+        assert_eq!(registry.plugin_specs().await.len(), 2);
+
+        registry.set_granted_permissions(Permission::READ_ONLY);
+
+        let specs = registry.plugin_specs().await;
+        let names: Vec<&str> = specs.iter().filter_map(|s| s["name"].as_str()).collect();
+        assert_eq!(names, ["read_file"]);
+        assert_eq!(registry.all().len(), 1);
+        assert!(registry.get("write_file").is_none());
+        let result = registry.execute("write_file", serde_json::json!({})).await;
+        assert!(matches!(result, Err(PluginError::PermissionDenied(_))));
+
+        registry.set_granted_permissions(Permission::READ_WRITE);
+        assert!(registry.get("write_file").is_some());
+    }
+
     #[test]
     fn test_registry_permissions() {
         let mut registry = PluginRegistry::new(Permission::READ_ONLY);
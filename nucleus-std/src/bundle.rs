@@ -0,0 +1,132 @@
+//! One-call registration of the standard plugins.
+
+use crate::{
+    ApplyPatchPlugin, EnvInfoPlugin, ExecPlugin, ReadFilePlugin, ReadFilesPlugin, SearchPlugin,
+    WriteFilePlugin, DEFAULT_ALLOWED_COMMANDS,
+};
+use nucleus_plugin::{Permission, Plugin, PluginRegistry};
+use std::path::Path;
+
+/// Registers every standard plugin that `permission` allows, and that the
+/// registry's own granted permissions accept.
+///
+/// Read, search and environment plugins need [`Permission::READ_ONLY`],
+/// `write_file` and `apply_patch` need [`Permission::READ_WRITE`], and `exec`
+/// needs [`Permission::ALL`]. `apply_patch` only changes files inside
+/// `workspace`, and `exec` only runs [`DEFAULT_ALLOWED_COMMANDS`]; register
+/// your own [`ExecPlugin`] first for other commands. Plugins whose name is
+/// already registered are left alone. Returns the names of the plugins that
+/// were installed.
+///
+/// ```no_run
+/// # use nucleus_plugin::{Permission, PluginRegistry};
+/// # async fn example() {
+/// let mut registry = PluginRegistry::new(Permission::ALL);
+/// let installed = nucleus_std::register_standard_plugins(
+///     &mut registry,
+///     &Permission::READ_ONLY,
+///     std::path::Path::new("."),
+/// )
+/// .await;
+/// assert!(!installed.contains(&"exec".to_string()));
+/// # }
+/// ```
+pub async fn register_standard_plugins(
+    registry: &mut PluginRegistry,
+    permission: &Permission,
+    workspace: &Path,
+) -> Vec<String> {
+    let mut installed = Vec::new();
+    install(registry, permission, ReadFilePlugin::new(), &mut installed).await;
+    install(registry, permission, ReadFilesPlugin::new(), &mut installed).await;
+    install(registry, permission, SearchPlugin::new(), &mut installed).await;
+    install(registry, permission, EnvInfoPlugin::new(), &mut installed).await;
+    install(registry, permission, WriteFilePlugin::new(), &mut installed).await;
+    let apply_patch = ApplyPatchPlugin::new().with_root(workspace);
+    install(registry, permission, apply_patch, &mut installed).await;
+    let exec = ExecPlugin::new().with_allowed_commands(DEFAULT_ALLOWED_COMMANDS.iter().copied());
+    install(registry, permission, exec, &mut installed).await;
+    installed
+}
+
+async fn install<T: Plugin + 'static>(
+    registry: &mut PluginRegistry,
+    permission: &Permission,
+    plugin: T,
+    installed: &mut Vec<String>,
+) {
+    let name = plugin.name().to_string();
+    if !permission.allows(&plugin.required_permission()) || registry.is_registered(&name) {
+        return;
+    }
+    if registry.register(plugin).await {
+        installed.push(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bundle_respects_permission() {
+        let workspace = Path::new(".");
+        let mut registry = PluginRegistry::new(Permission::ALL);
+        let installed =
+            register_standard_plugins(&mut registry, &Permission::READ_ONLY, workspace).await;
+        assert_eq!(
+            installed,
+            vec!["read_file", "read_files", "search", "env_info"]
+        );
+        assert_eq!(registry.get_count(), 4);
+        assert!(registry.get("write_file").is_none());
+        assert!(registry.get("exec").is_none());
+
+        let mut registry = PluginRegistry::new(Permission::ALL);
+        let installed = register_standard_plugins(&mut registry, &Permission::ALL, workspace).await;
+        assert_eq!(
+            installed,
+            vec![
+                "read_file",
+                "read_files",
+                "search",
+                "env_info",
+                "write_file",
+                "apply_patch",
+                "exec"
+            ]
+        );
+        assert_eq!(registry.get_count(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_bundle_keeps_registered_plugins_and_restricts_exec() {
+        let mut registry = PluginRegistry::new(Permission::ALL);
+        assert!(
+            registry
+                .register(ExecPlugin::new().with_allowed_commands(["echo"]))
+                .await
+        );
+        let installed =
+            register_standard_plugins(&mut registry, &Permission::ALL, Path::new(".")).await;
+        assert!(!installed.contains(&"exec".to_string()));
+
+        let output = registry
+            .execute(
+                "exec",
+                serde_json::json!({ "command": "echo", "args": ["hi"] }),
+            )
+            .await
+            .unwrap();
+        assert!(output.content.contains("hi"));
+
+        let mut registry = PluginRegistry::new(Permission::ALL);
+        register_standard_plugins(&mut registry, &Permission::ALL, Path::new(".")).await;
+        for command in ["rm", "sh", "echo"] {
+            let result = registry
+                .execute("exec", serde_json::json!({ "command": command }))
+                .await;
+            assert!(result.is_err(), "{} was allowed", command);
+        }
+    }
+}
@@ -3,13 +3,32 @@ use nucleus_plugin::{Permission, Plugin, PluginError, PluginOutput, Result};
 use schemars::{schema_for, JsonSchema};
 use serde::Deserialize;
 use serde_json::Value;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tokio::process::Command;
 
+/// Commands [`register_standard_plugins`](crate::register_standard_plugins)
+/// lets `exec` run: ones that only inspect files and can't start other
+/// programs.
+pub const DEFAULT_ALLOWED_COMMANDS: &[&str] = &[
+    "ls", "pwd", "cat", "head", "tail", "wc", "grep", "diff", "file", "stat",
+];
+
+/// Programs that run arbitrary other commands, so `exec` refuses them unless
+/// they are named in the allow-list.
+const COMMAND_RUNNERS: &[&str] = &[
+    "sh", "bash", "dash", "zsh", "ksh", "mksh", "csh", "tcsh", "fish", "busybox", "env",
+];
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ExecParams {
     /// The shell command to execute (e.g. "git status", "ls -la")
     command: String,
+    /// Arguments passed to the command
+    #[serde(default)]
+    args: Vec<String>,
     /// Current working directory for command execution. Defaults to current directory if not specied.
     #[serde(default)]
     cwd: Option<PathBuf>,
@@ -18,11 +37,128 @@ pub struct ExecParams {
     env: HashMap<String, String>,
 }
 
-pub struct ExecPlugin {}
+/// Plugin for executing shell commands.
+///
+/// Which commands may run can be restricted with
+/// [`with_allowed_commands`](Self::with_allowed_commands) and
+/// [`with_denied_commands`](Self::with_denied_commands). An empty allow-list
+/// allows every command that is not denied; the deny-list always wins. Shells
+/// and `env` are refused unless they are on the allow-list.
+///
+/// With [`with_dry_run`](Self::with_dry_run), commands are described instead
+/// of run, so a supervising layer can log or approve them.
+pub struct ExecPlugin {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+    dry_run: bool,
+}
 
 impl ExecPlugin {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+            dry_run: false,
+        }
+    }
+
+    /// Only allow these commands (e.g. `["git", "ls", "cargo"]`).
+    pub fn with_allowed_commands<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = commands.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Never allow these commands (e.g. `["rm", "curl"]`).
+    pub fn with_denied_commands<I, S>(mut self, commands: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied = commands.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Return the resolved command line (program, args, cwd and the names of
+    /// the env variables) instead of running it.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Describes what would be run for `params`, leaving out env values.
+    fn describe(params: &ExecParams) -> Result<String> {
+        let cwd = match &params.cwd {
+            Some(cwd) => cwd.clone(),
+            None => {
+                std::env::current_dir().map_err(|e| PluginError::ExecutionFailed(e.to_string()))?
+            }
+        };
+        let mut env_keys: Vec<&str> = params.env.keys().map(String::as_str).collect();
+        env_keys.sort_unstable();
+
+        Ok(format!(
+            "dry run, not executed\nprogram: {}\nargs: {:?}\ncwd: {}\nenv: {:?}",
+            params.command,
+            params.args,
+            cwd.display(),
+            env_keys
+        ))
+    }
+
+    /// Checks a command against the allow and deny lists, returning the path
+    /// of the program to run.
+    ///
+    /// The command and the list entries are resolved (through `PATH` for bare
+    /// names) and compared by canonical path, so `/bin/rm` is treated as `rm`
+    /// and a symlink named `ls` pointing at `rm` is not.
+    fn check_command(&self, command: &str, cwd: &Path) -> Result<PathBuf> {
+        let program = resolve_program(command, cwd).ok_or_else(|| {
+            PluginError::InvalidInput(format!("Command '{}' was not found", command))
+        })?;
+        let canonical = std::fs::canonicalize(&program)
+            .map_err(|e| PluginError::ExecutionFailed(format!("{}: {}", command, e)))?;
+        let name = canonical
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(command);
+        let same_program =
+            |entry: &str| canonical_program(entry, cwd).is_some_and(|path| path == canonical);
+
+        if self
+            .denied
+            .iter()
+            .any(|denied| denied == name || same_program(denied))
+        {
+            return Err(PluginError::InvalidInput(format!(
+                "Command '{}' is not allowed",
+                command
+            )));
+        }
+
+        let listed = self.allowed.iter().any(|allowed| same_program(allowed));
+        if !listed
+            && COMMAND_RUNNERS
+                .iter()
+                .any(|runner| *runner == name || same_program(runner))
+        {
+            return Err(PluginError::InvalidInput(format!(
+                "Command '{}' runs other commands and must be explicitly allowed",
+                command
+            )));
+        }
+        if !self.allowed.is_empty() && !listed {
+            return Err(PluginError::InvalidInput(format!(
+                "Command '{}' is not in the list of allowed commands: {}",
+                command,
+                self.allowed.join(", ")
+            )));
+        }
+
+        Ok(program)
     }
 
     pub async fn run(
@@ -41,6 +177,27 @@ impl ExecPlugin {
     }
 }
 
+/// Finds the program `command` names: a path (relative ones against `cwd`) if
+/// it contains a separator, otherwise the first match on `PATH`.
+fn resolve_program(command: &str, cwd: &Path) -> Option<PathBuf> {
+    if command.is_empty() {
+        return None;
+    }
+    if Path::new(command).components().count() > 1 || Path::new(command).is_absolute() {
+        let path = cwd.join(command);
+        return path.is_file().then_some(path);
+    }
+
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| cwd.join(dir).join(command))
+        .find(|path| path.is_file())
+}
+
+fn canonical_program(command: &str, cwd: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(resolve_program(command, cwd)?).ok()
+}
+
 #[async_trait]
 impl Plugin for ExecPlugin {
     fn name(&self) -> &str {
@@ -64,7 +221,20 @@ impl Plugin for ExecPlugin {
         let params: ExecParams = serde_json::from_value(input)
             .map_err(|e| PluginError::InvalidInput(format!("Invalid parameters: {}", e)))?;
 
-        let mut command = Command::new(&params.command);
+        let cwd = match &params.cwd {
+            Some(cwd) => cwd.clone(),
+            None => {
+                std::env::current_dir().map_err(|e| PluginError::ExecutionFailed(e.to_string()))?
+            }
+        };
+        let program = self.check_command(&params.command, &cwd)?;
+
+        if self.dry_run {
+            return Self::describe(&params).map(PluginOutput::new);
+        }
+
+        let mut command = Command::new(&program);
+        command.args(&params.args);
         command.envs(&params.env);
         if params.cwd.is_some() {
             command.current_dir(&params.cwd.unwrap_or_default());
@@ -104,4 +274,113 @@ mod tests {
         let result = plugin.execute(input).await;
         assert!(result.is_ok(), "ls with cwd succeeded")
     }
+
+    #[tokio::test]
+    async fn denied_command_is_rejected() {
+        let plugin = ExecPlugin::new().with_denied_commands(["rm", "curl"]);
+
+        for command in ["rm", "/bin/rm"] {
+            let input = serde_json::json!({ "command": command, "cwd": "src" });
+            let result = plugin.execute(input).await;
+            assert!(
+                matches!(result, Err(PluginError::InvalidInput(_))),
+                "{} was not rejected",
+                command
+            );
+        }
+
+        let result = plugin.execute(serde_json::json!({ "command": "ls" })).await;
+        assert!(result.is_ok(), "ls is not on the deny-list");
+    }
+
+    #[tokio::test]
+    async fn allow_list_restricts_commands() {
+        let plugin = ExecPlugin::new()
+            .with_allowed_commands(["ls", "rm"])
+            .with_denied_commands(["rm"]);
+
+        let result = plugin.execute(serde_json::json!({ "command": "ls" })).await;
+        let output = result.expect("allowed command runs");
+        assert!(output.content.contains("exit_code: 0"));
+
+        for command in ["pwd", "rm"] {
+            let result = plugin
+                .execute(serde_json::json!({ "command": command }))
+                .await;
+            assert!(
+                matches!(result, Err(PluginError::InvalidInput(_))),
+                "{} was not rejected",
+                command
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn shells_must_be_explicitly_allowed() {
+        let plugin = ExecPlugin::new().with_dry_run(true);
+        for command in ["sh", "/bin/sh", "env"] {
+            let result = plugin
+                .execute(serde_json::json!({ "command": command, "args": ["-c", "id"] }))
+                .await;
+            assert!(
+                matches!(result, Err(PluginError::InvalidInput(_))),
+                "{} was not rejected",
+                command
+            );
+        }
+
+        let plugin = plugin.with_allowed_commands(["sh"]);
+        let result = plugin.execute(serde_json::json!({ "command": "sh" })).await;
+        assert!(result.is_ok(), "sh is explicitly allowed");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn commands_are_matched_by_canonical_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let rm = canonical_program("rm", temp.path()).expect("rm is on PATH");
+        std::os::unix::fs::symlink(&rm, temp.path().join("ls")).unwrap();
+
+        let allowed = ExecPlugin::new()
+            .with_allowed_commands(["ls"])
+            .with_dry_run(true);
+        let denied = ExecPlugin::new()
+            .with_denied_commands(["rm"])
+            .with_dry_run(true);
+        for plugin in [allowed, denied] {
+            let input = serde_json::json!({ "command": "./ls", "cwd": temp.path() });
+            let result = plugin.execute(input).await;
+            assert!(
+                matches!(result, Err(PluginError::InvalidInput(_))),
+                "a link to rm named ls was not rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_describes_without_running() {
+        let marker = std::env::temp_dir().join("nucleus_test_exec_dry_run");
+        let _ = std::fs::remove_file(&marker);
+        let plugin = ExecPlugin::new().with_dry_run(true);
+
+        let input = serde_json::json!({
+            "command": "touch",
+            "args": [marker],
+            "cwd": "/",
+            "env": { "API_KEY": "hunter2", "LANG": "C" }
+        });
+        let output = plugin.execute(input).await.expect("dry run succeeds");
+
+        assert!(output.content.contains("program: touch"));
+        assert!(output
+            .content
+            .contains(&format!("args: [{:?}]", marker.to_string_lossy())));
+        assert!(output.content.contains("cwd: /"));
+        assert!(output.content.contains(r#"env: ["API_KEY", "LANG"]"#));
+        assert!(
+            !output.content.contains("hunter2"),
+            "env values are not shown"
+        );
+        assert!(!marker.exists(), "the command was not run");
+    }
 }
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use nucleus_plugin::{Permission, Plugin, PluginError, PluginOutput, Result};
+use schemars::{schema_for, JsonSchema};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct EnvInfoParams {
+    /// Directory to report on (git branch, commit). Defaults to the current directory.
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+/// Plugin reporting basic facts about the environment.
+///
+/// Only an allow-listed set of facts is returned: OS and architecture, the
+/// working directory, the Rust toolchain version and, inside a git repository,
+/// the current branch and commit. Environment variables are never read, so
+/// secrets stored in them cannot leak to the model.
+pub struct EnvInfoPlugin;
+
+impl EnvInfoPlugin {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Runs a fixed command and returns its trimmed stdout if it succeeded.
+async fn command_output(program: &str, args: &[&str], cwd: &Path) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(cwd)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!stdout.is_empty()).then_some(stdout)
+}
+
+#[async_trait]
+impl Plugin for EnvInfoPlugin {
+    fn name(&self) -> &str {
+        "env_info"
+    }
+
+    fn description(&self) -> &str {
+        "Get basic information about the environment: OS, architecture, current directory, Rust toolchain version and git branch/commit"
+    }
+
+    fn parameter_schema(&self) -> Value {
+        let schema = schema_for!(EnvInfoParams);
+        serde_json::to_value(schema).unwrap_or_default()
+    }
+
+    fn required_permission(&self) -> Permission {
+        Permission::READ_ONLY
+    }
+
+    async fn execute(&self, input: Value) -> Result<PluginOutput> {
+        let params: EnvInfoParams = serde_json::from_value(input)
+            .map_err(|e| PluginError::InvalidInput(format!("Invalid parameters: {}", e)))?;
+
+        let cwd = match params.cwd {
+            Some(cwd) => cwd,
+            None => std::env::current_dir().map_err(|e| {
+                PluginError::ExecutionFailed(format!("Failed to get current directory: {}", e))
+            })?,
+        };
+
+        let mut info = Map::new();
+        info.insert("os".to_string(), std::env::consts::OS.into());
+        info.insert("arch".to_string(), std::env::consts::ARCH.into());
+        info.insert("cwd".to_string(), cwd.display().to_string().into());
+
+        let facts = [
+            ("rust_version", "rustc", &["--version"][..]),
+            (
+                "git_branch",
+                "git",
+                &["rev-parse", "--abbrev-ref", "HEAD"][..],
+            ),
+            ("git_commit", "git", &["rev-parse", "--short", "HEAD"][..]),
+        ];
+        for (key, program, args) in facts {
+            let value = command_output(program, args, &cwd).await;
+            info.insert(key.to_string(), value.map_or(Value::Null, Value::from));
+        }
+
+        let info = Value::Object(info);
+        let content = serde_json::to_string_pretty(&info)
+            .map_err(|e| PluginError::ExecutionFailed(e.to_string()))?;
+
+        Ok(PluginOutput::new(content).with_data(info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_env_info_reports_allow_listed_fields() {
+        let plugin = EnvInfoPlugin::new();
+        let result = plugin.execute(serde_json::json!({})).await.unwrap();
+
+        let info: Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(result.data.as_ref(), Some(&info));
+        let keys: Vec<&str> = info
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys.len(), 6, "unexpected fields in env info: {:?}", keys);
+        for key in [
+            "os",
+            "arch",
+            "cwd",
+            "rust_version",
+            "git_branch",
+            "git_commit",
+        ] {
+            assert!(keys.contains(&key), "missing {}", key);
+        }
+
+        assert_eq!(info["os"], std::env::consts::OS);
+        assert_eq!(info["arch"], std::env::consts::ARCH);
+        assert_eq!(
+            info["cwd"],
+            std::env::current_dir().unwrap().display().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_env_info_excludes_environment_variables() {
+        let secret = "nucleus-env-info-secret-value";
+        std::env::set_var("NUCLEUS_ENV_INFO_SECRET", secret);
+
+        let plugin = EnvInfoPlugin::new();
+        let result = plugin.execute(serde_json::json!({})).await.unwrap();
+
+        assert!(!result.content.contains(secret));
+        assert!(!result.content.contains("NUCLEUS_ENV_INFO_SECRET"));
+        if let Ok(path) = std::env::var("PATH") {
+            assert!(!result.content.contains(&path));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_env_info_uses_given_directory() {
+        let dir = std::env::temp_dir();
+        let plugin = EnvInfoPlugin::new();
+        let result = plugin
+            .execute(serde_json::json!({ "cwd": dir }))
+            .await
+            .unwrap();
+
+        let info: Value = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(info["cwd"], dir.display().to_string());
+    }
+}
@@ -4,11 +4,32 @@ use schemars::{schema_for, JsonSchema};
 use serde::Deserialize;
 use serde_json::Value;
 use std::path::{Path, PathBuf};
+use tokio::io::AsyncReadExt;
 
 /// Plugin for reading file contents.
-pub struct ReadFilePlugin;
+///
+/// Files larger than the size limit (1 MiB by default) are truncated, with a
+/// note saying so appended to the output.
+pub struct ReadFilePlugin {
+    max_file_bytes: u64,
+}
 pub struct WriteFilePlugin;
 
+/// Plugin for reading several files in one call.
+///
+/// Each file is returned under a `=== path ===` header. Files that are missing,
+/// larger than the per-file limit or outside the workspace root (if one is set)
+/// are listed as skipped instead. Once the total limit is reached the remaining
+/// output is truncated.
+///
+/// Relative paths resolve against the `pwd` parameter if given, or else the
+/// workspace root.
+pub struct ReadFilesPlugin {
+    root: Option<PathBuf>,
+    max_file_bytes: usize,
+    max_total_bytes: usize,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 struct ReadFileParams {
     /// Absolute or relative path to the file to read
@@ -26,7 +47,15 @@ struct WriteFileParams {
 
 impl ReadFilePlugin {
     pub fn new() -> Self {
-        Self
+        Self {
+            max_file_bytes: 1024 * 1024,
+        }
+    }
+
+    /// Truncate files larger than `max` bytes.
+    pub fn with_max_file_bytes(mut self, max: u64) -> Self {
+        self.max_file_bytes = max;
+        self
     }
 
     pub async fn read(&self, path: &Path) -> Result<PluginOutput> {
@@ -43,6 +72,95 @@ impl WriteFilePlugin {
     }
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ReadFilesParams {
+    /// Absolute or relative paths of the files to read
+    paths: Vec<String>,
+    /// Directory relative paths resolve against, defaulting to the workspace root
+    #[serde(default)]
+    pwd: Option<String>,
+}
+
+impl ReadFilesPlugin {
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            max_file_bytes: 256 * 1024,
+            max_total_bytes: 1024 * 1024,
+        }
+    }
+
+    /// Only allow reading files inside `root`.
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// Skip files larger than `max` bytes.
+    pub fn with_max_file_bytes(mut self, max: usize) -> Self {
+        self.max_file_bytes = max;
+        self
+    }
+
+    /// Truncate the combined output after `max` bytes of file content.
+    pub fn with_max_total_bytes(mut self, max: usize) -> Self {
+        self.max_total_bytes = max;
+        self
+    }
+
+    /// The directory relative paths resolve against: `pwd` if given, or else
+    /// the workspace root.
+    fn base_dir(&self, pwd: Option<&str>) -> Option<PathBuf> {
+        match (&self.root, pwd) {
+            (Some(root), Some(pwd)) => Some(root.join(pwd)),
+            (None, Some(pwd)) => Some(PathBuf::from(pwd)),
+            (root, None) => root.clone(),
+        }
+    }
+
+    /// Reads a single file, returning why it was skipped on failure.
+    async fn read_one(
+        &self,
+        base: Option<&Path>,
+        path: &Path,
+    ) -> std::result::Result<String, String> {
+        let path = match base {
+            Some(base) => base.join(path),
+            None => path.to_path_buf(),
+        };
+        let resolved = tokio::fs::canonicalize(&path)
+            .await
+            .map_err(|_| "not found".to_string())?;
+
+        if let Some(root) = &self.root {
+            let root = tokio::fs::canonicalize(root)
+                .await
+                .map_err(|e| format!("workspace root unavailable: {}", e))?;
+            if !resolved.starts_with(&root) {
+                return Err("outside the workspace".to_string());
+            }
+        }
+
+        let metadata = tokio::fs::metadata(&resolved)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !metadata.is_file() {
+            return Err("not a file".to_string());
+        }
+        if metadata.len() > self.max_file_bytes as u64 {
+            return Err(format!(
+                "too large ({} bytes, limit {})",
+                metadata.len(),
+                self.max_file_bytes
+            ));
+        }
+
+        tokio::fs::read_to_string(&resolved)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
 #[async_trait]
 impl Plugin for ReadFilePlugin {
     fn name(&self) -> &str {
@@ -67,11 +185,41 @@ impl Plugin for ReadFilePlugin {
             .map_err(|e| PluginError::InvalidInput(format!("Invalid parameters: {}", e)))?;
 
         let path = PathBuf::from(&params.path);
-
-        // Read file
-        let content = tokio::fs::read_to_string(&path)
+        let read_error =
+            |e: std::io::Error| PluginError::ExecutionFailed(format!("Failed to read file: {}", e));
+
+        // Read file, up to the size limit
+        let file = tokio::fs::File::open(&path).await.map_err(read_error)?;
+        let size = file.metadata().await.map_err(read_error)?.len();
+        let mut bytes = Vec::new();
+        file.take(self.max_file_bytes)
+            .read_to_end(&mut bytes)
             .await
-            .map_err(|e| PluginError::ExecutionFailed(format!("Failed to read file: {}", e)))?;
+            .map_err(read_error)?;
+
+        let truncated = size > self.max_file_bytes;
+        let mut content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            // The limit may cut a multi-byte character in half
+            Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+                let valid = e.utf8_error().valid_up_to();
+                let mut bytes = e.into_bytes();
+                bytes.truncate(valid);
+                String::from_utf8(bytes).unwrap_or_default()
+            }
+            Err(_) => {
+                return Err(PluginError::ExecutionFailed(
+                    "Failed to read file: stream did not contain valid UTF-8".to_string(),
+                ))
+            }
+        };
+
+        if truncated {
+            content.push_str(&format!(
+                "\n[truncated: file is {} bytes, limit {}]\n",
+                size, self.max_file_bytes
+            ));
+        }
 
         // Log the operation
         println!("Read file: {}", path.display());
@@ -80,6 +228,77 @@ impl Plugin for ReadFilePlugin {
     }
 }
 
+#[async_trait]
+impl Plugin for ReadFilesPlugin {
+    fn name(&self) -> &str {
+        "read_files"
+    }
+
+    fn description(&self) -> &str {
+        "Read the contents of several files at once. Each file is preceded by a '=== path ===' header; files that could not be read are listed at the end"
+    }
+
+    fn parameter_schema(&self) -> Value {
+        let schema = schema_for!(ReadFilesParams);
+        serde_json::to_value(schema).unwrap_or_default()
+    }
+
+    fn required_permission(&self) -> Permission {
+        Permission::READ_ONLY
+    }
+
+    async fn execute(&self, input: Value) -> Result<PluginOutput> {
+        let params: ReadFilesParams = serde_json::from_value(input)
+            .map_err(|e| PluginError::InvalidInput(format!("Invalid parameters: {}", e)))?;
+
+        let base = self.base_dir(params.pwd.as_deref());
+        let mut output = String::new();
+        let mut skipped = Vec::new();
+        let mut remaining = self.max_total_bytes;
+
+        for path in &params.paths {
+            if remaining == 0 {
+                skipped.push(format!("{}: total size limit reached", path));
+                continue;
+            }
+
+            let mut content = match self.read_one(base.as_deref(), Path::new(path)).await {
+                Ok(content) => content,
+                Err(reason) => {
+                    skipped.push(format!("{}: {}", path, reason));
+                    continue;
+                }
+            };
+
+            let truncated = content.len() > remaining;
+            if truncated {
+                let mut end = remaining;
+                while !content.is_char_boundary(end) {
+                    end -= 1;
+                }
+                content.truncate(end);
+            }
+            remaining -= content.len();
+
+            output.push_str(&format!("=== {} ===\n{}\n", path, content));
+            if truncated {
+                output.push_str("[truncated: total size limit reached]\n");
+                remaining = 0;
+            }
+            output.push('\n');
+        }
+
+        if !skipped.is_empty() {
+            output.push_str("Skipped:\n");
+            for entry in &skipped {
+                output.push_str(&format!("- {}\n", entry));
+            }
+        }
+
+        Ok(PluginOutput::new(output))
+    }
+}
+
 #[async_trait]
 impl Plugin for WriteFilePlugin {
     fn name(&self) -> &str {
@@ -145,6 +364,26 @@ mod tests {
         std::fs::remove_file(test_file).ok();
     }
 
+    #[tokio::test]
+    async fn test_read_file_truncates_large_files() {
+        let test_file = std::env::temp_dir().join("nucleus_test_read_large.txt");
+        std::fs::write(&test_file, "héllo wörld").unwrap();
+
+        // The limit falls in the middle of "é"
+        let plugin = ReadFilePlugin::new().with_max_file_bytes(2);
+        let result = plugin.read(&test_file).await.unwrap();
+        assert_eq!(
+            result.content,
+            "h\n[truncated: file is 13 bytes, limit 2]\n"
+        );
+
+        let plugin = ReadFilePlugin::new().with_max_file_bytes(13);
+        let result = plugin.read(&test_file).await.unwrap();
+        assert_eq!(result.content, "héllo wörld");
+
+        std::fs::remove_file(test_file).ok();
+    }
+
     #[tokio::test]
     async fn test_read_nonexistent_file() {
         let plugin = ReadFilePlugin::new();
@@ -156,6 +395,96 @@ mod tests {
         assert!(result.is_err());
     }
 
+    fn read_files_dir() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("a.txt"), "alpha").unwrap();
+        std::fs::write(temp.path().join("b.txt"), "bravo").unwrap();
+        temp
+    }
+
+    #[tokio::test]
+    async fn test_read_multiple_files() {
+        let temp = read_files_dir();
+        let dir = temp.path();
+        let a = dir.join("a.txt").display().to_string();
+        let b = dir.join("b.txt").display().to_string();
+        let missing = dir.join("missing.txt").display().to_string();
+
+        let plugin = ReadFilesPlugin::new().with_root(dir);
+        let input = serde_json::json!({ "paths": [a, missing, b, "/etc/hostname"] });
+        let result = plugin.execute(input).await.unwrap();
+
+        assert!(result
+            .content
+            .starts_with(&format!("=== {} ===\nalpha\n\n=== {} ===\nbravo\n", a, b)));
+        assert!(result.content.contains("Skipped:\n"));
+        assert!(result
+            .content
+            .contains(&format!("- {}: not found\n", missing)));
+        assert!(!result.content.contains("=== /etc/hostname ==="));
+    }
+
+    #[tokio::test]
+    async fn test_read_files_resolves_relative_paths() {
+        let temp = read_files_dir();
+        let dir = temp.path();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/c.txt"), "charlie").unwrap();
+
+        let plugin = ReadFilesPlugin::new().with_root(dir);
+        let input = serde_json::json!({ "paths": ["a.txt", "sub/c.txt"] });
+        let result = plugin.execute(input).await.unwrap();
+        assert_eq!(
+            result.content,
+            "=== a.txt ===\nalpha\n\n=== sub/c.txt ===\ncharlie\n\n"
+        );
+
+        let input = serde_json::json!({ "paths": ["c.txt", "../b.txt"], "pwd": "sub" });
+        let result = plugin.execute(input).await.unwrap();
+        assert_eq!(
+            result.content,
+            "=== c.txt ===\ncharlie\n\n=== ../b.txt ===\nbravo\n\n"
+        );
+
+        let plugin = ReadFilesPlugin::new().with_root(dir.join("sub"));
+        let input = serde_json::json!({ "paths": ["../a.txt"] });
+        let result = plugin.execute(input).await.unwrap();
+        assert_eq!(
+            result.content,
+            "Skipped:\n- ../a.txt: outside the workspace\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_files_size_caps() {
+        let temp = read_files_dir();
+        let dir = temp.path();
+        std::fs::write(dir.join("big.txt"), "x".repeat(100)).unwrap();
+        let a = dir.join("a.txt").display().to_string();
+        let b = dir.join("b.txt").display().to_string();
+        let big = dir.join("big.txt").display().to_string();
+
+        let plugin = ReadFilesPlugin::new()
+            .with_max_file_bytes(50)
+            .with_max_total_bytes(8);
+        let input = serde_json::json!({ "paths": [big, a, b] });
+        let result = plugin.execute(input).await.unwrap();
+
+        assert!(result
+            .content
+            .contains(&format!("- {}: too large (100 bytes, limit 50)\n", big)));
+        assert!(result.content.contains(&format!(
+            "=== {} ===\nalpha\n\n=== {} ===\nbra\n[truncated: total size limit reached]\n",
+            a, b
+        )));
+
+        let input = serde_json::json!({ "paths": [a, b, a] });
+        let result = plugin.execute(input).await.unwrap();
+        assert!(result
+            .content
+            .contains(&format!("- {}: total size limit reached\n", a)));
+    }
+
     #[tokio::test]
     async fn test_write_file() {
         let temp_dir = std::env::temp_dir();
@@ -0,0 +1,13 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Output format requested from a plugin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+    /// Human-readable output (default)
+    #[default]
+    Text,
+    /// A JSON array of result objects, for programmatic consumers
+    Json,
+}
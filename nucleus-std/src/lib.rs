@@ -2,15 +2,35 @@
 //!
 //! The standard library is a collection of built-in plugins that are typical in most use-cases.
 //! Provides essential plugins that work out of the box:
-//! - File operations (read, write, list)
+//! - File operations (read, write, list, apply diffs)
 //! - Search (text and code search)
 //! - Execution (safe command execution)
+//! - Environment info (OS, toolchain, git state)
+//!
+//! [`register_standard_plugins`] installs all of them that a permission level
+//! allows, and the [`prelude`] brings the common plugin types into scope.
 
+mod bundle;
 mod commands;
+mod env;
 mod files;
+mod format;
+mod patch;
 mod search;
 
-pub use commands::ExecPlugin;
-pub use files::{ReadFilePlugin, WriteFilePlugin};
+pub use bundle::register_standard_plugins;
+pub use commands::{ExecPlugin, DEFAULT_ALLOWED_COMMANDS};
+pub use env::EnvInfoPlugin;
+pub use files::{ReadFilePlugin, ReadFilesPlugin, WriteFilePlugin};
+pub use patch::ApplyPatchPlugin;
 pub use search::SearchPlugin;
 // TODO: Implement ListDirectoryPlugin
+
+/// Common plugin types and the standard plugins, for glob imports.
+pub mod prelude {
+    pub use crate::{
+        register_standard_plugins, ApplyPatchPlugin, EnvInfoPlugin, ExecPlugin, ReadFilePlugin,
+        ReadFilesPlugin, SearchPlugin, WriteFilePlugin,
+    };
+    pub use nucleus_plugin::{Permission, Plugin, PluginError, PluginOutput, PluginRegistry};
+}
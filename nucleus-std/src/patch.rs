@@ -0,0 +1,671 @@
+use async_trait::async_trait;
+use nucleus_plugin::{Permission, Plugin, PluginError, PluginOutput, Result};
+use schemars::{schema_for, JsonSchema};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+
+/// Plugin for applying a unified diff to one or more files.
+///
+/// Every hunk must match its context and removed lines exactly, though it may
+/// have moved from the line its header names. All files are patched in memory
+/// first: if any hunk doesn't apply, or writing any file fails, no file is
+/// changed. Patched files keep their CRLF or LF line endings. Paths may carry
+/// the `a/` and `b/` prefixes of `git diff`, and `/dev/null` creates or
+/// deletes a file.
+///
+/// Paths resolve against the workspace root and files outside it are
+/// rejected. A `pwd` parameter narrows the root to that directory, which must
+/// itself be inside the root; without a root set with
+/// [`with_root`](Self::with_root), every call must give a `pwd`.
+pub struct ApplyPatchPlugin {
+    root: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ApplyPatchParams {
+    /// Unified diff to apply, with `---`/`+++` file headers and `@@` hunks
+    patch: String,
+    /// Directory the paths in the diff are relative to, inside the workspace
+    #[serde(default)]
+    pwd: Option<String>,
+}
+
+/// The changes a diff makes to one file.
+#[derive(Debug)]
+struct FilePatch {
+    /// `None` when the file is created.
+    old_path: Option<String>,
+    /// `None` when the file is deleted.
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    old_start: usize,
+    lines: Vec<HunkLine>,
+}
+
+#[derive(Debug)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl Hunk {
+    /// Lines the file must contain for the hunk to apply.
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                HunkLine::Context(text) | HunkLine::Remove(text) => Some(text.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Lines replacing the old ones.
+    fn new_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            HunkLine::Context(text) | HunkLine::Add(text) => Some(text.as_str()),
+            HunkLine::Remove(_) => None,
+        })
+    }
+
+    /// Index of the first old line in the file, according to the header.
+    fn expected_start(&self) -> usize {
+        // Hunks that only add lines name the line they follow
+        if self.old_lines().is_empty() {
+            self.old_start
+        } else {
+            self.old_start.saturating_sub(1)
+        }
+    }
+}
+
+impl ApplyPatchPlugin {
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// Only allow patching files inside `root`.
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(root.into());
+        self
+    }
+
+    /// The directory paths in the diff resolve against: `pwd` if given,
+    /// which must be inside the workspace root, or else the root.
+    async fn effective_root(&self, pwd: Option<&str>) -> Result<PathBuf> {
+        match (&self.root, pwd) {
+            (Some(root), Some(pwd)) => resolve(root, pwd)
+                .await
+                .map_err(|e| PluginError::PermissionDenied(format!("pwd is {}", e))),
+            (None, Some(pwd)) => Ok(PathBuf::from(pwd)),
+            (Some(root), None) => Ok(root.clone()),
+            (None, None) => Err(PluginError::InvalidInput(
+                "No workspace root is set, so a pwd is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Resolves a path from the diff, checking that it is inside the workspace.
+async fn resolve(root: &Path, path: &str) -> std::result::Result<PathBuf, String> {
+    let path = root.join(path);
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err("outside the workspace".to_string());
+    }
+    let root = tokio::fs::canonicalize(root)
+        .await
+        .map_err(|e| format!("workspace root unavailable: {}", e))?;
+
+    // The file may not exist yet, so check its nearest existing ancestor
+    let mut existing = path.as_path();
+    while !tokio::fs::try_exists(existing).await.unwrap_or(false) {
+        existing = existing
+            .parent()
+            .ok_or_else(|| "outside the workspace".to_string())?;
+    }
+    let resolved = tokio::fs::canonicalize(existing)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resolved.starts_with(&root) {
+        return Err("outside the workspace".to_string());
+    }
+    Ok(path)
+}
+
+/// Computes the new content of a file, or `None` if it is deleted.
+async fn patch_file(
+    root: &Path,
+    file: &FilePatch,
+) -> std::result::Result<(PathBuf, Option<String>), String> {
+    let original = match &file.old_path {
+        Some(old_path) => {
+            let path = resolve(root, old_path).await?;
+            tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| format!("failed to read: {}", e))?
+        }
+        None => String::new(),
+    };
+
+    let content = apply_hunks(&original, &file.hunks)?;
+    match &file.new_path {
+        Some(new_path) => {
+            let path = resolve(root, new_path).await?;
+            if file.old_path.is_none() && tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                return Err("already exists".to_string());
+            }
+            Ok((path, Some(content)))
+        }
+        None if content.is_empty() => {
+            let old_path = file.old_path.as_deref().unwrap_or_default();
+            Ok((resolve(root, old_path).await?, None))
+        }
+        None => Err("deleted file still has content after patching".to_string()),
+    }
+}
+
+/// Writes every change, restoring the files as they were if any step fails.
+///
+/// New contents are staged in temporary files next to their targets first.
+/// Each target is then moved aside to a backup and replaced by its staged
+/// file; the backups are moved back if a later replacement fails, and removed
+/// once every change is in place.
+async fn commit(changes: &[(PathBuf, Option<String>)]) -> std::io::Result<()> {
+    let mut staged = Vec::new();
+    for (path, content) in changes {
+        let Some(content) = content else {
+            staged.push(None);
+            continue;
+        };
+        let temp = sibling_path(path, "tmp");
+        let written = async {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&temp, content).await
+        };
+        if let Err(e) = written.await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            for temp in staged.iter().flatten() {
+                let _ = tokio::fs::remove_file(temp).await;
+            }
+            return Err(e);
+        }
+        staged.push(Some(temp));
+    }
+
+    let mut swapped = Vec::new();
+    for ((path, _), temp) in changes.iter().zip(&staged) {
+        match swap(path, temp.as_deref()).await {
+            Ok(backup) => swapped.push((path, temp.is_some(), backup)),
+            Err(e) => {
+                for temp in staged.iter().flatten() {
+                    let _ = tokio::fs::remove_file(temp).await;
+                }
+                for (path, replaced, backup) in swapped.into_iter().rev() {
+                    if replaced {
+                        let _ = tokio::fs::remove_file(path).await;
+                    }
+                    if let Some(backup) = backup {
+                        let _ = tokio::fs::rename(&backup, path).await;
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    for (_, _, backup) in swapped {
+        if let Some(backup) = backup {
+            let _ = tokio::fs::remove_file(backup).await;
+        }
+    }
+    Ok(())
+}
+
+/// Moves `path` aside to a backup, then moves `temp` into its place, or
+/// leaves it gone when there is no `temp` (the file is deleted).
+///
+/// A new file has nothing to back up; a file being deleted must exist.
+/// Returns the backup, if one was made.
+async fn swap(path: &Path, temp: Option<&Path>) -> std::io::Result<Option<PathBuf>> {
+    let backup = if temp.is_none() || tokio::fs::try_exists(path).await? {
+        let backup = sibling_path(path, "orig");
+        tokio::fs::rename(path, &backup).await?;
+        Some(backup)
+    } else {
+        None
+    };
+
+    if let Some(temp) = temp {
+        if let Err(e) = tokio::fs::rename(temp, path).await {
+            if let Some(backup) = &backup {
+                let _ = tokio::fs::rename(backup, path).await;
+            }
+            return Err(e);
+        }
+    }
+    Ok(backup)
+}
+
+/// A hidden file next to `path` for staging or backing up its content.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.apply_patch.{}", name, suffix))
+}
+
+/// Parses the file sections of a unified diff. Lines outside them, such as
+/// `diff --git` and `index` headers, are ignored.
+fn parse_patch(patch: &str) -> std::result::Result<Vec<FilePatch>, String> {
+    let mut files = Vec::new();
+    let mut lines = patch.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(old) = line.strip_prefix("--- ") else {
+            continue;
+        };
+        let new = lines
+            .next()
+            .and_then(|line| line.strip_prefix("+++ "))
+            .ok_or_else(|| format!("expected a '+++' line after '{}'", line))?;
+
+        let mut file = FilePatch {
+            old_path: patch_path(old),
+            new_path: patch_path(new),
+            hunks: Vec::new(),
+        };
+        if file.old_path.is_none() && file.new_path.is_none() {
+            return Err(format!("no file named in '{}'", line));
+        }
+
+        while let Some(header) = lines.next_if(|line| line.starts_with("@@")) {
+            let (old_start, mut old_remaining, mut new_remaining) = parse_hunk_header(header)?;
+            let mut hunk = Hunk {
+                old_start,
+                lines: Vec::new(),
+            };
+            while old_remaining > 0 || new_remaining > 0 {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| format!("hunk '{}' is cut short", header))?;
+                let text = line.get(1..).unwrap_or_default().to_string();
+                let line = match line.chars().next() {
+                    // Editors often strip the space from empty context lines
+                    Some(' ') | None => HunkLine::Context(text),
+                    Some('-') => HunkLine::Remove(text),
+                    Some('+') => HunkLine::Add(text),
+                    Some('\\') => continue,
+                    _ => return Err(format!("unexpected line in hunk '{}': {}", header, line)),
+                };
+                match line {
+                    HunkLine::Context(_) => {
+                        old_remaining = old_remaining.saturating_sub(1);
+                        new_remaining = new_remaining.saturating_sub(1);
+                    }
+                    HunkLine::Remove(_) => old_remaining = old_remaining.saturating_sub(1),
+                    HunkLine::Add(_) => new_remaining = new_remaining.saturating_sub(1),
+                }
+                hunk.lines.push(line);
+            }
+            // "\ No newline at end of file"
+            lines.next_if(|line| line.starts_with('\\'));
+            file.hunks.push(hunk);
+        }
+
+        if file.hunks.is_empty() {
+            return Err(format!("no hunks for '{}'", line));
+        }
+        files.push(file);
+    }
+
+    if files.is_empty() {
+        return Err("no file changes found in patch".to_string());
+    }
+    Ok(files)
+}
+
+/// The path in a `---`/`+++` header, without `git diff` prefixes or
+/// timestamps, or `None` for `/dev/null`.
+fn patch_path(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    if path == "/dev/null" || path.is_empty() {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// Parses `@@ -start,count +start,count @@` into the old start line and the
+/// old and new line counts.
+fn parse_hunk_header(header: &str) -> std::result::Result<(usize, usize, usize), String> {
+    let invalid = || format!("invalid hunk header '{}'", header);
+    let mut ranges = header
+        .trim_start_matches('@')
+        .split_whitespace()
+        .take_while(|part| !part.starts_with("@@"));
+
+    let parse_range = |range: Option<&str>, sign: char| {
+        let range = range.and_then(|range| range.strip_prefix(sign))?;
+        let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+        Some((start.parse::<usize>().ok()?, count.parse::<usize>().ok()?))
+    };
+    let (old_start, old_count) = parse_range(ranges.next(), '-').ok_or_else(invalid)?;
+    let (_, new_count) = parse_range(ranges.next(), '+').ok_or_else(invalid)?;
+    Ok((old_start, old_count, new_count))
+}
+
+/// Applies `hunks` in order to `original`.
+///
+/// A hunk is placed where its old lines occur closest to the line its header
+/// names, shifted by how far earlier hunks moved, and never before the end of
+/// the previous hunk.
+fn apply_hunks(original: &str, hunks: &[Hunk]) -> std::result::Result<String, String> {
+    // Keep the file's line endings, judged by its first line
+    let line_ending = match original.find('\n') {
+        Some(end) if original[..end].ends_with('\r') => "\r\n",
+        _ => "\n",
+    };
+    let lines: Vec<&str> = original.lines().collect();
+    let mut output: Vec<&str> = Vec::new();
+    let mut position = 0;
+    let mut offset: isize = 0;
+
+    for (i, hunk) in hunks.iter().enumerate() {
+        let old = hunk.old_lines();
+        let expected = hunk.expected_start().saturating_add_signed(offset);
+        let last_start = lines.len().checked_sub(old.len());
+        let start = last_start
+            .and_then(|last_start| {
+                (position..=last_start)
+                    .filter(|&start| lines[start..start + old.len()] == old[..])
+                    .min_by_key(|&start| start.abs_diff(expected))
+            })
+            .ok_or_else(|| {
+                format!(
+                    "hunk {} (at line {}) does not match the file",
+                    i + 1,
+                    hunk.old_start
+                )
+            })?;
+
+        output.extend(&lines[position..start]);
+        output.extend(hunk.new_lines());
+        position = start + old.len();
+        offset = start as isize - hunk.expected_start() as isize;
+    }
+    output.extend(&lines[position..]);
+
+    let mut content = output.join(line_ending);
+    if !output.is_empty() && (original.is_empty() || original.ends_with('\n')) {
+        content.push_str(line_ending);
+    }
+    Ok(content)
+}
+
+#[async_trait]
+impl Plugin for ApplyPatchPlugin {
+    fn name(&self) -> &str {
+        "apply_patch"
+    }
+
+    fn description(&self) -> &str {
+        "Apply a unified diff to one or more files. Context lines must match the files exactly; if any hunk fails to apply, no file is changed"
+    }
+
+    fn parameter_schema(&self) -> Value {
+        let schema = schema_for!(ApplyPatchParams);
+        serde_json::to_value(schema).unwrap_or_default()
+    }
+
+    fn required_permission(&self) -> Permission {
+        Permission::READ_WRITE
+    }
+
+    async fn execute(&self, input: Value) -> Result<PluginOutput> {
+        let params: ApplyPatchParams = serde_json::from_value(input)
+            .map_err(|e| PluginError::InvalidInput(format!("Invalid parameters: {}", e)))?;
+        let files = parse_patch(&params.patch)
+            .map_err(|e| PluginError::InvalidInput(format!("Invalid patch: {}", e)))?;
+
+        let root = self.effective_root(params.pwd.as_deref()).await?;
+
+        let mut changes = Vec::new();
+        for file in &files {
+            let name = file.new_path.as_ref().or(file.old_path.as_ref());
+            let change = patch_file(&root, file).await.map_err(|e| {
+                PluginError::ExecutionFailed(format!(
+                    "Patch does not apply to {}: {}",
+                    name.map(String::as_str).unwrap_or_default(),
+                    e
+                ))
+            })?;
+            changes.push(change);
+        }
+
+        commit(&changes)
+            .await
+            .map_err(|e| PluginError::ExecutionFailed(format!("Failed to write patch: {}", e)))?;
+
+        let hunks: usize = files.iter().map(|file| file.hunks.len()).sum();
+        let paths: Vec<String> = changes
+            .iter()
+            .map(|(path, _)| path.display().to_string())
+            .collect();
+        println!("Applied patch to: {}", paths.join(", "));
+
+        Ok(PluginOutput::new(format!(
+            "Applied {} hunk(s) to {} file(s): {}",
+            hunks,
+            paths.len(),
+            paths.join(", ")
+        ))
+        .with_data(json!({ "files": paths, "hunks": hunks })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_applies_multiple_hunks() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("lib.rs"), numbered_lines(12)).unwrap();
+
+        let patch = "\
+diff --git a/lib.rs b/lib.rs
+--- a/lib.rs
++++ b/lib.rs
+@@ -1,3 +1,3 @@
+ line 1
+-line 2
++line two
+ line 3
+@@ -9,3 +9,4 @@
+ line 9
+ line 10
++line 10.5
+ line 11
+";
+        let plugin = ApplyPatchPlugin::new().with_root(dir);
+        let output = plugin.execute(json!({ "patch": patch })).await.unwrap();
+
+        assert!(output.content.contains("Applied 2 hunk(s) to 1 file(s)"));
+        let expected = numbered_lines(12)
+            .replace("line 2\n", "line two\n")
+            .replace("line 11\n", "line 10.5\nline 11\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("lib.rs")).unwrap(),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejects_mismatched_context() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("lib.rs"), numbered_lines(5)).unwrap();
+
+        let patch = "\
+--- a/lib.rs
++++ b/lib.rs
+@@ -2,3 +2,3 @@
+ line 2
+-line three
++line 3!
+ line 4
+";
+        let plugin = ApplyPatchPlugin::new().with_root(dir);
+        let err = plugin.execute(json!({ "patch": patch })).await.unwrap_err();
+
+        assert!(matches!(err, PluginError::ExecutionFailed(_)));
+        assert!(err.to_string().contains("hunk 1"), "{err}");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("lib.rs")).unwrap(),
+            numbered_lines(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_failure_leaves_files_unchanged() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("a.txt"), numbered_lines(3)).unwrap();
+        std::fs::write(dir.join("b.txt"), numbered_lines(3)).unwrap();
+
+        let patch = "\
+--- a/a.txt
++++ b/a.txt
+@@ -1,2 +1,2 @@
+-line 1
++line one
+ line 2
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++created
+--- a/b.txt
++++ b/b.txt
+@@ -1,2 +1,2 @@
+-line 7
++line seven
+ line 2
+";
+        let plugin = ApplyPatchPlugin::new().with_root(dir);
+        assert!(plugin.execute(json!({ "patch": patch })).await.is_err());
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.txt")).unwrap(),
+            numbered_lines(3)
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("b.txt")).unwrap(),
+            numbered_lines(3)
+        );
+        assert!(!dir.join("new.txt").exists());
+
+        let outside = "--- a/../escape.txt\n+++ b/../escape.txt\n@@ -0,0 +1 @@\n+escaped\n";
+        let err = plugin
+            .execute(json!({ "patch": outside }))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("outside the workspace"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_pwd_narrows_the_workspace() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::create_dir_all(dir.join("project")).unwrap();
+        std::fs::write(dir.join("project/lib.rs"), numbered_lines(2)).unwrap();
+
+        let patch = "--- a/lib.rs\n+++ b/lib.rs\n@@ -1,2 +1,2 @@\n-line 1\n+line one\n line 2\n";
+        let plugin = ApplyPatchPlugin::new().with_root(dir);
+        plugin
+            .execute(json!({ "patch": patch, "pwd": "project" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("project/lib.rs")).unwrap(),
+            "line one\nline 2\n"
+        );
+
+        let err = plugin
+            .execute(json!({ "patch": patch, "pwd": "../" }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PluginError::PermissionDenied(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_keeps_crlf_line_endings() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("win.txt"), "one\r\ntwo\r\nthree\r\n").unwrap();
+
+        let patch = "--- a/win.txt\n+++ b/win.txt\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n";
+        let plugin = ApplyPatchPlugin::new().with_root(dir);
+        plugin.execute(json!({ "patch": patch })).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("win.txt")).unwrap(),
+            "one\r\n2\r\nthree\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requires_a_root_or_pwd() {
+        let patch = "--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+created\n";
+        let err = ApplyPatchPlugin::new()
+            .execute(json!({ "patch": patch }))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PluginError::InvalidInput(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_restores_written_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        std::fs::write(dir.join("a.txt"), "before\n").unwrap();
+
+        let changes = vec![
+            (dir.join("a.txt"), Some("after\n".to_string())),
+            (dir.join("new.txt"), Some("created\n".to_string())),
+            (dir.join("missing.txt"), None),
+        ];
+        assert!(commit(&changes).await.is_err());
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.txt")).unwrap(),
+            "before\n"
+        );
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["a.txt"],
+            "no new, staged or backup files remain"
+        );
+    }
+}
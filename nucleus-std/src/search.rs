@@ -1,3 +1,4 @@
+use crate::format::OutputFormat;
 use async_trait::async_trait;
 use nucleus_core::patterns;
 use nucleus_plugin::{Permission, Plugin, PluginError, PluginOutput, Result};
@@ -29,6 +30,9 @@ struct SearchParams {
     /// Patterns to exclude from search (e.g., "node_modules", "*.log")
     #[serde(default = "default_exclude_patterns")]
     exclude_patterns: Vec<String>,
+    /// Output format: "text" (default) or "json" for a plain array of matches
+    #[serde(default)]
+    format: OutputFormat,
 }
 
 fn default_max_results() -> usize {
@@ -51,7 +55,9 @@ impl Plugin for SearchPlugin {
     }
 
     fn description(&self) -> &str {
-        "Search for text patterns in files and/or directories"
+        "Search for text patterns in files and/or directories. \
+        Returns a summary with the matches by default, or with format \"json\" \
+        a JSON array of {file, line, content} objects"
     }
 
     fn parameter_schema(&self) -> Value {
@@ -130,6 +136,11 @@ impl Plugin for SearchPlugin {
             }
         }
 
+        if params.format == OutputFormat::Json {
+            let results = Value::Array(results);
+            return Ok(PluginOutput::new(results.to_string()).with_data(results));
+        }
+
         let result_json = serde_json::json!({
             "summary": format!("Found {} matches", results.len()),
             "results": results
@@ -144,3 +155,68 @@ impl Plugin for SearchPlugin {
 fn should_skip(path: &std::path::Path, exclude_patterns: &[String]) -> bool {
     nucleus_core::patterns::should_exclude(path, exclude_patterns)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "first line\nneedle here\nlast line").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_search_json_format() {
+        let dir = search_dir("nucleus_test_search_json");
+        let plugin = SearchPlugin::new();
+        let input = serde_json::json!({
+            "query": "needle",
+            "path": dir,
+            "exclude_patterns": [],
+            "format": "json"
+        });
+
+        let result = plugin.execute(input).await.unwrap();
+        let matches: Vec<Value> = serde_json::from_str(&result.content).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0]["file"],
+            dir.join("notes.txt").display().to_string()
+        );
+        assert_eq!(matches[0]["line"], 2);
+        assert_eq!(matches[0]["content"], "needle here");
+        assert_eq!(result.data, Some(Value::Array(matches)));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_search_text_format_is_default() {
+        let dir = search_dir("nucleus_test_search_text");
+        let plugin = SearchPlugin::new();
+        let input = serde_json::json!({
+            "query": "needle",
+            "path": dir,
+            "exclude_patterns": []
+        });
+
+        let result = plugin.execute(input).await.unwrap();
+        let expected = serde_json::json!({
+            "summary": "Found 1 matches",
+            "results": [{
+                "file": dir.join("notes.txt").display().to_string(),
+                "line": 2,
+                "content": "needle here"
+            }]
+        });
+        assert_eq!(
+            result.content,
+            serde_json::to_string_pretty(&expected).unwrap()
+        );
+        assert!(result.data.is_none());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}